@@ -15,6 +15,8 @@
 //!
 //! The benchmark measures round-trip latency of `SELECT 1` through both
 //! a direct connection and through the pgvpd proxy, reporting the overhead.
+//! It then times full proxy sessions (connect, `SELECT 1`, Terminate); with
+//! pgvpd in `pool_mode = session` this covers the checkin/checkout reset path.
 
 use std::io::{Read, Write};
 use std::net::TcpStream;
@@ -160,6 +162,29 @@ fn bench_select1(stream: &mut TcpStream, iterations: u32) -> Duration {
     total / iterations
 }
 
+/// Open, use, and close `iterations` proxy sessions; return average session time.
+fn bench_session_cycle(
+    addr: &str,
+    user: &str,
+    database: &str,
+    password: Option<&str>,
+    iterations: u32,
+) -> Option<Duration> {
+    let query = build_query("SELECT 1");
+    let mut total = Duration::ZERO;
+
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let mut stream = connect_and_auth(addr, user, database, password)?;
+        stream.write_all(&query).ok()?;
+        read_until_ready(&mut stream).ok()?;
+        stream.write_all(&build_terminate()).ok()?;
+        total += start.elapsed();
+    }
+
+    Some(total / iterations)
+}
+
 fn main() {
     let pg_port = std::env::var("PGVPD_BENCH_PG_PORT").unwrap_or_else(|_| "5432".into());
     let proxy_port = std::env::var("PGVPD_BENCH_PROXY_PORT").unwrap_or_else(|_| "6432".into());
@@ -215,6 +240,12 @@ fn main() {
     let proxy_avg = bench_select1(&mut proxy, iterations);
     proxy.write_all(&build_terminate()).ok();
 
+    // ─── Proxy session cycle ─────────────────────────────────────────
+
+    let sessions = (iterations / 10).max(1);
+    let session_avg =
+        bench_session_cycle(&proxy_addr, &proxy_user, &db, pg_pass.as_deref(), sessions);
+
     // ─── Results ─────────────────────────────────────────────────────
 
     let overhead = proxy_avg.saturating_sub(direct_avg);
@@ -238,4 +269,12 @@ fn main() {
         overhead.as_nanos() as f64 / 1000.0,
         overhead_pct
     );
+
+    match session_avg {
+        Some(avg) => println!(
+            "  session:   {:>8.1}us (connect + SELECT 1 + close, {sessions} sessions)",
+            avg.as_nanos() as f64 / 1000.0
+        ),
+        None => println!("  session:   failed to open a proxy session"),
+    }
 }
@@ -0,0 +1,34 @@
+//! Fuzz `try_read_backend_message` with arbitrary upstream bytes.
+//!
+//! Invariants: never panics; on `None` the buffer is untouched; on `Some`
+//! exactly `1 + length` bytes are consumed and returned as `raw`.
+
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/protocol.rs"]
+mod protocol;
+
+fuzz_target!(|data: &[u8]| {
+    let mut buf = BytesMut::from(data);
+    let parsed = protocol::try_read_backend_message(&mut buf);
+    let consumed = data.len() - buf.len();
+
+    match parsed {
+        None => assert_eq!(consumed, 0, "consumed bytes without returning a message"),
+        Some(msg) => {
+            let length = i32::from_be_bytes([data[1], data[2], data[3], data[4]]) as usize;
+            assert_eq!(
+                consumed,
+                1 + length,
+                "consumed bytes differ from length field"
+            );
+            assert_eq!(&msg.raw[..], &data[..consumed]);
+            assert_eq!(msg.payload.len(), length - 4);
+            assert_eq!(msg.msg_type, data[0]);
+        }
+    }
+});
@@ -0,0 +1,28 @@
+//! Fuzz `try_read_startup` with arbitrary client bytes.
+//!
+//! Invariants: never panics; on `None` the buffer is untouched; on `Some`
+//! exactly the length-prefixed message is consumed.
+
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/protocol.rs"]
+mod protocol;
+
+fuzz_target!(|data: &[u8]| {
+    let mut buf = BytesMut::from(data);
+    let parsed = protocol::try_read_startup(&mut buf, protocol::DEFAULT_MAX_STARTUP_MESSAGE_BYTES);
+    let consumed = data.len() - buf.len();
+
+    match parsed {
+        None => assert_eq!(consumed, 0, "consumed bytes without returning a message"),
+        Some(_) => {
+            let length = i32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+            assert_eq!(consumed, length, "consumed bytes differ from length field");
+            assert_eq!(&buf[..], &data[length..]);
+        }
+    }
+});
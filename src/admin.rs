@@ -3,40 +3,77 @@
 //! Spawned as a background task when `admin_port` is configured.
 //! Endpoints:
 //!   GET /health  — 200 OK, for load balancer health checks
+//!                  (`?check=upstream` also probes the upstream, for readiness)
 //!   GET /metrics — Prometheus exposition format
 //!   GET /status  — JSON snapshot of pool and resolver state
+//!   GET /version — build metadata and uptime
+//!   GET /tenants — per-tenant connections and rate-window usage
+//!   POST /pool/drain — drain one (database, role) pool bucket
+//!   POST /pool/rebalance — trim buckets hoarding idle connections
+//!
+//! The POST routes require `Content-Type: application/json`.
+//! Requests are rate limited per `admin_rate_limit_rps`, /health separately.
 
-use axum::Router;
-use axum::extract::State;
-use axum::http::StatusCode;
+use axum::extract::{Query, Request, State};
+use axum::http::{HeaderValue, Method, StatusCode, header};
+use axum::middleware::{self, Next};
 use axum::response::{IntoResponse, Response};
-use axum::routing::get;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use rustls::ClientConfig;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::net::TcpListener;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
-use crate::metrics::Metrics;
-use crate::pool::Pool;
-use crate::resolver::ResolverEngine;
+use crate::config::Config;
+use crate::connection::{self, ConnectionState};
+use crate::dns::DnsCache;
+use crate::metrics::{Histogram, HistogramSnapshot, Metrics};
+use crate::pool::{BucketDrainStats, Pool, PoolBucketSnapshot, RebalanceStats};
+use crate::resolver::{DatabaseResolvers, SharedResolver};
+use crate::tenant::{TenantRegistry, TenantSnapshot};
 
 /// Shared state for admin endpoints.
 #[derive(Clone)]
 pub struct AdminState {
     pub metrics: Arc<Metrics>,
     pub pool: Option<Arc<Pool>>,
-    pub resolver: Option<Arc<ResolverEngine>>,
+    pub resolver: SharedResolver,
+    pub database_resolvers: DatabaseResolvers,
+    pub dns: DnsCache,
+    pub config: Arc<Config>,
+    pub upstream_tls: Option<Arc<ClientConfig>>,
+    /// Last `/health?check=upstream` result and when it was taken.
+    pub upstream_health: Arc<tokio::sync::Mutex<Option<(Instant, bool)>>>,
+    /// Present when tenant isolation is configured.
+    pub tenant: Option<Arc<TenantRegistry>>,
 }
 
-/// Start the admin HTTP server on the given port.
-pub async fn serve(state: AdminState, port: u16) {
-    let app = Router::new()
-        .route("/health", get(health))
-        .route("/metrics", get(metrics))
-        .route("/status", get(status))
-        .with_state(state);
+/// How long `POST /pool/drain` waits for checked-out connections to return.
+const POOL_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long an upstream health result is reused before probing again.
+const UPSTREAM_HEALTH_CACHE: Duration = Duration::from_secs(5);
+
+/// Time allowed for the upstream probe.
+const UPSTREAM_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
 
-    let addr = format!("0.0.0.0:{port}");
+/// `/health` gets this many times `admin_rate_limit_rps`: load balancers
+/// probe it often and must not be starved by a busy scraper.
+const HEALTH_RATE_LIMIT_FACTOR: u64 = 10;
+
+/// Start the admin HTTP server on the given host and port.
+pub async fn serve(state: AdminState, host: String, port: u16) {
+    let rps = u64::from(state.config.admin_rate_limit_rps);
+    let limiter = Arc::new(TokenBucketRateLimiter::new(rps));
+    let health_limiter = Arc::new(TokenBucketRateLimiter::new(rps * HEALTH_RATE_LIMIT_FACTOR));
+    let app = router(state, limiter, health_limiter);
+
+    let addr = bind_addr(&host, port);
     match TcpListener::bind(&addr).await {
         Ok(listener) => {
             info!(addr = %addr, "admin API");
@@ -50,9 +87,203 @@ pub async fn serve(state: AdminState, port: u16) {
     }
 }
 
+/// All admin routes with their middleware. CORS is outermost, so preflights
+/// don't spend rate-limit tokens and 429s still carry CORS headers.
+fn router(
+    state: AdminState,
+    limiter: Arc<TokenBucketRateLimiter>,
+    health_limiter: Arc<TokenBucketRateLimiter>,
+) -> Router {
+    let health_routes = Router::new()
+        .route("/health", get(health))
+        .layer(middleware::from_fn_with_state(health_limiter, rate_limit));
+    let pool_routes = Router::new()
+        .route("/pool/drain", post(pool_drain))
+        .route("/pool/rebalance", post(pool_rebalance))
+        .route_layer(middleware::from_fn(require_json));
+    Router::new()
+        .route("/metrics", get(metrics))
+        .route("/status", get(status))
+        .route("/version", get(version))
+        .route("/tenants", get(tenants))
+        .route("/resolver/cache", get(resolver_cache))
+        .merge(pool_routes)
+        .layer(middleware::from_fn_with_state(limiter, rate_limit))
+        .merge(health_routes)
+        .layer(middleware::from_fn_with_state(
+            Arc::clone(&state.config),
+            cors,
+        ))
+        .with_state(state)
+}
+
+/// `host:port` for binding, with IPv6 hosts bracketed.
+fn bind_addr(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+// ─── Rate limiting ───────────────────────────────────────────────────────────
+
+/// Token bucket holding up to `rate` tokens, refilled at `rate` per second.
+/// Lock-free: tokens are kept in thousandths so partial refills aren't lost.
+pub struct TokenBucketRateLimiter {
+    /// Tokens per second, and the bucket's capacity. 0 = unlimited.
+    rate: u64,
+    /// Available tokens × 1000.
+    milli_tokens: AtomicU64,
+    /// Last refill, in milliseconds since `origin`.
+    last_refill_ms: AtomicU64,
+    origin: Instant,
+}
+
+impl TokenBucketRateLimiter {
+    /// A full bucket for `rate` requests per second.
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            milli_tokens: AtomicU64::new(rate.saturating_mul(1000)),
+            last_refill_ms: AtomicU64::new(0),
+            origin: Instant::now(),
+        }
+    }
+
+    /// Take one token; false if the bucket is empty.
+    pub fn try_acquire(&self) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+        self.refill();
+        self.milli_tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |t| t.checked_sub(1000))
+            .is_ok()
+    }
+
+    /// Credit the tokens earned since the last refill. Whoever advances
+    /// `last_refill_ms` adds them, so concurrent callers don't double-count.
+    fn refill(&self) {
+        let now = self.origin.elapsed().as_millis() as u64;
+        let last = self.last_refill_ms.load(Ordering::Acquire);
+        if now <= last
+            || self
+                .last_refill_ms
+                .compare_exchange(last, now, Ordering::AcqRel, Ordering::Acquire)
+                .is_err()
+        {
+            return;
+        }
+        // ms × tokens/s = thousandths of a token
+        let earned = (now - last).saturating_mul(self.rate);
+        let capacity = self.rate.saturating_mul(1000);
+        let _ = self
+            .milli_tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |t| {
+                Some(t.saturating_add(earned).min(capacity))
+            });
+    }
+}
+
+/// Middleware: spend a token per request, 429 when the bucket is empty.
+async fn rate_limit(
+    State(limiter): State<Arc<TokenBucketRateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if limiter.try_acquire() {
+        return next.run(request).await;
+    }
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [("content-type", "application/json")],
+        r#"{"error":"rate limited"}"#,
+    )
+        .into_response()
+}
+
+// ─── State-changing routes ───────────────────────────────────────────────────
+
+/// Middleware: POSTs must say `Content-Type: application/json`. Browsers
+/// can't send that cross-origin without a CORS preflight, so a page an
+/// operator happens to visit can't drain or rebalance the pool with a plain
+/// form post.
+async fn require_json(request: Request, next: Next) -> Response {
+    let is_json = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"));
+    if request.method() == Method::POST && !is_json {
+        return (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            [("content-type", "application/json")],
+            "{\"error\":\"Content-Type must be application/json\"}\n",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+// ─── CORS ────────────────────────────────────────────────────────────────────
+
+/// Middleware: CORS headers for requests whose `Origin` is in
+/// `admin_cors_origins` (any origin with `admin_cors_allow_all`), and 204 for
+/// their `OPTIONS` preflights. Other requests pass through untouched.
+async fn cors(State(config): State<Arc<Config>>, request: Request, next: Next) -> Response {
+    let Some(origin) = request.headers().get(header::ORIGIN) else {
+        return next.run(request).await;
+    };
+    let allow_origin = if config.admin_cors_allow_all {
+        HeaderValue::from_static("*")
+    } else if config
+        .admin_cors_origins
+        .iter()
+        .any(|allowed| origin.as_bytes() == allowed.as_bytes())
+    {
+        origin.clone()
+    } else {
+        return next.run(request).await;
+    };
+
+    let mut response = if request.method() == Method::OPTIONS {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        next.run(request).await
+    };
+    let headers = response.headers_mut();
+    if allow_origin != "*" {
+        headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+    }
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("GET, POST, OPTIONS"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static("Authorization, Content-Type"),
+    );
+    response
+}
+
 // ─── GET /health ─────────────────────────────────────────────────────────────
 
-async fn health() -> impl IntoResponse {
+async fn health(
+    State(state): State<AdminState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    if params.get("check").map(String::as_str) == Some("upstream")
+        && !upstream_healthy(&state).await
+    {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [("content-type", "application/json")],
+            r#"{"status":"unhealthy","reason":"upstream unreachable"}"#,
+        );
+    }
     (
         StatusCode::OK,
         [("content-type", "application/json")],
@@ -60,12 +291,68 @@ async fn health() -> impl IntoResponse {
     )
 }
 
+/// Probe the upstream, or reuse a result younger than `UPSTREAM_HEALTH_CACHE`.
+/// The lock is held across the probe, so concurrent requests share one probe.
+async fn upstream_healthy(state: &AdminState) -> bool {
+    let mut cached = state.upstream_health.lock().await;
+    if let Some((checked, healthy)) = *cached
+        && checked.elapsed() < UPSTREAM_HEALTH_CACHE
+    {
+        return healthy;
+    }
+    let probe = connection::probe_upstream(&state.config, &state.upstream_tls, &state.dns);
+    let healthy = match tokio::time::timeout(UPSTREAM_PROBE_TIMEOUT, probe).await {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            warn!(error = %e, "upstream health check failed");
+            false
+        }
+        Err(_) => {
+            warn!("upstream health check timed out");
+            false
+        }
+    };
+    *cached = Some((Instant::now(), healthy));
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    state
+        .metrics
+        .upstream_health_last_check_secs
+        .store(now, Ordering::Relaxed);
+    healthy
+}
+
+// ─── GET /version ────────────────────────────────────────────────────────────
+
+async fn version() -> impl IntoResponse {
+    let json = format!(
+        "{{\"version\":\"{}\",\"build_date\":\"{}\",\"git_commit\":\"{}\",\"rustc_version\":\"{}\",\"uptime_secs\":{}}}",
+        env!("CARGO_PKG_VERSION"),
+        env!("PGVPD_BUILD_DATE"),
+        env!("PGVPD_GIT_SHA"),
+        env!("PGVPD_RUSTC_VERSION"),
+        crate::STARTED_AT.elapsed().as_secs(),
+    );
+    (StatusCode::OK, [("content-type", "application/json")], json)
+}
+
 // ─── GET /metrics ────────────────────────────────────────────────────────────
 
 async fn metrics(State(state): State<AdminState>) -> Response {
     let m = &state.metrics;
     let mut out = String::with_capacity(2048);
 
+    out.push_str("# HELP pgvpd_uptime_seconds Seconds since pgvpd started.\n");
+    out.push_str("# TYPE pgvpd_uptime_seconds gauge\n");
+    push_metric(
+        &mut out,
+        "pgvpd_uptime_seconds",
+        "",
+        crate::STARTED_AT.elapsed().as_secs(),
+    );
+
     // Connection metrics
     out.push_str("# HELP pgvpd_connections_total Total connections accepted.\n");
     out.push_str("# TYPE pgvpd_connections_total counter\n");
@@ -83,6 +370,79 @@ async fn metrics(State(state): State<AdminState>) -> Response {
         "",
         m.connections_active.load(Ordering::Relaxed),
     );
+    out.push_str(
+        "# HELP pgvpd_connections_rejected_total Connections refused at max_total_connections.\n",
+    );
+    out.push_str("# TYPE pgvpd_connections_rejected_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_connections_rejected_total",
+        "",
+        m.connections_rejected_max_total.load(Ordering::Relaxed),
+    );
+    out.push_str(
+        "# HELP pgvpd_client_idle_timeouts_total Client connections closed for idleness.\n",
+    );
+    out.push_str("# TYPE pgvpd_client_idle_timeouts_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_client_idle_timeouts_total",
+        "",
+        m.client_idle_timeouts.load(Ordering::Relaxed),
+    );
+    out.push_str(
+        "# HELP pgvpd_state_transitions_total Handshake state transitions (pairs seen so far).\n",
+    );
+    out.push_str("# TYPE pgvpd_state_transitions_total counter\n");
+    for from in ConnectionState::ALL {
+        for to in ConnectionState::ALL {
+            let count = m.state_transitions(from, to);
+            if count > 0 {
+                let labels = format!(r#"from="{}",to="{}""#, from.as_str(), to.as_str());
+                push_metric(&mut out, "pgvpd_state_transitions_total", &labels, count);
+            }
+        }
+    }
+
+    // Query metrics
+    out.push_str(
+        "# HELP pgvpd_queries_total Queries forwarded upstream (simple Query and Execute messages).\n",
+    );
+    out.push_str("# TYPE pgvpd_queries_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_queries_total",
+        "",
+        m.queries_total.load(Ordering::Relaxed),
+    );
+    out.push_str("# HELP pgvpd_query_errors_total ErrorResponses forwarded from upstream.\n");
+    out.push_str("# TYPE pgvpd_query_errors_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_query_errors_total",
+        "",
+        m.query_errors_total.load(Ordering::Relaxed),
+    );
+    out.push_str(
+        "# HELP pgvpd_query_errors_by_class_total ErrorResponses forwarded from upstream, by SQLSTATE class.\n",
+    );
+    out.push_str("# TYPE pgvpd_query_errors_by_class_total counter\n");
+    let mut by_class: Vec<(String, u64)> = m
+        .query_errors_by_class
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(class, count)| (class.clone(), *count))
+        .collect();
+    by_class.sort();
+    for (class, count) in &by_class {
+        push_metric(
+            &mut out,
+            "pgvpd_query_errors_by_class_total",
+            &format!(r#"class="{}""#, escape_label(class)),
+            *count,
+        );
+    }
 
     // Pool metrics (per bucket from snapshot)
     if let Some(pool) = &state.pool {
@@ -91,8 +451,12 @@ async fn metrics(State(state): State<AdminState>) -> Response {
         out.push_str("# TYPE pgvpd_pool_connections_total gauge\n");
         out.push_str("# HELP pgvpd_pool_connections_idle Idle connections in pool bucket.\n");
         out.push_str("# TYPE pgvpd_pool_connections_idle gauge\n");
+        out.push_str(
+            "# HELP pgvpd_pool_connections_injecting Checked-out connections still resolving or injecting context.\n",
+        );
+        out.push_str("# TYPE pgvpd_pool_connections_injecting gauge\n");
         for b in &snap.buckets {
-            let labels = format!(r#"database="{}",role="{}""#, b.database, b.role);
+            let labels = bucket_labels(b);
             push_metric(
                 &mut out,
                 "pgvpd_pool_connections_total",
@@ -105,6 +469,69 @@ async fn metrics(State(state): State<AdminState>) -> Response {
                 &labels,
                 b.idle as u64,
             );
+            push_metric(
+                &mut out,
+                "pgvpd_pool_connections_injecting",
+                &labels,
+                b.injecting as u64,
+            );
+        }
+
+        let bucket_counters: [(&str, &str, fn(&PoolBucketSnapshot) -> u64); 6] = [
+            ("checkouts", "Checkouts from this pool bucket.", |b| {
+                b.checkouts
+            }),
+            (
+                "reuses",
+                "Idle connections reused in this pool bucket.",
+                |b| b.reuses,
+            ),
+            ("creates", "Connections created in this pool bucket.", |b| {
+                b.creates
+            }),
+            (
+                "checkins",
+                "Connections returned to this pool bucket.",
+                |b| b.checkins,
+            ),
+            (
+                "discards",
+                "Connections discarded from this pool bucket.",
+                |b| b.discards,
+            ),
+            (
+                "wait_us",
+                "Microseconds spent waiting for checkouts from this pool bucket.",
+                |b| b.total_wait_us,
+            ),
+        ];
+        for (name, help, value) in bucket_counters {
+            let metric = format!("pgvpd_pool_bucket_{name}_total");
+            out.push_str(&format!("# HELP {metric} {help}\n"));
+            out.push_str(&format!("# TYPE {metric} counter\n"));
+            for b in &snap.buckets {
+                push_metric(&mut out, &metric, &bucket_labels(b), value(b));
+            }
+        }
+
+        let bucket_histograms: [(&str, &str, fn(&PoolBucketSnapshot) -> &HistogramSnapshot); 2] = [
+            (
+                "pgvpd_pool_connection_idle_age_seconds",
+                "Idle time of connections evicted by the pool reaper.",
+                |b| &b.age,
+            ),
+            (
+                "pgvpd_pool_connection_lifetime_seconds",
+                "Lifetime of pooled connections when closed.",
+                |b| &b.lifetime,
+            ),
+        ];
+        for (name, help, histogram) in bucket_histograms {
+            out.push_str(&format!("# HELP {name} {help}\n"));
+            out.push_str(&format!("# TYPE {name} histogram\n"));
+            for b in &snap.buckets {
+                push_histogram(&mut out, name, &bucket_labels(b), histogram(b));
+            }
         }
     }
 
@@ -158,9 +585,142 @@ async fn metrics(State(state): State<AdminState>) -> Response {
         "",
         m.pool_timeouts.load(Ordering::Relaxed),
     );
+    out.push_str(
+        "# HELP pgvpd_pool_wait_wakeups_total Waiting checkouts woken by a returned connection.\n",
+    );
+    out.push_str("# TYPE pgvpd_pool_wait_wakeups_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_pool_wait_wakeups_total",
+        "",
+        m.pool_wait_wakeups.load(Ordering::Relaxed),
+    );
+    out.push_str(
+        "# HELP pgvpd_pool_topup_attempts_total Connections created to reach pool_min_size.\n",
+    );
+    out.push_str("# TYPE pgvpd_pool_topup_attempts_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_pool_topup_attempts_total",
+        "",
+        m.pool_topup_attempts_total.load(Ordering::Relaxed),
+    );
+    out.push_str("# HELP pgvpd_pool_topup_failures_total Failed pool_min_size top-up attempts.\n");
+    out.push_str("# TYPE pgvpd_pool_topup_failures_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_pool_topup_failures_total",
+        "",
+        m.pool_topup_failures_total.load(Ordering::Relaxed),
+    );
+    out.push_str(
+        "# HELP pgvpd_pool_keepalive_pings_total Keepalive pings sent to idle pool connections.\n",
+    );
+    out.push_str("# TYPE pgvpd_pool_keepalive_pings_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_pool_keepalive_pings_total",
+        "",
+        m.pool_keepalive_pings_total.load(Ordering::Relaxed),
+    );
+    out.push_str(
+        "# HELP pgvpd_pool_keepalive_failures_total Failed keepalive pings (connection discarded).\n",
+    );
+    out.push_str("# TYPE pgvpd_pool_keepalive_failures_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_pool_keepalive_failures_total",
+        "",
+        m.pool_keepalive_failures_total.load(Ordering::Relaxed),
+    );
+    out.push_str(
+        "# HELP pgvpd_scram_cache_hits_total Upstream SCRAM logins that reused a cached SaltedPassword.\n",
+    );
+    out.push_str("# TYPE pgvpd_scram_cache_hits_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_scram_cache_hits_total",
+        "",
+        m.scram_cache_hits_total.load(Ordering::Relaxed),
+    );
+    out.push_str(
+        "# HELP pgvpd_pool_trust_ips_connections_total Pool clients admitted via trust_ips.\n",
+    );
+    out.push_str("# TYPE pgvpd_pool_trust_ips_connections_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_pool_trust_ips_connections_total",
+        "",
+        m.pool_trust_connections.load(Ordering::Relaxed),
+    );
+    out.push_str("# HELP pgvpd_replica_checkouts_total Pool checkouts routed to a read replica.\n");
+    out.push_str("# TYPE pgvpd_replica_checkouts_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_replica_checkouts_total",
+        "",
+        m.replica_checkouts_total.load(Ordering::Relaxed),
+    );
+
+    out.push_str(
+        "# HELP pgvpd_upstream_health_last_check_secs Unix time of the last upstream health probe.\n",
+    );
+    out.push_str("# TYPE pgvpd_upstream_health_last_check_secs gauge\n");
+    push_metric(
+        &mut out,
+        "pgvpd_upstream_health_last_check_secs",
+        "",
+        m.upstream_health_last_check_secs.load(Ordering::Relaxed),
+    );
+    out.push_str(
+        "# HELP pgvpd_upstream_max_connections Upstream max_connections setting (0 = not read yet).\n",
+    );
+    out.push_str("# TYPE pgvpd_upstream_max_connections gauge\n");
+    push_metric(
+        &mut out,
+        "pgvpd_upstream_max_connections",
+        "",
+        m.upstream_max_connections.load(Ordering::Relaxed),
+    );
+    out.push_str("# HELP pgvpd_config_reloads_total Successful SIGHUP config reloads.\n");
+    out.push_str("# TYPE pgvpd_config_reloads_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_config_reloads_total",
+        "",
+        m.config_reloads_total.load(Ordering::Relaxed),
+    );
+    out.push_str(
+        "# HELP pgvpd_config_reload_errors_total SIGHUP config reloads rejected (current config kept).\n",
+    );
+    out.push_str("# TYPE pgvpd_config_reload_errors_total counter\n");
+    push_metric(
+        &mut out,
+        "pgvpd_config_reload_errors_total",
+        "",
+        m.config_reload_errors_total.load(Ordering::Relaxed),
+    );
+    out.push_str(
+        "# HELP pgvpd_config_last_reload_timestamp Unix time of the last successful config reload (0 = never).\n",
+    );
+    out.push_str("# TYPE pgvpd_config_last_reload_timestamp gauge\n");
+    push_metric(
+        &mut out,
+        "pgvpd_config_last_reload_timestamp",
+        "",
+        m.config_last_reload_timestamp.load(Ordering::Relaxed),
+    );
+    out.push_str("# HELP pgvpd_dns_cache_size Cached upstream address lookups.\n");
+    out.push_str("# TYPE pgvpd_dns_cache_size gauge\n");
+    push_metric(
+        &mut out,
+        "pgvpd_dns_cache_size",
+        "",
+        state.dns.size() as u64,
+    );
 
     // Resolver metrics
-    if let Some(resolver) = &state.resolver {
+    if let Some(resolver) = state.resolver.current() {
         let cache_size = resolver.cache_size().await;
         out.push_str("# HELP pgvpd_resolver_cache_size Current resolver cache entries.\n");
         out.push_str("# TYPE pgvpd_resolver_cache_size gauge\n");
@@ -183,6 +743,37 @@ async fn metrics(State(state): State<AdminState>) -> Response {
         "",
         m.resolver_cache_misses.load(Ordering::Relaxed),
     );
+    out.push_str(
+        "# HELP pgvpd_resolver_cache_hit_rate Resolver cache hits / (hits + misses) since startup. \
+         Derived from the counters at scrape time, not a running average; NaN before any lookup.\n",
+    );
+    out.push_str("# TYPE pgvpd_resolver_cache_hit_rate gauge\n");
+    push_gauge(
+        &mut out,
+        "pgvpd_resolver_cache_hit_rate",
+        "",
+        hit_rate(
+            m.resolver_cache_hits.load(Ordering::Relaxed),
+            m.resolver_cache_misses.load(Ordering::Relaxed),
+        ),
+    );
+    // Per resolver, every execution is a cache miss
+    for (i, name) in m.resolver_names.iter().enumerate() {
+        if let (Some(hits), Some(executions)) = (
+            m.resolver_cache_hits_by_resolver.get(i),
+            m.resolver_executions.get(i),
+        ) {
+            push_gauge(
+                &mut out,
+                "pgvpd_resolver_cache_hit_rate",
+                &format!(r#"resolver="{}""#, name),
+                hit_rate(
+                    hits.load(Ordering::Relaxed),
+                    executions.load(Ordering::Relaxed),
+                ),
+            );
+        }
+    }
 
     if !m.resolver_names.is_empty() {
         out.push_str("# HELP pgvpd_resolver_executions_total Resolver executions.\n");
@@ -234,6 +825,24 @@ async fn metrics(State(state): State<AdminState>) -> Response {
         r#"reason="rate""#,
         m.tenant_rejected_rate.load(Ordering::Relaxed),
     );
+    out.push_str("# HELP pgvpd_tenant_connection_duration_seconds Tenant connection duration.\n");
+    out.push_str("# TYPE pgvpd_tenant_connection_duration_seconds histogram\n");
+    let durations: Vec<(String, Arc<Histogram>)> = m
+        .tenant_connection_duration
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(tenant, h)| (tenant.clone(), Arc::clone(h)))
+        .collect();
+    for (tenant, histogram) in &durations {
+        let labels = format!(r#"tenant="{}""#, escape_label(tenant));
+        push_histogram(
+            &mut out,
+            "pgvpd_tenant_connection_duration_seconds",
+            &labels,
+            &histogram.snapshot(),
+        );
+    }
     out.push_str("# HELP pgvpd_tenant_timeouts_total Tenant query timeouts.\n");
     out.push_str("# TYPE pgvpd_tenant_timeouts_total counter\n");
     push_metric(
@@ -259,6 +868,59 @@ fn push_metric(out: &mut String, name: &str, labels: &str, value: u64) {
     }
 }
 
+fn push_gauge(out: &mut String, name: &str, labels: &str, value: f64) {
+    if labels.is_empty() {
+        out.push_str(&format!("{name} {value}\n"));
+    } else {
+        out.push_str(&format!("{name}{{{labels}}} {value}\n"));
+    }
+}
+
+/// `hits / (hits + misses)`, or NaN when there were no lookups.
+fn hit_rate(hits: u64, misses: u64) -> f64 {
+    let lookups = hits + misses;
+    if lookups == 0 {
+        f64::NAN
+    } else {
+        hits as f64 / lookups as f64
+    }
+}
+
+/// Prometheus labels identifying a pool bucket.
+fn bucket_labels(b: &PoolBucketSnapshot) -> String {
+    format!(
+        r#"database="{}",role="{}",upstream="{}""#,
+        b.database, b.role, b.upstream
+    )
+}
+
+/// Render a histogram's `_bucket`, `_sum` and `_count` series.
+fn push_histogram(out: &mut String, name: &str, labels: &str, snap: &HistogramSnapshot) {
+    for (bound, count) in &snap.buckets {
+        let le = if bound.is_infinite() {
+            "+Inf".to_string()
+        } else {
+            bound.to_string()
+        };
+        push_metric(
+            out,
+            &format!("{name}_bucket"),
+            &format!(r#"{labels},le="{le}""#),
+            *count,
+        );
+    }
+    out.push_str(&format!("{name}_sum{{{labels}}} {}\n", snap.sum));
+    push_metric(out, &format!("{name}_count"), labels, snap.count);
+}
+
+/// Escape a Prometheus label value (client-supplied tenant names).
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
 // ─── GET /status ─────────────────────────────────────────────────────────────
 
 async fn status(State(state): State<AdminState>) -> Response {
@@ -269,11 +931,31 @@ async fn status(State(state): State<AdminState>) -> Response {
 
     // Connections
     json.push_str(&format!(
-        "  \"connections_total\": {},\n  \"connections_active\": {},\n",
+        "  \"connections_total\": {},\n  \"connections_active\": {},\n  \"connections_rejected\": {},\n",
         m.connections_total.load(Ordering::Relaxed),
         m.connections_active.load(Ordering::Relaxed),
+        m.connections_rejected_max_total.load(Ordering::Relaxed),
+    ));
+
+    // Queries
+    json.push_str(&format!(
+        "  \"queries_total\": {},\n  \"query_errors_total\": {},\n",
+        m.queries_total.load(Ordering::Relaxed),
+        m.query_errors_total.load(Ordering::Relaxed),
     ));
 
+    // Config reloads
+    let last_error = m.config_last_reload_error.lock().unwrap().clone();
+    json.push_str("  \"config\": {\n");
+    json.push_str(&format!(
+        "    \"reloads\": {},\n    \"reload_errors\": {},\n    \"last_reload_timestamp\": {},\n    \"last_reload_error\": {}\n",
+        m.config_reloads_total.load(Ordering::Relaxed),
+        m.config_reload_errors_total.load(Ordering::Relaxed),
+        m.config_last_reload_timestamp.load(Ordering::Relaxed),
+        serde_json::to_string(&last_error).unwrap_or_else(|_| "null".into()),
+    ));
+    json.push_str("  },\n");
+
     // Pool
     json.push_str("  \"pool\": {\n");
     json.push_str(&format!(
@@ -300,6 +982,18 @@ async fn status(State(state): State<AdminState>) -> Response {
         "    \"timeouts\": {},\n",
         m.pool_timeouts.load(Ordering::Relaxed)
     ));
+    json.push_str(&format!(
+        "    \"wait_wakeups\": {},\n",
+        m.pool_wait_wakeups.load(Ordering::Relaxed)
+    ));
+    json.push_str(&format!(
+        "    \"trust_connections\": {},\n",
+        m.pool_trust_connections.load(Ordering::Relaxed)
+    ));
+    json.push_str(&format!(
+        "    \"replica_checkouts\": {},\n",
+        m.replica_checkouts_total.load(Ordering::Relaxed)
+    ));
 
     json.push_str("    \"buckets\": [");
     if let Some(pool) = &state.pool {
@@ -309,8 +1003,15 @@ async fn status(State(state): State<AdminState>) -> Response {
                 json.push(',');
             }
             json.push_str(&format!(
-                "\n      {{\"database\": \"{}\", \"role\": \"{}\", \"total\": {}, \"idle\": {}}}",
-                b.database, b.role, b.total, b.idle
+                "\n      {{\"database\": \"{}\", \"role\": \"{}\", \"upstream\": \"{}\", \"total\": {}, \"idle\": {}, \"injecting\": {}, \"checkouts\": {}, \"wait_us_total\": {}}}",
+                b.database,
+                b.role,
+                b.upstream,
+                b.total,
+                b.idle,
+                b.injecting,
+                b.checkouts,
+                b.total_wait_us
             ));
         }
         if !snap.buckets.is_empty() {
@@ -332,7 +1033,7 @@ async fn status(State(state): State<AdminState>) -> Response {
         m.resolver_cache_misses.load(Ordering::Relaxed)
     ));
 
-    if let Some(resolver) = &state.resolver {
+    if let Some(resolver) = state.resolver.current() {
         let cache_size = resolver.cache_size().await;
         json.push_str(&format!("    \"cache_size\": {},\n", cache_size));
     } else {
@@ -363,6 +1064,32 @@ async fn status(State(state): State<AdminState>) -> Response {
         json.push('\n');
         json.push_str("    ");
     }
+    json.push_str("],\n");
+
+    // Per-database engines (`[[database_resolver]]`), sorted by database
+    json.push_str("    \"databases\": [");
+    let mut databases: Vec<_> = state.database_resolvers.iter().collect();
+    databases.sort_by(|a, b| a.0.cmp(b.0));
+    for (i, (database, engine)) in databases.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        let names: Vec<String> = engine
+            .resolvers
+            .iter()
+            .map(|r| format!("\"{}\"", escape_label(&r.name)))
+            .collect();
+        json.push_str(&format!(
+            "\n      {{\"database\": \"{}\", \"resolvers\": [{}], \"cache_size\": {}}}",
+            escape_label(database),
+            names.join(", "),
+            engine.cache_size().await
+        ));
+    }
+    if !databases.is_empty() {
+        json.push('\n');
+        json.push_str("    ");
+    }
     json.push_str("]\n");
     json.push_str("  },\n");
 
@@ -384,9 +1111,425 @@ async fn status(State(state): State<AdminState>) -> Response {
         "    \"timeouts\": {}\n",
         m.tenant_timeouts.load(Ordering::Relaxed)
     ));
-    json.push_str("  }\n");
+    json.push_str("  },\n");
+
+    json.push_str("  \"tenants\": [");
+    let tenants = tenant_snapshot(&state).await;
+    for (i, t) in tenants.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        json.push_str(&format!("\n    {}", tenant_json(t)));
+    }
+    if !tenants.is_empty() {
+        json.push_str("\n  ");
+    }
+    json.push_str("]\n");
 
     json.push_str("}\n");
 
     (StatusCode::OK, [("content-type", "application/json")], json).into_response()
 }
+
+// ─── GET /tenants ────────────────────────────────────────────────────────────
+
+async fn tenants(State(state): State<AdminState>) -> Response {
+    let entries: Vec<String> = tenant_snapshot(&state)
+        .await
+        .iter()
+        .map(|t| format!("\n  {}", tenant_json(t)))
+        .collect();
+    let json = if entries.is_empty() {
+        "[]\n".to_string()
+    } else {
+        format!("[{}\n]\n", entries.join(","))
+    };
+    (StatusCode::OK, [("content-type", "application/json")], json).into_response()
+}
+
+/// Tenant runtime state, sorted by tenant ID; empty without tenant isolation.
+async fn tenant_snapshot(state: &AdminState) -> Vec<TenantSnapshot> {
+    match &state.tenant {
+        Some(registry) => registry.snapshot().await,
+        None => Vec::new(),
+    }
+}
+
+fn tenant_json(t: &TenantSnapshot) -> String {
+    format!(
+        "{{\"tenant_id\": \"{}\", \"active_connections\": {}, \"rate_window_count\": {}, \"rate_window_age_ms\": {}}}",
+        escape_label(&t.tenant_id),
+        t.active_connections,
+        t.rate_window_count,
+        t.rate_window_age_ms
+    )
+}
+
+// ─── POST /pool/rebalance ────────────────────────────────────────────────────
+
+/// Trim idle connections from buckets holding more than their share of the
+/// pool. 404 outside pool mode.
+async fn pool_rebalance(State(state): State<AdminState>) -> Response {
+    let Some(pool) = &state.pool else {
+        return (
+            StatusCode::NOT_FOUND,
+            [("content-type", "application/json")],
+            "{\"error\":\"pool mode is not enabled\"}\n",
+        )
+            .into_response();
+    };
+    let json = rebalance_json(&pool.rebalance().await);
+    (StatusCode::OK, [("content-type", "application/json")], json).into_response()
+}
+
+fn rebalance_json(stats: &RebalanceStats) -> String {
+    format!(
+        "{{\"evicted\": {}, \"buckets_affected\": {}}}\n",
+        stats.evicted, stats.buckets_affected
+    )
+}
+
+// ─── GET /resolver/cache ─────────────────────────────────────────────────────
+
+/// Redacted resolver cache dump. Only served when `PGVPD_DEBUG=true`, since
+/// even column names and key hashes reveal which tenants are active.
+async fn resolver_cache(State(state): State<AdminState>) -> Response {
+    if !std::env::var("PGVPD_DEBUG").is_ok_and(|v| matches!(v.as_str(), "true" | "1" | "yes")) {
+        return (
+            StatusCode::FORBIDDEN,
+            "resolver cache inspection requires PGVPD_DEBUG=true\n",
+        )
+            .into_response();
+    }
+
+    let entries = match state.resolver.current() {
+        Some(resolver) => resolver.cache_entries_debug().await,
+        None => Vec::new(),
+    };
+
+    let mut json = String::with_capacity(256);
+    json.push_str("{\n  \"entries\": [");
+    for (i, e) in entries.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        let columns: Vec<String> = e
+            .columns
+            .iter()
+            .map(|(name, len)| match len {
+                Some(len) => format!(
+                    "{{\"name\": \"{}\", \"length\": {}}}",
+                    escape_label(name),
+                    len
+                ),
+                None => format!("{{\"name\": \"{}\", \"length\": null}}", escape_label(name)),
+            })
+            .collect();
+        json.push_str(&format!(
+            "\n    {{\"resolver\": \"{}\", \"key_hash\": \"{:016x}\", \"columns\": [{}], \"value_count\": {}, \"ttl_remaining_secs\": {}}}",
+            escape_label(&e.resolver_name),
+            e.key_hash,
+            columns.join(", "),
+            e.value_count,
+            e.ttl_remaining_secs
+        ));
+    }
+    if !entries.is_empty() {
+        json.push_str("\n  ");
+    }
+    json.push_str("]\n}\n");
+
+    (StatusCode::OK, [("content-type", "application/json")], json).into_response()
+}
+
+// ─── POST /pool/drain ────────────────────────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+struct PoolDrainRequest {
+    database: String,
+    role: String,
+}
+
+/// Drain one `(database, role)` bucket, e.g. before upgrading its database.
+/// Answers once every connection is back, or after `POOL_DRAIN_TIMEOUT`.
+/// 404 outside pool mode.
+async fn pool_drain(
+    State(state): State<AdminState>,
+    Json(request): Json<PoolDrainRequest>,
+) -> Response {
+    let Some(pool) = &state.pool else {
+        return (
+            StatusCode::NOT_FOUND,
+            [("content-type", "application/json")],
+            "{\"error\":\"pool mode is not enabled\"}\n",
+        )
+            .into_response();
+    };
+    let stats = pool
+        .drain_bucket(&request.database, &request.role, POOL_DRAIN_TIMEOUT)
+        .await;
+    let json = drain_json(&stats);
+    (StatusCode::OK, [("content-type", "application/json")], json).into_response()
+}
+
+fn drain_json(stats: &BucketDrainStats) -> String {
+    format!(
+        "{{\"drained_idle\": {}, \"waiting_for_checkin\": {}}}\n",
+        stats.drained_idle, stats.waiting_for_checkin
+    )
+}
+
+// ─── Tests ──────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limiter_allows_burst_then_refuses() {
+        let limiter = TokenBucketRateLimiter::new(3);
+        assert!((0..3).all(|_| limiter.try_acquire()));
+        assert!(!limiter.try_acquire());
+    }
+
+    #[test]
+    fn rate_limiter_refills_over_time() {
+        let limiter = TokenBucketRateLimiter {
+            rate: 2,
+            milli_tokens: AtomicU64::new(0),
+            last_refill_ms: AtomicU64::new(0),
+            // Pretend 750ms have passed since the last refill
+            origin: Instant::now() - Duration::from_millis(750),
+        };
+        // 0.75s × 2/s = 1.5 tokens
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+        assert!(limiter.milli_tokens.load(Ordering::Relaxed) >= 500);
+    }
+
+    #[test]
+    fn rate_limiter_zero_is_unlimited() {
+        let limiter = TokenBucketRateLimiter::new(0);
+        assert!((0..10_000).all(|_| limiter.try_acquire()));
+    }
+
+    #[test]
+    fn bind_addr_brackets_ipv6() {
+        assert_eq!(bind_addr("127.0.0.1", 8080), "127.0.0.1:8080");
+        assert_eq!(bind_addr("::1", 8080), "[::1]:8080");
+    }
+
+    fn test_state(config: Config, metrics: Metrics) -> AdminState {
+        AdminState {
+            metrics: Arc::new(metrics),
+            pool: None,
+            resolver: SharedResolver::default(),
+            database_resolvers: Default::default(),
+            dns: DnsCache::new(),
+            config: Arc::new(config),
+            upstream_tls: None,
+            upstream_health: Default::default(),
+            tenant: None,
+        }
+    }
+
+    /// Serve the admin router on a loopback port and send one raw request;
+    /// returns the response head, lowercased.
+    async fn admin_request(config: Config, request: &str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let state = test_state(config, Metrics::new(Vec::new()));
+        let unlimited = Arc::new(TokenBucketRateLimiter::new(0));
+        let app = router(state, Arc::clone(&unlimited), unlimited);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+        socket.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).await.unwrap();
+        let head = response.split("\r\n\r\n").next().unwrap_or_default();
+        head.to_lowercase()
+    }
+
+    fn cors_config() -> Config {
+        Config {
+            admin_cors_origins: vec!["https://dash.example.com".into()],
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn cors_preflight_for_allowed_origin() {
+        let head = admin_request(
+            cors_config(),
+            "OPTIONS /status HTTP/1.1\r\nHost: x\r\nOrigin: https://dash.example.com\r\n\
+             Access-Control-Request-Method: GET\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        assert!(head.starts_with("http/1.1 204"), "{head}");
+        assert!(head.contains("access-control-allow-origin: https://dash.example.com"));
+        assert!(head.contains("access-control-allow-methods: get, post, options"));
+        assert!(head.contains("access-control-allow-headers: authorization, content-type"));
+    }
+
+    #[tokio::test]
+    async fn cors_headers_only_for_listed_origins() {
+        let get = |origin: &str| {
+            format!(
+                "GET /health HTTP/1.1\r\nHost: x\r\nOrigin: {origin}\r\nConnection: close\r\n\r\n"
+            )
+        };
+        let head = admin_request(cors_config(), &get("https://dash.example.com")).await;
+        assert!(head.starts_with("http/1.1 200"), "{head}");
+        assert!(head.contains("access-control-allow-origin: https://dash.example.com"));
+        assert!(head.contains("vary: origin"));
+
+        let head = admin_request(cors_config(), &get("https://evil.example.com")).await;
+        assert!(head.starts_with("http/1.1 200"), "{head}");
+        assert!(!head.contains("access-control-"));
+
+        // Nothing configured: no CORS headers at all
+        let head = admin_request(Config::default(), &get("https://dash.example.com")).await;
+        assert!(!head.contains("access-control-"));
+
+        let config = Config {
+            admin_cors_allow_all: true,
+            ..Config::default()
+        };
+        let head = admin_request(config, &get("http://localhost:3000")).await;
+        assert!(head.contains("access-control-allow-origin: *"));
+    }
+
+    #[test]
+    fn hit_rate_handles_no_lookups() {
+        assert!(hit_rate(0, 0).is_nan());
+        assert_eq!(hit_rate(3, 1), 0.75);
+        assert_eq!(hit_rate(0, 5), 0.0);
+    }
+
+    #[tokio::test]
+    async fn metrics_include_resolver_cache_hit_rate() {
+        let m = Metrics::new(vec!["org".into(), "team".into()]);
+        m.resolver_cache_hits.store(3, Ordering::Relaxed);
+        m.resolver_cache_misses.store(1, Ordering::Relaxed);
+        m.resolver_cache_hits_by_resolver[0].store(3, Ordering::Relaxed);
+        m.resolver_executions[0].store(1, Ordering::Relaxed);
+
+        let response = metrics(State(test_state(Config::default(), m))).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains("# TYPE pgvpd_resolver_cache_hit_rate gauge"));
+        assert!(text.contains("pgvpd_resolver_cache_hit_rate 0.75\n"));
+        assert!(text.contains("pgvpd_resolver_cache_hit_rate{resolver=\"org\"} 0.75\n"));
+        assert!(text.contains("pgvpd_resolver_cache_hit_rate{resolver=\"team\"} NaN\n"));
+    }
+
+    async fn body_text(response: Response) -> String {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn pool_rebalance_is_post_only_and_needs_pool_mode() {
+        let request = |method: &str, content_type: &str| {
+            format!(
+                "{method} /pool/rebalance HTTP/1.1\r\nHost: x\r\n{content_type}\
+                 Content-Length: 0\r\nConnection: close\r\n\r\n"
+            )
+        };
+        let json = "Content-Type: application/json\r\n";
+        let head = admin_request(Config::default(), &request("POST", json)).await;
+        assert!(head.starts_with("http/1.1 404"), "{head}");
+        let head = admin_request(Config::default(), &request("GET", "")).await;
+        assert!(head.starts_with("http/1.1 405"), "{head}");
+
+        // A form post (what a cross-site page can send) is refused
+        let form = "Content-Type: application/x-www-form-urlencoded\r\n";
+        let head = admin_request(Config::default(), &request("POST", form)).await;
+        assert!(head.starts_with("http/1.1 415"), "{head}");
+        let head = admin_request(Config::default(), &request("POST", "")).await;
+        assert!(head.starts_with("http/1.1 415"), "{head}");
+
+        let json = rebalance_json(&RebalanceStats {
+            evicted: 3,
+            buckets_affected: 2,
+        });
+        assert_eq!(json, "{\"evicted\": 3, \"buckets_affected\": 2}\n");
+    }
+
+    #[tokio::test]
+    async fn pool_drain_needs_a_bucket_and_pool_mode() {
+        let request = |body: &str| {
+            format!(
+                "POST /pool/drain HTTP/1.1\r\nHost: x\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        };
+        let head = admin_request(
+            Config::default(),
+            &request(r#"{"database":"app","role":"app_user"}"#),
+        )
+        .await;
+        assert!(head.starts_with("http/1.1 404"), "{head}");
+        let head = admin_request(Config::default(), &request(r#"{"database":"app"}"#)).await;
+        assert!(head.starts_with("http/1.1 422"), "{head}");
+
+        let json = drain_json(&BucketDrainStats {
+            drained_idle: 2,
+            waiting_for_checkin: 1,
+        });
+        assert_eq!(json, "{\"drained_idle\": 2, \"waiting_for_checkin\": 1}\n");
+    }
+
+    #[tokio::test]
+    async fn query_errors_by_sqlstate_class_in_metrics() {
+        let m = Metrics::new(Vec::new());
+        m.record_query_error(Some("23"));
+        m.record_query_error(Some("23"));
+        m.record_query_error(Some("53"));
+        m.record_query_error(None);
+
+        let text = body_text(metrics(State(test_state(Config::default(), m))).await).await;
+        assert!(text.contains("pgvpd_query_errors_total 4\n"));
+        assert!(text.contains("pgvpd_query_errors_by_class_total{class=\"23\"} 2\n"));
+        assert!(text.contains("pgvpd_query_errors_by_class_total{class=\"53\"} 1\n"));
+        assert!(text.contains("pgvpd_query_errors_by_class_total{class=\"unknown\"} 1\n"));
+    }
+
+    #[tokio::test]
+    async fn config_reloads_in_metrics_and_status() {
+        let state = test_state(Config::default(), Metrics::new(Vec::new()));
+        let text = body_text(metrics(State(state.clone())).await).await;
+        assert!(text.contains("pgvpd_config_reloads_total 0\n"));
+        assert!(text.contains("pgvpd_config_last_reload_timestamp 0\n"));
+
+        state
+            .metrics
+            .record_config_reload_error("bad \"value\" for pool_size");
+        let text = body_text(status(State(state.clone())).await).await;
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(json["config"]["reload_errors"], 1);
+        assert_eq!(
+            json["config"]["last_reload_error"],
+            "bad \"value\" for pool_size"
+        );
+
+        // A successful reload stamps the time and clears the error
+        state.metrics.record_config_reload();
+        let text = body_text(metrics(State(state.clone())).await).await;
+        assert!(text.contains("pgvpd_config_reloads_total 1\n"));
+        assert!(text.contains("pgvpd_config_reload_errors_total 1\n"));
+        assert!(!text.contains("pgvpd_config_last_reload_timestamp 0\n"));
+        let text = body_text(status(State(state)).await).await;
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(json["config"]["reloads"], 1);
+        assert!(json["config"]["last_reload_error"].is_null());
+    }
+}
@@ -0,0 +1,212 @@
+//! Audit Log — one JSON line per finished client connection.
+//!
+//! The AuditLogger is shared across all connection tasks. Each entry records who
+//! connected, with what tenant context, for how long and how much data moved.
+//! Lines are flushed as they are written; the file is rotated to `<path>.1` once
+//! it would grow past `audit_log_max_bytes`.
+
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::Mutex;
+
+/// One audit record, serialized as a single JSON line.
+#[derive(Debug, Default, Serialize)]
+pub struct AuditEntry {
+    /// Connection end time, RFC 3339 UTC.
+    pub timestamp: String,
+    pub conn_id: u64,
+    pub peer_addr: String,
+    pub tenant: Option<String>,
+    pub role: Option<String>,
+    pub database: Option<String>,
+    pub duration_ms: u64,
+    /// Bytes sent to the client.
+    pub bytes_sent: u64,
+    /// Bytes received from the client.
+    pub bytes_received: u64,
+    pub disconnect_reason: String,
+}
+
+impl AuditEntry {
+    /// Render as a JSON line (with trailing newline).
+    pub fn to_json_line(&self) -> String {
+        let mut line = serde_json::to_string(self).unwrap_or_else(|_| "{}".into());
+        line.push('\n');
+        line
+    }
+}
+
+struct AuditFile {
+    writer: BufWriter<File>,
+    written: u64,
+}
+
+/// Append-only JSON-lines audit writer with size-based rotation.
+pub struct AuditLogger {
+    path: PathBuf,
+    max_bytes: u64,
+    file: Mutex<AuditFile>,
+}
+
+impl AuditLogger {
+    /// Open (or create) the audit log at `path`, appending to existing content.
+    pub async fn open(path: &str, max_bytes: u64) -> io::Result<Self> {
+        let path = PathBuf::from(path);
+        let file = open_append(&path).await?;
+        let written = file.metadata().await?.len();
+        Ok(Self {
+            path,
+            max_bytes,
+            file: Mutex::new(AuditFile {
+                writer: BufWriter::new(file),
+                written,
+            }),
+        })
+    }
+
+    /// Write one entry and flush. Rotates first if the entry would push the
+    /// file past `max_bytes`.
+    pub async fn log(&self, entry: &AuditEntry) -> io::Result<()> {
+        let line = entry.to_json_line();
+        let mut file = self.file.lock().await;
+
+        if file.written > 0 && file.written + line.len() as u64 > self.max_bytes {
+            file.writer.flush().await?;
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(".1");
+            tokio::fs::rename(&self.path, &rotated).await?;
+            file.writer = BufWriter::new(open_append(&self.path).await?);
+            file.written = 0;
+        }
+
+        file.writer.write_all(line.as_bytes()).await?;
+        file.writer.flush().await?;
+        file.written += line.len() as u64;
+        Ok(())
+    }
+}
+
+async fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+}
+
+/// Format a SystemTime as RFC 3339 UTC with millisecond precision,
+/// e.g. `2024-03-01T12:34:56.789Z`.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let millis = since_epoch.subsec_millis();
+
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let (hour, min, sec) = (rem / 3600, (rem % 3600) / 60, rem % 60);
+
+    // Civil-from-days (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{min:02}:{sec:02}.{millis:03}Z")
+}
+
+// ─── Tests ──────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn sample_entry() -> AuditEntry {
+        AuditEntry {
+            timestamp: "2024-03-01T12:34:56.789Z".into(),
+            conn_id: 7,
+            peer_addr: "127.0.0.1:50000".into(),
+            tenant: Some("acme".into()),
+            role: Some("app_user".into()),
+            database: Some("mydb".into()),
+            duration_ms: 1500,
+            bytes_sent: 2048,
+            bytes_received: 512,
+            disconnect_reason: "closed".into(),
+        }
+    }
+
+    #[test]
+    fn json_line_has_all_fields() {
+        let line = sample_entry().to_json_line();
+        assert!(line.ends_with('\n'));
+        let v: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(v["timestamp"], "2024-03-01T12:34:56.789Z");
+        assert_eq!(v["conn_id"], 7);
+        assert_eq!(v["peer_addr"], "127.0.0.1:50000");
+        assert_eq!(v["tenant"], "acme");
+        assert_eq!(v["role"], "app_user");
+        assert_eq!(v["database"], "mydb");
+        assert_eq!(v["duration_ms"], 1500);
+        assert_eq!(v["bytes_sent"], 2048);
+        assert_eq!(v["bytes_received"], 512);
+        assert_eq!(v["disconnect_reason"], "closed");
+    }
+
+    #[test]
+    fn json_line_null_context_and_escaping() {
+        let entry = AuditEntry {
+            disconnect_reason: "upstream said \"no\"".into(),
+            ..Default::default()
+        };
+        let line = entry.to_json_line();
+        assert_eq!(line.matches('\n').count(), 1);
+        let v: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+        assert!(v["tenant"].is_null());
+        assert_eq!(v["disconnect_reason"], "upstream said \"no\"");
+    }
+
+    #[test]
+    fn timestamp_format() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let t = UNIX_EPOCH + Duration::from_millis(1_709_296_496_789);
+        assert_eq!(format_timestamp(t), "2024-03-01T12:34:56.789Z");
+        // Leap day
+        let t = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(format_timestamp(t), "2000-02-29T00:00:00.000Z");
+    }
+
+    #[tokio::test]
+    async fn log_appends_and_rotates() {
+        let dir = std::env::temp_dir().join(format!("pgvpd-audit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.log");
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(dir.join("audit.log.1"));
+
+        let line_len = sample_entry().to_json_line().len() as u64;
+        let logger = AuditLogger::open(path.to_str().unwrap(), line_len * 2)
+            .await
+            .unwrap();
+        logger.log(&sample_entry()).await.unwrap();
+        logger.log(&sample_entry()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        // Third entry exceeds max_bytes — previous content moves to .1
+        logger.log(&sample_entry()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+        let rotated = std::fs::read_to_string(dir.join("audit.log.1")).unwrap();
+        assert_eq!(rotated.lines().count(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,6 @@
 //! Authentication — client-facing and upstream-facing auth handlers.
 //!
-//! Client-facing: cleartext password challenge (pgvpd authenticates the client).
+//! Client-facing: cleartext or MD5 password challenge (pgvpd authenticates the client).
 //! Upstream-facing: cleartext, MD5, and SCRAM-SHA-256 (pgvpd authenticates to Postgres).
 
 use bytes::BytesMut;
@@ -8,14 +8,20 @@ use hmac::{Hmac, Mac};
 use md5::Digest as Md5Digest;
 use md5::Md5 as Md5Hasher;
 use sha2::Sha256;
+use std::collections::HashMap;
 use std::io;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+use subtle::{Choice, ConstantTimeEq};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tracing::debug;
 
+use crate::config::UpstreamAuthMethod;
+use crate::metrics::Metrics;
 use crate::protocol::{
-    BackendMessage, auth, build_auth_cleartext_request, build_auth_ok, build_password_message,
-    build_sasl_initial_response, build_sasl_response, try_read_backend_message,
-    try_read_password_message,
+    BackendMessage, auth, build_auth_cleartext_request, build_auth_md5_request, build_auth_ok,
+    build_password_message, build_sasl_initial_response, build_sasl_response,
+    try_read_backend_message, try_read_password_message,
 };
 use crate::stream::{ClientStream, UpstreamStream};
 
@@ -38,7 +44,134 @@ pub async fn authenticate_client(
         .await
         .map_err(|e| format!("failed to send auth request: {e}"))?;
 
-    // Read password response
+    let password = read_password(client).await?;
+    if bool::from(secret_eq(password.as_bytes(), expected_password.as_bytes())) {
+        debug!(conn_id, "client password verified");
+        send_auth_ok(client).await
+    } else {
+        Err("password authentication failed".into())
+    }
+}
+
+/// Authenticate a client using MD5 password.
+/// Sends AuthenticationMD5Password with a random 4-byte salt, reads the PasswordMessage,
+/// and compares it (in constant time) against `md5(md5(password + username) + salt)`.
+/// `username` must be the user name the client sent in its StartupMessage.
+pub async fn authenticate_client_md5(
+    client: &mut ClientStream,
+    username: &str,
+    expected_password: &str,
+    conn_id: u64,
+) -> Result<(), String> {
+    use rand::RngCore;
+    let mut salt = [0u8; 4];
+    rand::thread_rng().fill_bytes(&mut salt);
+
+    let req = build_auth_md5_request(&salt);
+    client
+        .write_all(&req)
+        .await
+        .map_err(|e| format!("failed to send auth request: {e}"))?;
+
+    let response = read_password(client).await?;
+    if verify_md5_response(username, expected_password, &salt, &response) {
+        debug!(conn_id, "client MD5 password verified");
+        send_auth_ok(client).await
+    } else {
+        Err("password authentication failed".into())
+    }
+}
+
+/// Authenticate a client whose cleartext password is an HS256-signed JWT.
+/// Returns the token's claims so the caller can inject them as context.
+pub async fn authenticate_client_jwt(
+    client: &mut ClientStream,
+    secret: &str,
+    conn_id: u64,
+) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    let req = build_auth_cleartext_request();
+    client
+        .write_all(&req)
+        .await
+        .map_err(|e| format!("failed to send auth request: {e}"))?;
+
+    let token = read_password(client).await?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let claims = verify_jwt_hs256(&token, secret.as_bytes(), now)?;
+    debug!(conn_id, "client JWT verified");
+    send_auth_ok(client).await?;
+    Ok(claims)
+}
+
+/// Verify a compact HS256 JWT and return its claims. Rejects other
+/// algorithms, bad signatures and tokens whose `exp` is not after `now`.
+fn verify_jwt_hs256(
+    token: &str,
+    secret: &[u8],
+    now: u64,
+) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    use base64::Engine;
+    let b64 = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let invalid = || "invalid JWT".to_string();
+
+    let (signing_input, signature) = token.trim().rsplit_once('.').ok_or_else(invalid)?;
+    let (header, payload) = signing_input.split_once('.').ok_or_else(invalid)?;
+    if payload.contains('.') {
+        return Err(invalid());
+    }
+
+    let header: serde_json::Value =
+        serde_json::from_slice(&b64.decode(header).map_err(|_| invalid())?)
+            .map_err(|_| invalid())?;
+    if header.get("alg").and_then(|a| a.as_str()) != Some("HS256") {
+        return Err("JWT must be signed with HS256".into());
+    }
+
+    let signature = b64.decode(signature).map_err(|_| invalid())?;
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(signing_input.as_bytes());
+    mac.verify_slice(&signature)
+        .map_err(|_| "JWT signature verification failed".to_string())?;
+
+    let claims: serde_json::Value =
+        serde_json::from_slice(&b64.decode(payload).map_err(|_| invalid())?)
+            .map_err(|_| invalid())?;
+    let serde_json::Value::Object(claims) = claims else {
+        return Err("JWT payload must be a JSON object".into());
+    };
+    if let Some(exp) = claims.get("exp") {
+        match exp.as_u64() {
+            Some(exp) if exp > now => {}
+            Some(_) => return Err("JWT has expired".into()),
+            None => return Err("JWT exp claim must be a number".into()),
+        }
+    }
+    Ok(claims)
+}
+
+/// Check a client's MD5 PasswordMessage against the expected password.
+fn verify_md5_response(
+    username: &str,
+    expected_password: &str,
+    salt: &[u8],
+    response: &str,
+) -> bool {
+    let expected = compute_md5_password(username, expected_password, salt);
+    secret_eq(response.as_bytes(), expected.as_bytes()).into()
+}
+
+/// Constant-time secret comparison. Both inputs are digested to a fixed
+/// 32 bytes first, so neither the content nor the length of `expected`
+/// leaks through an early return.
+fn secret_eq(provided: &[u8], expected: &[u8]) -> Choice {
+    sha256(provided).ct_eq(&sha256(expected))
+}
+
+/// Read a PasswordMessage from the client.
+async fn read_password(client: &mut ClientStream) -> Result<String, String> {
     let mut buf = BytesMut::with_capacity(1024);
     loop {
         let n = client
@@ -49,44 +182,56 @@ pub async fn authenticate_client(
             return Err("client disconnected during auth".into());
         }
         if let Some(password) = try_read_password_message(&mut buf) {
-            if password == expected_password {
-                debug!(conn_id, "client password verified");
-                // Send AuthenticationOk
-                let ok = build_auth_ok();
-                client
-                    .write_all(&ok)
-                    .await
-                    .map_err(|e| format!("failed to send auth ok: {e}"))?;
-                return Ok(());
-            } else {
-                return Err("password authentication failed".into());
-            }
+            return Ok(password);
         }
     }
 }
 
+async fn send_auth_ok(client: &mut ClientStream) -> Result<(), String> {
+    let ok = build_auth_ok();
+    client
+        .write_all(&ok)
+        .await
+        .map_err(|e| format!("failed to send auth ok: {e}"))
+}
+
 // ─── Upstream-facing authentication ─────────────────────────────────────────
 
 /// Authenticate to upstream Postgres, handling cleartext, MD5, and SCRAM-SHA-256.
 /// Reads auth challenge messages from `server_buf`/server, sends appropriate responses.
-/// Returns Ok(()) when AuthenticationOk is received.
+/// Unless `required` is `Auto`, the server must ask for exactly that method.
+/// SCRAM is refused if the server asks for an iteration count outside
+/// `scram_iterations`, and reuses a SaltedPassword from `scram_cache` when
+/// one was derived for the same user, salt and iteration count. Returns the method used (`None` if the server asked
+/// for none, i.e. trust) when AuthenticationOk is received.
+#[allow(clippy::too_many_arguments)]
 pub async fn authenticate_upstream(
     server: &mut UpstreamStream,
     server_buf: &mut BytesMut,
     username: &str,
     password: &str,
+    required: UpstreamAuthMethod,
+    scram_iterations: RangeInclusive<u32>,
+    scram_cache: Option<&ScramCache>,
     conn_id: u64,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<Option<UpstreamAuthMethod>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut used = None;
     loop {
         // Read more data if buffer has no complete message
-        if server_buf.is_empty() {
-            server.read_buf(server_buf).await?;
+        if server_buf.is_empty() && server.read_buf(server_buf).await? == 0 {
+            return Err("upstream closed the connection during auth".into());
         }
 
         while let Some(msg) = try_read_backend_message(server_buf) {
             if msg.is_auth_ok() {
+                if used.is_none() && required != UpstreamAuthMethod::Auto {
+                    return Err(format!(
+                        "upstream_auth_method = {required}, but upstream did not request authentication"
+                    )
+                    .into());
+                }
                 debug!(conn_id, "upstream auth OK");
-                return Ok(());
+                return Ok(used);
             }
 
             if msg.is_error_response() {
@@ -97,6 +242,22 @@ pub async fn authenticate_upstream(
                 continue;
             };
 
+            let requested = match subtype {
+                auth::CLEARTEXT_PASSWORD => Some(UpstreamAuthMethod::Cleartext),
+                auth::MD5_PASSWORD => Some(UpstreamAuthMethod::Md5),
+                auth::SASL => Some(sasl_method(&msg)?),
+                _ => None,
+            };
+            if let Some(requested) = requested {
+                if required != UpstreamAuthMethod::Auto && required != requested {
+                    return Err(format!(
+                        "upstream requested {requested} authentication, but upstream_auth_method = {required}"
+                    )
+                    .into());
+                }
+                used = Some(requested);
+            }
+
             match subtype {
                 auth::CLEARTEXT_PASSWORD => {
                     debug!(conn_id, "upstream wants cleartext password");
@@ -115,7 +276,17 @@ pub async fn authenticate_upstream(
                 }
                 auth::SASL => {
                     debug!(conn_id, "upstream wants SCRAM-SHA-256");
-                    scram_authenticate(server, server_buf, &msg, password, conn_id).await?;
+                    scram_authenticate(
+                        server,
+                        server_buf,
+                        &msg,
+                        username,
+                        password,
+                        &scram_iterations,
+                        scram_cache,
+                        conn_id,
+                    )
+                    .await?;
                     // After SCRAM, AuthenticationOk should follow
                     continue;
                 }
@@ -131,6 +302,33 @@ pub async fn authenticate_upstream(
     }
 }
 
+/// Pick the SASL mechanism from an AuthenticationSASL message. Only plain
+/// SCRAM-SHA-256 is implemented; a server that insists on channel binding
+/// gets a clear error instead of a failed exchange.
+fn sasl_method(msg: &BackendMessage) -> Result<UpstreamAuthMethod, String> {
+    let mechanisms: Vec<String> = msg
+        .payload
+        .get(4..)
+        .unwrap_or_default()
+        .split(|&b| b == 0)
+        .filter(|m| !m.is_empty())
+        .map(|m| String::from_utf8_lossy(m).into_owned())
+        .collect();
+    if mechanisms.iter().any(|m| m == "SCRAM-SHA-256") {
+        Ok(UpstreamAuthMethod::ScramSha256)
+    } else if mechanisms.iter().any(|m| m == "SCRAM-SHA-256-PLUS") {
+        Err(
+            "upstream requires SCRAM-SHA-256-PLUS (channel binding), which pgvpd does not support"
+                .into(),
+        )
+    } else {
+        Err(format!(
+            "upstream offered unsupported SASL mechanisms: {}",
+            mechanisms.join(", ")
+        ))
+    }
+}
+
 // ─── MD5 ────────────────────────────────────────────────────────────────────
 
 /// Compute MD5 password hash: `md5` || md5(md5(password + username) + salt)
@@ -160,11 +358,15 @@ pub fn compute_md5_password(username: &str, password: &str, salt: &[u8]) -> Stri
 ///   3. Receive AuthenticationSASLContinue with server-first-message
 ///   4. Compute client proof, send SASLResponse with client-final-message
 ///   5. Receive AuthenticationSASLFinal with server signature (verify it)
+#[allow(clippy::too_many_arguments)]
 async fn scram_authenticate(
     server: &mut UpstreamStream,
     server_buf: &mut BytesMut,
     _sasl_msg: &BackendMessage,
+    username: &str,
     password: &str,
+    scram_iterations: &RangeInclusive<u32>,
+    scram_cache: Option<&ScramCache>,
     conn_id: u64,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     use base64::Engine;
@@ -172,6 +374,9 @@ async fn scram_authenticate(
 
     // Generate client nonce
     let client_nonce = generate_nonce();
+    if !validate_nonce(&client_nonce) {
+        return Err("SCRAM: generated client nonce has invalid characters".into());
+    }
     let client_first_bare = format!("n=,r={client_nonce}");
     let client_first_message = format!("n,,{client_first_bare}");
 
@@ -199,17 +404,28 @@ async fn scram_authenticate(
     // Parse server-first-message: r=<nonce>,s=<salt>,i=<iterations>
     let (server_nonce, salt_b64, iterations) = parse_server_first(&server_first)?;
 
-    // Verify server nonce starts with our client nonce
-    if !server_nonce.starts_with(&client_nonce) {
-        return Err("SCRAM: server nonce doesn't start with client nonce".into());
-    }
+    check_server_nonce(server_nonce, &client_nonce)?;
+    check_iterations(iterations, scram_iterations)?;
 
     let salt = b64
         .decode(salt_b64)
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("bad salt: {e}")))?;
 
-    // Derive keys
-    let salted_password = hi(password.as_bytes(), &salt, iterations);
+    // Derive keys (PBKDF2 is the expensive part, so reuse a cached result)
+    let cached = scram_cache.and_then(|c| c.get(username, salt_b64, iterations));
+    let salted_password = match cached {
+        Some(salted_password) => {
+            debug!(conn_id, "SCRAM: SaltedPassword from cache");
+            salted_password
+        }
+        None => {
+            let salted_password = hi(password.as_bytes(), &salt, iterations);
+            if let Some(cache) = scram_cache {
+                cache.insert(username, salt_b64, iterations, salted_password);
+            }
+            salted_password
+        }
+    };
     let client_key = hmac_sha256(&salted_password, b"Client Key");
     let stored_key = sha256(&client_key);
     let server_key = hmac_sha256(&salted_password, b"Server Key");
@@ -268,6 +484,112 @@ async fn scram_authenticate(
     Ok(())
 }
 
+/// Whether `nonce` is a valid SCRAM nonce: non-empty printable ASCII
+/// (`\x21`–`\x7E`) without `,` (RFC 5802 §5.1) or `=`.
+fn validate_nonce(nonce: &str) -> bool {
+    !nonce.is_empty()
+        && nonce
+            .bytes()
+            .all(|b| (0x21..=0x7e).contains(&b) && b != b',' && b != b'=')
+}
+
+/// The server's nonce must be valid and extend ours. The prefix comparison
+/// is constant-time.
+fn check_server_nonce(server_nonce: &str, client_nonce: &str) -> Result<(), String> {
+    if !validate_nonce(server_nonce) {
+        return Err("SCRAM: server nonce contains invalid characters".into());
+    }
+    let extends_ours = server_nonce.len() > client_nonce.len()
+        && bool::from(server_nonce.as_bytes()[..client_nonce.len()].ct_eq(client_nonce.as_bytes()));
+    if !extends_ours {
+        return Err("SCRAM: server nonce doesn't start with client nonce".into());
+    }
+    Ok(())
+}
+
+/// Bounded cache of SCRAM SaltedPassword values, keyed by
+/// `(username, salt_b64, iterations)`, so a burst of new upstream connections
+/// (pool warm-up, upstream restart) doesn't repeat PBKDF2 for each one.
+///
+/// The password is not part of the key: one cache must only ever be used
+/// with one password per user (the pool's `upstream_password`). A changed
+/// upstream password comes with a new salt, so it misses.
+pub struct ScramCache {
+    inner: Mutex<ScramCacheInner>,
+    max_entries: usize,
+    metrics: Arc<Metrics>,
+}
+
+struct ScramCacheInner {
+    /// Key → (SaltedPassword, last-use tick).
+    entries: HashMap<(String, String, u32), ([u8; 32], u64)>,
+    tick: u64,
+}
+
+impl ScramCache {
+    pub fn new(max_entries: usize, metrics: Arc<Metrics>) -> Self {
+        Self {
+            inner: Mutex::new(ScramCacheInner {
+                entries: HashMap::new(),
+                tick: 0,
+            }),
+            max_entries,
+            metrics,
+        }
+    }
+
+    fn get(&self, username: &str, salt_b64: &str, iterations: u32) -> Option<[u8; 32]> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        let key = (username.to_string(), salt_b64.to_string(), iterations);
+        let (salted_password, last_used) = inner.entries.get_mut(&key)?;
+        *last_used = tick;
+        Metrics::inc(&self.metrics.scram_cache_hits_total);
+        Some(*salted_password)
+    }
+
+    /// Store a SaltedPassword, evicting the least recently used entry when full.
+    fn insert(&self, username: &str, salt_b64: &str, iterations: u32, salted_password: [u8; 32]) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        let key = (username.to_string(), salt_b64.to_string(), iterations);
+        if !inner.entries.contains_key(&key) && inner.entries.len() >= self.max_entries {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                inner.entries.remove(&oldest);
+            }
+        }
+        inner.entries.insert(key, (salted_password, tick));
+    }
+}
+
+/// Refuse iteration counts too low to protect the password or high enough
+/// to burn pgvpd's CPU.
+fn check_iterations(iterations: u32, allowed: &RangeInclusive<u32>) -> Result<(), String> {
+    if iterations < *allowed.start() {
+        return Err(format!(
+            "SCRAM: server requested {iterations} iterations, minimum is {}",
+            allowed.start()
+        ));
+    }
+    if iterations > *allowed.end() {
+        return Err(format!(
+            "SCRAM: server requested {iterations} iterations, maximum is {}",
+            allowed.end()
+        ));
+    }
+    Ok(())
+}
+
 /// Parse server-first-message into (nonce, salt_b64, iterations).
 fn parse_server_first(
     msg: &str,
@@ -324,6 +646,62 @@ fn generate_nonce() -> String {
 mod tests {
     use super::*;
 
+    // ─── JWT verification ────────────────────────────────────────────────
+
+    fn sign_jwt(header: &str, payload: &str, secret: &[u8]) -> String {
+        use base64::Engine;
+        let b64 = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let signing_input = format!("{}.{}", b64.encode(header), b64.encode(payload));
+        let sig = hmac_sha256(secret, signing_input.as_bytes());
+        format!("{signing_input}.{}", b64.encode(sig))
+    }
+
+    const HS256: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+    #[test]
+    fn jwt_valid_token_returns_claims() {
+        let token = sign_jwt(HS256, r#"{"tenant_id":"acme","exp":2000}"#, b"secret");
+        let claims = verify_jwt_hs256(&token, b"secret", 1000).unwrap();
+        assert_eq!(claims["tenant_id"], "acme");
+    }
+
+    #[test]
+    fn jwt_rejects_bad_signature_and_expiry() {
+        let token = sign_jwt(HS256, r#"{"tenant_id":"acme","exp":2000}"#, b"secret");
+        assert!(
+            verify_jwt_hs256(&token, b"wrong", 1000)
+                .unwrap_err()
+                .contains("signature")
+        );
+        assert!(
+            verify_jwt_hs256(&token, b"secret", 2000)
+                .unwrap_err()
+                .contains("expired")
+        );
+
+        // Tampered payload with the original signature
+        use base64::Engine;
+        let forged_payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(r#"{"tenant_id":"evil","exp":2000}"#);
+        let parts: Vec<&str> = token.split('.').collect();
+        let forged = format!("{}.{forged_payload}.{}", parts[0], parts[2]);
+        assert!(verify_jwt_hs256(&forged, b"secret", 1000).is_err());
+    }
+
+    #[test]
+    fn jwt_rejects_other_algorithms_and_malformed_tokens() {
+        let none = sign_jwt(r#"{"alg":"none"}"#, r#"{"tenant_id":"acme"}"#, b"secret");
+        assert!(
+            verify_jwt_hs256(&none, b"secret", 0)
+                .unwrap_err()
+                .contains("HS256")
+        );
+        assert!(verify_jwt_hs256("not-a-jwt", b"secret", 0).is_err());
+        assert!(verify_jwt_hs256("a.b.c.d", b"secret", 0).is_err());
+        let array = sign_jwt(HS256, "[1,2]", b"secret");
+        assert!(verify_jwt_hs256(&array, b"secret", 0).is_err());
+    }
+
     // ─── MD5 password computation ────────────────────────────────────────
 
     #[test]
@@ -366,8 +744,170 @@ mod tests {
         assert_ne!(r1, r2);
     }
 
+    // ─── Client-facing MD5 ───────────────────────────────────────────────
+
+    #[test]
+    fn md5_response_correct_hash_accepted() {
+        let salt = [9, 8, 7, 6];
+        let response = compute_md5_password("app_user.acme", "secret", &salt);
+        assert!(verify_md5_response(
+            "app_user.acme",
+            "secret",
+            &salt,
+            &response
+        ));
+    }
+
+    #[test]
+    fn md5_response_wrong_password_rejected() {
+        let salt = [9, 8, 7, 6];
+        let response = compute_md5_password("app_user.acme", "guess", &salt);
+        assert!(!verify_md5_response(
+            "app_user.acme",
+            "secret",
+            &salt,
+            &response
+        ));
+    }
+
+    #[test]
+    fn md5_response_wrong_salt_rejected() {
+        let response = compute_md5_password("app_user.acme", "secret", &[1, 1, 1, 1]);
+        assert!(!verify_md5_response(
+            "app_user.acme",
+            "secret",
+            &[2, 2, 2, 2],
+            &response
+        ));
+    }
+
+    #[test]
+    fn md5_response_cleartext_rejected() {
+        assert!(!verify_md5_response("u", "secret", &[1, 2, 3, 4], "secret"));
+    }
+
+    #[tokio::test]
+    async fn md5_client_handshake_roundtrip() {
+        use tokio::net::{TcpListener, TcpStream};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut client = ClientStream::Plain(socket);
+            authenticate_client_md5(&mut client, "app_user.acme", "secret", 1).await
+        });
+
+        let mut conn = TcpStream::connect(addr).await.unwrap();
+        let mut buf = BytesMut::new();
+        let challenge = loop {
+            conn.read_buf(&mut buf).await.unwrap();
+            if let Some(msg) = try_read_backend_message(&mut buf) {
+                break msg;
+            }
+        };
+        assert_eq!(challenge.auth_subtype(), Some(auth::MD5_PASSWORD));
+        let salt = &challenge.payload[4..8];
+        let hashed = compute_md5_password("app_user.acme", "secret", salt);
+        conn.write_all(&build_password_message(hashed.as_bytes()))
+            .await
+            .unwrap();
+
+        let ok = loop {
+            if let Some(msg) = try_read_backend_message(&mut buf) {
+                break msg;
+            }
+            conn.read_buf(&mut buf).await.unwrap();
+        };
+        assert!(ok.is_auth_ok());
+        assert!(server.await.unwrap().is_ok());
+    }
+
+    // ─── Constant-time comparison ────────────────────────────────────────
+
+    #[test]
+    fn secret_eq_same_length_wrong_and_right_share_path() {
+        // Both outcomes come back as a `Choice` from the same digest comparison;
+        // there is no separate early-exit branch for a same-length mismatch.
+        let right: Choice = secret_eq(b"hunter22", b"hunter22");
+        let wrong: Choice = secret_eq(b"hunter23", b"hunter22");
+        assert_eq!(right.unwrap_u8(), 1);
+        assert_eq!(wrong.unwrap_u8(), 0);
+    }
+
+    #[test]
+    fn secret_eq_length_mismatch() {
+        assert_eq!(secret_eq(b"short", b"much-longer-secret").unwrap_u8(), 0);
+        assert_eq!(secret_eq(b"", b"x").unwrap_u8(), 0);
+        assert_eq!(secret_eq(b"", b"").unwrap_u8(), 1);
+    }
+
+    #[test]
+    fn secret_eq_digests_are_fixed_length() {
+        assert_eq!(sha256(b"").len(), sha256(&[0u8; 4096]).len());
+    }
+
     // ─── SCRAM helpers ───────────────────────────────────────────────────
 
+    // ─── ScramCache ─────────────────────────────────────────────────────
+
+    #[test]
+    fn scram_cache_hits_and_counts() {
+        let metrics = Arc::new(Metrics::new(Vec::new()));
+        let cache = ScramCache::new(10, metrics.clone());
+        let salted = hi(b"secret", b"salt", 4096);
+
+        assert_eq!(cache.get("app", "c2FsdA==", 4096), None);
+        cache.insert("app", "c2FsdA==", 4096, salted);
+        assert_eq!(cache.get("app", "c2FsdA==", 4096), Some(salted));
+        // Any part of the key differing is a miss
+        assert_eq!(cache.get("other", "c2FsdA==", 4096), None);
+        assert_eq!(cache.get("app", "bmV3", 4096), None);
+        assert_eq!(cache.get("app", "c2FsdA==", 8192), None);
+        assert_eq!(
+            metrics
+                .scram_cache_hits_total
+                .load(std::sync::atomic::Ordering::Relaxed),
+            1
+        );
+    }
+
+    #[test]
+    fn scram_cache_evicts_least_recently_used() {
+        let cache = ScramCache::new(2, Arc::new(Metrics::new(Vec::new())));
+        cache.insert("a", "s", 4096, [1; 32]);
+        cache.insert("b", "s", 4096, [2; 32]);
+        // Touch "a" so "b" is the oldest
+        assert!(cache.get("a", "s", 4096).is_some());
+        cache.insert("c", "s", 4096, [3; 32]);
+
+        assert_eq!(cache.get("a", "s", 4096), Some([1; 32]));
+        assert_eq!(cache.get("b", "s", 4096), None);
+        assert_eq!(cache.get("c", "s", 4096), Some([3; 32]));
+    }
+
+    #[test]
+    fn scram_cache_disabled_at_zero() {
+        let cache = ScramCache::new(0, Arc::new(Metrics::new(Vec::new())));
+        cache.insert("a", "s", 4096, [1; 32]);
+        assert_eq!(cache.get("a", "s", 4096), None);
+    }
+
+    #[test]
+    fn iteration_count_limits() {
+        let allowed = 4096..=1_000_000;
+        assert!(check_iterations(4096, &allowed).is_ok());
+        assert!(check_iterations(1_000_000, &allowed).is_ok());
+
+        let err = check_iterations(1, &allowed).unwrap_err();
+        assert_eq!(err, "SCRAM: server requested 1 iterations, minimum is 4096");
+        let err = check_iterations(1_000_001, &allowed).unwrap_err();
+        assert_eq!(
+            err,
+            "SCRAM: server requested 1000001 iterations, maximum is 1000000"
+        );
+    }
+
     #[test]
     fn parse_server_first_valid() {
         let msg = "r=clientnonceservernonce,s=c2FsdA==,i=4096";
@@ -453,6 +993,37 @@ mod tests {
         assert_ne!(n1, n2);
     }
 
+    #[test]
+    fn generated_nonce_is_valid() {
+        assert!(validate_nonce(&generate_nonce()));
+    }
+
+    #[test]
+    fn validate_nonce_rejects_bad_characters() {
+        assert!(validate_nonce("fyko+d2lbbFgONRv9qkxdawL"));
+        assert!(!validate_nonce(""));
+        assert!(!validate_nonce("abc,def"));
+        assert!(!validate_nonce("abc=def"));
+        assert!(!validate_nonce("abc def"));
+        assert!(!validate_nonce("abc\x7f"));
+        assert!(!validate_nonce("nonce\u{e9}"));
+    }
+
+    #[test]
+    fn server_nonce_must_extend_client_nonce() {
+        let client = "fyko+d2lbbFgONRv9qkxdawL";
+        assert!(check_server_nonce(&format!("{client}3rfcNHYJY1ZVvWVs7j"), client).is_ok());
+
+        let err = check_server_nonce("someoneelse3rfcNHYJY1ZVvWVs7j", client).unwrap_err();
+        assert!(err.contains("doesn't start with client nonce"));
+        // The server must add its own part
+        assert!(check_server_nonce(client, client).is_err());
+        assert!(check_server_nonce("fyko", client).is_err());
+
+        let err = check_server_nonce(&format!("{client}abc,def"), client).unwrap_err();
+        assert!(err.contains("invalid characters"));
+    }
+
     #[test]
     fn nonce_is_base64() {
         use base64::Engine;
@@ -467,6 +1038,82 @@ mod tests {
 
     // ─── SCRAM full derivation ───────────────────────────────────────────
 
+    // ─── Upstream auth ───────────────────────────────────────────────────
+
+    /// Upstream that sends `challenge`, captures the client's PasswordMessage
+    /// and accepts it.
+    async fn fake_upstream(
+        challenge: BytesMut,
+    ) -> (std::net::SocketAddr, tokio::task::JoinHandle<String>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            socket.write_all(&challenge).await.unwrap();
+            let mut buf = BytesMut::new();
+            let password = loop {
+                if let Some(pw) = try_read_password_message(&mut buf) {
+                    break pw;
+                }
+                if socket.read_buf(&mut buf).await.unwrap() == 0 {
+                    break String::new();
+                }
+            };
+            let _ = socket.write_all(&build_auth_ok()).await;
+            password
+        });
+        (addr, handle)
+    }
+
+    async fn connect(addr: std::net::SocketAddr) -> UpstreamStream {
+        UpstreamStream::Plain(tokio::net::TcpStream::connect(addr).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn upstream_md5_sends_hashed_password() {
+        let salt = [9, 8, 7, 6];
+        let (addr, server) = fake_upstream(build_auth_md5_request(&salt)).await;
+        let mut stream = connect(addr).await;
+        let used = authenticate_upstream(
+            &mut stream,
+            &mut BytesMut::new(),
+            "pgvpd_pool",
+            "secret",
+            UpstreamAuthMethod::Md5,
+            4096..=1_000_000,
+            None,
+            1,
+        )
+        .await
+        .unwrap();
+        assert_eq!(used, Some(UpstreamAuthMethod::Md5));
+        assert_eq!(
+            server.await.unwrap(),
+            compute_md5_password("pgvpd_pool", "secret", &salt)
+        );
+    }
+
+    #[tokio::test]
+    async fn upstream_method_mismatch_fails_clearly() {
+        let (addr, _server) = fake_upstream(build_auth_cleartext_request()).await;
+        let mut stream = connect(addr).await;
+        let err = authenticate_upstream(
+            &mut stream,
+            &mut BytesMut::new(),
+            "pgvpd_pool",
+            "secret",
+            UpstreamAuthMethod::ScramSha256,
+            4096..=1_000_000,
+            None,
+            1,
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("requested cleartext"), "{err}");
+        assert!(err.contains("scram-sha-256"), "{err}");
+    }
+
     #[test]
     fn scram_key_derivation_consistency() {
         // Verify the full key derivation chain produces consistent results
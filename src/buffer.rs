@@ -0,0 +1,91 @@
+//! Buffer recycling for the pooled pipe.
+//!
+//! Every pooled session needs a client and a server read buffer. Instead of
+//! allocating a fresh pair per session, finished sessions hand theirs back
+//! through a bounded channel and the next session picks them up.
+
+use bytes::BytesMut;
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+
+/// Buffers that grew past this many times the base size are dropped on `put`
+/// rather than kept around (one huge result set shouldn't pin memory).
+const MAX_GROWTH: usize = 16;
+
+/// A bounded free list of `BytesMut` buffers.
+pub struct BufferPool {
+    buf_size: usize,
+    tx: mpsc::Sender<BytesMut>,
+    rx: Mutex<mpsc::Receiver<BytesMut>>,
+}
+
+impl BufferPool {
+    /// Pool of `buf_size`-byte buffers keeping at most `max_idle` spares.
+    pub fn new(buf_size: usize, max_idle: usize) -> Self {
+        let (tx, rx) = mpsc::channel(max_idle.max(1));
+        Self {
+            buf_size,
+            tx,
+            rx: Mutex::new(rx),
+        }
+    }
+
+    /// Take a recycled buffer, or allocate one if none are spare.
+    pub fn get(&self) -> BytesMut {
+        self.rx
+            .lock()
+            .unwrap()
+            .try_recv()
+            .unwrap_or_else(|_| BytesMut::with_capacity(self.buf_size))
+    }
+
+    /// Return a buffer for reuse. It is cleared first; if the pool is full or
+    /// the buffer has grown too large it is simply dropped.
+    pub fn put(&self, mut buf: BytesMut) {
+        if buf.capacity() > self.buf_size * MAX_GROWTH {
+            return;
+        }
+        buf.clear();
+        let _ = self.tx.try_send(buf);
+    }
+}
+
+// ─── Tests ──────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn put_buffer_is_reused_and_cleared() {
+        let pool = BufferPool::new(64, 2);
+        let mut buf = pool.get();
+        buf.extend_from_slice(b"leftover");
+        let ptr = buf.as_ptr();
+        pool.put(buf);
+
+        let reused = pool.get();
+        assert_eq!(reused.as_ptr(), ptr);
+        assert!(reused.is_empty());
+        assert!(reused.capacity() >= 64);
+    }
+
+    #[test]
+    fn full_pool_drops_extra_buffers() {
+        let pool = BufferPool::new(64, 1);
+        pool.put(BytesMut::with_capacity(64));
+        pool.put(BytesMut::with_capacity(64));
+        let _first = pool.get();
+        // Only one was kept; the next get allocates
+        let second = pool.get();
+        assert!(second.capacity() >= 64);
+        assert!(pool.rx.lock().unwrap().try_recv().is_err());
+    }
+
+    #[test]
+    fn oversized_buffers_are_not_kept() {
+        let pool = BufferPool::new(64, 4);
+        pool.put(BytesMut::with_capacity(64 * MAX_GROWTH + 1));
+        assert!(pool.rx.lock().unwrap().try_recv().is_err());
+    }
+}
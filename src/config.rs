@@ -1,9 +1,17 @@
 //! Configuration — CLI flags, environment variables, config file.
 
 use clap::Parser;
+use ipnetwork::IpNetwork;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::net::{IpAddr, Ipv6Addr};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use crate::protocol::escape_set_value;
 
 /// Pool mode — how upstream connections are managed.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -23,13 +31,238 @@ impl fmt::Display for PoolMode {
     }
 }
 
+/// How clients authenticate to pgvpd in pool mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolAuthMethod {
+    /// AuthenticationCleartextPassword — password sent in the clear (use TLS).
+    Cleartext,
+    /// AuthenticationMD5Password — salted MD5 hash of password + username.
+    Md5,
+}
+
+impl fmt::Display for PoolAuthMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cleartext => write!(f, "cleartext"),
+            Self::Md5 => write!(f, "md5"),
+        }
+    }
+}
+
+/// How pgvpd authenticates to upstream (`upstream_auth_method`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamAuthMethod {
+    /// Answer whatever the server asks for (cleartext, MD5 or SCRAM-SHA-256).
+    Auto,
+    Cleartext,
+    Md5,
+    ScramSha256,
+    /// SCRAM with channel binding. Parsed so the error is explicit, but not
+    /// supported — `validate()` rejects it.
+    ScramSha256Plus,
+}
+
+impl fmt::Display for UpstreamAuthMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Cleartext => write!(f, "cleartext"),
+            Self::Md5 => write!(f, "md5"),
+            Self::ScramSha256 => write!(f, "scram-sha-256"),
+            Self::ScramSha256Plus => write!(f, "scram-sha-256-plus"),
+        }
+    }
+}
+
+/// Whether client connections start with a PROXY protocol header
+/// (`proxy_protocol_mode`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyProtocolMode {
+    Off,
+    /// Every connection must start with a v1 or v2 header.
+    Required,
+    /// Parse a header if one is present, otherwise use the TCP peer address.
+    Detect,
+}
+
+impl fmt::Display for ProxyProtocolMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::Required => write!(f, "required"),
+            Self::Detect => write!(f, "detect"),
+        }
+    }
+}
+
+/// How a pooled connection is reset between clients (`pool_reset_query`).
+/// Every checkin starts with ROLLBACK; the variants differ in what follows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoolReset {
+    /// Checkin runs RESET ROLE; RESET ALL, and this statement (`DISCARD ALL`
+    /// by default) runs on the next checkout. Safest.
+    Query(String),
+    /// `reset_role`: checkin runs RESET ROLE only. Fastest, but session
+    /// settings the previous client made outlive its session.
+    ResetRole,
+    /// `none`: no reset beyond ROLLBACK, for applications that clean up after
+    /// themselves.
+    Skip,
+}
+
+impl PoolReset {
+    /// Statement run on checkout, if any.
+    pub fn checkout_query(&self) -> Option<&str> {
+        match self {
+            Self::Query(sql) => Some(sql),
+            Self::ResetRole | Self::Skip => None,
+        }
+    }
+
+    /// Statement run on checkin after ROLLBACK, if any.
+    pub fn checkin_query(&self) -> Option<&'static str> {
+        match self {
+            Self::Query(_) => Some("RESET ROLE; RESET ALL"),
+            Self::ResetRole => Some("RESET ROLE"),
+            Self::Skip => None,
+        }
+    }
+}
+
+impl fmt::Display for PoolReset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Query(sql) => write!(f, "{sql}"),
+            Self::ResetRole => write!(f, "reset_role"),
+            Self::Skip => write!(f, "none"),
+        }
+    }
+}
+
+/// Which upstream servers a session may be routed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoutingMode {
+    /// Every session goes to `upstream_host` (the default).
+    PrimaryOnly,
+    /// Read-only sessions are spread across `upstream_replica_hosts`.
+    ReplicaReads,
+}
+
+impl fmt::Display for RoutingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PrimaryOnly => write!(f, "primary_only"),
+            Self::ReplicaReads => write!(f, "replica_reads"),
+        }
+    }
+}
+
+/// Log line format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines (the default).
+    Text,
+    /// One JSON object per line, for log aggregators.
+    Json,
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// How often `log_file` rolls over to a new, date-suffixed file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogRotation {
+    Never,
+    Minutely,
+    Hourly,
+    Daily,
+}
+
+impl fmt::Display for LogRotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Never => write!(f, "never"),
+            Self::Minutely => write!(f, "minutely"),
+            Self::Hourly => write!(f, "hourly"),
+            Self::Daily => write!(f, "daily"),
+        }
+    }
+}
+
+/// A `[[database_resolver]]` block: connections to `database` run the
+/// resolvers in this file instead of the global `resolvers` file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatabaseResolver {
+    pub database: String,
+    pub resolvers: String,
+}
+
+/// A `[[tls_cert]]` block: TLS clients asking for a server name that matches
+/// `sni` (exactly, or `*.example.com` for one label under it) get this
+/// certificate instead of `tls_cert`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsSniCert {
+    pub sni: String,
+    pub cert: String,
+    pub key: String,
+}
+
+/// A `superuser_bypass` rule: `user` skips tenant extraction, on every
+/// database when `databases` is empty, otherwise only on those listed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SuperuserBypassRule {
+    pub user: String,
+    pub databases: Vec<String>,
+}
+
+impl SuperuserBypassRule {
+    /// Rules from a comma-separated user list (`superuser_bypass = a, b`),
+    /// each applying to every database.
+    fn from_users(value: &str) -> Vec<Self> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| Self {
+                user: s.to_string(),
+                databases: Vec::new(),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for SuperuserBypassRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.databases.is_empty() {
+            write!(f, "{}", self.user)
+        } else {
+            write!(f, "{} ({})", self.user, self.databases.join(", "))
+        }
+    }
+}
+
 /// Pgvpd — Virtual Private Database for PostgreSQL
 #[derive(Parser, Debug)]
 #[command(name = "pgvpd", version, about)]
 pub struct Cli {
-    /// Config file path
-    #[arg(long, default_value = "pgvpd.conf")]
-    pub config: String,
+    /// Config file path [default: first found of ./pgvpd.conf,
+    /// /etc/pgvpd/pgvpd.conf, /usr/local/etc/pgvpd.conf]
+    #[arg(long)]
+    pub config: Option<String>,
+
+    /// Skip config files; use only environment variables and flags
+    #[arg(long, conflicts_with = "config")]
+    pub no_config: bool,
+
+    /// Treat config file warnings (unknown keys, invalid values) as errors
+    #[arg(long)]
+    pub strict_config: bool,
 
     /// Listen port
     #[arg(long, short = 'p')]
@@ -39,6 +272,14 @@ pub struct Cli {
     #[arg(long)]
     pub listen_host: Option<String>,
 
+    /// Also listen on listen_host_v6 (IPv6 only, same port)
+    #[arg(long)]
+    pub listen_ipv6: Option<bool>,
+
+    /// IPv6 bind address used when listen_ipv6 is set
+    #[arg(long)]
+    pub listen_host_v6: Option<String>,
+
     /// Upstream Postgres host
     #[arg(long)]
     pub upstream_host: Option<String>,
@@ -47,10 +288,34 @@ pub struct Cli {
     #[arg(long)]
     pub upstream_port: Option<u16>,
 
+    /// Retries after a failed upstream TCP connect
+    #[arg(long)]
+    pub upstream_connect_retries: Option<u32>,
+
+    /// Initial delay between upstream connect retries (ms); doubles each attempt
+    #[arg(long)]
+    pub upstream_connect_retry_delay_ms: Option<u64>,
+
+    /// Seconds to cache resolved upstream addresses (0 disables caching)
+    #[arg(long)]
+    pub upstream_dns_ttl_secs: Option<u64>,
+
+    /// Read replicas as comma-separated host[:port]
+    #[arg(long)]
+    pub upstream_replica_hosts: Option<String>,
+
+    /// Routing mode: primary_only or replica_reads
+    #[arg(long)]
+    pub routing_mode: Option<String>,
+
     /// Tenant separator in username
     #[arg(long)]
     pub separator: Option<String>,
 
+    /// Regex with named groups `user` and `context` (overrides --separator)
+    #[arg(long)]
+    pub separator_regex: Option<String>,
+
     /// Comma-separated context variable names
     #[arg(long)]
     pub context: Option<String>,
@@ -59,14 +324,39 @@ pub struct Cli {
     #[arg(long)]
     pub value_separator: Option<String>,
 
+    /// Allow Unicode letters and digits in tenant IDs, not just ASCII (true/false)
+    #[arg(long)]
+    pub tenant_id_allow_unicode: Option<bool>,
+
     /// Comma-separated superuser bypass usernames
     #[arg(long)]
     pub superuser: Option<String>,
 
+    /// Comma-separated users allowed to open replication connections
+    /// (default: the superuser bypass list)
+    #[arg(long)]
+    pub replication_passthrough_usernames: Option<String>,
+
     /// Log level
     #[arg(long)]
     pub log_level: Option<String>,
 
+    /// Log format: text or json
+    #[arg(long)]
+    pub log_format: Option<String>,
+
+    /// Include timestamps in log lines (true/false)
+    #[arg(long)]
+    pub log_timestamps: Option<bool>,
+
+    /// Also write logs to this file
+    #[arg(long)]
+    pub log_file: Option<String>,
+
+    /// log_file rotation: never, minutely, hourly or daily
+    #[arg(long)]
+    pub log_rotation: Option<String>,
+
     /// TLS listen port (enables TLS termination)
     #[arg(long)]
     pub tls_port: Option<u16>,
@@ -79,6 +369,26 @@ pub struct Cli {
     #[arg(long)]
     pub tls_key: Option<String>,
 
+    /// Path to TLS certificate + key bundle (PKCS#12 / .pfx)
+    #[arg(long)]
+    pub tls_cert_pkcs12: Option<String>,
+
+    /// Password for the PKCS#12 bundle
+    #[arg(long)]
+    pub tls_cert_pkcs12_password: Option<String>,
+
+    /// Seconds between checks of tls_cert / tls_key for changes (0 = never reload)
+    #[arg(long)]
+    pub tls_watch_interval_secs: Option<u64>,
+
+    /// Comma-separated list of allowed TLS cipher suites
+    #[arg(long)]
+    pub tls_cipher_suites: Option<String>,
+
+    /// Minimum TLS protocol version: 1.2 or 1.3
+    #[arg(long)]
+    pub tls_min_version: Option<String>,
+
     /// Enable TLS to upstream Postgres
     #[arg(long)]
     pub upstream_tls: bool,
@@ -91,10 +401,42 @@ pub struct Cli {
     #[arg(long)]
     pub upstream_tls_ca: Option<String>,
 
+    /// Base64 SHA-256 of the upstream certificate's public key (SPKI pin)
+    #[arg(long)]
+    pub upstream_tls_pin_pubkey: Option<String>,
+
     /// Handshake timeout in seconds
     #[arg(long)]
     pub handshake_timeout: Option<u64>,
 
+    /// Max StartupMessage size in bytes (64..=1048576)
+    #[arg(long)]
+    pub max_startup_message_bytes: Option<usize>,
+
+    /// Path to a JSON-lines audit log of finished connections
+    #[arg(long)]
+    pub audit_log_file: Option<String>,
+
+    /// Rotate the audit log when it exceeds this many bytes
+    #[arg(long)]
+    pub audit_log_max_bytes: Option<u64>,
+
+    /// Close client connections idle for this many seconds (0 = disabled)
+    #[arg(long)]
+    pub client_idle_timeout: Option<u64>,
+
+    /// Max concurrent client connections across all tenants (0 = unlimited)
+    #[arg(long)]
+    pub max_total_connections: Option<u64>,
+
+    /// PROXY protocol on client connections: off, required or detect
+    #[arg(long)]
+    pub proxy_protocol_mode: Option<String>,
+
+    /// CIDR ranges (load balancers) allowed to send a PROXY header (comma-separated)
+    #[arg(long)]
+    pub proxy_protocol_trusted_sources: Option<String>,
+
     /// Pool mode: none or session
     #[arg(long)]
     pub pool_mode: Option<String>,
@@ -103,22 +445,130 @@ pub struct Cli {
     #[arg(long)]
     pub pool_size: Option<u32>,
 
+    /// Idle connections kept open per (database, role) once it has been used
+    #[arg(long)]
+    pub pool_min_size: Option<u32>,
+
     /// Password clients must provide in pool mode
     #[arg(long)]
     pub pool_password: Option<String>,
 
+    /// Client auth method in pool mode: cleartext or md5
+    #[arg(long)]
+    pub pool_auth_method: Option<String>,
+
+    /// CIDR ranges whose clients skip pool-mode password auth (comma-separated)
+    #[arg(long)]
+    pub trust_ips: Option<String>,
+
+    /// StartupMessage parameters forwarded upstream (comma-separated)
+    #[arg(long)]
+    pub startup_param_allowlist: Option<String>,
+
+    /// Extra startup parameter sent upstream as name=value (repeatable)
+    #[arg(long = "startup-param")]
+    pub startup_param: Vec<String>,
+
+    /// Rename a client startup parameter as from=to (repeatable)
+    #[arg(long = "startup-param-rename")]
+    pub startup_param_rename: Vec<String>,
+
+    /// SQL comment template prepended to the injected SET batch
+    #[arg(long)]
+    pub inject_comment_template: Option<String>,
+
+    /// Set the pgvpd connection ID as a session variable (true/false)
+    #[arg(long)]
+    pub inject_conn_id: Option<bool>,
+
+    /// Session variable that receives the connection ID
+    #[arg(long)]
+    pub inject_conn_id_var: Option<String>,
+
+    /// Label pooled sessions with the tenant and client role (true/false)
+    #[arg(long)]
+    pub pool_inject_tenant_label: Option<bool>,
+
+    /// Session variable that receives the tenant ID in pool mode
+    #[arg(long)]
+    pub pool_tenant_label_var: Option<String>,
+
+    /// Send the client a NOTICE listing the injected context (true/false)
+    #[arg(long)]
+    pub send_context_notice: Option<bool>,
+
+    /// Suffix appended to the client's application_name upstream
+    #[arg(long)]
+    pub application_name_suffix: Option<String>,
+
+    /// Rewrite application_name upstream (true/false)
+    #[arg(long)]
+    pub rewrite_application_name: Option<bool>,
+
+    /// Forward upstream NegotiateProtocolVersion to clients during auth (true/false)
+    #[arg(long)]
+    pub protocol_negotiate_passthrough: Option<bool>,
+
     /// Password pgvpd uses to authenticate to upstream in pool mode
     #[arg(long)]
     pub upstream_password: Option<String>,
 
+    /// Upstream auth method to require: auto, cleartext, md5, scram-sha-256
+    #[arg(long)]
+    pub upstream_auth_method: Option<String>,
+
+    /// Fewest SCRAM iterations accepted from the upstream
+    #[arg(long)]
+    pub scram_min_iterations: Option<u32>,
+
+    /// Most SCRAM iterations computed for the upstream
+    #[arg(long)]
+    pub scram_max_iterations: Option<u32>,
+
+    /// Fixed user pool connections log in as (default: the client's role)
+    #[arg(long)]
+    pub pool_upstream_user: Option<String>,
+
+    /// Treat the pool-mode password as an HS256 JWT carrying context (true/false)
+    #[arg(long)]
+    pub pool_jwt_context: Option<bool>,
+
+    /// JWT claims to inject, as claim=context_variable pairs (comma-separated)
+    #[arg(long)]
+    pub pool_jwt_claims: Option<String>,
+
+    /// HS256 secret the pool-mode JWT must be signed with
+    #[arg(long)]
+    pub pool_jwt_secret: Option<String>,
+
     /// Seconds idle before a pooled connection is closed
     #[arg(long)]
     pub pool_idle_timeout: Option<u64>,
 
+    /// Seconds idle before a pooled connection is pinged to keep it open (0 = off)
+    #[arg(long)]
+    pub pool_keepalive_secs: Option<u64>,
+
+    /// Upstream SCRAM SaltedPassword values kept by the pool (0 = off)
+    #[arg(long)]
+    pub scram_cache_max_entries: Option<usize>,
+
     /// Seconds to wait for a connection when pool is full
     #[arg(long)]
     pub pool_checkout_timeout: Option<u64>,
 
+    /// Pooled connection reset: SQL run on checkout, or reset_role / none
+    #[arg(long)]
+    pub pool_reset_query: Option<String>,
+
+    /// Query run on every pool checkout to check the connection (empty disables)
+    #[arg(long)]
+    pub pool_health_check_query: Option<String>,
+
+    /// Minimum upstream major version for pooled connections (e.g. 14)
+    #[arg(long)]
+    pub pool_upstream_min_version: Option<u32>,
+
     /// Path to context resolver TOML file
     #[arg(long)]
     pub resolvers: Option<String>,
@@ -127,10 +577,58 @@ pub struct Cli {
     #[arg(long)]
     pub admin_port: Option<u16>,
 
+    /// Bind address for the admin API
+    #[arg(long)]
+    pub admin_host: Option<String>,
+
+    /// Admin API requests per second before 429s (0 = unlimited)
+    #[arg(long)]
+    pub admin_rate_limit_rps: Option<u32>,
+
+    /// Browser origins allowed to call the admin API (comma-separated)
+    #[arg(long)]
+    pub admin_cors_origins: Option<String>,
+
+    /// Allow any origin to call the admin API, for development (true/false)
+    #[arg(long)]
+    pub admin_cors_allow_all: Option<bool>,
+
+    /// OTLP gRPC collector to push metrics to (requires the otlp feature)
+    #[arg(long)]
+    pub otlp_endpoint: Option<String>,
+
+    /// Seconds between OTLP metric exports
+    #[arg(long)]
+    pub otlp_export_interval_secs: Option<u64>,
+
+    /// StatsD/DogStatsD host to send metrics to over UDP
+    #[arg(long)]
+    pub statsd_host: Option<String>,
+
+    /// StatsD/DogStatsD UDP port
+    #[arg(long)]
+    pub statsd_port: Option<u16>,
+
+    /// Milliseconds between StatsD flushes
+    #[arg(long)]
+    pub statsd_flush_interval_ms: Option<u64>,
+
+    /// Prefix for StatsD metric names
+    #[arg(long)]
+    pub statsd_prefix: Option<String>,
+
     /// Override SET ROLE target (default: use rewritten username)
     #[arg(long)]
     pub set_role: Option<String>,
 
+    /// Check the SET ROLE target exists upstream and is not a superuser
+    #[arg(long)]
+    pub require_role_exists: Option<bool>,
+
+    /// Comma-separated roles clients may never connect as
+    #[arg(long)]
+    pub forbidden_roles: Option<String>,
+
     /// Comma-separated tenant allow list (only these tenants may connect)
     #[arg(long)]
     pub tenant_allow: Option<String>,
@@ -139,14 +637,26 @@ pub struct Cli {
     #[arg(long)]
     pub tenant_deny: Option<String>,
 
+    /// Deny every tenant not in tenant_allow, even if it is unset (true/false)
+    #[arg(long)]
+    pub tenant_allowlist_only: Option<bool>,
+
     /// Max concurrent connections per tenant
     #[arg(long)]
     pub tenant_max_connections: Option<u32>,
 
-    /// Max new connections per tenant per second
+    /// Per-tenant overrides of tenant_max_connections, as tenant=max,...
+    #[arg(long)]
+    pub tenant_max_connections_map: Option<String>,
+
+    /// Max new connections per tenant per rate window
     #[arg(long)]
     pub tenant_rate_limit: Option<u32>,
 
+    /// Sliding window for tenant_rate_limit, in seconds
+    #[arg(long)]
+    pub tenant_rate_limit_window_secs: Option<u64>,
+
     /// Seconds of inactivity before tenant connection is terminated
     #[arg(long)]
     pub tenant_query_timeout: Option<u64>,
@@ -156,34 +666,152 @@ pub struct Cli {
 pub struct Config {
     pub listen_port: u16,
     pub listen_host: String,
+    /// Bind a second, IPv6-only listener on `listen_host_v6`.
+    pub listen_ipv6: bool,
+    pub listen_host_v6: String,
     pub upstream_host: String,
     pub upstream_port: u16,
+    pub upstream_connect_retries: u32,
+    pub upstream_connect_retry_delay_ms: u64,
+    /// How long a resolved upstream address is reused; 0 resolves every connect.
+    pub upstream_dns_ttl_secs: u64,
+    /// Read replicas as `host[:port]`; the port defaults to `upstream_port`.
+    pub upstream_replica_hosts: Vec<String>,
+    pub routing_mode: RoutingMode,
     pub tenant_separator: String,
+    /// Raw `tenant_separator_regex`; when set it overrides `tenant_separator`.
+    pub tenant_separator_regex: Option<String>,
+    /// `tenant_separator_regex`, compiled once at load time.
+    pub tenant_regex: Option<Regex>,
     pub context_variables: Vec<String>,
     pub value_separator: String,
-    pub superuser_bypass: Vec<String>,
+    /// Accept any Unicode alphanumeric in tenant IDs (`escape_literal_unicode`)
+    /// instead of ASCII only (`escape_literal`).
+    pub tenant_id_allow_unicode: bool,
+    pub superuser_bypass: Vec<SuperuserBypassRule>,
+    /// Users whose replication connections are passed through; `None` =
+    /// `superuser_bypass`.
+    pub replication_passthrough_usernames: Option<Vec<String>>,
     pub log_level: String,
+    pub log_format: LogFormat,
+    pub log_timestamps: bool,
+    pub log_file: Option<String>,
+    pub log_rotation: LogRotation,
     pub tls_port: Option<u16>,
     pub tls_cert: Option<String>,
     pub tls_key: Option<String>,
+    pub tls_cert_pkcs12: Option<String>,
+    pub tls_cert_pkcs12_password: Option<String>,
+    /// How often `tls_cert` / `tls_key` are checked for renewal; 0 disables.
+    pub tls_watch_interval_secs: u64,
+    pub tls_cipher_suites: Option<Vec<String>>,
+    pub tls_min_version: Option<String>,
+    /// Certificates picked by SNI (`[[tls_cert]]`); `tls_cert` is the fallback.
+    pub tls_sni_certs: Vec<TlsSniCert>,
     pub upstream_tls: bool,
     pub upstream_tls_verify: bool,
     pub upstream_tls_ca: Option<String>,
+    pub upstream_tls_pin_pubkey: Option<String>,
     pub handshake_timeout_secs: u64,
+    pub max_startup_message_bytes: usize,
+    pub client_idle_timeout_secs: u64,
+    pub max_total_connections: u64,
+    pub proxy_protocol_mode: ProxyProtocolMode,
+    /// TCP peers whose PROXY header is honoured; required unless mode is off.
+    pub proxy_protocol_trusted_sources: Vec<IpNetwork>,
+    pub audit_log_file: Option<String>,
+    pub audit_log_max_bytes: u64,
     pub pool_mode: PoolMode,
     pub pool_size: u32,
+    /// Idle connections the reaper keeps (and tops up to) per bucket.
+    pub pool_min_size: u32,
     pub pool_password: Option<String>,
+    pub pool_auth_method: PoolAuthMethod,
+    pub trust_ips: Vec<IpNetwork>,
+    pub startup_param_allowlist: Vec<String>,
+    /// Extra (name, value) startup parameters merged into every upstream StartupMessage.
+    pub startup_params: Vec<(String, String)>,
+    /// (from, to) renames applied to client-supplied startup parameters.
+    pub startup_param_renames: Vec<(String, String)>,
+    pub inject_comment_template: Option<String>,
+    /// SET `inject_conn_id_var` to the connection ID (and, in pool mode,
+    /// `app.pgvpd_conn_start` to the checkout time) ahead of the context.
+    pub inject_conn_id: bool,
+    pub inject_conn_id_var: String,
+    /// Pool mode: SET `pool_tenant_label_var` to the tenant ID and
+    /// `app.pgvpd_role` to the client's role, first in the injection batch.
+    pub pool_inject_tenant_label: bool,
+    pub pool_tenant_label_var: String,
+    pub send_context_notice: bool,
+    pub application_name_suffix: String,
+    pub rewrite_application_name: bool,
+    pub protocol_negotiate_passthrough: bool,
     pub upstream_password: Option<String>,
+    /// Auth method the upstream must ask for; `Auto` accepts any supported one.
+    pub upstream_auth_method: UpstreamAuthMethod,
+    /// SCRAM iteration counts the upstream may ask for: fewer makes the
+    /// exchange cheap to brute-force, more costs pgvpd CPU per connection.
+    pub scram_min_iterations: u32,
+    pub scram_max_iterations: u32,
+    /// Login user for pooled upstream connections; `None` = the bucket's role.
+    pub pool_upstream_user: Option<String>,
+    /// Pool-mode clients authenticate with a signed JWT instead of `pool_password`.
+    pub pool_jwt_context: bool,
+    /// `(claim, context_variable)` pairs copied from the JWT into the context.
+    pub pool_jwt_claims: Vec<(String, String)>,
+    pub pool_jwt_secret: Option<String>,
     pub pool_idle_timeout: u64,
+    /// Ping idle pooled connections this often (0 = never).
+    pub pool_keepalive_secs: u64,
+    /// SCRAM SaltedPassword values the pool reuses across upstream
+    /// connections (0 = derive every time).
+    pub scram_cache_max_entries: usize,
     pub pool_checkout_timeout: u64,
+    pub pool_reset_query: PoolReset,
+    /// Run on each checkout; a failure discards the connection. Empty disables.
+    pub pool_health_check_query: String,
+    /// Pooled connections to an older major version are discarded. The version
+    /// is read from the first column of the health check's first row.
+    pub pool_upstream_min_version: Option<u32>,
     pub resolvers: Option<String>,
+    /// Per-database resolver files (`[[database_resolver]]`), taking
+    /// precedence over `resolvers` for their database.
+    pub database_resolvers: Vec<DatabaseResolver>,
     pub admin_port: Option<u16>,
+    pub admin_host: String,
+    /// Admin API request budget per second; `/health` gets 10x. 0 = unlimited.
+    pub admin_rate_limit_rps: u32,
+    /// Origins that get CORS headers from the admin API. Empty = no CORS.
+    pub admin_cors_origins: Vec<String>,
+    /// Answer every origin with `Access-Control-Allow-Origin: *`.
+    pub admin_cors_allow_all: bool,
+    /// OTLP gRPC endpoint metrics are pushed to; `None` = Prometheus only.
+    pub otlp_endpoint: Option<String>,
+    pub otlp_export_interval_secs: u64,
+    /// StatsD export runs when both host and port are set.
+    pub statsd_host: Option<String>,
+    pub statsd_port: Option<u16>,
+    pub statsd_flush_interval_ms: u64,
+    pub statsd_prefix: String,
     pub set_role: Option<String>,
+    /// Before injection, look the SET ROLE target up in `pg_roles` and
+    /// refuse the client if it is missing or a superuser.
+    pub require_role_exists: bool,
+    /// Roles a client may not name in its username (e.g. `postgres`).
+    pub forbidden_roles: Vec<String>,
     pub tenant_allow: Option<Vec<String>>,
     pub tenant_deny: Option<Vec<String>>,
+    /// Only tenants in `tenant_allow` may connect. Implied by `tenant_allow`;
+    /// with no allow list it denies everyone.
+    pub tenant_allowlist_only: bool,
     pub tenant_max_connections: Option<u32>,
+    /// Per-tenant overrides of `tenant_max_connections` (`[tenant_limits]`).
+    pub tenant_max_connections_map: HashMap<String, u32>,
     pub tenant_rate_limit: Option<u32>,
+    pub tenant_rate_limit_window_secs: u64,
     pub tenant_query_timeout: Option<u64>,
+    /// The config file that was read, if any.
+    pub config_file: Option<PathBuf>,
 }
 
 impl Default for Config {
@@ -191,54 +819,160 @@ impl Default for Config {
         Self {
             listen_port: 6432,
             listen_host: "127.0.0.1".into(),
+            listen_ipv6: false,
+            listen_host_v6: "::1".into(),
             upstream_host: "127.0.0.1".into(),
             upstream_port: 5432,
+            upstream_connect_retries: 3,
+            upstream_connect_retry_delay_ms: 500,
+            upstream_dns_ttl_secs: 30,
+            upstream_replica_hosts: Vec::new(),
+            routing_mode: RoutingMode::PrimaryOnly,
             tenant_separator: ".".into(),
+            tenant_separator_regex: None,
+            tenant_regex: None,
             context_variables: vec!["app.current_tenant_id".into()],
             value_separator: ":".into(),
-            superuser_bypass: vec!["postgres".into()],
+            tenant_id_allow_unicode: false,
+            superuser_bypass: SuperuserBypassRule::from_users("postgres"),
+            replication_passthrough_usernames: None,
             log_level: "info".into(),
+            log_format: LogFormat::Text,
+            log_timestamps: true,
+            log_file: None,
+            log_rotation: LogRotation::Never,
             tls_port: None,
             tls_cert: None,
             tls_key: None,
+            tls_cert_pkcs12: None,
+            tls_cert_pkcs12_password: None,
+            tls_watch_interval_secs: 60,
+            tls_cipher_suites: None,
+            tls_min_version: None,
+            tls_sni_certs: Vec::new(),
             upstream_tls: false,
             upstream_tls_verify: true,
             upstream_tls_ca: None,
+            upstream_tls_pin_pubkey: None,
             handshake_timeout_secs: 30,
+            max_startup_message_bytes: crate::protocol::DEFAULT_MAX_STARTUP_MESSAGE_BYTES,
+            client_idle_timeout_secs: 0,
+            max_total_connections: 0,
+            proxy_protocol_mode: ProxyProtocolMode::Off,
+            proxy_protocol_trusted_sources: Vec::new(),
+            audit_log_file: None,
+            audit_log_max_bytes: 100 * 1024 * 1024,
             pool_mode: PoolMode::None,
             pool_size: 20,
+            pool_min_size: 0,
             pool_password: None,
+            pool_auth_method: PoolAuthMethod::Cleartext,
+            trust_ips: Vec::new(),
+            startup_param_allowlist: vec![
+                "user".into(),
+                "database".into(),
+                "application_name".into(),
+                "client_encoding".into(),
+                "DateStyle".into(),
+                "TimeZone".into(),
+            ],
+            startup_params: Vec::new(),
+            startup_param_renames: Vec::new(),
+            inject_comment_template: None,
+            inject_conn_id: false,
+            inject_conn_id_var: "app.pgvpd_conn_id".into(),
+            pool_inject_tenant_label: true,
+            pool_tenant_label_var: "app.pgvpd_tenant".into(),
+            send_context_notice: false,
+            application_name_suffix: "/pgvpd".into(),
+            rewrite_application_name: true,
+            protocol_negotiate_passthrough: true,
             upstream_password: None,
+            upstream_auth_method: UpstreamAuthMethod::Auto,
+            scram_min_iterations: 4096,
+            scram_max_iterations: 1_000_000,
+            pool_upstream_user: None,
+            pool_jwt_context: false,
+            pool_jwt_claims: Vec::new(),
+            pool_jwt_secret: None,
             pool_idle_timeout: 300,
+            pool_keepalive_secs: 0,
+            scram_cache_max_entries: 100,
             pool_checkout_timeout: 5,
+            pool_reset_query: PoolReset::Query("DISCARD ALL".into()),
+            pool_health_check_query: ";".into(),
+            pool_upstream_min_version: None,
             resolvers: None,
+            database_resolvers: Vec::new(),
             admin_port: None,
+            admin_host: "127.0.0.1".into(),
+            admin_rate_limit_rps: 100,
+            admin_cors_origins: Vec::new(),
+            admin_cors_allow_all: false,
+            otlp_endpoint: None,
+            otlp_export_interval_secs: 10,
+            statsd_host: None,
+            statsd_port: None,
+            statsd_flush_interval_ms: 1000,
+            statsd_prefix: "pgvpd".into(),
             set_role: None,
+            require_role_exists: false,
+            forbidden_roles: Vec::new(),
             tenant_allow: None,
             tenant_deny: None,
+            tenant_allowlist_only: false,
             tenant_max_connections: None,
+            tenant_max_connections_map: HashMap::new(),
             tenant_rate_limit: None,
+            tenant_rate_limit_window_secs: 1,
             tenant_query_timeout: None,
+            config_file: None,
         }
     }
 }
 
 impl Config {
     /// Load configuration: defaults → config file → env vars → CLI flags.
-    pub fn load() -> Self {
+    /// Exits the process if the config file cannot be read. Returns the
+    /// config file's warnings for logging once tracing is set up.
+    pub fn load() -> (Self, Vec<ConfigWarning>) {
+        match Self::try_load() {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("config error: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Like `load`, but returns config file errors instead of exiting.
+    /// Used to re-read the configuration on SIGHUP. With `--strict-config`,
+    /// any config file warning is an error.
+    pub fn try_load() -> Result<(Self, Vec<ConfigWarning>), String> {
         let cli = Cli::parse();
         let mut config = Config::default();
 
-        // 1. Config file
-        let config_path = Path::new(&cli.config);
-        if config_path.exists()
-            && let Ok(content) = fs::read_to_string(config_path)
-        {
-            apply_config_file(&mut config, &content);
+        // 1. Config file (plus any `include`d files)
+        config.config_file = if cli.no_config {
+            None
+        } else {
+            config_file_path(cli.config.as_deref(), CONFIG_SEARCH_PATHS)?
+        };
+        let mut warnings = Vec::new();
+        if let Some(path) = config.config_file.clone() {
+            warnings = load_config_file(&mut config, &path)?;
+        }
+        if cli.strict_config && !warnings.is_empty() {
+            let lines: Vec<String> = warnings.iter().map(|w| format!("\n  {w}")).collect();
+            return Err(format!(
+                "{} config warning(s) with --strict-config:{}",
+                warnings.len(),
+                lines.concat()
+            ));
         }
 
         // 2. Environment variables
-        apply_env(&mut config);
+        apply_env(&mut config)?;
 
         // 3. CLI flags (highest priority)
         if let Some(v) = cli.port {
@@ -247,27 +981,71 @@ impl Config {
         if let Some(v) = cli.listen_host {
             config.listen_host = v;
         }
+        if let Some(v) = cli.listen_ipv6 {
+            config.listen_ipv6 = v;
+        }
+        if let Some(v) = cli.listen_host_v6 {
+            config.listen_host_v6 = v;
+        }
         if let Some(v) = cli.upstream_host {
             config.upstream_host = v;
         }
         if let Some(v) = cli.upstream_port {
             config.upstream_port = v;
         }
+        if let Some(v) = cli.upstream_connect_retries {
+            config.upstream_connect_retries = v;
+        }
+        if let Some(v) = cli.upstream_connect_retry_delay_ms {
+            config.upstream_connect_retry_delay_ms = v;
+        }
+        if let Some(v) = cli.upstream_dns_ttl_secs {
+            config.upstream_dns_ttl_secs = v;
+        }
+        if let Some(v) = &cli.upstream_replica_hosts {
+            config.upstream_replica_hosts = parse_host_list(v);
+        }
+        if let Some(v) = &cli.routing_mode {
+            config.routing_mode = parse_routing_mode(v);
+        }
         if let Some(v) = cli.separator {
             config.tenant_separator = v;
         }
+        if let Some(v) = cli.separator_regex {
+            config.tenant_separator_regex = Some(v);
+        }
         if let Some(v) = cli.context {
             config.context_variables = v.split(',').map(|s| s.trim().to_string()).collect();
         }
         if let Some(v) = cli.value_separator {
             config.value_separator = v;
         }
+        if let Some(v) = cli.tenant_id_allow_unicode {
+            config.tenant_id_allow_unicode = v;
+        }
         if let Some(v) = cli.superuser {
-            config.superuser_bypass = v.split(',').map(|s| s.trim().to_string()).collect();
+            config.superuser_bypass = SuperuserBypassRule::from_users(&v);
+        }
+        if let Some(v) = cli.replication_passthrough_usernames {
+            config.replication_passthrough_usernames =
+                Some(v.split(',').map(|s| s.trim().to_string()).collect());
         }
         if let Some(v) = cli.log_level {
             config.log_level = v;
         }
+        if let Some(v) = &cli.log_format {
+            config.log_format = parse_log_format(v);
+        }
+        if let Some(v) = cli.log_timestamps {
+            config.log_timestamps = v;
+        }
+        if let Some(v) = cli.log_file {
+            config.log_file = Some(v);
+        }
+        if let Some(v) = &cli.log_rotation {
+            config.log_rotation =
+                parse_log_rotation(v).map_err(|e| format!("invalid --log-rotation '{v}' ({e})"))?;
+        }
         if let Some(v) = cli.tls_port {
             config.tls_port = Some(v);
         }
@@ -277,6 +1055,21 @@ impl Config {
         if let Some(v) = cli.tls_key {
             config.tls_key = Some(v);
         }
+        if let Some(v) = cli.tls_cert_pkcs12 {
+            config.tls_cert_pkcs12 = Some(v);
+        }
+        if let Some(v) = cli.tls_cert_pkcs12_password {
+            config.tls_cert_pkcs12_password = Some(v);
+        }
+        if let Some(v) = cli.tls_watch_interval_secs {
+            config.tls_watch_interval_secs = v;
+        }
+        if let Some(v) = cli.tls_cipher_suites {
+            config.tls_cipher_suites = Some(v.split(',').map(|s| s.trim().to_string()).collect());
+        }
+        if let Some(v) = cli.tls_min_version {
+            config.tls_min_version = Some(v);
+        }
         if cli.upstream_tls {
             config.upstream_tls = true;
         }
@@ -286,65 +1079,354 @@ impl Config {
         if let Some(v) = cli.upstream_tls_ca {
             config.upstream_tls_ca = Some(v);
         }
+        if let Some(v) = cli.upstream_tls_pin_pubkey {
+            config.upstream_tls_pin_pubkey = Some(v);
+        }
         if let Some(v) = cli.handshake_timeout {
             config.handshake_timeout_secs = v;
         }
-        if let Some(v) = &cli.pool_mode {
-            config.pool_mode = parse_pool_mode(v);
+        if let Some(v) = cli.max_startup_message_bytes {
+            config.max_startup_message_bytes = v;
         }
-        if let Some(v) = cli.pool_size {
+        if let Some(v) = cli.client_idle_timeout {
+            config.client_idle_timeout_secs = v;
+        }
+        if let Some(v) = cli.max_total_connections {
+            config.max_total_connections = v;
+        }
+        if let Some(v) = &cli.proxy_protocol_mode {
+            config.proxy_protocol_mode = parse_proxy_protocol_mode(v)
+                .map_err(|e| format!("invalid --proxy-protocol-mode '{v}' ({e})"))?;
+        }
+        if let Some(v) = &cli.proxy_protocol_trusted_sources {
+            config.proxy_protocol_trusted_sources = parse_cidr_list(v)
+                .map_err(|e| format!("invalid --proxy-protocol-trusted-sources '{v}' ({e})"))?;
+        }
+        if let Some(v) = cli.audit_log_file {
+            config.audit_log_file = Some(v);
+        }
+        if let Some(v) = cli.audit_log_max_bytes {
+            config.audit_log_max_bytes = v;
+        }
+        if let Some(v) = &cli.pool_mode {
+            config.pool_mode = parse_pool_mode(v);
+        }
+        if let Some(v) = cli.pool_size {
             config.pool_size = v;
         }
+        if let Some(v) = cli.pool_min_size {
+            config.pool_min_size = v;
+        }
         if let Some(v) = cli.pool_password {
             config.pool_password = Some(v);
         }
+        if let Some(v) = &cli.pool_auth_method {
+            config.pool_auth_method = parse_pool_auth_method(v)
+                .map_err(|e| format!("invalid --pool-auth-method '{v}' ({e})"))?;
+        }
+        if let Some(v) = &cli.trust_ips {
+            config.trust_ips =
+                parse_cidr_list(v).map_err(|e| format!("invalid --trust-ips '{v}' ({e})"))?;
+        }
+        if let Some(v) = cli.startup_param_allowlist {
+            config.startup_param_allowlist = v.split(',').map(|s| s.trim().to_string()).collect();
+        }
+        if !cli.startup_param.is_empty() {
+            config.startup_params = cli
+                .startup_param
+                .iter()
+                .map(|v| {
+                    parse_pair(v).ok_or_else(|| {
+                        format!("invalid --startup-param '{v}' (expected name=value)")
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if !cli.startup_param_rename.is_empty() {
+            config.startup_param_renames = cli
+                .startup_param_rename
+                .iter()
+                .map(|v| {
+                    parse_pair(v).ok_or_else(|| {
+                        format!("invalid --startup-param-rename '{v}' (expected from=to)")
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(v) = cli.inject_comment_template {
+            config.inject_comment_template = Some(v);
+        }
+        if let Some(v) = cli.inject_conn_id {
+            config.inject_conn_id = v;
+        }
+        if let Some(v) = cli.inject_conn_id_var {
+            config.inject_conn_id_var = v;
+        }
+        if let Some(v) = cli.pool_inject_tenant_label {
+            config.pool_inject_tenant_label = v;
+        }
+        if let Some(v) = cli.pool_tenant_label_var {
+            config.pool_tenant_label_var = v;
+        }
+        if let Some(v) = cli.send_context_notice {
+            config.send_context_notice = v;
+        }
+        if let Some(v) = cli.application_name_suffix {
+            config.application_name_suffix = v;
+        }
+        if let Some(v) = cli.rewrite_application_name {
+            config.rewrite_application_name = v;
+        }
+        if let Some(v) = cli.protocol_negotiate_passthrough {
+            config.protocol_negotiate_passthrough = v;
+        }
         if let Some(v) = cli.upstream_password {
             config.upstream_password = Some(v);
         }
+        if let Some(v) = &cli.upstream_auth_method {
+            config.upstream_auth_method = parse_upstream_auth_method(v)
+                .map_err(|e| format!("invalid --upstream-auth-method '{v}' ({e})"))?;
+        }
+        if let Some(v) = cli.scram_min_iterations {
+            config.scram_min_iterations = v;
+        }
+        if let Some(v) = cli.scram_max_iterations {
+            config.scram_max_iterations = v;
+        }
+        if let Some(v) = cli.pool_upstream_user {
+            config.pool_upstream_user = Some(v);
+        }
+        if let Some(v) = cli.pool_jwt_context {
+            config.pool_jwt_context = v;
+        }
+        if let Some(v) = &cli.pool_jwt_claims {
+            config.pool_jwt_claims = parse_jwt_claims(v);
+        }
+        if let Some(v) = cli.pool_jwt_secret {
+            config.pool_jwt_secret = Some(v);
+        }
         if let Some(v) = cli.pool_idle_timeout {
             config.pool_idle_timeout = v;
         }
+        if let Some(v) = cli.pool_keepalive_secs {
+            config.pool_keepalive_secs = v;
+        }
+        if let Some(v) = cli.scram_cache_max_entries {
+            config.scram_cache_max_entries = v;
+        }
         if let Some(v) = cli.pool_checkout_timeout {
             config.pool_checkout_timeout = v;
         }
+        if let Some(v) = &cli.pool_reset_query {
+            config.pool_reset_query = parse_pool_reset(v);
+        }
+        if let Some(v) = cli.pool_health_check_query {
+            config.pool_health_check_query = v;
+        }
+        if let Some(v) = cli.pool_upstream_min_version {
+            config.pool_upstream_min_version = Some(v);
+        }
         if let Some(v) = cli.resolvers {
             config.resolvers = Some(v);
         }
         if let Some(v) = cli.admin_port {
             config.admin_port = Some(v);
         }
+        if let Some(v) = cli.admin_host {
+            config.admin_host = v;
+        }
+        if let Some(v) = cli.admin_rate_limit_rps {
+            config.admin_rate_limit_rps = v;
+        }
+        if let Some(v) = cli.admin_cors_origins {
+            config.admin_cors_origins = parse_origins(&v);
+        }
+        if let Some(v) = cli.admin_cors_allow_all {
+            config.admin_cors_allow_all = v;
+        }
+        if let Some(v) = cli.otlp_endpoint {
+            config.otlp_endpoint = Some(v);
+        }
+        if let Some(v) = cli.otlp_export_interval_secs {
+            config.otlp_export_interval_secs = v;
+        }
+        if let Some(v) = cli.statsd_host {
+            config.statsd_host = Some(v);
+        }
+        if let Some(v) = cli.statsd_port {
+            config.statsd_port = Some(v);
+        }
+        if let Some(v) = cli.statsd_flush_interval_ms {
+            config.statsd_flush_interval_ms = v;
+        }
+        if let Some(v) = cli.statsd_prefix {
+            config.statsd_prefix = v;
+        }
         if let Some(v) = cli.set_role {
             config.set_role = Some(v);
         }
+        if let Some(v) = cli.require_role_exists {
+            config.require_role_exists = v;
+        }
+        if let Some(v) = &cli.forbidden_roles {
+            config.forbidden_roles = parse_roles(v);
+        }
         if let Some(v) = cli.tenant_allow {
             config.tenant_allow = Some(v.split(',').map(|s| s.trim().to_string()).collect());
         }
         if let Some(v) = cli.tenant_deny {
             config.tenant_deny = Some(v.split(',').map(|s| s.trim().to_string()).collect());
         }
+        if let Some(v) = cli.tenant_allowlist_only {
+            config.tenant_allowlist_only = v;
+        }
         if let Some(v) = cli.tenant_max_connections {
             config.tenant_max_connections = Some(v);
         }
+        if let Some(v) = cli.tenant_max_connections_map {
+            config.tenant_max_connections_map = parse_tenant_limits(&v);
+        }
         if let Some(v) = cli.tenant_rate_limit {
             config.tenant_rate_limit = Some(v);
         }
+        if let Some(v) = cli.tenant_rate_limit_window_secs {
+            config.tenant_rate_limit_window_secs = v;
+        }
         if let Some(v) = cli.tenant_query_timeout {
             config.tenant_query_timeout = Some(v);
         }
 
-        config
+        config.tenant_regex = config
+            .tenant_separator_regex
+            .as_deref()
+            .map(compile_tenant_regex)
+            .transpose()?;
+
+        Ok((config, warnings))
     }
 
     /// Validate configuration. Returns an error message if invalid.
     pub fn validate(&self) -> Result<(), String> {
-        if self.tls_port.is_some() && (self.tls_cert.is_none() || self.tls_key.is_none()) {
-            return Err("tls_port requires both tls_cert and tls_key".into());
+        let has_pem = self.tls_cert.is_some() || self.tls_key.is_some();
+        if has_pem && self.tls_cert_pkcs12.is_some() {
+            return Err("tls_cert_pkcs12 cannot be combined with tls_cert/tls_key".into());
+        }
+        if self.tls_cert_pkcs12_password.is_some() && self.tls_cert_pkcs12.is_none() {
+            return Err("tls_cert_pkcs12_password requires tls_cert_pkcs12".into());
+        }
+        if self.tls_port.is_some()
+            && self.tls_cert_pkcs12.is_none()
+            && (self.tls_cert.is_none() || self.tls_key.is_none())
+        {
+            return Err("tls_port requires both tls_cert and tls_key (or tls_cert_pkcs12)".into());
+        }
+        if !self.tls_sni_certs.is_empty() && self.tls_port.is_none() {
+            return Err("[[tls_cert]] requires tls_port".into());
+        }
+        let mut sni_patterns = HashSet::new();
+        for entry in &self.tls_sni_certs {
+            if entry.sni.is_empty() || entry.cert.is_empty() || entry.key.is_empty() {
+                return Err("[[tls_cert]] needs sni, cert and key".into());
+            }
+            if !crate::tls::is_valid_sni_pattern(&entry.sni) {
+                return Err(format!("invalid [[tls_cert]] sni '{}'", entry.sni));
+            }
+            if !sni_patterns.insert(entry.sni.to_ascii_lowercase()) {
+                return Err(format!("duplicate [[tls_cert]] for sni '{}'", entry.sni));
+            }
+        }
+        if let Some(ref pin) = self.upstream_tls_pin_pubkey {
+            crate::tls::parse_pin(pin).map_err(|e| e.to_string())?;
+        }
+        if let Some(ref suites) = self.tls_cipher_suites {
+            crate::tls::parse_cipher_suites(suites).map_err(|e| e.to_string())?;
+        }
+        if let Some(ref v) = self.tls_min_version
+            && v != "1.2"
+            && v != "1.3"
+        {
+            return Err(format!("tls_min_version must be 1.2 or 1.3, got '{v}'"));
+        }
+        if let Some(ref template) = self.inject_comment_template {
+            // One comment, closed at the end: anything after it, or a nested
+            // `/*` left open, would run as (or swallow) SQL
+            let inner = template
+                .strip_prefix("/*")
+                .and_then(|rest| rest.strip_suffix("*/"));
+            if inner.is_none_or(|inner| inner.contains("*/") || inner.contains("/*")) {
+                return Err("inject_comment_template must be a single /* ... */ comment".into());
+            }
+        }
+        if self.audit_log_file.is_some() && self.audit_log_max_bytes == 0 {
+            return Err("audit_log_max_bytes must be > 0".into());
         }
         if self.handshake_timeout_secs == 0 {
             return Err("handshake_timeout must be > 0".into());
         }
+        if self.proxy_protocol_mode != ProxyProtocolMode::Off
+            && self.proxy_protocol_trusted_sources.is_empty()
+        {
+            return Err(format!(
+                "proxy_protocol_mode = {} requires proxy_protocol_trusted_sources",
+                self.proxy_protocol_mode
+            ));
+        }
+        if let Some(user) = self
+            .replication_users()
+            .iter()
+            .find(|u| !self.superuser_bypass.iter().any(|rule| rule.user == **u))
+        {
+            return Err(format!(
+                "replication_passthrough_usernames: '{user}' is not in superuser_bypass"
+            ));
+        }
+        if self.otlp_endpoint.is_some() && self.otlp_export_interval_secs == 0 {
+            return Err("otlp_export_interval_secs must be > 0".into());
+        }
+        if self.statsd_host.is_some() != self.statsd_port.is_some() {
+            return Err("statsd_host and statsd_port must be set together".into());
+        }
+        if self.statsd_host.is_some() && self.statsd_flush_interval_ms == 0 {
+            return Err("statsd_flush_interval_ms must be > 0".into());
+        }
+        if self.listen_ipv6 && self.listen_host_v6.parse::<Ipv6Addr>().is_err() {
+            return Err(format!(
+                "listen_host_v6 must be an IPv6 address, got '{}'",
+                self.listen_host_v6
+            ));
+        }
+        if !(64..=1_048_576).contains(&self.max_startup_message_bytes) {
+            return Err(format!(
+                "max_startup_message_bytes must be between 64 and 1048576, got {}",
+                self.max_startup_message_bytes
+            ));
+        }
+        for entry in &self.upstream_replica_hosts {
+            if crate::router::parse_host_port(entry, self.upstream_port).is_none() {
+                return Err(format!(
+                    "upstream_replica_hosts: expected host[:port], got '{entry}'"
+                ));
+            }
+        }
+        if self.routing_mode == RoutingMode::ReplicaReads && self.upstream_replica_hosts.is_empty()
+        {
+            return Err("routing_mode = replica_reads requires upstream_replica_hosts".into());
+        }
+        if self.upstream_auth_method == UpstreamAuthMethod::ScramSha256Plus {
+            return Err(
+                "upstream_auth_method = scram-sha-256-plus is not supported (pgvpd does not \
+                 implement channel binding); use scram-sha-256 or auto"
+                    .into(),
+            );
+        }
+        if self.scram_min_iterations == 0 || self.scram_min_iterations > self.scram_max_iterations {
+            return Err(format!(
+                "scram_min_iterations ({}) must be between 1 and scram_max_iterations ({})",
+                self.scram_min_iterations, self.scram_max_iterations
+            ));
+        }
         if self.pool_mode == PoolMode::Session {
-            if self.pool_password.is_none() {
+            if self.pool_password.is_none() && !self.pool_jwt_context {
                 return Err("pool_mode = session requires pool_password".into());
             }
             if self.upstream_password.is_none() {
@@ -353,633 +1435,3943 @@ impl Config {
             if self.pool_size == 0 {
                 return Err("pool_size must be > 0".into());
             }
+            if self.pool_min_size > self.pool_size {
+                return Err(format!(
+                    "pool_min_size ({}) must not exceed pool_size ({})",
+                    self.pool_min_size, self.pool_size
+                ));
+            }
+            if self.pool_upstream_user.as_deref() == Some("") {
+                return Err("pool_upstream_user must not be empty".into());
+            }
+            if let PoolReset::Query(sql) = &self.pool_reset_query
+                && sql.trim().is_empty()
+            {
+                return Err(
+                    "pool_reset_query must be SQL, 'reset_role' or 'none' (not empty)".into(),
+                );
+            }
+            if self.pool_upstream_min_version.is_some()
+                && self.pool_health_check_query.trim().is_empty()
+            {
+                return Err(
+                    "pool_upstream_min_version requires pool_health_check_query (e.g. SHOW server_version)"
+                        .into(),
+                );
+            }
+            if self.pool_jwt_context {
+                if self.pool_jwt_secret.as_deref().unwrap_or("").is_empty() {
+                    return Err("pool_jwt_context requires pool_jwt_secret".into());
+                }
+                if self.pool_auth_method != PoolAuthMethod::Cleartext {
+                    return Err("pool_jwt_context requires pool_auth_method = cleartext".into());
+                }
+                if self.pool_jwt_claims.is_empty() {
+                    return Err("pool_jwt_context requires pool_jwt_claims".into());
+                }
+                for (claim, var) in &self.pool_jwt_claims {
+                    if claim.is_empty() || !is_valid_guc_name(var) {
+                        return Err(format!(
+                            "invalid pool_jwt_claims entry '{claim}={var}': expected claim=context_variable"
+                        ));
+                    }
+                }
+            }
         }
         if let Some(ref path) = self.resolvers
             && !std::path::Path::new(path).exists()
         {
             return Err(format!("resolvers file not found: {}", path));
         }
+        if self
+            .superuser_bypass
+            .iter()
+            .any(|rule| rule.user.is_empty())
+        {
+            return Err("[[superuser_bypass]] needs a user".into());
+        }
+        let mut databases = HashSet::new();
+        for entry in &self.database_resolvers {
+            if entry.database.is_empty() || entry.resolvers.is_empty() {
+                return Err("[[database_resolver]] needs both database and resolvers".to_string());
+            }
+            if !databases.insert(entry.database.as_str()) {
+                return Err(format!(
+                    "duplicate [[database_resolver]] for database '{}'",
+                    entry.database
+                ));
+            }
+            if !std::path::Path::new(&entry.resolvers).exists() {
+                return Err(format!(
+                    "resolvers file not found for database '{}': {}",
+                    entry.database, entry.resolvers
+                ));
+            }
+        }
+        if !is_valid_host(&self.admin_host) {
+            return Err(format!(
+                "admin_host must be an IP address or hostname, got '{}'",
+                self.admin_host
+            ));
+        }
         if self.tenant_allow.is_some() && self.tenant_deny.is_some() {
             return Err("tenant_allow and tenant_deny cannot both be set".into());
         }
+        if self.tenant_allowlist_only && self.tenant_deny.is_some() {
+            return Err("tenant_allowlist_only cannot be combined with tenant_deny".into());
+        }
+        for entry in self
+            .tenant_allow
+            .iter()
+            .chain(self.tenant_deny.iter())
+            .flatten()
+        {
+            if crate::tenant::is_glob(entry)
+                && let Err(e) = glob::Pattern::new(entry)
+            {
+                return Err(format!("invalid tenant pattern '{entry}': {e}"));
+            }
+        }
+        if self.tenant_rate_limit_window_secs == 0 {
+            return Err("tenant_rate_limit_window_secs must be > 0".into());
+        }
+        if let Some(ref pattern) = self.tenant_separator_regex {
+            compile_tenant_regex(pattern)?;
+        }
+        for name in &self.context_variables {
+            if !is_valid_guc_name(name) {
+                return Err(format!("invalid context variable name: '{name}'"));
+            }
+        }
+        if self.inject_conn_id && !is_valid_guc_name(&self.inject_conn_id_var) {
+            return Err(format!(
+                "invalid inject_conn_id_var: '{}'",
+                self.inject_conn_id_var
+            ));
+        }
+        if self.pool_inject_tenant_label && !is_valid_guc_name(&self.pool_tenant_label_var) {
+            return Err(format!(
+                "invalid pool_tenant_label_var: '{}'",
+                self.pool_tenant_label_var
+            ));
+        }
+        if let Some(ref role) = self.set_role
+            && !is_valid_role_name(role)
+        {
+            return Err(format!(
+                "invalid set_role '{role}': use a plain identifier or wrap it in double quotes"
+            ));
+        }
+        for (name, _) in &self.startup_params {
+            if name.is_empty() {
+                return Err("[[startup_param]] needs a name".into());
+            }
+            if is_reserved_startup_param(name) {
+                return Err(format!("startup_param cannot set '{name}'"));
+            }
+        }
+        for (from, to) in &self.startup_param_renames {
+            if from.is_empty() || to.is_empty() {
+                return Err("[[startup_param_rename]] needs both from and to".into());
+            }
+            if is_reserved_startup_param(from) || is_reserved_startup_param(to) {
+                return Err(format!(
+                    "startup_param_rename cannot rename '{from}' to '{to}': user and database are reserved"
+                ));
+            }
+        }
         Ok(())
     }
 
-    /// Returns true if any tenant isolation feature is configured.
-    pub fn has_tenant_limits(&self) -> bool {
-        self.tenant_allow.is_some()
-            || self.tenant_deny.is_some()
-            || self.tenant_max_connections.is_some()
-            || self.tenant_rate_limit.is_some()
+    /// `set_role` ready for `SET ROLE`: a plain identifier is quoted, an
+    /// already double-quoted one is used verbatim. Assumes `validate` passed.
+    pub fn set_role_ident(&self) -> Option<String> {
+        self.set_role.as_ref().map(|role| {
+            if role.starts_with('"') {
+                role.clone()
+            } else {
+                format!("\"{role}\"")
+            }
+        })
     }
-}
 
-fn apply_config_file(config: &mut Config, content: &str) {
-    for line in content.lines() {
-        let trimmed = line.trim();
-        if trimmed.is_empty() || trimmed.starts_with('#') {
-            continue;
+    /// The role SET ROLE switches to: `set_role` (unquoted) or the role
+    /// from the client's username.
+    pub fn target_role<'a>(&'a self, actual_user: &'a str) -> &'a str {
+        match &self.set_role {
+            Some(role) => role.trim_matches('"'),
+            None => actual_user,
         }
+    }
 
-        let Some(eq_pos) = trimmed.find('=') else {
-            continue;
+    /// The application_name to send upstream, or `None` when rewriting is disabled.
+    /// `client_app` is suffixed with `application_name_suffix` (bare `pgvpd` if the
+    /// client sent none); `tenant` is appended for pooled sessions.
+    pub fn application_name(
+        &self,
+        client_app: Option<&str>,
+        tenant: Option<&str>,
+    ) -> Option<String> {
+        if !self.rewrite_application_name {
+            return None;
+        }
+        let mut name = match client_app {
+            Some(app) if !app.is_empty() => format!("{app}{}", self.application_name_suffix),
+            _ => "pgvpd".to_string(),
         };
+        if let Some(tenant) = tenant {
+            name.push('/');
+            name.push_str(tenant);
+        }
+        Some(name)
+    }
 
-        let key = trimmed[..eq_pos].trim();
-        let mut value = trimmed[eq_pos + 1..].trim().to_string();
+    /// application_name for an idle pooled upstream connection,
+    /// `pgvpd-pool/{database}/{role}`, so pooled sessions are recognisable
+    /// in pg_stat_activity before a client tags them.
+    pub fn pool_application_name(&self, database: &str, role: &str) -> Option<String> {
+        self.rewrite_application_name
+            .then(|| format!("pgvpd-pool/{database}/{role}"))
+    }
 
-        // Strip quotes
-        if (value.starts_with('"') && value.ends_with('"'))
-            || (value.starts_with('\'') && value.ends_with('\''))
-        {
-            value = value[1..value.len() - 1].to_string();
+    /// Render `inject_comment_template`, replacing `{conn_id}`, `{tenant}`, `{role}`
+    /// and `{database}`. Substituted values have `*`, `/` and control characters
+    /// (including `\r` and `\n`) stripped so a crafted tenant or database name
+    /// cannot open or close a (nested) comment, even next to a `*` or `/` in the
+    /// template, or break the line.
+    pub fn render_inject_comment(
+        &self,
+        conn_id: u64,
+        tenant: &str,
+        role: &str,
+        database: &str,
+    ) -> Option<String> {
+        let template = self.inject_comment_template.as_deref()?;
+        let clean = |v: &str| -> String {
+            v.chars()
+                .filter(|c| !matches!(c, '*' | '/') && !c.is_control())
+                .collect()
+        };
+        Some(
+            template
+                .replace("{conn_id}", &conn_id.to_string())
+                .replace("{tenant}", &clean(tenant))
+                .replace("{role}", &clean(role))
+                .replace("{database}", &clean(database)),
+        )
+    }
+
+    /// SET statements for `inject_conn_id`, run before the context. Pool mode
+    /// passes the checkout time (Unix ms) to also set `app.pgvpd_conn_start`.
+    pub fn conn_id_set_clauses(&self, conn_id: u64, checkout_ms: Option<u128>) -> Vec<String> {
+        if !self.inject_conn_id {
+            return Vec::new();
+        }
+        let mut clauses = vec![format!("SET {} = '{conn_id}'", self.inject_conn_id_var)];
+        if let Some(ms) = checkout_ms {
+            clauses.push(format!("SET {CONN_START_VAR} = '{ms}'"));
         }
+        clauses
+    }
 
-        match key {
-            "port" | "listen_port" => {
-                if let Ok(v) = value.parse() {
-                    config.listen_port = v;
-                }
-            }
-            "listen_host" | "host" => config.listen_host = value,
-            "upstream_host" => config.upstream_host = value,
-            "upstream_port" => {
-                if let Ok(v) = value.parse() {
-                    config.upstream_port = v;
-                }
-            }
-            "tenant_separator" | "separator" => config.tenant_separator = value,
-            "context_variables" | "context" => {
-                config.context_variables = value.split(',').map(|s| s.trim().to_string()).collect();
-            }
-            "value_separator" => config.value_separator = value,
-            "superuser_bypass" | "superuser" => {
-                config.superuser_bypass = value.split(',').map(|s| s.trim().to_string()).collect();
-            }
-            "log_level" => config.log_level = value,
-            "tls_port" => {
-                if let Ok(v) = value.parse() {
-                    config.tls_port = Some(v);
-                }
-            }
-            "tls_cert" => config.tls_cert = Some(value),
-            "tls_key" => config.tls_key = Some(value),
-            "upstream_tls" => {
-                config.upstream_tls = matches!(value.as_str(), "true" | "1" | "yes");
-            }
-            "upstream_tls_verify" => {
-                config.upstream_tls_verify = !matches!(value.as_str(), "false" | "0" | "no");
-            }
-            "upstream_tls_ca" => config.upstream_tls_ca = Some(value),
-            "handshake_timeout" | "handshake_timeout_secs" => {
-                if let Ok(v) = value.parse() {
-                    config.handshake_timeout_secs = v;
-                }
-            }
-            "pool_mode" => {
-                config.pool_mode = parse_pool_mode(&value);
-            }
-            "pool_size" => {
-                if let Ok(v) = value.parse() {
-                    config.pool_size = v;
-                }
-            }
-            "pool_password" => config.pool_password = Some(value),
-            "upstream_password" => config.upstream_password = Some(value),
-            "pool_idle_timeout" => {
-                if let Ok(v) = value.parse() {
-                    config.pool_idle_timeout = v;
-                }
-            }
-            "pool_checkout_timeout" => {
-                if let Ok(v) = value.parse() {
-                    config.pool_checkout_timeout = v;
-                }
-            }
-            "resolvers" => config.resolvers = Some(value),
-            "admin_port" => {
-                if let Ok(v) = value.parse() {
-                    config.admin_port = Some(v);
-                }
-            }
-            "set_role" => config.set_role = Some(value),
-            "tenant_allow" => {
-                config.tenant_allow =
-                    Some(value.split(',').map(|s| s.trim().to_string()).collect());
-            }
-            "tenant_deny" => {
-                config.tenant_deny = Some(value.split(',').map(|s| s.trim().to_string()).collect());
-            }
-            "tenant_max_connections" => {
-                if let Ok(v) = value.parse() {
-                    config.tenant_max_connections = Some(v);
-                }
-            }
-            "tenant_rate_limit" => {
-                if let Ok(v) = value.parse() {
-                    config.tenant_rate_limit = Some(v);
-                }
-            }
-            "tenant_query_timeout" => {
-                if let Ok(v) = value.parse() {
-                    config.tenant_query_timeout = Some(v);
+    /// SET statements for `pool_inject_tenant_label`: the tenant ID and the
+    /// client's role, so pg_stat_activity tooling can tell pooled sessions
+    /// apart. Both are cleared by the reset on the next checkout.
+    pub fn tenant_label_set_clauses(&self, tenant: &str, role: &str) -> Vec<String> {
+        if !self.pool_inject_tenant_label {
+            return Vec::new();
+        }
+        vec![
+            format!(
+                "SET {} = {}",
+                self.pool_tenant_label_var,
+                escape_set_value(tenant)
+            ),
+            format!("SET {TENANT_ROLE_VAR} = {}", escape_set_value(role)),
+        ]
+    }
+
+    /// SCRAM iteration counts accepted from the upstream.
+    pub fn scram_iterations(&self) -> RangeInclusive<u32> {
+        self.scram_min_iterations..=self.scram_max_iterations
+    }
+
+    /// Users allowed to open replication connections. By default every
+    /// bypass user, whatever databases their rules name.
+    pub fn replication_users(&self) -> Vec<String> {
+        match &self.replication_passthrough_usernames {
+            Some(users) => users.clone(),
+            None => {
+                let mut users = Vec::new();
+                for rule in &self.superuser_bypass {
+                    if !users.contains(&rule.user) {
+                        users.push(rule.user.clone());
+                    }
                 }
+                users
             }
-            _ => {}
         }
     }
-}
 
-fn apply_env(config: &mut Config) {
-    if let Ok(v) = std::env::var("PGVPD_PORT")
-        && let Ok(p) = v.parse()
-    {
-        config.listen_port = p;
+    /// Whether `user` may open a replication connection. Physical replication
+    /// (`replication=true`) ignores the database, so only the user is checked;
+    /// logical replication (`replication=database`) reads `database`, so the
+    /// user's bypass rules must also cover it.
+    pub fn allows_replication(&self, user: &str, database: &str, logical: bool) -> bool {
+        self.replication_users().iter().any(|u| u == user)
+            && (!logical || self.is_superuser_bypass(user, database))
     }
-    if let Ok(v) = std::env::var("PGVPD_HOST") {
-        config.listen_host = v;
+
+    /// Whether `user` connecting to `database` bypasses tenant extraction.
+    /// A user's rules that list databases take precedence over their
+    /// all-database rules, so `[[superuser_bypass]]` can narrow the default
+    /// `postgres` rule.
+    pub fn is_superuser_bypass(&self, user: &str, database: &str) -> bool {
+        let rules: Vec<_> = self
+            .superuser_bypass
+            .iter()
+            .filter(|rule| rule.user == user)
+            .collect();
+        let mut restricted = rules
+            .iter()
+            .filter(|rule| !rule.databases.is_empty())
+            .peekable();
+        if restricted.peek().is_none() {
+            return !rules.is_empty();
+        }
+        restricted.any(|rule| rule.databases.iter().any(|db| db == database))
     }
-    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_HOST") {
-        config.upstream_host = v;
+
+    /// Whether `ip` falls within one of the `trust_ips` ranges.
+    /// IPv4-mapped IPv6 addresses are matched against IPv4 ranges.
+    pub fn is_trusted_ip(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.trust_ips.iter().any(|net| net.contains(ip))
     }
-    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_PORT")
-        && let Ok(p) = v.parse()
-    {
-        config.upstream_port = p;
+
+    /// Returns true if any tenant isolation feature is configured.
+    pub fn has_tenant_limits(&self) -> bool {
+        self.tenant_allow.is_some()
+            || self.tenant_deny.is_some()
+            || self.tenant_allowlist_only
+            || self.tenant_max_connections.is_some()
+            || !self.tenant_max_connections_map.is_empty()
+            || self.tenant_rate_limit.is_some()
     }
-    if let Ok(v) = std::env::var("PGVPD_TENANT_SEPARATOR") {
-        config.tenant_separator = v;
+
+    /// Split a username into (role, tenant payload), using the `user` and
+    /// `context` groups of `tenant_regex` when set, else the first
+    /// `tenant_separator`. `None` if the username doesn't match.
+    pub fn split_username<'a>(&self, raw_user: &'a str) -> Option<(&'a str, &'a str)> {
+        match &self.tenant_regex {
+            Some(re) => {
+                let caps = re.captures(raw_user)?;
+                Some((caps.name("user")?.as_str(), caps.name("context")?.as_str()))
+            }
+            None => {
+                let idx = raw_user.find(&self.tenant_separator)?;
+                Some((
+                    &raw_user[..idx],
+                    &raw_user[idx + self.tenant_separator.len()..],
+                ))
+            }
+        }
     }
-    if let Ok(v) = std::env::var("PGVPD_CONTEXT_VARIABLES") {
+
+    /// Whether the admin API listens on all interfaces.
+    pub fn admin_host_is_wildcard(&self) -> bool {
+        self.admin_host
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_unspecified())
+    }
+
+    /// Settings that differ between `self` and `new` but only take effect on
+    /// restart (listeners, upstream, tenant parsing, TLS, pooling, admin API).
+    pub fn restart_required_changes(&self, new: &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        let mut check = |name, differs: bool| {
+            if differs {
+                changed.push(name);
+            }
+        };
+        check("port", self.listen_port != new.listen_port);
+        check("listen_host", self.listen_host != new.listen_host);
+        check(
+            "listen_ipv6",
+            self.listen_ipv6 != new.listen_ipv6 || self.listen_host_v6 != new.listen_host_v6,
+        );
+        check("upstream_host", self.upstream_host != new.upstream_host);
+        check("upstream_port", self.upstream_port != new.upstream_port);
+        check(
+            "routing_mode",
+            self.routing_mode != new.routing_mode
+                || self.upstream_replica_hosts != new.upstream_replica_hosts,
+        );
+        check(
+            "context_variables",
+            self.context_variables != new.context_variables
+                || self.tenant_separator != new.tenant_separator
+                || self.tenant_separator_regex != new.tenant_separator_regex
+                || self.value_separator != new.value_separator
+                || self.tenant_id_allow_unicode != new.tenant_id_allow_unicode,
+        );
+        check("tls_port", self.tls_port != new.tls_port);
+        check(
+            "tls_cert",
+            self.tls_cert != new.tls_cert
+                || self.tls_key != new.tls_key
+                || self.tls_cert_pkcs12 != new.tls_cert_pkcs12
+                || self.tls_cert_pkcs12_password != new.tls_cert_pkcs12_password
+                || self.tls_watch_interval_secs != new.tls_watch_interval_secs
+                || self.tls_sni_certs != new.tls_sni_certs,
+        );
+        check(
+            "tls_cipher_suites",
+            self.tls_cipher_suites != new.tls_cipher_suites
+                || self.tls_min_version != new.tls_min_version,
+        );
+        check(
+            "upstream_tls",
+            self.upstream_tls != new.upstream_tls
+                || self.upstream_tls_verify != new.upstream_tls_verify
+                || self.upstream_tls_ca != new.upstream_tls_ca
+                || self.upstream_tls_pin_pubkey != new.upstream_tls_pin_pubkey,
+        );
+        check(
+            "max_total_connections",
+            self.max_total_connections != new.max_total_connections,
+        );
+        check(
+            "proxy_protocol_mode",
+            self.proxy_protocol_mode != new.proxy_protocol_mode
+                || self.proxy_protocol_trusted_sources != new.proxy_protocol_trusted_sources,
+        );
+        check("pool_mode", self.pool_mode != new.pool_mode);
+        check(
+            "pool_size",
+            self.pool_size != new.pool_size
+                || self.pool_min_size != new.pool_min_size
+                || self.scram_cache_max_entries != new.scram_cache_max_entries,
+        );
+        check(
+            "pool_upstream_user",
+            self.pool_upstream_user != new.pool_upstream_user,
+        );
+        check(
+            "admin_port",
+            self.admin_port != new.admin_port
+                || self.admin_host != new.admin_host
+                || self.admin_rate_limit_rps != new.admin_rate_limit_rps
+                || self.admin_cors_origins != new.admin_cors_origins
+                || self.admin_cors_allow_all != new.admin_cors_allow_all,
+        );
+        check(
+            "otlp_endpoint",
+            self.otlp_endpoint != new.otlp_endpoint
+                || self.otlp_export_interval_secs != new.otlp_export_interval_secs,
+        );
+        check(
+            "statsd_host",
+            self.statsd_host != new.statsd_host
+                || self.statsd_port != new.statsd_port
+                || self.statsd_flush_interval_ms != new.statsd_flush_interval_ms
+                || self.statsd_prefix != new.statsd_prefix,
+        );
+        check(
+            "database_resolver",
+            self.database_resolvers != new.database_resolvers,
+        );
+        check("audit_log_file", self.audit_log_file != new.audit_log_file);
+        check(
+            "log_format",
+            self.log_format != new.log_format
+                || self.log_timestamps != new.log_timestamps
+                || self.log_file != new.log_file
+                || self.log_rotation != new.log_rotation,
+        );
+        changed
+    }
+}
+
+/// Maximum nesting of `include` directives.
+const MAX_INCLUDE_DEPTH: usize = 5;
+
+/// Session variable holding the pool checkout time when `inject_conn_id` is on.
+const CONN_START_VAR: &str = "app.pgvpd_conn_start";
+
+/// Session variable holding the client's role when `pool_inject_tenant_label` is on.
+const TENANT_ROLE_VAR: &str = "app.pgvpd_role";
+
+/// Comma-separated origins, e.g. `https://dash.example.com,http://localhost:3000`.
+/// A trailing slash is dropped, since browsers never send one.
+fn parse_roles(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn parse_origins(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().trim_end_matches('/').to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Config files tried in order when `--config` is not given.
+pub const CONFIG_SEARCH_PATHS: &[&str] = &[
+    "./pgvpd.conf",
+    "/etc/pgvpd/pgvpd.conf",
+    "/usr/local/etc/pgvpd.conf",
+];
+
+/// The config file to read: `explicit` (which must exist), else the first of
+/// `search_paths` that exists. `None` when nothing is found.
+fn config_file_path(
+    explicit: Option<&str>,
+    search_paths: &[&str],
+) -> Result<Option<PathBuf>, String> {
+    if let Some(path) = explicit {
+        let path = PathBuf::from(path);
+        if !path.exists() {
+            return Err(format!("config file not found: {}", path.display()));
+        }
+        return Ok(Some(path));
+    }
+    Ok(search_paths
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists()))
+}
+
+/// A config file line that was skipped or only partly applied: an unknown
+/// key or section, a line that isn't `key = value`, or a value that doesn't
+/// parse. The rest of the file still applies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigWarning {
+    pub file: PathBuf,
+    /// 1-based; 0 for YAML files, whose lines are translated first.
+    pub line: usize,
+    pub key: String,
+    pub message: String,
+}
+
+impl ConfigWarning {
+    /// `file` is filled in by `include_config_file`.
+    fn new(line: usize, key: &str, message: String) -> Self {
+        Self {
+            file: PathBuf::new(),
+            line,
+            key: key.to_string(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ConfigWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "{}: {}", self.file.display(), self.message),
+            line => write!(f, "{}:{line}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+/// Read and apply a config file, following `include` directives.
+fn load_config_file(config: &mut Config, path: &Path) -> Result<Vec<ConfigWarning>, String> {
+    let mut warnings = Vec::new();
+    include_config_file(config, path, &mut HashSet::new(), 0, &mut warnings)?;
+    Ok(warnings)
+}
+
+/// Apply one config file at include nesting level `depth`. `stack` holds the
+/// canonical paths of the files currently being applied, to detect cycles.
+fn include_config_file(
+    config: &mut Config,
+    path: &Path,
+    stack: &mut HashSet<PathBuf>,
+    depth: usize,
+    warnings: &mut Vec<ConfigWarning>,
+) -> Result<(), String> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(format!(
+            "include depth exceeds {MAX_INCLUDE_DEPTH} at {}",
+            path.display()
+        ));
+    }
+    let canonical = fs::canonicalize(path)
+        .map_err(|e| format!("cannot read config file {}: {e}", path.display()))?;
+    if !stack.insert(canonical.clone()) {
+        return Err(format!("circular include of {}", path.display()));
+    }
+    let mut content = fs::read_to_string(&canonical)
+        .map_err(|e| format!("cannot read config file {}: {e}", path.display()))?;
+    let yaml = is_yaml_path(&canonical);
+    if yaml {
+        content = yaml_to_conf(&content)
+            .map_err(|e| format!("invalid YAML in {}: {e}", path.display()))?;
+    }
+    let base_dir = canonical.parent().unwrap_or(Path::new("/")).to_path_buf();
+    let first = warnings.len();
+    apply_config_content(config, &content, &base_dir, stack, depth, warnings)?;
+    // Nested includes have already claimed their own warnings
+    for warning in &mut warnings[first..] {
+        if warning.file.as_os_str().is_empty() {
+            warning.file = path.to_path_buf();
+            if yaml {
+                warning.line = 0;
+            }
+        }
+    }
+    stack.remove(&canonical);
+    Ok(())
+}
+
+// ─── YAML config files ──────────────────────────────────────────────────────
+
+/// A `.yaml`/`.yml` config file. Top-level keys are the usual option names;
+/// `pool:`, `tls:` and `tenant:` blocks (and any other mapping) prefix their
+/// keys with the block name, so `pool: {size: 20}` is `pool_size = 20`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ConfigYaml {
+    /// Same as the `[tenant_limits]` section.
+    tenant_limits: BTreeMap<String, u32>,
+    /// Same as `[[database_resolver]]` blocks.
+    database_resolver: Vec<DatabaseResolverYaml>,
+    /// User names, or `{user, databases}` entries for `[[superuser_bypass]]`.
+    superuser_bypass: Option<Vec<SuperuserBypassYaml>>,
+    /// Same as `[[tls_cert]]` blocks.
+    tls_sni_certs: Vec<TlsSniCertYaml>,
+    #[serde(flatten)]
+    settings: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DatabaseResolverYaml {
+    database: String,
+    resolvers: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TlsSniCertYaml {
+    sni: String,
+    cert: String,
+    key: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SuperuserBypassYaml {
+    User(String),
+    Rule {
+        user: String,
+        #[serde(default)]
+        databases: Vec<String>,
+    },
+}
+
+fn is_yaml_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml")
+}
+
+/// Translate a YAML config file into `key = value` config lines, so both
+/// formats go through `apply_config_content`. Lists become comma-separated
+/// values; nulls are skipped.
+fn yaml_to_conf(content: &str) -> Result<String, String> {
+    let yaml: ConfigYaml = if content.trim().is_empty() {
+        ConfigYaml::default()
+    } else {
+        serde_yaml::from_str(content).map_err(|e| e.to_string())?
+    };
+
+    let mut lines = Vec::new();
+    for (key, value) in &yaml.settings {
+        match value {
+            serde_yaml::Value::Mapping(block) => {
+                for (name, value) in block {
+                    let name =
+                        yaml_scalar(name).ok_or_else(|| format!("'{key}' has a non-scalar key"))?;
+                    push_yaml_setting(&mut lines, &format!("{key}_{name}"), value)?;
+                }
+            }
+            value => push_yaml_setting(&mut lines, key, value)?,
+        }
+    }
+    if !yaml.tenant_limits.is_empty() {
+        lines.push("[tenant_limits]".to_string());
+        for (tenant, max) in &yaml.tenant_limits {
+            lines.push(format!("{tenant} = {max}"));
+        }
+    }
+    for entry in &yaml.database_resolver {
+        lines.push("[[database_resolver]]".to_string());
+        lines.push(format!("database = {}", entry.database));
+        lines.push(format!("resolvers = {}", entry.resolvers));
+    }
+    if let Some(rules) = &yaml.superuser_bypass {
+        // Plain names replace the list like the flat key; rules are added as blocks
+        let users: Vec<&str> = rules
+            .iter()
+            .filter_map(|rule| match rule {
+                SuperuserBypassYaml::User(user) => Some(user.as_str()),
+                SuperuserBypassYaml::Rule { .. } => None,
+            })
+            .collect();
+        if !users.is_empty() {
+            lines.insert(0, format!("superuser_bypass = {}", users.join(",")));
+        }
+        for rule in rules {
+            if let SuperuserBypassYaml::Rule { user, databases } = rule {
+                lines.push("[[superuser_bypass]]".to_string());
+                lines.push(format!("user = {user}"));
+                lines.push(format!("databases = {}", databases.join(",")));
+            }
+        }
+    }
+    for entry in &yaml.tls_sni_certs {
+        lines.push("[[tls_cert]]".to_string());
+        lines.push(format!("sni = {}", entry.sni));
+        lines.push(format!("cert = {}", entry.cert));
+        lines.push(format!("key = {}", entry.key));
+    }
+    Ok(lines.join("\n"))
+}
+
+fn push_yaml_setting(
+    lines: &mut Vec<String>,
+    key: &str,
+    value: &serde_yaml::Value,
+) -> Result<(), String> {
+    let value = match value {
+        serde_yaml::Value::Null => return Ok(()),
+        serde_yaml::Value::Sequence(items) => items
+            .iter()
+            .map(|item| {
+                yaml_scalar(item).ok_or_else(|| format!("'{key}' must be a list of scalars"))
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .join(","),
+        value => yaml_scalar(value).ok_or_else(|| format!("'{key}' is nested too deeply"))?,
+    };
+    if value.contains('\n') {
+        return Err(format!("'{key}' must be a single line"));
+    }
+    lines.push(format!("{key} = {value}"));
+    Ok(())
+}
+
+/// A scalar YAML value as config text.
+fn yaml_scalar(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Expand `${VAR}` and `${VAR:-default}` in a config file value from the
+/// environment; the default applies when `VAR` is unset or empty. An unset
+/// variable without a default is left as written and reported as a warning.
+fn expand_env_vars(
+    value: &str,
+    key: &str,
+    line: usize,
+    warnings: &mut Vec<ConfigWarning>,
+) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let placeholder = &rest[start..start + len + 1];
+        let expr = &placeholder[2..placeholder.len() - 1];
+        let (name, default) = match expr.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (expr, None),
+        };
+        match (std::env::var(name), default) {
+            (Ok(v), Some(default)) if v.is_empty() => out.push_str(default),
+            (Ok(v), _) => out.push_str(&v),
+            (Err(_), Some(default)) => out.push_str(default),
+            (Err(_), None) => {
+                warnings.push(ConfigWarning::new(
+                    line,
+                    key,
+                    format!("'{key}' references unset environment variable '{name}'"),
+                ));
+                out.push_str(placeholder);
+            }
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Apply config content without file context (includes resolve against the
+/// working directory). Include errors are ignored.
+#[cfg(test)]
+fn apply_config_file(config: &mut Config, content: &str) -> Vec<ConfigWarning> {
+    let mut warnings = Vec::new();
+    let _ = apply_config_content(
+        config,
+        content,
+        Path::new("."),
+        &mut HashSet::new(),
+        0,
+        &mut warnings,
+    );
+    warnings
+}
+
+fn apply_config_content(
+    config: &mut Config,
+    content: &str,
+    base_dir: &Path,
+    stack: &mut HashSet<PathBuf>,
+    depth: usize,
+    warnings: &mut Vec<ConfigWarning>,
+) -> Result<(), String> {
+    // `[section]` headers scope the lines that follow; top-level keys come
+    // first. `[[database_resolver]]`, `[[superuser_bypass]]`, `[[tls_cert]]`,
+    // `[[startup_param]]` and `[[startup_param_rename]]` start a new block
+    // each time they appear.
+    let mut section: Option<&str> = None;
+    for (n, line) in content.lines().enumerate() {
+        let line_no = n + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = trimmed
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            let name = name.trim();
+            if name == "[database_resolver]" {
+                config.database_resolvers.push(DatabaseResolver::default());
+            }
+            if name == "[superuser_bypass]" {
+                config.superuser_bypass.push(SuperuserBypassRule::default());
+            }
+            if name == "[tls_cert]" {
+                config.tls_sni_certs.push(TlsSniCert::default());
+            }
+            if name == "[startup_param]" {
+                config.startup_params.push(Default::default());
+            }
+            if name == "[startup_param_rename]" {
+                config.startup_param_renames.push(Default::default());
+            }
+            if !matches!(
+                name,
+                "tenant_limits"
+                    | "[database_resolver]"
+                    | "[superuser_bypass]"
+                    | "[tls_cert]"
+                    | "[startup_param]"
+                    | "[startup_param_rename]"
+            ) {
+                warnings.push(ConfigWarning::new(
+                    line_no,
+                    name,
+                    format!("unknown config section '[{name}]'"),
+                ));
+            }
+            section = Some(name);
+            continue;
+        }
+
+        let Some(eq_pos) = trimmed.find('=') else {
+            warnings.push(ConfigWarning::new(
+                line_no,
+                trimmed,
+                format!("expected 'key = value', got '{trimmed}'"),
+            ));
+            continue;
+        };
+
+        let key = trimmed[..eq_pos].trim();
+        let mut value = trimmed[eq_pos + 1..].trim().to_string();
+
+        // Strip quotes
+        if (value.starts_with('"') && value.ends_with('"'))
+            || (value.starts_with('\'') && value.ends_with('\''))
+        {
+            value = value[1..value.len() - 1].to_string();
+        }
+        let value = expand_env_vars(&value, key, line_no, warnings);
+
+        match section {
+            None => {}
+            Some("tenant_limits") => {
+                if let Some(max) = parse_number(&value, key, line_no, warnings) {
+                    config
+                        .tenant_max_connections_map
+                        .insert(key.to_string(), max);
+                }
+                continue;
+            }
+            Some("[database_resolver]") => {
+                // The header pushed this block's entry
+                let entry = config.database_resolvers.last_mut().unwrap();
+                match key {
+                    "database" => entry.database = value,
+                    "resolvers" => entry.resolvers = value,
+                    _ => warnings.push(unknown_key(line_no, key)),
+                }
+                continue;
+            }
+            Some("[superuser_bypass]") => {
+                let rule = config.superuser_bypass.last_mut().unwrap();
+                match key {
+                    "user" => rule.user = value,
+                    "databases" => {
+                        rule.databases = value
+                            .split(',')
+                            .map(|s| s.trim().to_string())
+                            .filter(|s| !s.is_empty())
+                            .collect();
+                    }
+                    _ => warnings.push(unknown_key(line_no, key)),
+                }
+                continue;
+            }
+            Some("[tls_cert]") => {
+                let entry = config.tls_sni_certs.last_mut().unwrap();
+                match key {
+                    "sni" => entry.sni = value,
+                    "cert" => entry.cert = value,
+                    "key" => entry.key = value,
+                    _ => warnings.push(unknown_key(line_no, key)),
+                }
+                continue;
+            }
+            Some("[startup_param]") => {
+                let (name, val) = config.startup_params.last_mut().unwrap();
+                match key {
+                    "name" => *name = value,
+                    "value" => *val = value,
+                    _ => warnings.push(unknown_key(line_no, key)),
+                }
+                continue;
+            }
+            Some("[startup_param_rename]") => {
+                let (from, to) = config.startup_param_renames.last_mut().unwrap();
+                match key {
+                    "from" => *from = value,
+                    "to" => *to = value,
+                    _ => warnings.push(unknown_key(line_no, key)),
+                }
+                continue;
+            }
+            Some(_) => continue,
+        }
+
+        match key {
+            "port" | "listen_port" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.listen_port = v;
+                }
+            }
+            "listen_host" | "host" => config.listen_host = value,
+            "listen_ipv6" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.listen_ipv6 = v;
+                }
+            }
+            "listen_host_v6" => config.listen_host_v6 = value,
+            "upstream_host" => config.upstream_host = value,
+            "upstream_port" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.upstream_port = v;
+                }
+            }
+            "upstream_connect_retries" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.upstream_connect_retries = v;
+                }
+            }
+            "upstream_replica_hosts" => config.upstream_replica_hosts = parse_host_list(&value),
+            "routing_mode" => config.routing_mode = parse_routing_mode(&value),
+            "upstream_connect_retry_delay_ms" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.upstream_connect_retry_delay_ms = v;
+                }
+            }
+            "upstream_dns_ttl_secs" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.upstream_dns_ttl_secs = v;
+                }
+            }
+            "tenant_separator" | "separator" => config.tenant_separator = value,
+            "tenant_separator_regex" => config.tenant_separator_regex = Some(value),
+            "context_variables" | "context" => {
+                config.context_variables = value.split(',').map(|s| s.trim().to_string()).collect();
+            }
+            "value_separator" => config.value_separator = value,
+            "tenant_id_allow_unicode" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.tenant_id_allow_unicode = v;
+                }
+            }
+            "superuser_bypass" | "superuser" => {
+                config.superuser_bypass = SuperuserBypassRule::from_users(&value);
+            }
+            "replication_passthrough_usernames" => {
+                config.replication_passthrough_usernames =
+                    Some(value.split(',').map(|s| s.trim().to_string()).collect());
+            }
+            "log_level" => config.log_level = value,
+            "log_format" => config.log_format = parse_log_format(&value),
+            "log_timestamps" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.log_timestamps = v;
+                }
+            }
+            "log_file" => config.log_file = Some(value),
+            "log_rotation" => {
+                if let Some(v) = parse_choice(&value, key, line_no, warnings, parse_log_rotation) {
+                    config.log_rotation = v;
+                }
+            }
+            "tls_port" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.tls_port = Some(v);
+                }
+            }
+            "tls_cert" => config.tls_cert = Some(value),
+            "tls_key" => config.tls_key = Some(value),
+            "tls_cert_pkcs12" => config.tls_cert_pkcs12 = Some(value),
+            "tls_cert_pkcs12_password" => config.tls_cert_pkcs12_password = Some(value),
+            "tls_watch_interval_secs" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.tls_watch_interval_secs = v;
+                }
+            }
+            "tls_cipher_suites" => {
+                config.tls_cipher_suites =
+                    Some(value.split(',').map(|s| s.trim().to_string()).collect());
+            }
+            "tls_min_version" => config.tls_min_version = Some(value),
+            "upstream_tls" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.upstream_tls = v;
+                }
+            }
+            "upstream_tls_verify" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.upstream_tls_verify = v;
+                }
+            }
+            "upstream_tls_ca" => config.upstream_tls_ca = Some(value),
+            "upstream_tls_pin_pubkey" => config.upstream_tls_pin_pubkey = Some(value),
+            "handshake_timeout" | "handshake_timeout_secs" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.handshake_timeout_secs = v;
+                }
+            }
+            "max_startup_message_bytes" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.max_startup_message_bytes = v;
+                }
+            }
+            "client_idle_timeout" | "client_idle_timeout_secs" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.client_idle_timeout_secs = v;
+                }
+            }
+            "max_total_connections" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.max_total_connections = v;
+                }
+            }
+            "proxy_protocol_mode" => {
+                // Not a warning: a typo would silently turn PROXY parsing off
+                config.proxy_protocol_mode = parse_proxy_protocol_mode(&value).map_err(|e| {
+                    format!("line {line_no}: invalid value '{value}' for '{key}' ({e})")
+                })?;
+            }
+            "proxy_protocol_trusted_sources" => match parse_cidr_list(&value) {
+                Ok(nets) => config.proxy_protocol_trusted_sources = nets,
+                Err(e) => warnings.push(ConfigWarning::new(
+                    line_no,
+                    key,
+                    format!("invalid value '{value}' for '{key}' ({e}), using default"),
+                )),
+            },
+            "audit_log_file" => config.audit_log_file = Some(value),
+            "audit_log_max_bytes" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.audit_log_max_bytes = v;
+                }
+            }
+            "pool_mode" => {
+                config.pool_mode = parse_pool_mode(&value);
+            }
+            "pool_size" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.pool_size = v;
+                }
+            }
+            "pool_min_size" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.pool_min_size = v;
+                }
+            }
+            "pool_password" => config.pool_password = Some(value),
+            "pool_auth_method" => {
+                if let Some(v) =
+                    parse_choice(&value, key, line_no, warnings, parse_pool_auth_method)
+                {
+                    config.pool_auth_method = v;
+                }
+            }
+            "trust_ips" => match parse_cidr_list(&value) {
+                Ok(nets) => config.trust_ips = nets,
+                Err(e) => warnings.push(ConfigWarning::new(
+                    line_no,
+                    key,
+                    format!("invalid value '{value}' for '{key}' ({e}), using default"),
+                )),
+            },
+            "startup_param_allowlist" => {
+                config.startup_param_allowlist =
+                    value.split(',').map(|s| s.trim().to_string()).collect();
+            }
+            "startup_param" => match parse_pair(&value) {
+                Some(pair) => config.startup_params.push(pair),
+                None => warnings.push(invalid_pair(line_no, key, &value)),
+            },
+            "startup_param_rename" => match parse_pair(&value) {
+                Some(pair) => config.startup_param_renames.push(pair),
+                None => warnings.push(invalid_pair(line_no, key, &value)),
+            },
+            "inject_comment_template" => config.inject_comment_template = Some(value),
+            "inject_conn_id" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.inject_conn_id = v;
+                }
+            }
+            "inject_conn_id_var" => config.inject_conn_id_var = value,
+            "pool_inject_tenant_label" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.pool_inject_tenant_label = v;
+                }
+            }
+            "pool_tenant_label_var" => config.pool_tenant_label_var = value,
+            "send_context_notice" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.send_context_notice = v;
+                }
+            }
+            "application_name_suffix" => config.application_name_suffix = value,
+            "rewrite_application_name" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.rewrite_application_name = v;
+                }
+            }
+            "protocol_negotiate_passthrough" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.protocol_negotiate_passthrough = v;
+                }
+            }
+            "upstream_password" => config.upstream_password = Some(value),
+            "upstream_auth_method" => {
+                if let Some(v) =
+                    parse_choice(&value, key, line_no, warnings, parse_upstream_auth_method)
+                {
+                    config.upstream_auth_method = v;
+                }
+            }
+            "scram_min_iterations" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.scram_min_iterations = v;
+                }
+            }
+            "scram_max_iterations" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.scram_max_iterations = v;
+                }
+            }
+            "pool_upstream_user" => config.pool_upstream_user = Some(value),
+            "pool_jwt_context" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.pool_jwt_context = v;
+                }
+            }
+            "pool_jwt_claims" => config.pool_jwt_claims = parse_jwt_claims(&value),
+            "pool_jwt_secret" => config.pool_jwt_secret = Some(value),
+            "pool_idle_timeout" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.pool_idle_timeout = v;
+                }
+            }
+            "pool_keepalive_secs" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.pool_keepalive_secs = v;
+                }
+            }
+            "scram_cache_max_entries" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.scram_cache_max_entries = v;
+                }
+            }
+            "pool_checkout_timeout" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.pool_checkout_timeout = v;
+                }
+            }
+            "pool_reset_query" => config.pool_reset_query = parse_pool_reset(&value),
+            "pool_health_check_query" => config.pool_health_check_query = value,
+            "pool_upstream_min_version" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.pool_upstream_min_version = Some(v);
+                }
+            }
+            "resolvers" => config.resolvers = Some(value),
+            "admin_port" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.admin_port = Some(v);
+                }
+            }
+            "admin_host" => config.admin_host = value,
+            "admin_rate_limit_rps" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.admin_rate_limit_rps = v;
+                }
+            }
+            "admin_cors_origins" => config.admin_cors_origins = parse_origins(&value),
+            "admin_cors_allow_all" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.admin_cors_allow_all = v;
+                }
+            }
+            "otlp_endpoint" => config.otlp_endpoint = Some(value),
+            "otlp_export_interval_secs" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.otlp_export_interval_secs = v;
+                }
+            }
+            "statsd_host" => config.statsd_host = Some(value),
+            "statsd_port" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.statsd_port = Some(v);
+                }
+            }
+            "statsd_flush_interval_ms" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.statsd_flush_interval_ms = v;
+                }
+            }
+            "statsd_prefix" => config.statsd_prefix = value,
+            "set_role" => config.set_role = Some(value),
+            "require_role_exists" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.require_role_exists = v;
+                }
+            }
+            "forbidden_roles" => config.forbidden_roles = parse_roles(&value),
+            "tenant_allow" => {
+                config.tenant_allow =
+                    Some(value.split(',').map(|s| s.trim().to_string()).collect());
+            }
+            "tenant_deny" => {
+                config.tenant_deny = Some(value.split(',').map(|s| s.trim().to_string()).collect());
+            }
+            "tenant_allowlist_only" => {
+                if let Some(v) = parse_flag(&value, key, line_no, warnings) {
+                    config.tenant_allowlist_only = v;
+                }
+            }
+            "tenant_max_connections" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.tenant_max_connections = Some(v);
+                }
+            }
+            "tenant_rate_limit" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.tenant_rate_limit = Some(v);
+                }
+            }
+            "tenant_rate_limit_window_secs" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.tenant_rate_limit_window_secs = v;
+                }
+            }
+            "tenant_query_timeout" => {
+                if let Some(v) = parse_number(&value, key, line_no, warnings) {
+                    config.tenant_query_timeout = Some(v);
+                }
+            }
+            "include" => {
+                // Relative paths resolve against the including file's directory
+                let included = base_dir.join(&value);
+                include_config_file(config, &included, stack, depth + 1, warnings)?;
+            }
+            _ => warnings.push(unknown_key(line_no, key)),
+        }
+    }
+    Ok(())
+}
+
+fn unknown_key(line: usize, key: &str) -> ConfigWarning {
+    ConfigWarning::new(line, key, format!("unknown config key '{key}'"))
+}
+
+fn invalid_pair(line: usize, key: &str, value: &str) -> ConfigWarning {
+    ConfigWarning::new(
+        line,
+        key,
+        format!("invalid value '{value}' for '{key}': expected name=value, skipping"),
+    )
+}
+
+/// A numeric config value, or `None` (with a warning) if it doesn't parse.
+fn parse_number<T: std::str::FromStr>(
+    value: &str,
+    key: &str,
+    line: usize,
+    warnings: &mut Vec<ConfigWarning>,
+) -> Option<T> {
+    let parsed = value.parse().ok();
+    if parsed.is_none() {
+        warnings.push(ConfigWarning::new(
+            line,
+            key,
+            format!("invalid value '{value}' for '{key}', using default"),
+        ));
+    }
+    parsed
+}
+
+/// A boolean config value (true/yes/1 or false/no/0), or `None` (with a
+/// warning) for anything else.
+fn parse_flag(
+    value: &str,
+    key: &str,
+    line: usize,
+    warnings: &mut Vec<ConfigWarning>,
+) -> Option<bool> {
+    match value {
+        "true" | "yes" | "1" => Some(true),
+        "false" | "no" | "0" => Some(false),
+        _ => {
+            warnings.push(ConfigWarning::new(
+                line,
+                key,
+                format!(
+                    "invalid value '{value}' for '{key}' (expected true or false), using default"
+                ),
+            ));
+            None
+        }
+    }
+}
+
+/// A keyword config value, or `None` (with a warning) if `parse` rejects it.
+/// An unknown keyword keeps the previous value rather than falling back to
+/// whichever variant a catch-all arm would pick.
+fn parse_choice<T>(
+    value: &str,
+    key: &str,
+    line: usize,
+    warnings: &mut Vec<ConfigWarning>,
+    parse: fn(&str) -> Result<T, &'static str>,
+) -> Option<T> {
+    match parse(value) {
+        Ok(v) => Some(v),
+        Err(expected) => {
+            warnings.push(ConfigWarning::new(
+                line,
+                key,
+                format!("invalid value '{value}' for '{key}' ({expected}), using default"),
+            ));
+            None
+        }
+    }
+}
+
+/// Apply `PGVPD_*` overrides. Fails on an unknown keyword for an enum-valued
+/// setting, since there is no config file line to warn about.
+fn apply_env(config: &mut Config) -> Result<(), String> {
+    if let Ok(v) = std::env::var("PGVPD_PORT")
+        && let Ok(p) = v.parse()
+    {
+        config.listen_port = p;
+    }
+    if let Ok(v) = std::env::var("PGVPD_HOST") {
+        config.listen_host = v;
+    }
+    if let Ok(v) = std::env::var("PGVPD_LISTEN_IPV6") {
+        config.listen_ipv6 = matches!(v.as_str(), "true" | "1" | "yes");
+    }
+    if let Ok(v) = std::env::var("PGVPD_HOST_V6") {
+        config.listen_host_v6 = v;
+    }
+    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_HOST") {
+        config.upstream_host = v;
+    }
+    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_PORT")
+        && let Ok(p) = v.parse()
+    {
+        config.upstream_port = p;
+    }
+    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_CONNECT_RETRIES")
+        && let Ok(n) = v.parse()
+    {
+        config.upstream_connect_retries = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_CONNECT_RETRY_DELAY_MS")
+        && let Ok(n) = v.parse()
+    {
+        config.upstream_connect_retry_delay_ms = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_DNS_TTL_SECS")
+        && let Ok(n) = v.parse()
+    {
+        config.upstream_dns_ttl_secs = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_REPLICA_HOSTS") {
+        config.upstream_replica_hosts = parse_host_list(&v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_ROUTING_MODE") {
+        config.routing_mode = parse_routing_mode(&v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_TENANT_SEPARATOR") {
+        config.tenant_separator = v;
+    }
+    if let Ok(v) = std::env::var("PGVPD_TENANT_SEPARATOR_REGEX") {
+        config.tenant_separator_regex = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_CONTEXT_VARIABLES") {
         config.context_variables = v.split(',').map(|s| s.trim().to_string()).collect();
     }
-    if let Ok(v) = std::env::var("PGVPD_VALUE_SEPARATOR") {
-        config.value_separator = v;
+    if let Ok(v) = std::env::var("PGVPD_VALUE_SEPARATOR") {
+        config.value_separator = v;
+    }
+    if let Ok(v) = std::env::var("PGVPD_TENANT_ID_ALLOW_UNICODE") {
+        config.tenant_id_allow_unicode = matches!(v.as_str(), "true" | "1" | "yes");
+    }
+    if let Ok(v) = std::env::var("PGVPD_SUPERUSER_BYPASS") {
+        config.superuser_bypass = SuperuserBypassRule::from_users(&v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_REPLICATION_PASSTHROUGH_USERNAMES") {
+        config.replication_passthrough_usernames =
+            Some(v.split(',').map(|s| s.trim().to_string()).collect());
+    }
+    if let Ok(v) = std::env::var("PGVPD_LOG_LEVEL") {
+        config.log_level = v;
+    }
+    if let Ok(v) = std::env::var("PGVPD_LOG_FORMAT") {
+        config.log_format = parse_log_format(&v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_LOG_TIMESTAMPS") {
+        config.log_timestamps = !matches!(v.as_str(), "false" | "0" | "no");
+    }
+    if let Ok(v) = std::env::var("PGVPD_LOG_FILE") {
+        config.log_file = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_LOG_ROTATION") {
+        config.log_rotation = parse_log_rotation(&v)
+            .map_err(|e| format!("invalid PGVPD_LOG_ROTATION '{v}' ({e})"))?;
+    }
+    if let Ok(v) = std::env::var("PGVPD_TLS_PORT")
+        && let Ok(p) = v.parse()
+    {
+        config.tls_port = Some(p);
+    }
+    if let Ok(v) = std::env::var("PGVPD_TLS_CERT") {
+        config.tls_cert = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_TLS_KEY") {
+        config.tls_key = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_TLS_CERT_PKCS12") {
+        config.tls_cert_pkcs12 = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_TLS_CERT_PKCS12_PASSWORD") {
+        config.tls_cert_pkcs12_password = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_TLS_WATCH_INTERVAL_SECS")
+        && let Ok(n) = v.parse()
+    {
+        config.tls_watch_interval_secs = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_TLS_CIPHER_SUITES") {
+        config.tls_cipher_suites = Some(v.split(',').map(|s| s.trim().to_string()).collect());
+    }
+    if let Ok(v) = std::env::var("PGVPD_TLS_MIN_VERSION") {
+        config.tls_min_version = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_TLS") {
+        config.upstream_tls = matches!(v.as_str(), "true" | "1" | "yes");
+    }
+    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_TLS_VERIFY") {
+        config.upstream_tls_verify = !matches!(v.as_str(), "false" | "0" | "no");
+    }
+    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_TLS_CA") {
+        config.upstream_tls_ca = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_TLS_PIN_PUBKEY") {
+        config.upstream_tls_pin_pubkey = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_HANDSHAKE_TIMEOUT")
+        && let Ok(t) = v.parse()
+    {
+        config.handshake_timeout_secs = t;
+    }
+    if let Ok(v) = std::env::var("PGVPD_MAX_STARTUP_MESSAGE_BYTES")
+        && let Ok(n) = v.parse()
+    {
+        config.max_startup_message_bytes = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_CLIENT_IDLE_TIMEOUT")
+        && let Ok(t) = v.parse()
+    {
+        config.client_idle_timeout_secs = t;
+    }
+    if let Ok(v) = std::env::var("PGVPD_MAX_TOTAL_CONNECTIONS")
+        && let Ok(n) = v.parse()
+    {
+        config.max_total_connections = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_PROXY_PROTOCOL_MODE") {
+        config.proxy_protocol_mode = parse_proxy_protocol_mode(&v)
+            .map_err(|e| format!("invalid PGVPD_PROXY_PROTOCOL_MODE '{v}' ({e})"))?;
+    }
+    if let Ok(v) = std::env::var("PGVPD_PROXY_PROTOCOL_TRUSTED_SOURCES") {
+        config.proxy_protocol_trusted_sources = parse_cidr_list(&v)
+            .map_err(|e| format!("invalid PGVPD_PROXY_PROTOCOL_TRUSTED_SOURCES '{v}' ({e})"))?;
+    }
+    if let Ok(v) = std::env::var("PGVPD_AUDIT_LOG_FILE") {
+        config.audit_log_file = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_AUDIT_LOG_MAX_BYTES")
+        && let Ok(n) = v.parse()
+    {
+        config.audit_log_max_bytes = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_MODE") {
+        config.pool_mode = parse_pool_mode(&v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_SIZE")
+        && let Ok(n) = v.parse()
+    {
+        config.pool_size = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_MIN_SIZE")
+        && let Ok(n) = v.parse()
+    {
+        config.pool_min_size = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_PASSWORD") {
+        config.pool_password = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_AUTH_METHOD") {
+        config.pool_auth_method = parse_pool_auth_method(&v)
+            .map_err(|e| format!("invalid PGVPD_POOL_AUTH_METHOD '{v}' ({e})"))?;
+    }
+    if let Ok(v) = std::env::var("PGVPD_TRUST_IPS") {
+        config.trust_ips =
+            parse_cidr_list(&v).map_err(|e| format!("invalid PGVPD_TRUST_IPS '{v}' ({e})"))?;
+    }
+    if let Ok(v) = std::env::var("PGVPD_STARTUP_PARAM_ALLOWLIST") {
+        config.startup_param_allowlist = v.split(',').map(|s| s.trim().to_string()).collect();
+    }
+    // Semicolon-separated, since values such as search_path may contain commas
+    if let Ok(v) = std::env::var("PGVPD_STARTUP_PARAMS") {
+        config.startup_params =
+            parse_pair_list(&v).map_err(|e| format!("invalid PGVPD_STARTUP_PARAMS '{v}' ({e})"))?;
+    }
+    if let Ok(v) = std::env::var("PGVPD_STARTUP_PARAM_RENAMES") {
+        config.startup_param_renames = parse_pair_list(&v)
+            .map_err(|e| format!("invalid PGVPD_STARTUP_PARAM_RENAMES '{v}' ({e})"))?;
+    }
+    if let Ok(v) = std::env::var("PGVPD_INJECT_COMMENT_TEMPLATE") {
+        config.inject_comment_template = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_INJECT_CONN_ID") {
+        config.inject_conn_id = matches!(v.as_str(), "true" | "1" | "yes");
+    }
+    if let Ok(v) = std::env::var("PGVPD_INJECT_CONN_ID_VAR") {
+        config.inject_conn_id_var = v;
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_INJECT_TENANT_LABEL") {
+        config.pool_inject_tenant_label = !matches!(v.as_str(), "false" | "0" | "no");
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_TENANT_LABEL_VAR") {
+        config.pool_tenant_label_var = v;
+    }
+    if let Ok(v) = std::env::var("PGVPD_SEND_CONTEXT_NOTICE") {
+        config.send_context_notice = matches!(v.as_str(), "true" | "1" | "yes");
+    }
+    if let Ok(v) = std::env::var("PGVPD_APPLICATION_NAME_SUFFIX") {
+        config.application_name_suffix = v;
+    }
+    if let Ok(v) = std::env::var("PGVPD_REWRITE_APPLICATION_NAME") {
+        config.rewrite_application_name = matches!(v.as_str(), "true" | "1" | "yes");
+    }
+    if let Ok(v) = std::env::var("PGVPD_PROTOCOL_NEGOTIATE_PASSTHROUGH") {
+        config.protocol_negotiate_passthrough = !matches!(v.as_str(), "false" | "0" | "no");
+    }
+    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_PASSWORD") {
+        config.upstream_password = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_AUTH_METHOD") {
+        config.upstream_auth_method = parse_upstream_auth_method(&v)
+            .map_err(|e| format!("invalid PGVPD_UPSTREAM_AUTH_METHOD '{v}' ({e})"))?;
+    }
+    if let Ok(v) = std::env::var("PGVPD_SCRAM_MIN_ITERATIONS")
+        && let Ok(n) = v.parse()
+    {
+        config.scram_min_iterations = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_SCRAM_MAX_ITERATIONS")
+        && let Ok(n) = v.parse()
+    {
+        config.scram_max_iterations = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_UPSTREAM_USER") {
+        config.pool_upstream_user = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_JWT_CONTEXT") {
+        config.pool_jwt_context = matches!(v.as_str(), "true" | "1" | "yes");
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_JWT_CLAIMS") {
+        config.pool_jwt_claims = parse_jwt_claims(&v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_JWT_SECRET") {
+        config.pool_jwt_secret = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_IDLE_TIMEOUT")
+        && let Ok(t) = v.parse()
+    {
+        config.pool_idle_timeout = t;
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_KEEPALIVE_SECS")
+        && let Ok(t) = v.parse()
+    {
+        config.pool_keepalive_secs = t;
+    }
+    if let Ok(v) = std::env::var("PGVPD_SCRAM_CACHE_MAX_ENTRIES")
+        && let Ok(n) = v.parse()
+    {
+        config.scram_cache_max_entries = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_CHECKOUT_TIMEOUT")
+        && let Ok(t) = v.parse()
+    {
+        config.pool_checkout_timeout = t;
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_RESET_QUERY") {
+        config.pool_reset_query = parse_pool_reset(&v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_HEALTH_CHECK_QUERY") {
+        config.pool_health_check_query = v;
+    }
+    if let Ok(v) = std::env::var("PGVPD_POOL_UPSTREAM_MIN_VERSION")
+        && let Ok(n) = v.parse()
+    {
+        config.pool_upstream_min_version = Some(n);
+    }
+    if let Ok(v) = std::env::var("PGVPD_RESOLVERS") {
+        config.resolvers = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_ADMIN_PORT")
+        && let Ok(p) = v.parse()
+    {
+        config.admin_port = Some(p);
+    }
+    if let Ok(v) = std::env::var("PGVPD_ADMIN_HOST") {
+        config.admin_host = v;
+    }
+    if let Ok(v) = std::env::var("PGVPD_ADMIN_RATE_LIMIT_RPS")
+        && let Ok(n) = v.parse()
+    {
+        config.admin_rate_limit_rps = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_ADMIN_CORS_ORIGINS") {
+        config.admin_cors_origins = parse_origins(&v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_ADMIN_CORS_ALLOW_ALL") {
+        config.admin_cors_allow_all = matches!(v.as_str(), "true" | "1" | "yes");
+    }
+    if let Ok(v) = std::env::var("PGVPD_OTLP_ENDPOINT") {
+        config.otlp_endpoint = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_OTLP_EXPORT_INTERVAL_SECS")
+        && let Ok(n) = v.parse()
+    {
+        config.otlp_export_interval_secs = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_STATSD_HOST") {
+        config.statsd_host = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_STATSD_PORT")
+        && let Ok(p) = v.parse()
+    {
+        config.statsd_port = Some(p);
+    }
+    if let Ok(v) = std::env::var("PGVPD_STATSD_FLUSH_INTERVAL_MS")
+        && let Ok(n) = v.parse()
+    {
+        config.statsd_flush_interval_ms = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_STATSD_PREFIX") {
+        config.statsd_prefix = v;
+    }
+    if let Ok(v) = std::env::var("PGVPD_SET_ROLE") {
+        config.set_role = Some(v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_REQUIRE_ROLE_EXISTS") {
+        config.require_role_exists = matches!(v.as_str(), "true" | "1" | "yes");
+    }
+    if let Ok(v) = std::env::var("PGVPD_FORBIDDEN_ROLES") {
+        config.forbidden_roles = parse_roles(&v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_TENANT_ALLOW") {
+        config.tenant_allow = Some(v.split(',').map(|s| s.trim().to_string()).collect());
+    }
+    if let Ok(v) = std::env::var("PGVPD_TENANT_DENY") {
+        config.tenant_deny = Some(v.split(',').map(|s| s.trim().to_string()).collect());
+    }
+    if let Ok(v) = std::env::var("PGVPD_TENANT_ALLOWLIST_ONLY") {
+        config.tenant_allowlist_only = matches!(v.as_str(), "true" | "1" | "yes");
+    }
+    if let Ok(v) = std::env::var("PGVPD_TENANT_MAX_CONNECTIONS")
+        && let Ok(n) = v.parse()
+    {
+        config.tenant_max_connections = Some(n);
+    }
+    if let Ok(v) = std::env::var("PGVPD_TENANT_MAX_CONNECTIONS_MAP") {
+        config.tenant_max_connections_map = parse_tenant_limits(&v);
+    }
+    if let Ok(v) = std::env::var("PGVPD_TENANT_RATE_LIMIT")
+        && let Ok(n) = v.parse()
+    {
+        config.tenant_rate_limit = Some(n);
+    }
+    if let Ok(v) = std::env::var("PGVPD_TENANT_RATE_LIMIT_WINDOW_SECS")
+        && let Ok(n) = v.parse()
+    {
+        config.tenant_rate_limit_window_secs = n;
+    }
+    if let Ok(v) = std::env::var("PGVPD_TENANT_QUERY_TIMEOUT")
+        && let Ok(n) = v.parse()
+    {
+        config.tenant_query_timeout = Some(n);
+    }
+    Ok(())
+}
+
+fn parse_pool_mode(value: &str) -> PoolMode {
+    match value.trim().to_lowercase().as_str() {
+        "session" => PoolMode::Session,
+        _ => PoolMode::None,
+    }
+}
+
+/// Parse a `name=value` pair, trimming both sides. Returns `None` if there is no
+/// `=` or the name is empty.
+fn parse_pair(value: &str) -> Option<(String, String)> {
+    let (name, val) = value.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    Some((name.to_string(), val.trim().to_string()))
+}
+
+/// Parse `;`-separated `name=value` pairs. Empty entries are skipped; the whole
+/// list is rejected if any other entry is malformed.
+fn parse_pair_list(value: &str) -> Result<Vec<(String, String)>, String> {
+    value
+        .split(';')
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| parse_pair(s).ok_or_else(|| format!("expected name=value, got '{s}'")))
+        .collect()
+}
+
+/// Parse `tenant=max` pairs separated by commas; malformed entries are skipped.
+fn parse_tenant_limits(value: &str) -> HashMap<String, u32> {
+    value
+        .split(',')
+        .filter_map(parse_pair)
+        .filter_map(|(tenant, max)| Some((tenant, max.parse().ok()?)))
+        .collect()
+}
+
+/// Parse a comma-separated list of CIDR ranges. A bare address is treated as
+/// a single-host range. The whole list is rejected if any entry is invalid.
+fn parse_cidr_list(value: &str) -> Result<Vec<IpNetwork>, String> {
+    value
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<IpNetwork>()
+                .map_err(|e| format!("invalid CIDR '{s}': {e}"))
+        })
+        .collect()
+}
+
+/// Whether `name` is safe to use unquoted in `SET <name> = ...`:
+/// `^[a-zA-Z_][a-zA-Z0-9_.]*$` (qualified GUCs are `category.name`).
+fn is_valid_guc_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Compile `tenant_separator_regex`, requiring the `user` and `context` groups.
+fn compile_tenant_regex(pattern: &str) -> Result<Regex, String> {
+    let re = Regex::new(pattern).map_err(|e| format!("invalid tenant_separator_regex: {e}"))?;
+    for group in ["user", "context"] {
+        if !re.capture_names().any(|name| name == Some(group)) {
+            return Err(format!(
+                "tenant_separator_regex must have a named group '{group}'"
+            ));
+        }
+    }
+    Ok(re)
+}
+
+/// An IP address, or a hostname made of 1–63 character alphanumeric/hyphen labels.
+fn is_valid_host(host: &str) -> bool {
+    if host.parse::<IpAddr>().is_ok() {
+        return true;
+    }
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// A role is either an unquoted identifier (`^[a-zA-Z_][a-zA-Z0-9_]*$`) or a
+/// double-quoted identifier with any embedded quotes doubled.
+fn is_valid_role_name(name: &str) -> bool {
+    if let Some(inner) = name
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+    {
+        return !inner.is_empty()
+            && !inner.contains('\0')
+            && !inner.replace("\"\"", "").contains('"');
+    }
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `user` and `database` are rewritten by pgvpd itself and may not be overridden.
+fn is_reserved_startup_param(name: &str) -> bool {
+    name.eq_ignore_ascii_case("user") || name.eq_ignore_ascii_case("database")
+}
+
+fn parse_host_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn parse_routing_mode(value: &str) -> RoutingMode {
+    match value.trim().to_lowercase().as_str() {
+        "replica_reads" => RoutingMode::ReplicaReads,
+        _ => RoutingMode::PrimaryOnly,
+    }
+}
+
+fn parse_log_format(value: &str) -> LogFormat {
+    match value.trim().to_lowercase().as_str() {
+        "json" => LogFormat::Json,
+        _ => LogFormat::Text,
+    }
+}
+
+fn parse_log_rotation(value: &str) -> Result<LogRotation, &'static str> {
+    match value.trim().to_lowercase().as_str() {
+        "never" => Ok(LogRotation::Never),
+        "minutely" => Ok(LogRotation::Minutely),
+        "hourly" => Ok(LogRotation::Hourly),
+        "daily" => Ok(LogRotation::Daily),
+        _ => Err("expected never, minutely, hourly or daily"),
+    }
+}
+
+/// Parse `claim=var, claim2=var2`. An entry without `=` keeps its name on
+/// both sides so validation can report it.
+fn parse_jwt_claims(value: &str) -> Vec<(String, String)> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((claim, var)) => (claim.trim().to_string(), var.trim().to_string()),
+            None => (entry.to_string(), entry.to_string()),
+        })
+        .collect()
+}
+
+/// `reset_role` and `none` are keywords; anything else is SQL to run on checkout.
+fn parse_pool_reset(value: &str) -> PoolReset {
+    match value.trim().to_lowercase().as_str() {
+        "reset_role" => PoolReset::ResetRole,
+        "none" => PoolReset::Skip,
+        _ => PoolReset::Query(value.trim().to_string()),
+    }
+}
+
+fn parse_pool_auth_method(value: &str) -> Result<PoolAuthMethod, &'static str> {
+    match value.trim().to_lowercase().as_str() {
+        "cleartext" | "password" => Ok(PoolAuthMethod::Cleartext),
+        "md5" => Ok(PoolAuthMethod::Md5),
+        _ => Err("expected cleartext or md5"),
+    }
+}
+
+fn parse_proxy_protocol_mode(value: &str) -> Result<ProxyProtocolMode, &'static str> {
+    match value.trim().to_lowercase().as_str() {
+        "off" => Ok(ProxyProtocolMode::Off),
+        "required" => Ok(ProxyProtocolMode::Required),
+        "detect" => Ok(ProxyProtocolMode::Detect),
+        _ => Err("expected off, required or detect"),
+    }
+}
+
+fn parse_upstream_auth_method(value: &str) -> Result<UpstreamAuthMethod, &'static str> {
+    match value.trim().to_lowercase().as_str() {
+        "auto" => Ok(UpstreamAuthMethod::Auto),
+        "cleartext" | "password" => Ok(UpstreamAuthMethod::Cleartext),
+        "md5" => Ok(UpstreamAuthMethod::Md5),
+        "scram-sha-256" => Ok(UpstreamAuthMethod::ScramSha256),
+        "scram-sha-256-plus" => Ok(UpstreamAuthMethod::ScramSha256Plus),
+        _ => Err("expected auto, cleartext, md5, scram-sha-256 or scram-sha-256-plus"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ─── Config file parsing ─────────────────────────────────────────────
+
+    #[test]
+    fn parse_basic_config_file() {
+        let mut config = Config::default();
+        apply_config_file(
+            &mut config,
+            r#"
+port = 7777
+upstream_host = db.example.com
+upstream_port = 5433
+log_level = debug
+"#,
+        );
+        assert_eq!(config.listen_port, 7777);
+        assert_eq!(config.upstream_host, "db.example.com");
+        assert_eq!(config.upstream_port, 5433);
+        assert_eq!(config.log_level, "debug");
+    }
+
+    #[test]
+    fn parse_quoted_values() {
+        let mut config = Config::default();
+        apply_config_file(
+            &mut config,
+            r#"
+upstream_host = "db.example.com"
+pool_password = 'my secret'
+"#,
+        );
+        assert_eq!(config.upstream_host, "db.example.com");
+        assert_eq!(config.pool_password, Some("my secret".into()));
+    }
+
+    #[test]
+    fn comments_and_blank_lines_ignored() {
+        let mut config = Config::default();
+        apply_config_file(
+            &mut config,
+            r#"
+# This is a comment
+port = 9999
+
+  # Another comment
+upstream_port = 5433
+"#,
+        );
+        assert_eq!(config.listen_port, 9999);
+        assert_eq!(config.upstream_port, 5433);
+    }
+
+    #[test]
+    fn unknown_keys_ignored() {
+        let mut config = Config::default();
+        let warnings = apply_config_file(&mut config, "unknown_key = some_value\nport = 8888\n");
+        assert_eq!(config.listen_port, 8888);
+        assert_eq!(
+            warnings,
+            [ConfigWarning::new(
+                1,
+                "unknown_key",
+                "unknown config key 'unknown_key'".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn lines_without_equals_ignored() {
+        let mut config = Config::default();
+        let warnings = apply_config_file(&mut config, "no equals sign here\nport = 8888\n");
+        assert_eq!(config.listen_port, 8888);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].line, 1);
+        assert!(warnings[0].message.contains("expected 'key = value'"));
+    }
+
+    #[test]
+    fn invalid_values_warn_and_keep_default() {
+        let mut config = Config::default();
+        let warnings = apply_config_file(
+            &mut config,
+            "# comment\npool_size = lots\nupstream_tls = maybe\nlisten_ipv6 = yes\n",
+        );
+        assert_eq!(config.pool_size, Config::default().pool_size);
+        assert!(!config.upstream_tls);
+        assert!(config.listen_ipv6);
+        let found: Vec<_> = warnings
+            .iter()
+            .map(|w| (w.line, w.key.as_str(), w.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    2,
+                    "pool_size",
+                    "invalid value 'lots' for 'pool_size', using default"
+                ),
+                (
+                    3,
+                    "upstream_tls",
+                    "invalid value 'maybe' for 'upstream_tls' (expected true or false), using default"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_sections_and_block_keys_warn() {
+        let mut config = Config::default();
+        let warnings = apply_config_file(
+            &mut config,
+            "[tenant_limitz]\nacme = 5\n[[superuser_bypass]]\nuser = admin\ndatabase = app\n",
+        );
+        let found: Vec<_> = warnings.iter().map(|w| (w.line, w.key.as_str())).collect();
+        assert_eq!(found, [(1, "tenant_limitz"), (5, "database")]);
+        assert_eq!(warnings[1].message, "unknown config key 'database'");
+    }
+
+    #[test]
+    fn warnings_name_the_file_they_came_from() {
+        let dir = include_dir("warnings");
+        fs::write(dir.join("extra.conf"), "port = 7000\npool_sise = 5\n").unwrap();
+        fs::write(dir.join("extra.yaml"), "bogus: 1\n").unwrap();
+        let main = dir.join("pgvpd.conf");
+        fs::write(
+            &main,
+            "include = extra.conf\ninclude = extra.yaml\nadmin_port = none\n",
+        )
+        .unwrap();
+
+        let mut config = Config::default();
+        let warnings = load_config_file(&mut config, &main).unwrap();
+        let found: Vec<_> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            found,
+            [
+                format!(
+                    "{}:2: unknown config key 'pool_sise'",
+                    dir.join("extra.conf").display()
+                ),
+                // YAML is translated before parsing, so there is no line
+                format!(
+                    "{}: unknown config key 'bogus'",
+                    dir.join("extra.yaml").display()
+                ),
+                format!(
+                    "{}:3: invalid value 'none' for 'admin_port', using default",
+                    main.display()
+                ),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_context_variables() {
+        let mut config = Config::default();
+        apply_config_file(
+            &mut config,
+            "context_variables = app.tenant_id, app.user_id, app.role\n",
+        );
+        assert_eq!(
+            config.context_variables,
+            vec!["app.tenant_id", "app.user_id", "app.role"]
+        );
+    }
+
+    #[test]
+    fn pool_mode_parsing() {
+        let mut config = Config::default();
+        apply_config_file(&mut config, "pool_mode = session\n");
+        assert_eq!(config.pool_mode, PoolMode::Session);
+
+        let mut config = Config::default();
+        apply_config_file(&mut config, "pool_mode = none\n");
+        assert_eq!(config.pool_mode, PoolMode::None);
+
+        let mut config = Config::default();
+        apply_config_file(&mut config, "pool_mode = garbage\n");
+        assert_eq!(config.pool_mode, PoolMode::None);
+    }
+
+    #[test]
+    fn tls_config_from_file() {
+        let mut config = Config::default();
+        apply_config_file(
+            &mut config,
+            "tls_port = 6433\ntls_cert = /path/to/cert.pem\ntls_key = /path/to/key.pem\n",
+        );
+        assert_eq!(config.tls_port, Some(6433));
+        assert_eq!(config.tls_cert, Some("/path/to/cert.pem".into()));
+        assert_eq!(config.tls_key, Some("/path/to/key.pem".into()));
+
+        let mut config = Config::default();
+        apply_config_file(
+            &mut config,
+            "tls_cipher_suites = TLS13_AES_256_GCM_SHA384, TLS13_CHACHA20_POLY1305_SHA256\ntls_min_version = 1.3\n",
+        );
+        assert_eq!(
+            config.tls_cipher_suites,
+            Some(vec![
+                "TLS13_AES_256_GCM_SHA384".into(),
+                "TLS13_CHACHA20_POLY1305_SHA256".into()
+            ])
+        );
+        assert_eq!(config.tls_min_version, Some("1.3".into()));
+    }
+
+    #[test]
+    fn upstream_tls_booleans() {
+        let mut config = Config::default();
+        apply_config_file(&mut config, "upstream_tls = true\n");
+        assert!(config.upstream_tls);
+
+        let mut config = Config::default();
+        apply_config_file(&mut config, "upstream_tls = yes\n");
+        assert!(config.upstream_tls);
+
+        let mut config = Config::default();
+        apply_config_file(&mut config, "upstream_tls = 1\n");
+        assert!(config.upstream_tls);
+
+        let mut config = Config::default();
+        apply_config_file(&mut config, "upstream_tls = false\n");
+        assert!(!config.upstream_tls);
+
+        // upstream_tls_verify defaults to true; setting false flips it
+        let mut config = Config::default();
+        apply_config_file(&mut config, "upstream_tls_verify = false\n");
+        assert!(!config.upstream_tls_verify);
+
+        let mut config = Config::default();
+        apply_config_file(&mut config, "upstream_tls_verify = no\n");
+        assert!(!config.upstream_tls_verify);
+    }
+
+    #[test]
+    fn tenant_lists_from_file() {
+        let mut config = Config::default();
+        apply_config_file(&mut config, "tenant_allow = alpha, beta, gamma\n");
+        assert_eq!(
+            config.tenant_allow,
+            Some(vec!["alpha".into(), "beta".into(), "gamma".into()])
+        );
+
+        let mut config = Config::default();
+        apply_config_file(&mut config, "tenant_deny = bad_tenant\n");
+        assert_eq!(config.tenant_deny, Some(vec!["bad_tenant".into()]));
+    }
+
+    #[test]
+    fn admin_cors_settings() {
+        let mut config = Config::default();
+        assert!(config.admin_cors_origins.is_empty());
+        apply_config_file(
+            &mut config,
+            "admin_cors_origins = https://dash.example.com/, http://localhost:3000,\nadmin_cors_allow_all = yes\n",
+        );
+        assert_eq!(
+            config.admin_cors_origins,
+            vec!["https://dash.example.com", "http://localhost:3000"]
+        );
+        assert!(config.admin_cors_allow_all);
+    }
+
+    // ─── Config file search ──────────────────────────────────────────────
+
+    #[test]
+    fn config_search_takes_first_existing_file() {
+        let dir = include_dir("search");
+        let first = dir.join("first.conf");
+        let second = dir.join("second.conf");
+        let missing = dir.join("missing.conf");
+        fs::write(&second, "port = 7000\n").unwrap();
+        let paths = [
+            missing.to_str().unwrap(),
+            second.to_str().unwrap(),
+            first.to_str().unwrap(),
+        ];
+        assert_eq!(
+            config_file_path(None, &paths).unwrap(),
+            Some(second.clone())
+        );
+
+        // An earlier path wins once it exists
+        fs::write(&first, "port = 7001\n").unwrap();
+        let paths = [first.to_str().unwrap(), second.to_str().unwrap()];
+        assert_eq!(config_file_path(None, &paths).unwrap(), Some(first));
+
+        assert_eq!(
+            config_file_path(None, &[missing.to_str().unwrap()]).unwrap(),
+            None
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn explicit_config_path_skips_search() {
+        let dir = include_dir("explicit");
+        let searched = dir.join("pgvpd.conf");
+        let explicit = dir.join("custom.conf");
+        fs::write(&searched, "port = 7000\n").unwrap();
+        let paths = [searched.to_str().unwrap()];
+
+        let err = config_file_path(Some(explicit.to_str().unwrap()), &paths).unwrap_err();
+        assert!(err.contains("config file not found"), "{err}");
+
+        fs::write(&explicit, "port = 7001\n").unwrap();
+        assert_eq!(
+            config_file_path(Some(explicit.to_str().unwrap()), &paths).unwrap(),
+            Some(explicit)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // ─── YAML config files ───────────────────────────────────────────────
+
+    #[test]
+    fn yaml_config_file() {
+        let dir = include_dir("yaml");
+        let main = dir.join("pgvpd.yaml");
+        fs::write(
+            &main,
+            r#"
+port: 7000
+upstream_host: db.internal
+context_variables:
+  - app.current_tenant_id
+  - app.region
+superuser_bypass: [postgres, admin]
+pool_mode: session
+pool:
+  size: 40
+  idle_timeout: 120
+  checkout_timeout: 3
+tls:
+  port: 6433
+  cert: /etc/pgvpd/cert.pem
+  key: /etc/pgvpd/key.pem
+tenant:
+  deny: [blocked]
+  max_connections: 25
+  allowlist_only: false
+tenant_limits:
+  acme: 200
+database_resolver:
+  - database: billing
+    resolvers: /etc/pgvpd/billing.toml
+log_level: ~
+"#,
+        )
+        .unwrap();
+
+        let mut config = Config::default();
+        load_config_file(&mut config, &main).unwrap();
+        assert_eq!(config.listen_port, 7000);
+        assert_eq!(config.upstream_host, "db.internal");
+        assert_eq!(
+            config.context_variables,
+            vec!["app.current_tenant_id", "app.region"]
+        );
+        assert_eq!(
+            config.superuser_bypass,
+            SuperuserBypassRule::from_users("postgres, admin")
+        );
+        assert_eq!(config.pool_mode, PoolMode::Session);
+        assert_eq!(config.pool_size, 40);
+        assert_eq!(config.pool_idle_timeout, 120);
+        assert_eq!(config.pool_checkout_timeout, 3);
+        assert_eq!(config.tls_port, Some(6433));
+        assert_eq!(config.tls_cert.as_deref(), Some("/etc/pgvpd/cert.pem"));
+        assert_eq!(config.tls_key.as_deref(), Some("/etc/pgvpd/key.pem"));
+        assert_eq!(config.tenant_deny, Some(vec!["blocked".into()]));
+        assert_eq!(config.tenant_max_connections, Some(25));
+        assert!(!config.tenant_allowlist_only);
+        assert_eq!(config.tenant_max_connections_map["acme"], 200);
+        assert_eq!(config.database_resolvers[0].database, "billing");
+        // Null values leave the default
+        assert_eq!(config.log_level, Config::default().log_level);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn yaml_config_errors() {
+        assert!(
+            yaml_to_conf("port: [1, [2]]")
+                .unwrap_err()
+                .contains("'port'")
+        );
+        assert!(yaml_to_conf("- not a mapping").is_err());
+        assert!(
+            yaml_to_conf("pool:\n  size:\n    nested: 1")
+                .unwrap_err()
+                .contains("pool_size")
+        );
+        assert_eq!(yaml_to_conf("").unwrap(), "");
+    }
+
+    // ─── include directive ───────────────────────────────────────────────
+
+    /// Fresh scratch directory for include tests.
+    fn include_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pgvpd-include-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn include_basic() {
+        let dir = include_dir("basic");
+        let secrets = dir.join("secrets.conf");
+        fs::write(&secrets, "pool_password = s3cret\n").unwrap();
+        let main = dir.join("pgvpd.conf");
+        fs::write(
+            &main,
+            format!("port = 7000\ninclude = \"{}\"\n", secrets.display()),
+        )
+        .unwrap();
+
+        let mut config = Config::default();
+        load_config_file(&mut config, &main).unwrap();
+        assert_eq!(config.listen_port, 7000);
+        assert_eq!(config.pool_password.as_deref(), Some("s3cret"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn include_relative_path_and_override_order() {
+        let dir = include_dir("relative");
+        fs::create_dir_all(dir.join("conf.d")).unwrap();
+        fs::write(dir.join("conf.d/override.conf"), "port = 7001\n").unwrap();
+        let main = dir.join("pgvpd.conf");
+        fs::write(&main, "port = 7000\ninclude = conf.d/override.conf\n").unwrap();
+
+        let mut config = Config::default();
+        load_config_file(&mut config, &main).unwrap();
+        // Later lines win, so the include overrides the earlier value
+        assert_eq!(config.listen_port, 7001);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn include_missing_file_fails() {
+        let dir = include_dir("missing");
+        let main = dir.join("pgvpd.conf");
+        fs::write(&main, "include = nope.conf\n").unwrap();
+
+        let mut config = Config::default();
+        let err = load_config_file(&mut config, &main).unwrap_err();
+        assert!(err.contains("nope.conf"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn include_circular_fails() {
+        let dir = include_dir("circular");
+        fs::write(dir.join("a.conf"), "include = b.conf\n").unwrap();
+        fs::write(dir.join("b.conf"), "include = a.conf\n").unwrap();
+
+        let mut config = Config::default();
+        let err = load_config_file(&mut config, &dir.join("a.conf")).unwrap_err();
+        assert!(err.contains("circular"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn include_depth_limit() {
+        let dir = include_dir("depth");
+        // 0.conf includes 1.conf ... includes 6.conf: six levels of nesting
+        for i in 0..6 {
+            fs::write(
+                dir.join(format!("{i}.conf")),
+                format!("include = {}.conf\n", i + 1),
+            )
+            .unwrap();
+        }
+        fs::write(dir.join("6.conf"), "port = 7002\n").unwrap();
+
+        let mut config = Config::default();
+        let err = load_config_file(&mut config, &dir.join("0.conf")).unwrap_err();
+        assert!(err.contains("include depth"));
+
+        // Five levels is fine
+        let mut config = Config::default();
+        load_config_file(&mut config, &dir.join("1.conf")).unwrap();
+        assert_eq!(config.listen_port, 7002);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn all_numeric_fields_parse() {
+        let mut config = Config::default();
+        apply_config_file(
+            &mut config,
+            r#"
+pool_size = 50
+pool_idle_timeout = 600
+pool_checkout_timeout = 10
+pool_reset_query = RESET ALL; DEALLOCATE ALL
+pool_health_check_query = SHOW server_version
+pool_upstream_min_version = 14
+pool_upstream_user = pgvpd_pool
+handshake_timeout = 60
+client_idle_timeout = 900
+audit_log_max_bytes = 1048576
+tenant_max_connections = 100
+tenant_rate_limit = 50
+tenant_rate_limit_window_secs = 10
+tenant_query_timeout = 30
+"#,
+        );
+        assert_eq!(config.pool_size, 50);
+        assert_eq!(config.pool_idle_timeout, 600);
+        assert_eq!(config.pool_checkout_timeout, 10);
+        assert_eq!(
+            config.pool_reset_query,
+            PoolReset::Query("RESET ALL; DEALLOCATE ALL".into())
+        );
+        assert_eq!(config.pool_health_check_query, "SHOW server_version");
+        assert_eq!(config.pool_upstream_min_version, Some(14));
+        assert_eq!(config.pool_upstream_user.as_deref(), Some("pgvpd_pool"));
+        assert_eq!(config.handshake_timeout_secs, 60);
+        assert_eq!(config.client_idle_timeout_secs, 900);
+        assert_eq!(config.audit_log_max_bytes, 1048576);
+        assert_eq!(config.tenant_max_connections, Some(100));
+        assert_eq!(config.tenant_rate_limit, Some(50));
+        assert_eq!(config.tenant_rate_limit_window_secs, 10);
+        assert_eq!(config.tenant_query_timeout, Some(30));
+    }
+
+    #[test]
+    fn invalid_numeric_values_are_ignored() {
+        let mut config = Config::default();
+        apply_config_file(&mut config, "port = not_a_number\n");
+        assert_eq!(config.listen_port, 6432); // stays at default
+    }
+
+    #[test]
+    fn key_aliases() {
+        // "listen_port" and "port" are aliases
+        let mut config = Config::default();
+        apply_config_file(&mut config, "listen_port = 7777\n");
+        assert_eq!(config.listen_port, 7777);
+
+        // "host" and "listen_host" are aliases
+        let mut config = Config::default();
+        apply_config_file(&mut config, "host = 0.0.0.0\n");
+        assert_eq!(config.listen_host, "0.0.0.0");
+
+        // "separator" and "tenant_separator" are aliases
+        let mut config = Config::default();
+        apply_config_file(&mut config, "separator = +\n");
+        assert_eq!(config.tenant_separator, "+");
+
+        // "superuser" and "superuser_bypass" are aliases
+        let mut config = Config::default();
+        apply_config_file(&mut config, "superuser = admin, root\n");
+        assert_eq!(
+            config.superuser_bypass,
+            SuperuserBypassRule::from_users("admin,root")
+        );
+    }
+
+    // ─── Env var overrides ───────────────────────────────────────────────
+
+    #[test]
+    fn env_var_overrides() {
+        // Set an env var, apply it, check it took effect
+        let mut config = Config::default();
+        apply_config_file(&mut config, "port = 7777\n");
+        assert_eq!(config.listen_port, 7777);
+
+        // Env var should override config file
+        // SAFETY: test runs single-threaded (cargo test default), no concurrent env access
+        unsafe { std::env::set_var("PGVPD_PORT", "8888") };
+        apply_env(&mut config).unwrap();
+        assert_eq!(config.listen_port, 8888);
+        unsafe { std::env::remove_var("PGVPD_PORT") };
+    }
+
+    #[test]
+    fn env_var_tenant_settings() {
+        let mut config = Config::default();
+        // SAFETY: test runs single-threaded (cargo test default), no concurrent env access
+        unsafe { std::env::set_var("PGVPD_TENANT_ALLOW", "t1,t2,t3") };
+        apply_env(&mut config).unwrap();
+        assert_eq!(
+            config.tenant_allow,
+            Some(vec!["t1".into(), "t2".into(), "t3".into()])
+        );
+        unsafe { std::env::remove_var("PGVPD_TENANT_ALLOW") };
+    }
+
+    #[test]
+    fn env_var_invalid_trust_ips_is_an_error() {
+        let mut config = Config::default();
+        // SAFETY: test runs single-threaded (cargo test default), no concurrent env access
+        unsafe { std::env::set_var("PGVPD_TRUST_IPS", "10.0.0.0/8,not-a-cidr") };
+        let err = apply_env(&mut config).unwrap_err();
+        unsafe { std::env::remove_var("PGVPD_TRUST_IPS") };
+        assert!(err.contains("PGVPD_TRUST_IPS"), "{err}");
+        assert!(err.contains("not-a-cidr"), "{err}");
+        assert!(config.trust_ips.is_empty());
+    }
+
+    #[test]
+    fn env_var_invalid_proxy_protocol_trusted_sources_is_an_error() {
+        let mut config = Config::default();
+        // SAFETY: test runs single-threaded (cargo test default), no concurrent env access
+        unsafe { std::env::set_var("PGVPD_PROXY_PROTOCOL_TRUSTED_SOURCES", "10.0.0.0/33") };
+        let err = apply_env(&mut config).unwrap_err();
+        unsafe { std::env::remove_var("PGVPD_PROXY_PROTOCOL_TRUSTED_SOURCES") };
+        assert!(
+            err.contains("PGVPD_PROXY_PROTOCOL_TRUSTED_SOURCES"),
+            "{err}"
+        );
+        assert!(err.contains("10.0.0.0/33"), "{err}");
+    }
+
+    fn expand(value: &str) -> String {
+        expand_env_vars(value, "key", 1, &mut Vec::new())
+    }
+
+    #[test]
+    fn env_var_expansion_in_values() {
+        // SAFETY: test runs single-threaded (cargo test default), no concurrent env access
+        unsafe { std::env::set_var("PGVPD_TEST_DB_PASSWORD", "s3cret") };
+        assert_eq!(expand("${PGVPD_TEST_DB_PASSWORD}"), "s3cret");
+        assert_eq!(
+            expand("pre-${PGVPD_TEST_DB_PASSWORD}-${PGVPD_TEST_DB_PASSWORD}"),
+            "pre-s3cret-s3cret"
+        );
+
+        let mut config = Config::default();
+        apply_config_file(
+            &mut config,
+            "pool_password = \"${PGVPD_TEST_DB_PASSWORD}\"\n",
+        );
+        assert_eq!(config.pool_password.as_deref(), Some("s3cret"));
+        unsafe { std::env::remove_var("PGVPD_TEST_DB_PASSWORD") };
+    }
+
+    #[test]
+    fn env_var_expansion_missing_and_default() {
+        // Unset without a default: left as written
+        assert_eq!(expand("${PGVPD_TEST_UNSET}/x"), "${PGVPD_TEST_UNSET}/x");
+        assert_eq!(expand("${PGVPD_TEST_UNSET:-6433}"), "6433");
+        assert_eq!(expand("${PGVPD_TEST_UNSET:-}"), "");
+        // Unterminated placeholders and plain values pass through
+        assert_eq!(expand("${PGVPD_TEST_UNSET"), "${PGVPD_TEST_UNSET");
+        assert_eq!(expand("a$b{c}"), "a$b{c}");
+
+        // SAFETY: test runs single-threaded (cargo test default), no concurrent env access
+        unsafe { std::env::set_var("PGVPD_TEST_EMPTY", "") };
+        assert_eq!(expand("${PGVPD_TEST_EMPTY:-fallback}"), "fallback");
+        assert_eq!(expand("${PGVPD_TEST_EMPTY}"), "");
+        unsafe { std::env::remove_var("PGVPD_TEST_EMPTY") };
+
+        let mut config = Config::default();
+        apply_config_file(&mut config, "port = ${PGVPD_TEST_UNSET:-6433}\n");
+        assert_eq!(config.listen_port, 6433);
+    }
+
+    #[test]
+    fn env_var_expansion_warns_on_unset_variable() {
+        let mut config = Config::default();
+        let warnings = apply_config_file(
+            &mut config,
+            "port = 6433\npool_password = ${PGVPD_TEST_UNSET_PASSWORD}\n",
+        );
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].line, 2);
+        assert_eq!(warnings[0].key, "pool_password");
+        assert!(warnings[0].message.contains("PGVPD_TEST_UNSET_PASSWORD"));
+        // The placeholder is kept; --strict-config turns the warning fatal
+        assert_eq!(
+            config.pool_password.as_deref(),
+            Some("${PGVPD_TEST_UNSET_PASSWORD}")
+        );
+    }
+
+    // ─── Validation ──────────────────────────────────────────────────────
+
+    #[test]
+    fn validate_default_config_passes() {
+        let config = Config::default();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_max_startup_message_bytes_range() {
+        let mut config = Config::default();
+        apply_config_file(&mut config, "max_startup_message_bytes = 65536\n");
+        assert_eq!(config.max_startup_message_bytes, 65536);
+        assert!(config.validate().is_ok());
+        config.max_startup_message_bytes = 63;
+        assert!(config.validate().is_err());
+        config.max_startup_message_bytes = 1_048_577;
+        assert!(config.validate().is_err());
     }
-    if let Ok(v) = std::env::var("PGVPD_SUPERUSER_BYPASS") {
-        config.superuser_bypass = v.split(',').map(|s| s.trim().to_string()).collect();
+
+    #[test]
+    fn validate_upstream_tls_pin() {
+        let mut config = Config::default();
+        apply_config_file(
+            &mut config,
+            "upstream_tls_pin_pubkey = 17oGZDtiduoVaCbZ9A0iIPq6QfYvhKGOATvaCkogqGI=\n",
+        );
+        assert!(config.validate().is_ok());
+        config.upstream_tls_pin_pubkey = Some("not-a-pin".into());
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("upstream_tls_pin_pubkey")
+        );
     }
-    if let Ok(v) = std::env::var("PGVPD_LOG_LEVEL") {
-        config.log_level = v;
+
+    // ─── Identifier validation ───────────────────────────────────────────
+
+    #[test]
+    fn guc_name_valid() {
+        assert!(is_valid_guc_name("app.current_tenant_id"));
+        assert!(is_valid_guc_name("_private.x1"));
+        assert!(is_valid_guc_name("search_path"));
     }
-    if let Ok(v) = std::env::var("PGVPD_TLS_PORT")
-        && let Ok(p) = v.parse()
-    {
-        config.tls_port = Some(p);
+
+    #[test]
+    fn guc_name_invalid() {
+        assert!(!is_valid_guc_name(""));
+        assert!(!is_valid_guc_name("app current tenant id"));
+        assert!(!is_valid_guc_name("1app.tenant"));
+        assert!(!is_valid_guc_name("app.tenant; DROP TABLE t"));
+        assert!(!is_valid_guc_name("app-tenant"));
     }
-    if let Ok(v) = std::env::var("PGVPD_TLS_CERT") {
-        config.tls_cert = Some(v);
+
+    #[test]
+    fn validate_rejects_bad_context_variable() {
+        let mut config = Config::default();
+        config.context_variables = vec!["app.ok".into(), "app bad".into()];
+        let err = config.validate().unwrap_err();
+        assert!(err.contains("'app bad'"));
     }
-    if let Ok(v) = std::env::var("PGVPD_TLS_KEY") {
-        config.tls_key = Some(v);
+
+    #[test]
+    fn role_name_validation() {
+        assert!(is_valid_role_name("authenticated"));
+        assert!(is_valid_role_name("_role1"));
+        assert!(is_valid_role_name("\"my-role\""));
+        assert!(is_valid_role_name("\"say \"\"hi\"\"\""));
+        assert!(!is_valid_role_name("my-role"));
+        assert!(!is_valid_role_name("1role"));
+        assert!(!is_valid_role_name("\"\""));
+        assert!(!is_valid_role_name("\"a\"; DROP ROLE x; \"\""));
     }
-    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_TLS") {
-        config.upstream_tls = matches!(v.as_str(), "true" | "1" | "yes");
+
+    // ─── Tenant separator regex ──────────────────────────────────────────
+
+    #[test]
+    fn split_username_literal_separator() {
+        let config = Config::default();
+        assert_eq!(
+            config.split_username("app_user.acme.eu"),
+            Some(("app_user", "acme.eu"))
+        );
+        assert_eq!(config.split_username("app_user"), None);
     }
-    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_TLS_VERIFY") {
-        config.upstream_tls_verify = !matches!(v.as_str(), "false" | "0" | "no");
+
+    #[test]
+    fn split_username_regex() {
+        let mut config = Config::default();
+        config.tenant_regex =
+            Some(compile_tenant_regex(r"^(?P<user>[a-z_]+)\+(?P<context>.+)$").unwrap());
+        assert_eq!(
+            config.split_username("alice+tenantA.orgB"),
+            Some(("alice", "tenantA.orgB"))
+        );
+        // Regex overrides the literal separator
+        assert_eq!(config.split_username("app_user.acme"), None);
     }
-    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_TLS_CA") {
-        config.upstream_tls_ca = Some(v);
+
+    #[test]
+    fn tenant_regex_requires_named_groups() {
+        assert!(compile_tenant_regex(r"(?P<user>\w+)\+(?P<context>\w+)").is_ok());
+        let err = compile_tenant_regex(r"(?P<user>\w+)\+(\w+)").unwrap_err();
+        assert!(err.contains("'context'"));
+        let err = compile_tenant_regex(r"(?P<user>\w+").unwrap_err();
+        assert!(err.contains("invalid tenant_separator_regex"));
     }
-    if let Ok(v) = std::env::var("PGVPD_HANDSHAKE_TIMEOUT")
-        && let Ok(t) = v.parse()
-    {
-        config.handshake_timeout_secs = t;
+
+    #[test]
+    fn validate_rejects_bad_tenant_regex() {
+        let mut config = Config::default();
+        config.tenant_separator_regex = Some(r"(?P<user>\w+)@(?P<tenant>\w+)".into());
+        assert!(config.validate().unwrap_err().contains("'context'"));
     }
-    if let Ok(v) = std::env::var("PGVPD_POOL_MODE") {
-        config.pool_mode = parse_pool_mode(&v);
+
+    #[test]
+    fn host_validation() {
+        assert!(is_valid_host("127.0.0.1"));
+        assert!(is_valid_host("::"));
+        assert!(is_valid_host("fd00::1"));
+        assert!(is_valid_host("localhost"));
+        assert!(is_valid_host("admin.internal-net.example"));
+        assert!(!is_valid_host(""));
+        assert!(!is_valid_host("bad host"));
+        assert!(!is_valid_host("-leading.example"));
+        assert!(!is_valid_host("a..b"));
+        assert!(!is_valid_host("10.0.0.1:9090"));
     }
-    if let Ok(v) = std::env::var("PGVPD_POOL_SIZE")
-        && let Ok(n) = v.parse()
-    {
-        config.pool_size = n;
+
+    #[test]
+    fn validate_admin_host() {
+        let mut config = Config::default();
+        assert_eq!(config.admin_host, "127.0.0.1");
+        assert!(!config.admin_host_is_wildcard());
+        config.admin_host = "0.0.0.0".into();
+        assert!(config.admin_host_is_wildcard());
+        config.admin_port = Some(9090);
+        // Wildcard binding is allowed; proxy::run warns once at startup
+        assert!(config.validate().is_ok());
+        config.admin_host = "::".into();
+        assert!(config.admin_host_is_wildcard());
+        config.admin_host = "not a host".into();
+        assert!(config.validate().unwrap_err().contains("admin_host"));
     }
-    if let Ok(v) = std::env::var("PGVPD_POOL_PASSWORD") {
-        config.pool_password = Some(v);
+
+    #[test]
+    fn validate_rejects_bad_set_role() {
+        let mut config = Config::default();
+        config.set_role = Some("auth role".into());
+        assert!(config.validate().unwrap_err().contains("set_role"));
     }
-    if let Ok(v) = std::env::var("PGVPD_UPSTREAM_PASSWORD") {
-        config.upstream_password = Some(v);
+
+    #[test]
+    fn role_checks_parse() {
+        let mut config = Config::default();
+        assert!(!config.require_role_exists);
+        assert!(config.forbidden_roles.is_empty());
+        apply_config_file(
+            &mut config,
+            "require_role_exists = true\nforbidden_roles = postgres, rds_superuser,\n",
+        );
+        assert!(config.require_role_exists);
+        assert_eq!(config.forbidden_roles, ["postgres", "rds_superuser"]);
     }
-    if let Ok(v) = std::env::var("PGVPD_POOL_IDLE_TIMEOUT")
-        && let Ok(t) = v.parse()
-    {
-        config.pool_idle_timeout = t;
+
+    #[test]
+    fn target_role_prefers_set_role() {
+        let mut config = Config::default();
+        assert_eq!(config.target_role("app_user"), "app_user");
+        config.set_role = Some("\"my-role\"".into());
+        assert_eq!(config.target_role("app_user"), "my-role");
     }
-    if let Ok(v) = std::env::var("PGVPD_POOL_CHECKOUT_TIMEOUT")
-        && let Ok(t) = v.parse()
-    {
-        config.pool_checkout_timeout = t;
+
+    #[test]
+    fn set_role_ident_quotes_plain_names_only() {
+        let mut config = Config::default();
+        assert_eq!(config.set_role_ident(), None);
+        config.set_role = Some("authenticated".into());
+        assert_eq!(
+            config.set_role_ident().as_deref(),
+            Some("\"authenticated\"")
+        );
+        config.set_role = Some("\"my-role\"".into());
+        assert_eq!(config.set_role_ident().as_deref(), Some("\"my-role\""));
     }
-    if let Ok(v) = std::env::var("PGVPD_RESOLVERS") {
-        config.resolvers = Some(v);
+
+    #[test]
+    fn validate_tls_port_without_cert_fails() {
+        let mut config = Config::default();
+        config.tls_port = Some(6433);
+        assert!(config.validate().is_err());
+        assert!(config.validate().unwrap_err().contains("tls_cert"));
     }
-    if let Ok(v) = std::env::var("PGVPD_ADMIN_PORT")
-        && let Ok(p) = v.parse()
-    {
-        config.admin_port = Some(p);
+
+    #[test]
+    fn validate_tls_port_with_cert_and_key_passes() {
+        let mut config = Config::default();
+        config.tls_port = Some(6433);
+        config.tls_cert = Some("/tmp/cert.pem".into());
+        config.tls_key = Some("/tmp/key.pem".into());
+        assert!(config.validate().is_ok());
     }
-    if let Ok(v) = std::env::var("PGVPD_SET_ROLE") {
-        config.set_role = Some(v);
+
+    #[test]
+    fn validate_tls_port_with_pkcs12_passes() {
+        let mut config = Config::default();
+        config.tls_port = Some(6433);
+        config.tls_cert_pkcs12 = Some("/tmp/server.p12".into());
+        config.tls_cert_pkcs12_password = Some("secret".into());
+        assert!(config.validate().is_ok());
     }
-    if let Ok(v) = std::env::var("PGVPD_TENANT_ALLOW") {
-        config.tenant_allow = Some(v.split(',').map(|s| s.trim().to_string()).collect());
+
+    #[test]
+    fn validate_pkcs12_and_pem_are_exclusive() {
+        let mut config = Config::default();
+        config.tls_port = Some(6433);
+        config.tls_cert = Some("/tmp/cert.pem".into());
+        config.tls_key = Some("/tmp/key.pem".into());
+        config.tls_cert_pkcs12 = Some("/tmp/server.p12".into());
+        assert!(config.validate().is_err());
+        assert!(config.validate().unwrap_err().contains("tls_cert_pkcs12"));
     }
-    if let Ok(v) = std::env::var("PGVPD_TENANT_DENY") {
-        config.tenant_deny = Some(v.split(',').map(|s| s.trim().to_string()).collect());
+
+    #[test]
+    fn validate_unknown_cipher_suite_fails() {
+        let mut config = Config::default();
+        config.tls_cipher_suites = Some(vec!["TLS13_AES_256_GCM_SHA384".into(), "RC4".into()]);
+        assert!(config.validate().is_err());
+        assert!(config.validate().unwrap_err().contains("RC4"));
     }
-    if let Ok(v) = std::env::var("PGVPD_TENANT_MAX_CONNECTIONS")
-        && let Ok(n) = v.parse()
-    {
-        config.tenant_max_connections = Some(n);
+
+    #[test]
+    fn validate_tls_min_version() {
+        let mut config = Config::default();
+        config.tls_min_version = Some("1.3".into());
+        assert!(config.validate().is_ok());
+        config.tls_min_version = Some("1.0".into());
+        assert!(config.validate().unwrap_err().contains("tls_min_version"));
     }
-    if let Ok(v) = std::env::var("PGVPD_TENANT_RATE_LIMIT")
-        && let Ok(n) = v.parse()
-    {
-        config.tenant_rate_limit = Some(n);
+
+    #[test]
+    fn validate_zero_handshake_timeout_fails() {
+        let mut config = Config::default();
+        config.handshake_timeout_secs = 0;
+        assert!(config.validate().is_err());
+        assert!(config.validate().unwrap_err().contains("handshake_timeout"));
     }
-    if let Ok(v) = std::env::var("PGVPD_TENANT_QUERY_TIMEOUT")
-        && let Ok(n) = v.parse()
-    {
-        config.tenant_query_timeout = Some(n);
+
+    #[test]
+    fn validate_session_pool_without_password_fails() {
+        let mut config = Config::default();
+        config.pool_mode = PoolMode::Session;
+        config.upstream_password = Some("pass".into());
+        // Missing pool_password
+        assert!(config.validate().is_err());
+        assert!(config.validate().unwrap_err().contains("pool_password"));
+    }
+
+    #[test]
+    fn validate_session_pool_without_upstream_password_fails() {
+        let mut config = Config::default();
+        config.pool_mode = PoolMode::Session;
+        config.pool_password = Some("pass".into());
+        // Missing upstream_password
+        assert!(config.validate().is_err());
+        assert!(config.validate().unwrap_err().contains("upstream_password"));
+    }
+
+    #[test]
+    fn validate_session_pool_with_zero_pool_size_fails() {
+        let mut config = Config::default();
+        config.pool_mode = PoolMode::Session;
+        config.pool_password = Some("pass".into());
+        config.upstream_password = Some("pass".into());
+        config.pool_size = 0;
+        assert!(config.validate().is_err());
+        assert!(config.validate().unwrap_err().contains("pool_size"));
+    }
+
+    #[test]
+    fn validate_pool_min_size_within_pool_size() {
+        let mut config = Config {
+            pool_mode: PoolMode::Session,
+            pool_password: Some("pass".into()),
+            upstream_password: Some("pass".into()),
+            pool_size: 5,
+            ..Config::default()
+        };
+        apply_config_file(&mut config, "pool_min_size = 5\n");
+        assert_eq!(config.pool_min_size, 5);
+        assert!(config.validate().is_ok());
+        config.pool_min_size = 6;
+        assert!(config.validate().unwrap_err().contains("pool_min_size"));
+    }
+
+    #[test]
+    fn validate_session_pool_fully_configured_passes() {
+        let mut config = Config::default();
+        config.pool_mode = PoolMode::Session;
+        config.pool_password = Some("pass".into());
+        config.upstream_password = Some("pass".into());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_resolvers_file_not_found_fails() {
+        let mut config = Config::default();
+        config.resolvers = Some("/nonexistent/path/resolvers.toml".into());
+        assert!(config.validate().is_err());
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("resolvers file not found")
+        );
+    }
+
+    #[test]
+    fn validate_allowlist_only_with_deny_fails() {
+        let mut config = Config::default();
+        config.tenant_allowlist_only = true;
+        assert!(config.validate().is_ok());
+        assert!(config.has_tenant_limits());
+        config.tenant_deny = Some(vec!["b".into()]);
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("tenant_allowlist_only")
+        );
+    }
+
+    #[test]
+    fn validate_both_allow_and_deny_fails() {
+        let mut config = Config::default();
+        config.tenant_allow = Some(vec!["a".into()]);
+        config.tenant_deny = Some(vec!["b".into()]);
+        assert!(config.validate().is_err());
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("cannot both be set")
+        );
     }
-}
 
-fn parse_pool_mode(value: &str) -> PoolMode {
-    match value.trim().to_lowercase().as_str() {
-        "session" => PoolMode::Session,
-        _ => PoolMode::None,
-    }
-}
+    // ─── has_tenant_limits ───────────────────────────────────────────────
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn restart_required_changes_detected() {
+        let old = Config::default();
+        let mut new = Config::default();
+        assert!(old.restart_required_changes(&new).is_empty());
 
-    // ─── Config file parsing ─────────────────────────────────────────────
+        // Runtime-reloadable settings are not reported
+        new.log_level = "debug".into();
+        new.tenant_deny = Some(vec!["bad".into()]);
+        new.resolvers = Some("resolvers.toml".into());
+        assert!(old.restart_required_changes(&new).is_empty());
+
+        new.listen_port = 7000;
+        new.upstream_host = "db.internal".into();
+        new.tls_key = Some("/tmp/key.pem".into());
+        assert_eq!(
+            old.restart_required_changes(&new),
+            vec!["port", "upstream_host", "tls_cert"]
+        );
+    }
 
     #[test]
-    fn parse_basic_config_file() {
+    fn validate_rejects_bad_tenant_pattern() {
+        let mut config = Config::default();
+        config.tenant_deny = Some(vec!["ok_*".into(), "bad_[".into()]);
+        assert!(config.validate().unwrap_err().contains("'bad_['"));
+        config.tenant_deny = Some(vec!["ok_*".into(), "tenant_[ab]".into()]);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn tenant_limits_section() {
         let mut config = Config::default();
         apply_config_file(
             &mut config,
             r#"
+tenant_max_connections = 10
+
+[tenant_limits]
+acme = 50
+tiny = 2
+broken = lots
+
+[something_else]
 port = 7777
-upstream_host = db.example.com
-upstream_port = 5433
-log_level = debug
 "#,
         );
-        assert_eq!(config.listen_port, 7777);
-        assert_eq!(config.upstream_host, "db.example.com");
-        assert_eq!(config.upstream_port, 5433);
-        assert_eq!(config.log_level, "debug");
+        assert_eq!(config.tenant_max_connections, Some(10));
+        assert_eq!(config.tenant_max_connections_map.len(), 2);
+        assert_eq!(config.tenant_max_connections_map["acme"], 50);
+        assert_eq!(config.tenant_max_connections_map["tiny"], 2);
+        // Keys in unknown sections are not top-level settings
+        assert_eq!(config.listen_port, 6432);
+        assert!(config.has_tenant_limits());
     }
 
     #[test]
-    fn parse_quoted_values() {
+    fn database_resolver_blocks() {
         let mut config = Config::default();
         apply_config_file(
             &mut config,
             r#"
-upstream_host = "db.example.com"
-pool_password = 'my secret'
+resolvers = /etc/pgvpd/resolvers.toml
+
+[[database_resolver]]
+database = "billing"
+resolvers = "/etc/pgvpd/billing.toml"
+
+[[database_resolver]]
+database = analytics
+resolvers = /etc/pgvpd/analytics.toml
 "#,
         );
-        assert_eq!(config.upstream_host, "db.example.com");
-        assert_eq!(config.pool_password, Some("my secret".into()));
+        assert_eq!(
+            config.resolvers.as_deref(),
+            Some("/etc/pgvpd/resolvers.toml")
+        );
+        assert_eq!(
+            config.database_resolvers,
+            vec![
+                DatabaseResolver {
+                    database: "billing".into(),
+                    resolvers: "/etc/pgvpd/billing.toml".into(),
+                },
+                DatabaseResolver {
+                    database: "analytics".into(),
+                    resolvers: "/etc/pgvpd/analytics.toml".into(),
+                },
+            ]
+        );
     }
 
     #[test]
-    fn comments_and_blank_lines_ignored() {
+    fn superuser_bypass_blocks() {
         let mut config = Config::default();
         apply_config_file(
             &mut config,
             r#"
-# This is a comment
-port = 9999
+superuser_bypass = postgres, migrator
 
-  # Another comment
-upstream_port = 5433
+[[superuser_bypass]]
+user = postgres
+databases = postgres, template1
+
+[[superuser_bypass]]
+user = "reporting"
+databases = analytics
 "#,
         );
-        assert_eq!(config.listen_port, 9999);
-        assert_eq!(config.upstream_port, 5433);
-    }
+        assert_eq!(config.superuser_bypass.len(), 4);
+        assert_eq!(
+            config.superuser_bypass[2],
+            SuperuserBypassRule {
+                user: "postgres".into(),
+                databases: vec!["postgres".into(), "template1".into()],
+            }
+        );
+        assert!(config.validate().is_ok());
 
-    #[test]
-    fn unknown_keys_ignored() {
-        let mut config = Config::default();
-        apply_config_file(&mut config, "unknown_key = some_value\nport = 8888\n");
-        assert_eq!(config.listen_port, 8888);
+        // The listed databases narrow postgres' all-database rule
+        assert!(config.is_superuser_bypass("postgres", "postgres"));
+        assert!(config.is_superuser_bypass("postgres", "template1"));
+        assert!(!config.is_superuser_bypass("postgres", "app_db"));
+        // Flat entries still bypass everywhere
+        assert!(config.is_superuser_bypass("migrator", "app_db"));
+        assert!(config.is_superuser_bypass("reporting", "analytics"));
+        assert!(!config.is_superuser_bypass("reporting", "app_db"));
+        assert!(!config.is_superuser_bypass("app_user", "postgres"));
+
+        assert_eq!(
+            config.replication_users(),
+            ["postgres", "migrator", "reporting"]
+        );
+        // Logical replication is held to the same databases as the bypass
+        assert!(config.allows_replication("reporting", "app_db", false));
+        assert!(config.allows_replication("reporting", "analytics", true));
+        assert!(!config.allows_replication("reporting", "app_db", true));
+        assert!(!config.allows_replication("postgres", "app_db", true));
+        assert!(!config.allows_replication("app_user", "app_db", false));
+
+        apply_config_file(&mut config, "[[superuser_bypass]]\ndatabases = app_db\n");
+        assert!(config.validate().unwrap_err().contains("needs a user"));
     }
 
     #[test]
-    fn lines_without_equals_ignored() {
+    fn yaml_superuser_bypass_rules() {
+        let conf = yaml_to_conf(
+            r#"
+superuser_bypass:
+  - migrator
+  - user: postgres
+    databases: [postgres, template1]
+"#,
+        )
+        .unwrap();
         let mut config = Config::default();
-        apply_config_file(&mut config, "no equals sign here\nport = 8888\n");
-        assert_eq!(config.listen_port, 8888);
+        apply_config_file(&mut config, &conf);
+        assert!(config.is_superuser_bypass("migrator", "app_db"));
+        assert!(config.is_superuser_bypass("postgres", "template1"));
+        assert!(!config.is_superuser_bypass("postgres", "app_db"));
     }
 
     #[test]
-    fn csv_context_variables() {
+    fn tls_cert_blocks() {
         let mut config = Config::default();
         apply_config_file(
             &mut config,
-            "context_variables = app.tenant_id, app.user_id, app.role\n",
+            r#"
+tls_port = 6433
+tls_cert = /etc/pgvpd/server.crt
+tls_key = /etc/pgvpd/server.key
+
+[[tls_cert]]
+sni = "*.tenant-a.com"
+cert = /etc/pgvpd/tenant-a.crt
+key = /etc/pgvpd/tenant-a.key
+"#,
         );
+        assert_eq!(config.tls_cert.as_deref(), Some("/etc/pgvpd/server.crt"));
         assert_eq!(
-            config.context_variables,
-            vec!["app.tenant_id", "app.user_id", "app.role"]
+            config.tls_sni_certs,
+            vec![TlsSniCert {
+                sni: "*.tenant-a.com".into(),
+                cert: "/etc/pgvpd/tenant-a.crt".into(),
+                key: "/etc/pgvpd/tenant-a.key".into(),
+            }]
         );
+        assert!(config.validate().is_ok());
+
+        let conf = yaml_to_conf(
+            r#"
+tls_sni_certs:
+  - sni: db.tenant-b.com
+    cert: /etc/pgvpd/tenant-b.crt
+    key: /etc/pgvpd/tenant-b.key
+"#,
+        )
+        .unwrap();
+        let mut yaml = Config::default();
+        apply_config_file(&mut yaml, &conf);
+        assert_eq!(yaml.tls_sni_certs.len(), 1);
+        assert_eq!(yaml.tls_sni_certs[0].sni, "db.tenant-b.com");
     }
 
     #[test]
-    fn pool_mode_parsing() {
+    fn validate_tls_cert_blocks() {
+        let entry = |sni: &str| TlsSniCert {
+            sni: sni.into(),
+            cert: "/etc/pgvpd/a.crt".into(),
+            key: "/etc/pgvpd/a.key".into(),
+        };
+        let mut config = Config {
+            tls_sni_certs: vec![entry("*.tenant-a.com")],
+            ..Config::default()
+        };
+        assert!(config.validate().unwrap_err().contains("requires tls_port"));
+
+        config.tls_port = Some(6433);
+        config.tls_cert = Some("/etc/pgvpd/server.crt".into());
+        config.tls_key = Some("/etc/pgvpd/server.key".into());
+        assert!(config.validate().is_ok());
+
+        for bad in ["*", "a.*.com", "*.", "tenant..com", "**.com"] {
+            config.tls_sni_certs = vec![entry(bad)];
+            assert!(config.validate().unwrap_err().contains("invalid"), "{bad}");
+        }
+
+        config.tls_sni_certs = vec![entry("db.tenant-a.com"), entry("DB.tenant-a.com")];
+        assert!(config.validate().unwrap_err().contains("duplicate"));
+
+        config.tls_sni_certs = vec![TlsSniCert {
+            key: String::new(),
+            ..entry("db.tenant-a.com")
+        }];
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("needs sni, cert and key")
+        );
+    }
+
+    #[test]
+    fn validate_database_resolvers() {
+        let file =
+            std::env::temp_dir().join(format!("pgvpd-db-resolvers-{}.toml", std::process::id()));
+        std::fs::write(&file, "").unwrap();
+        let path = file.to_string_lossy().to_string();
+        let entry = |database: &str, resolvers: &str| DatabaseResolver {
+            database: database.into(),
+            resolvers: resolvers.into(),
+        };
+
         let mut config = Config::default();
-        apply_config_file(&mut config, "pool_mode = session\n");
-        assert_eq!(config.pool_mode, PoolMode::Session);
+        config.database_resolvers = vec![entry("billing", &path)];
+        assert!(config.validate().is_ok());
+
+        config.database_resolvers = vec![entry("billing", "/nonexistent/billing.toml")];
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("resolvers file not found for database 'billing'")
+        );
+
+        config.database_resolvers = vec![entry("billing", &path), entry("billing", &path)];
+        assert!(config.validate().unwrap_err().contains("duplicate"));
+
+        config.database_resolvers = vec![entry("", &path)];
+        assert!(config.validate().is_err());
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[test]
+    fn parse_tenant_limits_pairs() {
+        let map = parse_tenant_limits("acme=50, tiny = 2, bad, nope=x");
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["acme"], 50);
+        assert_eq!(map["tiny"], 2);
+    }
+
+    #[test]
+    fn has_tenant_limits_detection() {
+        let config = Config::default();
+        assert!(!config.has_tenant_limits());
 
         let mut config = Config::default();
-        apply_config_file(&mut config, "pool_mode = none\n");
-        assert_eq!(config.pool_mode, PoolMode::None);
+        config.tenant_allow = Some(vec!["a".into()]);
+        assert!(config.has_tenant_limits());
 
         let mut config = Config::default();
-        apply_config_file(&mut config, "pool_mode = garbage\n");
-        assert_eq!(config.pool_mode, PoolMode::None);
+        config.tenant_deny = Some(vec!["b".into()]);
+        assert!(config.has_tenant_limits());
+
+        let mut config = Config::default();
+        config.tenant_max_connections = Some(10);
+        assert!(config.has_tenant_limits());
+
+        let mut config = Config::default();
+        config.tenant_rate_limit = Some(5);
+        assert!(config.has_tenant_limits());
     }
 
+    // ─── parse_pool_mode ─────────────────────────────────────────────────
+
     #[test]
-    fn tls_config_from_file() {
+    fn pool_mode_case_insensitive() {
+        assert_eq!(parse_pool_mode("Session"), PoolMode::Session);
+        assert_eq!(parse_pool_mode("SESSION"), PoolMode::Session);
+        assert_eq!(parse_pool_mode("  session  "), PoolMode::Session);
+        assert_eq!(parse_pool_mode("none"), PoolMode::None);
+        assert_eq!(parse_pool_mode("anything_else"), PoolMode::None);
+    }
+
+    #[test]
+    fn replica_routing_parsing() {
         let mut config = Config::default();
+        assert_eq!(config.routing_mode, RoutingMode::PrimaryOnly);
         apply_config_file(
             &mut config,
-            "tls_port = 6433\ntls_cert = /path/to/cert.pem\ntls_key = /path/to/key.pem\n",
+            "routing_mode = replica_reads\nupstream_replica_hosts = replica1:5433, replica2\n",
         );
-        assert_eq!(config.tls_port, Some(6433));
-        assert_eq!(config.tls_cert, Some("/path/to/cert.pem".into()));
-        assert_eq!(config.tls_key, Some("/path/to/key.pem".into()));
+        assert_eq!(config.routing_mode, RoutingMode::ReplicaReads);
+        assert_eq!(
+            config.upstream_replica_hosts,
+            vec!["replica1:5433", "replica2"]
+        );
+        assert!(config.validate().is_ok());
     }
 
     #[test]
-    fn upstream_tls_booleans() {
+    fn replica_reads_requires_replicas() {
+        let mut config = Config {
+            routing_mode: RoutingMode::ReplicaReads,
+            ..Config::default()
+        };
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("upstream_replica_hosts")
+        );
+
+        config.upstream_replica_hosts = vec!["replica1:notaport".into()];
+        assert!(config.validate().unwrap_err().contains("host[:port]"));
+    }
+
+    #[test]
+    fn log_output_parsing() {
         let mut config = Config::default();
-        apply_config_file(&mut config, "upstream_tls = true\n");
-        assert!(config.upstream_tls);
+        assert_eq!(config.log_format, LogFormat::Text);
+        assert!(config.log_timestamps);
+        assert_eq!(config.log_rotation, LogRotation::Never);
+        apply_config_file(
+            &mut config,
+            "log_format = json\nlog_timestamps = false\nlog_file = /var/log/pgvpd/pgvpd.log\nlog_rotation = daily\n",
+        );
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert!(!config.log_timestamps);
+        assert_eq!(config.log_file.as_deref(), Some("/var/log/pgvpd/pgvpd.log"));
+        assert_eq!(config.log_rotation, LogRotation::Daily);
+        assert_eq!(parse_log_format("JSON"), LogFormat::Json);
+        assert_eq!(parse_log_format("bogus"), LogFormat::Text);
+        assert_eq!(parse_log_rotation("Hourly"), Ok(LogRotation::Hourly));
+
+        // A typo keeps the previous value and warns
+        let warnings = apply_config_file(&mut config, "log_rotation = weekly\n");
+        assert_eq!(config.log_rotation, LogRotation::Daily);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.contains("expected never, minutely"));
+    }
 
+    #[test]
+    fn inject_conn_id_clauses() {
         let mut config = Config::default();
-        apply_config_file(&mut config, "upstream_tls = yes\n");
-        assert!(config.upstream_tls);
+        assert!(
+            config
+                .conn_id_set_clauses(42, Some(1_700_000_000_000))
+                .is_empty()
+        );
+
+        apply_config_file(&mut config, "inject_conn_id = true\n");
+        assert_eq!(
+            config.conn_id_set_clauses(42, None),
+            vec!["SET app.pgvpd_conn_id = '42'"]
+        );
+        assert_eq!(
+            config.conn_id_set_clauses(42, Some(1_700_000_000_000)),
+            vec![
+                "SET app.pgvpd_conn_id = '42'",
+                "SET app.pgvpd_conn_start = '1700000000000'"
+            ]
+        );
+
+        apply_config_file(&mut config, "inject_conn_id_var = trace.conn\n");
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.conn_id_set_clauses(7, None),
+            vec!["SET trace.conn = '7'"]
+        );
+        config.inject_conn_id_var = "app.id; DROP TABLE t".into();
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("inject_conn_id_var")
+        );
+    }
 
+    #[test]
+    fn tenant_label_clauses() {
         let mut config = Config::default();
-        apply_config_file(&mut config, "upstream_tls = 1\n");
-        assert!(config.upstream_tls);
+        assert_eq!(
+            config.tenant_label_set_clauses("acme", "app_user"),
+            vec![
+                "SET app.pgvpd_tenant = 'acme'",
+                "SET app.pgvpd_role = 'app_user'"
+            ]
+        );
+        // Values are escaped like any other context value
+        assert_eq!(
+            config.tenant_label_set_clauses("o'brien", "app_user")[0],
+            "SET app.pgvpd_tenant = 'o''brien'"
+        );
+
+        apply_config_file(&mut config, "pool_tenant_label_var = mon.tenant\n");
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.tenant_label_set_clauses("acme", "app_user")[0],
+            "SET mon.tenant = 'acme'"
+        );
+        config.pool_tenant_label_var = "bad name".into();
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("pool_tenant_label_var")
+        );
+
+        apply_config_file(&mut config, "pool_inject_tenant_label = false\n");
+        assert!(config.validate().is_ok());
+        assert!(
+            config
+                .tenant_label_set_clauses("acme", "app_user")
+                .is_empty()
+        );
+    }
 
+    #[test]
+    fn pool_jwt_settings() {
         let mut config = Config::default();
-        apply_config_file(&mut config, "upstream_tls = false\n");
-        assert!(!config.upstream_tls);
+        apply_config_file(
+            &mut config,
+            "pool_mode = session\nupstream_password = up\npool_jwt_context = true\n\
+             pool_jwt_claims = tenant_id=app.current_tenant_id, sub = app.user_id\n",
+        );
+        assert!(config.pool_jwt_context);
+        assert_eq!(
+            config.pool_jwt_claims,
+            vec![
+                ("tenant_id".to_string(), "app.current_tenant_id".to_string()),
+                ("sub".to_string(), "app.user_id".to_string()),
+            ]
+        );
+        // No pool_password needed, but the secret is
+        assert!(config.validate().unwrap_err().contains("pool_jwt_secret"));
+        config.pool_jwt_secret = Some("s3cret".into());
+        assert!(config.validate().is_ok());
 
-        // upstream_tls_verify defaults to true; setting false flips it
+        config.pool_auth_method = PoolAuthMethod::Md5;
+        assert!(config.validate().unwrap_err().contains("cleartext"));
+        config.pool_auth_method = PoolAuthMethod::Cleartext;
+
+        config.pool_jwt_claims = parse_jwt_claims("tenant_id");
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("claim=context_variable")
+        );
+    }
+
+    #[test]
+    fn pool_reset_parsing() {
+        let config = Config::default();
+        assert_eq!(
+            config.pool_reset_query.checkout_query(),
+            Some("DISCARD ALL")
+        );
+        assert_eq!(
+            config.pool_reset_query.checkin_query(),
+            Some("RESET ROLE; RESET ALL")
+        );
+
+        assert_eq!(parse_pool_reset("reset_role"), PoolReset::ResetRole);
+        assert_eq!(PoolReset::ResetRole.checkout_query(), None);
+        assert_eq!(PoolReset::ResetRole.checkin_query(), Some("RESET ROLE"));
+        assert_eq!(parse_pool_reset(" NONE "), PoolReset::Skip);
+        assert_eq!(PoolReset::Skip.checkin_query(), None);
+        assert_eq!(
+            parse_pool_reset("DEALLOCATE ALL"),
+            PoolReset::Query("DEALLOCATE ALL".into())
+        );
+
+        let config = Config {
+            pool_mode: PoolMode::Session,
+            pool_password: Some("p".into()),
+            upstream_password: Some("u".into()),
+            pool_reset_query: parse_pool_reset("  "),
+            ..Config::default()
+        };
+        assert!(config.validate().unwrap_err().contains("pool_reset_query"));
+    }
+
+    #[test]
+    fn replication_users_default_to_superuser_bypass() {
         let mut config = Config::default();
-        apply_config_file(&mut config, "upstream_tls_verify = false\n");
-        assert!(!config.upstream_tls_verify);
+        assert_eq!(config.replication_users(), ["postgres"]);
+
+        apply_config_file(
+            &mut config,
+            "superuser_bypass = postgres, replicator\nreplication_passthrough_usernames = replicator\n",
+        );
+        assert_eq!(config.replication_users(), ["replicator"]);
+        assert!(config.validate().is_ok());
 
-        let mut config = Config::default();
-        apply_config_file(&mut config, "upstream_tls_verify = no\n");
-        assert!(!config.upstream_tls_verify);
+        config.replication_passthrough_usernames = Some(vec!["app_user".into()]);
+        assert!(config.validate().unwrap_err().contains("app_user"));
     }
 
     #[test]
-    fn tenant_lists_from_file() {
+    fn otlp_settings() {
         let mut config = Config::default();
-        apply_config_file(&mut config, "tenant_allow = alpha, beta, gamma\n");
+        assert_eq!(config.otlp_endpoint, None);
+        assert_eq!(config.otlp_export_interval_secs, 10);
+        apply_config_file(
+            &mut config,
+            "otlp_endpoint = http://otel-collector:4317\notlp_export_interval_secs = 0\n",
+        );
         assert_eq!(
-            config.tenant_allow,
-            Some(vec!["alpha".into(), "beta".into(), "gamma".into()])
+            config.otlp_endpoint.as_deref(),
+            Some("http://otel-collector:4317")
         );
-
-        let mut config = Config::default();
-        apply_config_file(&mut config, "tenant_deny = bad_tenant\n");
-        assert_eq!(config.tenant_deny, Some(vec!["bad_tenant".into()]));
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("otlp_export_interval_secs")
+        );
+        config.otlp_export_interval_secs = 30;
+        assert!(config.validate().is_ok());
     }
 
     #[test]
-    fn all_numeric_fields_parse() {
+    fn statsd_settings() {
         let mut config = Config::default();
+        assert_eq!(config.statsd_prefix, "pgvpd");
+        assert_eq!(config.statsd_flush_interval_ms, 1000);
         apply_config_file(
             &mut config,
-            r#"
-pool_size = 50
-pool_idle_timeout = 600
-pool_checkout_timeout = 10
-handshake_timeout = 60
-tenant_max_connections = 100
-tenant_rate_limit = 50
-tenant_query_timeout = 30
-"#,
+            "statsd_host = localhost\nstatsd_prefix = db.proxy\n",
         );
-        assert_eq!(config.pool_size, 50);
-        assert_eq!(config.pool_idle_timeout, 600);
-        assert_eq!(config.pool_checkout_timeout, 10);
-        assert_eq!(config.handshake_timeout_secs, 60);
-        assert_eq!(config.tenant_max_connections, Some(100));
-        assert_eq!(config.tenant_rate_limit, Some(50));
-        assert_eq!(config.tenant_query_timeout, Some(30));
+        assert_eq!(config.statsd_prefix, "db.proxy");
+        assert!(config.validate().unwrap_err().contains("statsd_port"));
+        apply_config_file(&mut config, "statsd_port = 8125\n");
+        assert_eq!(config.statsd_port, Some(8125));
+        assert!(config.validate().is_ok());
     }
 
     #[test]
-    fn invalid_numeric_values_are_ignored() {
+    fn listen_ipv6_validation() {
         let mut config = Config::default();
-        apply_config_file(&mut config, "port = not_a_number\n");
-        assert_eq!(config.listen_port, 6432); // stays at default
+        apply_config_file(&mut config, "listen_ipv6 = true\nlisten_host_v6 = ::\n");
+        assert!(config.listen_ipv6);
+        assert_eq!(config.listen_host_v6, "::");
+        assert!(config.validate().is_ok());
+
+        config.listen_host_v6 = "0.0.0.0".into();
+        assert!(config.validate().unwrap_err().contains("listen_host_v6"));
+        // Not checked while the IPv6 listener is off
+        config.listen_ipv6 = false;
+        assert!(config.validate().is_ok());
     }
 
     #[test]
-    fn key_aliases() {
-        // "listen_port" and "port" are aliases
+    fn proxy_protocol_mode_parsing() {
         let mut config = Config::default();
-        apply_config_file(&mut config, "listen_port = 7777\n");
-        assert_eq!(config.listen_port, 7777);
+        assert_eq!(config.proxy_protocol_mode, ProxyProtocolMode::Off);
+        apply_config_file(&mut config, "proxy_protocol_mode = Detect\n");
+        assert_eq!(config.proxy_protocol_mode, ProxyProtocolMode::Detect);
+        assert_eq!(
+            parse_proxy_protocol_mode("required"),
+            Ok(ProxyProtocolMode::Required)
+        );
+        assert!(parse_proxy_protocol_mode("v2").is_err());
 
-        // "host" and "listen_host" are aliases
-        let mut config = Config::default();
-        apply_config_file(&mut config, "host = 0.0.0.0\n");
-        assert_eq!(config.listen_host, "0.0.0.0");
+        // A typo is an error rather than a silent fallback to off
+        let mut warnings = Vec::new();
+        let err = apply_config_content(
+            &mut config,
+            "proxy_protocol_mode = requried\n",
+            Path::new("."),
+            &mut HashSet::new(),
+            0,
+            &mut warnings,
+        )
+        .unwrap_err();
+        assert!(err.contains("expected off, required or detect"));
+        assert_eq!(config.proxy_protocol_mode, ProxyProtocolMode::Detect);
+    }
 
-        // "separator" and "tenant_separator" are aliases
+    #[test]
+    fn validate_proxy_protocol_needs_trusted_sources() {
         let mut config = Config::default();
-        apply_config_file(&mut config, "separator = +\n");
-        assert_eq!(config.tenant_separator, "+");
+        config.proxy_protocol_mode = ProxyProtocolMode::Required;
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("proxy_protocol_trusted_sources")
+        );
+        apply_config_file(&mut config, "proxy_protocol_trusted_sources = 10.0.0.0/8\n");
+        assert!(config.validate().is_ok());
+    }
 
-        // "superuser" and "superuser_bypass" are aliases
+    #[test]
+    fn scram_iteration_limits() {
         let mut config = Config::default();
-        apply_config_file(&mut config, "superuser = admin, root\n");
-        assert_eq!(config.superuser_bypass, vec!["admin", "root"]);
-    }
+        assert_eq!(config.scram_iterations(), 4096..=1_000_000);
+        apply_config_file(
+            &mut config,
+            "scram_min_iterations = 10000\nscram_max_iterations = 20000\n",
+        );
+        assert_eq!(config.scram_iterations(), 10_000..=20_000);
+        assert!(config.validate().is_ok());
 
-    // ─── Env var overrides ───────────────────────────────────────────────
+        config.scram_max_iterations = 5000;
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("scram_min_iterations")
+        );
+        config.scram_min_iterations = 0;
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("scram_min_iterations")
+        );
+    }
 
     #[test]
-    fn env_var_overrides() {
-        // Set an env var, apply it, check it took effect
+    fn upstream_auth_method_parsing() {
         let mut config = Config::default();
-        apply_config_file(&mut config, "port = 7777\n");
-        assert_eq!(config.listen_port, 7777);
+        assert_eq!(config.upstream_auth_method, UpstreamAuthMethod::Auto);
+        apply_config_file(&mut config, "upstream_auth_method = SCRAM-SHA-256\n");
+        assert_eq!(config.upstream_auth_method, UpstreamAuthMethod::ScramSha256);
+        assert_eq!(
+            parse_upstream_auth_method("md5"),
+            Ok(UpstreamAuthMethod::Md5)
+        );
+        assert_eq!(
+            parse_upstream_auth_method("cleartext"),
+            Ok(UpstreamAuthMethod::Cleartext)
+        );
+        assert_eq!(
+            parse_upstream_auth_method("auto"),
+            Ok(UpstreamAuthMethod::Auto)
+        );
+        assert!(parse_upstream_auth_method("garbage").is_err());
 
-        // Env var should override config file
-        // SAFETY: test runs single-threaded (cargo test default), no concurrent env access
-        unsafe { std::env::set_var("PGVPD_PORT", "8888") };
-        apply_env(&mut config);
-        assert_eq!(config.listen_port, 8888);
-        unsafe { std::env::remove_var("PGVPD_PORT") };
+        // An unknown method is a warning (fatal with --strict-config), not Auto
+        let warnings = apply_config_file(&mut config, "upstream_auth_method = scram-sha256\n");
+        assert_eq!(config.upstream_auth_method, UpstreamAuthMethod::ScramSha256);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].key, "upstream_auth_method");
+
+        apply_config_file(&mut config, "upstream_auth_method = scram-sha-256-plus\n");
+        assert!(config.validate().unwrap_err().contains("channel binding"));
     }
 
     #[test]
-    fn env_var_tenant_settings() {
+    fn pool_auth_method_parsing() {
         let mut config = Config::default();
-        // SAFETY: test runs single-threaded (cargo test default), no concurrent env access
-        unsafe { std::env::set_var("PGVPD_TENANT_ALLOW", "t1,t2,t3") };
-        apply_env(&mut config);
+        assert_eq!(config.pool_auth_method, PoolAuthMethod::Cleartext);
+        apply_config_file(&mut config, "pool_auth_method = md5\n");
+        assert_eq!(config.pool_auth_method, PoolAuthMethod::Md5);
+        assert_eq!(parse_pool_auth_method("MD5"), Ok(PoolAuthMethod::Md5));
         assert_eq!(
-            config.tenant_allow,
-            Some(vec!["t1".into(), "t2".into(), "t3".into()])
+            parse_pool_auth_method("cleartext"),
+            Ok(PoolAuthMethod::Cleartext)
         );
-        unsafe { std::env::remove_var("PGVPD_TENANT_ALLOW") };
+        assert!(parse_pool_auth_method("garbage").is_err());
+        let warnings = apply_config_file(&mut config, "pool_auth_method = mdfive\n");
+        assert_eq!(config.pool_auth_method, PoolAuthMethod::Md5);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(format!("{}", PoolAuthMethod::Md5), "md5");
     }
 
-    // ─── Validation ──────────────────────────────────────────────────────
-
     #[test]
-    fn validate_default_config_passes() {
-        let config = Config::default();
-        assert!(config.validate().is_ok());
+    fn trust_ips_parsing() {
+        let mut config = Config::default();
+        assert!(config.trust_ips.is_empty());
+        apply_config_file(&mut config, "trust_ips = 127.0.0.1/8, ::1/128\n");
+        assert_eq!(config.trust_ips.len(), 2);
+        assert!(config.trust_ips[0].contains("127.0.0.5".parse().unwrap()));
+        assert!(config.trust_ips[1].contains("::1".parse().unwrap()));
+        assert!(!config.trust_ips[0].contains("10.0.0.1".parse().unwrap()));
     }
 
     #[test]
-    fn validate_tls_port_without_cert_fails() {
+    fn trust_ips_invalid_entry_rejects_list() {
+        assert!(parse_cidr_list("127.0.0.1/8,not-a-cidr").is_err());
         let mut config = Config::default();
-        config.tls_port = Some(6433);
-        assert!(config.validate().is_err());
-        assert!(config.validate().unwrap_err().contains("tls_cert"));
+        apply_config_file(&mut config, "trust_ips = 127.0.0.1/8,10.0.0.0/99\n");
+        assert!(config.trust_ips.is_empty());
     }
 
     #[test]
-    fn validate_tls_port_with_cert_and_key_passes() {
+    fn is_trusted_ip_matches_ranges() {
         let mut config = Config::default();
-        config.tls_port = Some(6433);
-        config.tls_cert = Some("/tmp/cert.pem".into());
-        config.tls_key = Some("/tmp/key.pem".into());
-        assert!(config.validate().is_ok());
+        assert!(!config.is_trusted_ip("127.0.0.1".parse().unwrap()));
+        config.trust_ips = parse_cidr_list("127.0.0.1/8,::1/128").unwrap();
+        assert!(config.is_trusted_ip("127.0.0.1".parse().unwrap()));
+        assert!(config.is_trusted_ip("::1".parse().unwrap()));
+        assert!(config.is_trusted_ip("::ffff:127.0.0.1".parse().unwrap()));
+        assert!(!config.is_trusted_ip("192.168.1.10".parse().unwrap()));
     }
 
     #[test]
-    fn validate_zero_handshake_timeout_fails() {
+    fn startup_param_allowlist_parsing() {
         let mut config = Config::default();
-        config.handshake_timeout_secs = 0;
-        assert!(config.validate().is_err());
-        assert!(config.validate().unwrap_err().contains("handshake_timeout"));
+        assert!(
+            config
+                .startup_param_allowlist
+                .contains(&"TimeZone".to_string())
+        );
+        apply_config_file(
+            &mut config,
+            "startup_param_allowlist = application_name, search_path\n",
+        );
+        assert_eq!(
+            config.startup_param_allowlist,
+            vec!["application_name", "search_path"]
+        );
     }
 
     #[test]
-    fn validate_session_pool_without_password_fails() {
+    fn application_name_rewrite() {
+        let config = Config::default();
+        assert_eq!(
+            config.application_name(Some("myapp"), None).as_deref(),
+            Some("myapp/pgvpd")
+        );
+        assert_eq!(
+            config
+                .application_name(Some("myapp"), Some("tenant123"))
+                .as_deref(),
+            Some("myapp/pgvpd/tenant123")
+        );
+        assert_eq!(
+            config.application_name(None, None).as_deref(),
+            Some("pgvpd")
+        );
+        assert_eq!(
+            config.application_name(Some(""), None).as_deref(),
+            Some("pgvpd")
+        );
+    }
+
+    #[test]
+    fn pool_application_name_labels_bucket() {
         let mut config = Config::default();
-        config.pool_mode = PoolMode::Session;
-        config.upstream_password = Some("pass".into());
-        // Missing pool_password
-        assert!(config.validate().is_err());
-        assert!(config.validate().unwrap_err().contains("pool_password"));
+        assert_eq!(
+            config.pool_application_name("app", "app_user").as_deref(),
+            Some("pgvpd-pool/app/app_user")
+        );
+        config.rewrite_application_name = false;
+        assert_eq!(config.pool_application_name("app", "app_user"), None);
     }
 
     #[test]
-    fn validate_session_pool_without_upstream_password_fails() {
+    fn application_name_rewrite_disabled() {
         let mut config = Config::default();
-        config.pool_mode = PoolMode::Session;
-        config.pool_password = Some("pass".into());
-        // Missing upstream_password
-        assert!(config.validate().is_err());
-        assert!(config.validate().unwrap_err().contains("upstream_password"));
+        apply_config_file(
+            &mut config,
+            "rewrite_application_name = false\napplication_name_suffix = @proxy\n",
+        );
+        assert!(!config.rewrite_application_name);
+        assert_eq!(config.application_name_suffix, "@proxy");
+        assert_eq!(config.application_name(Some("myapp"), None), None);
     }
 
     #[test]
-    fn validate_session_pool_with_zero_pool_size_fails() {
+    fn protocol_negotiate_passthrough_parsing() {
+        assert!(Config::default().protocol_negotiate_passthrough);
         let mut config = Config::default();
-        config.pool_mode = PoolMode::Session;
-        config.pool_password = Some("pass".into());
-        config.upstream_password = Some("pass".into());
-        config.pool_size = 0;
-        assert!(config.validate().is_err());
-        assert!(config.validate().unwrap_err().contains("pool_size"));
+        apply_config_file(&mut config, "protocol_negotiate_passthrough = false\n");
+        assert!(!config.protocol_negotiate_passthrough);
     }
 
     #[test]
-    fn validate_session_pool_fully_configured_passes() {
+    fn startup_param_rules_parsing() {
         let mut config = Config::default();
-        config.pool_mode = PoolMode::Session;
-        config.pool_password = Some("pass".into());
-        config.upstream_password = Some("pass".into());
+        let warnings = apply_config_file(
+            &mut config,
+            "startup_param = search_path=tenant_schema, public\n\
+             startup_param = statement_timeout=30s\n\
+             startup_param_rename = myapp_tenant=app.tenant\n\
+             startup_param = garbage\n\
+             startup_param_rename = =app.tenant\n",
+        );
+        assert_eq!(
+            config.startup_params,
+            vec![
+                ("search_path".into(), "tenant_schema, public".into()),
+                ("statement_timeout".into(), "30s".into()),
+            ]
+        );
+        assert_eq!(
+            config.startup_param_renames,
+            vec![("myapp_tenant".into(), "app.tenant".into())]
+        );
         assert!(config.validate().is_ok());
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            (warnings[0].line, warnings[0].key.as_str()),
+            (4, "startup_param")
+        );
+        assert_eq!(
+            (warnings[1].line, warnings[1].key.as_str()),
+            (5, "startup_param_rename")
+        );
     }
 
     #[test]
-    fn validate_resolvers_file_not_found_fails() {
+    fn startup_param_blocks() {
         let mut config = Config::default();
-        config.resolvers = Some("/nonexistent/path/resolvers.toml".into());
-        assert!(config.validate().is_err());
+        let warnings = apply_config_file(
+            &mut config,
+            "startup_param = statement_timeout=30s\n\
+             [[startup_param]]\n\
+             name = search_path\n\
+             value = \"tenant_schema, public\"\n\
+             [[startup_param_rename]]\n\
+             from = myapp_tenant\n\
+             to = app.tenant\n",
+        );
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(
+            config.startup_params,
+            vec![
+                ("statement_timeout".into(), "30s".into()),
+                ("search_path".into(), "tenant_schema, public".into()),
+            ]
+        );
+        assert_eq!(
+            config.startup_param_renames,
+            vec![("myapp_tenant".into(), "app.tenant".into())]
+        );
+        assert!(config.validate().is_ok());
+
+        // A block missing its name or target fails validation
+        let mut config = Config::default();
+        apply_config_file(&mut config, "[[startup_param]]\nvalue = x\n");
+        assert!(config.validate().unwrap_err().contains("[[startup_param]]"));
+        let mut config = Config::default();
+        apply_config_file(&mut config, "[[startup_param_rename]]\nfrom = a\n");
         assert!(
             config
                 .validate()
                 .unwrap_err()
-                .contains("resolvers file not found")
+                .contains("[[startup_param_rename]]")
         );
     }
 
     #[test]
-    fn validate_both_allow_and_deny_fails() {
+    fn validate_startup_param_reserved_names_fail() {
         let mut config = Config::default();
-        config.tenant_allow = Some(vec!["a".into()]);
-        config.tenant_deny = Some(vec!["b".into()]);
-        assert!(config.validate().is_err());
+        config.startup_params = vec![("user".into(), "postgres".into())];
+        assert!(config.validate().unwrap_err().contains("startup_param"));
+
+        let mut config = Config::default();
+        config.startup_param_renames = vec![("myapp_db".into(), "Database".into())];
         assert!(
             config
                 .validate()
                 .unwrap_err()
-                .contains("cannot both be set")
+                .contains("startup_param_rename")
         );
     }
 
-    // ─── has_tenant_limits ───────────────────────────────────────────────
-
     #[test]
-    fn has_tenant_limits_detection() {
-        let config = Config::default();
-        assert!(!config.has_tenant_limits());
-
-        let mut config = Config::default();
-        config.tenant_allow = Some(vec!["a".into()]);
-        assert!(config.has_tenant_limits());
-
+    fn inject_comment_renders_all_tokens() {
         let mut config = Config::default();
-        config.tenant_deny = Some(vec!["b".into()]);
-        assert!(config.has_tenant_limits());
+        assert_eq!(
+            config.render_inject_comment(1, "acme", "app_user", "mydb"),
+            None
+        );
+        apply_config_file(
+            &mut config,
+            "inject_comment_template = /* pgvpd conn_id={conn_id} tenant={tenant} role={role} db={database} */\n",
+        );
+        assert_eq!(
+            config
+                .render_inject_comment(42, "acme", "app_user", "mydb")
+                .as_deref(),
+            Some("/* pgvpd conn_id=42 tenant=acme role=app_user db=mydb */")
+        );
+    }
 
+    #[test]
+    fn inject_comment_strips_comment_delimiters() {
         let mut config = Config::default();
-        config.tenant_max_connections = Some(10);
-        assert!(config.has_tenant_limits());
+        config.inject_comment_template = Some("/* tenant={tenant} */".into());
+        assert_eq!(
+            config
+                .render_inject_comment(1, "x**//; DROP TABLE t; /*", "r", "d")
+                .as_deref(),
+            Some("/* tenant=x; DROP TABLE t;  */")
+        );
+        // A leading '/' must not pair with a '*' in the template
+        config.inject_comment_template = Some("/* x*{database} */".into());
+        assert_eq!(
+            config
+                .render_inject_comment(1, "t", "r", "/ SELECT 1; --")
+                .as_deref(),
+            Some("/* x* SELECT 1; -- */")
+        );
+        assert_eq!(
+            config
+                .render_inject_comment(1, "a\r\nb\u{0}c\td", "r", "d")
+                .as_deref(),
+            Some("/* tenant=abcd */")
+        );
+    }
 
+    #[test]
+    fn validate_inject_comment_template() {
         let mut config = Config::default();
-        config.tenant_rate_limit = Some(5);
-        assert!(config.has_tenant_limits());
+        config.inject_comment_template = Some("/* pgvpd tenant={tenant} */".into());
+        assert!(config.validate().is_ok());
+        for bad in [
+            "pgvpd tenant={tenant}",
+            "/* tenant={tenant}",
+            "/*/",
+            "/* a */ SELECT 1; /* b */",
+            "/* a /* b */",
+        ] {
+            config.inject_comment_template = Some(bad.into());
+            assert!(
+                config
+                    .validate()
+                    .unwrap_err()
+                    .contains("inject_comment_template"),
+                "{bad}"
+            );
+        }
     }
 
-    // ─── parse_pool_mode ─────────────────────────────────────────────────
-
     #[test]
-    fn pool_mode_case_insensitive() {
-        assert_eq!(parse_pool_mode("Session"), PoolMode::Session);
-        assert_eq!(parse_pool_mode("SESSION"), PoolMode::Session);
-        assert_eq!(parse_pool_mode("  session  "), PoolMode::Session);
-        assert_eq!(parse_pool_mode("none"), PoolMode::None);
-        assert_eq!(parse_pool_mode("anything_else"), PoolMode::None);
+    fn trust_ips_bare_address() {
+        let nets = parse_cidr_list("10.1.2.3").unwrap();
+        assert!(nets[0].contains("10.1.2.3".parse().unwrap()));
+        assert!(!nets[0].contains("10.1.2.4".parse().unwrap()));
     }
 
     #[test]
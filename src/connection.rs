@@ -1,7 +1,7 @@
 //! Per-Connection Handler
 //!
-//! Async state machine managing a single client connection through:
-//!   WaitStartup → Authenticating → PostAuth → Resolving → Injecting → Transparent
+//! Async state machine (`ConnectionState`) managing a single client connection through:
+//!   WaitingForStartup → Authenticating → PostAuth → Resolving → Injecting → Transparent
 //!
 //! In pool mode, pgvpd authenticates the client itself, checks out a pooled
 //! upstream connection, resets + resolves + re-injects context, then enters
@@ -10,21 +10,28 @@
 use bytes::{Buf, BytesMut};
 use rustls::ClientConfig;
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tracing::{debug, error, info, warn};
 
+use crate::audit::{self, AuditEntry, AuditLogger};
 use crate::auth;
-use crate::config::{Config, PoolMode};
+use crate::config::{Config, PoolAuthMethod, PoolMode};
+use crate::dns::DnsCache;
 use crate::metrics::Metrics;
-use crate::pool::{Pool, PoolKey};
+use crate::pool::{CheckoutPermit, InjectingGuard, Pool, PoolKey, query_first_value};
 use crate::protocol::{
-    SSL_DENY, StartupType, build_error_response, build_query_message, build_startup_message,
-    escape_set_value, quote_ident, try_read_backend_message, try_read_startup,
+    BackendMessage, ErrorFields, MessageScanner, SSL_DENY, StartupType, build_auth_ok,
+    build_error_response, build_notice_response, build_query_message, build_startup_message,
+    escape_literal, escape_literal_unicode, escape_set_value, frontend_message_type_name,
+    peek_frontend_message_type, quote_ident, rewrite_startup_params, try_read_backend_message,
+    try_read_startup, wrap_in_transaction,
 };
-use crate::resolver::ResolverEngine;
+use crate::resolver::{DatabaseResolvers, ResolverEngine};
+use crate::router::{ReadWriteRouter, Upstream};
 use crate::stream::{ClientStream, UpstreamStream};
 use crate::tenant::{TenantGuard, TenantRegistry};
 use crate::tls::parse_server_name;
@@ -38,113 +45,277 @@ pub enum HandshakeResult {
         stream: UpstreamStream,
         key: PoolKey,
         pool: Arc<Pool>,
+        /// When the upstream connection was opened, for pool lifetime stats.
+        created_at: Instant,
+        permit: CheckoutPermit,
     },
     /// Fully handled (cancel request, error, etc.) — nothing more to do.
     Done,
 }
 
+/// Tenant context learned during the handshake, reported in the audit log.
+#[derive(Debug, Default)]
+pub struct SessionInfo {
+    /// Client address — from the PROXY header if there was one, otherwise
+    /// the TCP peer.
+    pub peer_addr: Option<SocketAddr>,
+    pub tenant: Option<String>,
+    pub role: Option<String>,
+    pub database: Option<String>,
+}
+
+/// Bytes moved through the transparent pipe, from the client's point of view.
+#[derive(Debug, Default)]
+pub struct PipeStats {
+    /// Bytes forwarded to the client.
+    pub bytes_sent: u64,
+    /// Bytes received from the client.
+    pub bytes_received: u64,
+}
+
 /// Handle a single client connection through its full lifecycle.
 #[allow(clippy::too_many_arguments)]
 pub async fn handle_connection(
     mut client: ClientStream,
+    proxied_peer: Option<SocketAddr>,
     config: Arc<Config>,
     upstream_tls: Option<Arc<ClientConfig>>,
+    dns: DnsCache,
     pool: Option<Arc<Pool>>,
     resolver_engine: Option<Arc<ResolverEngine>>,
+    database_resolvers: DatabaseResolvers,
     tenant_registry: Option<Arc<TenantRegistry>>,
+    audit: Option<Arc<AuditLogger>>,
     config_metrics: Arc<Metrics>,
     conn_id: u64,
 ) {
-    let peer = client
-        .peer_addr()
+    let mut session = SessionInfo {
+        peer_addr: proxied_peer.or_else(|| client.peer_addr().ok()),
+        ..SessionInfo::default()
+    };
+    let peer = session
+        .peer_addr
+        .map(|a| a.to_string())
+        .unwrap_or_else(|| "unknown".into());
+    let local = client
+        .local_addr()
         .map(|a| a.to_string())
         .unwrap_or_else(|_| "unknown".into());
-    debug!(conn_id, peer, "new connection");
+    debug!(
+        conn_id,
+        peer,
+        local,
+        proxied = proxied_peer.is_some(),
+        "new connection"
+    );
 
+    let started = Instant::now();
+    let mut stats = PipeStats::default();
     let timeout = Duration::from_secs(config.handshake_timeout_secs);
 
-    let (result, _tenant_guard) = match tokio::time::timeout(
-        timeout,
-        handshake(
-            &mut client,
-            &config,
-            &upstream_tls,
-            &pool,
-            &resolver_engine,
-            &tenant_registry,
-            conn_id,
-        ),
-    )
-    .await
-    {
-        Ok(Ok(r)) => r,
-        Ok(Err(e)) => {
-            debug!(conn_id, error = %e, "connection ended");
-            return;
-        }
-        Err(_) => {
-            warn!(conn_id, "handshake timeout");
-            send_error(
+    let disconnect_reason: String = 'conn: {
+        let (result, _tenant_guard) = match tokio::time::timeout(
+            timeout,
+            handshake(
                 &mut client,
-                "FATAL",
-                "08006",
-                "handshake timeout — no StartupMessage received in time",
-            )
-            .await;
-            return;
-        }
-    };
-    // _tenant_guard lives here until handle_connection returns,
-    // decrementing the per-tenant active connection count on drop.
-
-    let query_timeout = config.tenant_query_timeout.map(Duration::from_secs);
-
-    match result {
-        HandshakeResult::Done => {}
-        HandshakeResult::Passthrough(mut server) => {
-            debug!(conn_id, "transparent pipe");
-            let result = if let Some(timeout) = query_timeout {
-                match tokio::time::timeout(
-                    timeout,
-                    tokio::io::copy_bidirectional(&mut client, &mut server),
+                &config,
+                &upstream_tls,
+                &dns,
+                &pool,
+                &resolver_engine,
+                &database_resolvers,
+                &tenant_registry,
+                &config_metrics,
+                &mut session,
+                conn_id,
+            ),
+        )
+        .await
+        {
+            Ok(Ok(r)) => r,
+            Ok(Err(e)) => {
+                debug!(conn_id, error = %e, "connection ended");
+                break 'conn format!("handshake failed: {e}");
+            }
+            Err(_) => {
+                warn!(conn_id, "handshake timeout");
+                send_error(
+                    &mut client,
+                    "FATAL",
+                    "08006",
+                    "handshake timeout — no StartupMessage received in time",
                 )
-                .await
-                {
-                    Ok(r) => r,
-                    Err(_) => {
-                        warn!(conn_id, "query timeout (passthrough)");
-                        Metrics::inc(&config_metrics.tenant_timeouts);
-                        Err(std::io::Error::new(
-                            std::io::ErrorKind::TimedOut,
-                            "tenant query timeout",
-                        ))
+                .await;
+                break 'conn "handshake timeout".into();
+            }
+        };
+        // _tenant_guard lives until this block ends,
+        // decrementing the per-tenant active connection count on drop.
+
+        let query_timeout = config.tenant_query_timeout.map(Duration::from_secs);
+        let client_idle_timeout = Some(config.client_idle_timeout_secs)
+            .filter(|&s| s > 0)
+            .map(Duration::from_secs);
+
+        let result = match result {
+            HandshakeResult::Done => break 'conn "rejected".into(),
+            HandshakeResult::Passthrough(mut server) => {
+                debug!(conn_id, "transparent pipe");
+                let pipe = pipe_passthrough(
+                    &mut client,
+                    &mut server,
+                    conn_id,
+                    client_idle_timeout,
+                    &config_metrics,
+                    &mut stats,
+                );
+                if let Some(timeout) = query_timeout {
+                    match tokio::time::timeout(timeout, pipe).await {
+                        Ok(r) => r,
+                        Err(_) => {
+                            warn!(conn_id, "query timeout (passthrough)");
+                            Metrics::inc(&config_metrics.tenant_timeouts);
+                            Err(std::io::Error::new(
+                                std::io::ErrorKind::TimedOut,
+                                "tenant query timeout",
+                            ))
+                        }
                     }
+                } else {
+                    pipe.await
                 }
-            } else {
-                tokio::io::copy_bidirectional(&mut client, &mut server).await
-            };
-            if let Err(e) = result {
+            }
+            HandshakeResult::Pooled {
+                mut stream,
+                key,
+                pool,
+                created_at,
+                permit,
+            } => {
+                debug!(conn_id, "transparent pipe (pooled)");
+                let mut client_buf = pool.buffers().get();
+                let mut server_buf = pool.buffers().get();
+                let result = pipe_pooled(
+                    &mut client,
+                    &mut stream,
+                    &mut client_buf,
+                    &mut server_buf,
+                    conn_id,
+                    query_timeout,
+                    client_idle_timeout,
+                    &config_metrics,
+                    &mut stats,
+                )
+                .await;
+                pool.buffers().put(client_buf);
+                pool.buffers().put(server_buf);
+                pool.checkin(key, stream, created_at, permit, conn_id).await;
+                result
+            }
+        };
+        match result {
+            Ok(()) => "closed".into(),
+            Err(e) => {
                 debug!(conn_id, error = %e, "connection ended");
+                e.to_string()
             }
         }
-        HandshakeResult::Pooled {
-            mut stream,
-            key,
-            pool,
-        } => {
-            debug!(conn_id, "transparent pipe (pooled)");
-            if let Err(e) = pipe_pooled(
-                &mut client,
-                &mut stream,
-                conn_id,
-                query_timeout,
-                &config_metrics,
-            )
-            .await
-            {
-                debug!(conn_id, error = %e, "connection ended");
+    };
+
+    if let Some(logger) = audit {
+        let entry = AuditEntry {
+            timestamp: audit::format_timestamp(SystemTime::now()),
+            conn_id,
+            peer_addr: peer,
+            tenant: session.tenant,
+            role: session.role,
+            database: session.database,
+            duration_ms: started.elapsed().as_millis() as u64,
+            bytes_sent: stats.bytes_sent,
+            bytes_received: stats.bytes_received,
+            disconnect_reason,
+        };
+        if let Err(e) = logger.log(&entry).await {
+            error!(conn_id, error = %e, "audit log write failed");
+        }
+    }
+}
+
+/// Bidirectional pipe for passthrough connections.
+///
+/// Equivalent to `copy_bidirectional`, plus an optional `client_idle_timeout`:
+/// if the client sends nothing for that long, it gets a 57P01 ErrorResponse and
+/// both streams are closed. EOF on one side shuts down the peer's write half,
+/// and the other direction keeps flowing until it reaches EOF too.
+async fn pipe_passthrough(
+    client: &mut ClientStream,
+    server: &mut UpstreamStream,
+    conn_id: u64,
+    client_idle_timeout: Option<Duration>,
+    metrics: &Metrics,
+    stats: &mut PipeStats,
+) -> std::io::Result<()> {
+    use std::pin::pin;
+    use tokio::time::Instant;
+
+    let mut client_buf = BytesMut::with_capacity(8192);
+    let mut server_buf = BytesMut::with_capacity(8192);
+    let client_idle = client_idle_timeout.unwrap_or(Duration::from_secs(86400 * 365));
+    let mut client_deadline = pin!(tokio::time::sleep(client_idle));
+    let mut client_scan = MessageScanner::default();
+    let mut server_scan = MessageScanner::default();
+    let mut client_eof = false;
+    let mut server_eof = false;
+
+    loop {
+        tokio::select! {
+            result = client.read_buf(&mut client_buf), if !client_eof => {
+                let n = result?;
+                if n == 0 {
+                    let _ = server.shutdown().await;
+                    if server_eof {
+                        return Ok(());
+                    }
+                    client_eof = true;
+                    continue;
+                }
+                stats.bytes_received += n as u64;
+                client_scan.scan(&client_buf, |t| {
+                    count_frontend_message(metrics, t);
+                    debug!(
+                        conn_id,
+                        msg_type = %(t as char),
+                        name = frontend_message_type_name(t),
+                        "forwarding client message"
+                    );
+                });
+                server.write_all(&client_buf).await?;
+                client_buf.clear();
+                client_deadline.as_mut().reset(Instant::now() + client_idle);
+            }
+            result = server.read_buf(&mut server_buf), if !server_eof => {
+                let n = result?;
+                if n == 0 {
+                    let _ = client.shutdown().await;
+                    if client_eof {
+                        return Ok(());
+                    }
+                    server_eof = true;
+                    continue;
+                }
+                stats.bytes_sent += n as u64;
+                server_scan.scan_backend(&server_buf, |_| {}, |e| count_backend_error(metrics, e));
+                client.write_all(&server_buf).await?;
+                server_buf.clear();
+            }
+            _ = &mut client_deadline, if client_idle_timeout.is_some() && !client_eof => {
+                client_idle_timed_out(client, conn_id, metrics).await;
+                let _ = server.shutdown().await;
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "client idle timeout",
+                ));
             }
-            pool.checkin(key, stream, conn_id).await;
         }
     }
 }
@@ -154,37 +325,60 @@ pub async fn handle_connection(
 /// Unlike `copy_bidirectional`, this intercepts the Postgres Terminate message
 /// ('X') from the client so the upstream connection stays alive for pool reuse.
 /// If `query_timeout` is set, the connection is terminated after that many seconds
-/// of inactivity (no data in either direction).
+/// of inactivity (no data in either direction). If `client_idle_timeout` is set,
+/// the client is disconnected after that long without sending anything; the
+/// upstream is left open for `checkin` to reset and return to the pool.
+#[allow(clippy::too_many_arguments)]
 async fn pipe_pooled(
     client: &mut ClientStream,
     server: &mut UpstreamStream,
+    client_buf: &mut BytesMut,
+    server_buf: &mut BytesMut,
     conn_id: u64,
     query_timeout: Option<Duration>,
+    client_idle_timeout: Option<Duration>,
     metrics: &Metrics,
+    stats: &mut PipeStats,
 ) -> std::io::Result<()> {
     use std::pin::pin;
     use tokio::time::Instant;
 
-    let mut client_buf = BytesMut::with_capacity(8192);
-    let mut server_buf = BytesMut::with_capacity(8192);
     let idle_timeout = query_timeout.unwrap_or(Duration::from_secs(86400 * 365));
     let mut deadline = pin!(tokio::time::sleep(idle_timeout));
+    let client_idle = client_idle_timeout.unwrap_or(Duration::from_secs(86400 * 365));
+    let mut client_deadline = pin!(tokio::time::sleep(client_idle));
+    let mut server_scan = MessageScanner::default();
 
     loop {
         tokio::select! {
-            result = client.read_buf(&mut client_buf) => {
+            result = client.read_buf(client_buf) => {
                 let n = result?;
                 if n == 0 {
                     debug!(conn_id, "client EOF (no Terminate)");
                     return Ok(());
                 }
-                if forward_client_messages(&mut client_buf, server).await? {
-                    debug!(conn_id, "client sent Terminate — preserving upstream");
-                    return Ok(());
+                stats.bytes_received += n as u64;
+                match forward_client_messages(client_buf, server, conn_id, metrics).await? {
+                    Forwarded::More => {}
+                    Forwarded::Terminate => {
+                        debug!(conn_id, "client sent Terminate — preserving upstream");
+                        return Ok(());
+                    }
+                    Forwarded::Malformed => {
+                        // Nothing of the bad message reached upstream, so the
+                        // connection is still in sync for checkin
+                        warn!(conn_id, "malformed client message — disconnecting");
+                        send_error(client, "FATAL", "08P01", "invalid message length").await;
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "invalid message length",
+                        ));
+                    }
                 }
                 deadline.as_mut().reset(Instant::now() + idle_timeout);
+                client_deadline.as_mut().reset(Instant::now() + client_idle);
             }
-            result = server.read_buf(&mut server_buf) => {
+            result = server.read_buf(server_buf) => {
                 let n = result?;
                 if n == 0 {
                     return Err(std::io::Error::new(
@@ -192,7 +386,9 @@ async fn pipe_pooled(
                         "upstream closed unexpectedly",
                     ));
                 }
-                client.write_all(&server_buf).await?;
+                stats.bytes_sent += n as u64;
+                server_scan.scan_backend(server_buf, |_| {}, |e| count_backend_error(metrics, e));
+                client.write_all(server_buf).await?;
                 server_buf.clear();
                 deadline.as_mut().reset(Instant::now() + idle_timeout);
             }
@@ -204,519 +400,1133 @@ async fn pipe_pooled(
                     "tenant query timeout",
                 ));
             }
+            _ = &mut client_deadline, if client_idle_timeout.is_some() => {
+                client_idle_timed_out(client, conn_id, metrics).await;
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "client idle timeout",
+                ));
+            }
         }
     }
 }
 
-/// Forward complete frontend messages to server, stopping on Terminate ('X').
+/// Notify and disconnect a client that exceeded `client_idle_timeout`.
+async fn client_idle_timed_out(client: &mut ClientStream, conn_id: u64, metrics: &Metrics) {
+    warn!(conn_id, "client idle timeout");
+    Metrics::inc(&metrics.client_idle_timeouts);
+    send_error(
+        client,
+        "FATAL",
+        "57P01",
+        "terminating connection due to client idle timeout",
+    )
+    .await;
+}
+
+/// Where `forward_client_messages` stopped.
+#[derive(Debug, PartialEq, Eq)]
+enum Forwarded {
+    /// Every complete message was forwarded; wait for more data.
+    More,
+    /// The client sent Terminate (caller should stop piping).
+    Terminate,
+    /// A message declared a length below 4, so the stream can't be framed
+    /// past it. It was not forwarded.
+    Malformed,
+}
+
+/// Forward complete frontend messages to server, stopping on Terminate ('X')
+/// or a malformed message.
 ///
-/// Returns `true` if Terminate was found (caller should stop piping).
 /// Leaves incomplete messages in the buffer for the next read.
 async fn forward_client_messages(
     buf: &mut BytesMut,
     server: &mut UpstreamStream,
-) -> std::io::Result<bool> {
+    conn_id: u64,
+    metrics: &Metrics,
+) -> std::io::Result<Forwarded> {
     loop {
         if buf.len() < 5 {
-            return Ok(false);
+            return Ok(Forwarded::More);
         }
 
         let msg_type = buf[0];
         let length = i32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]);
         if length < 4 {
-            // Malformed framing — forward everything, let upstream handle it
-            server.write_all(buf).await?;
-            buf.clear();
-            return Ok(false);
+            // Forwarding it would desync a connection that goes back to the pool
+            return Ok(Forwarded::Malformed);
         }
         let total = 1 + length as usize;
 
         if buf.len() < total {
-            return Ok(false); // Incomplete message, wait for more data
+            return Ok(Forwarded::More); // Incomplete message, wait for more data
         }
 
         if msg_type == b'X' {
             // Terminate — consume but don't forward
             buf.advance(total);
-            return Ok(true);
+            return Ok(Forwarded::Terminate);
         }
 
+        if tracing::enabled!(tracing::Level::DEBUG)
+            && let Some(type_char) = peek_frontend_message_type(buf)
+        {
+            debug!(
+                conn_id,
+                msg_type = %type_char,
+                name = frontend_message_type_name(msg_type),
+                len = total,
+                "forwarding client message"
+            );
+        }
+        count_frontend_message(metrics, msg_type);
         server.write_all(&buf[..total]).await?;
         buf.advance(total);
     }
 }
 
-/// Run the handshake phases: startup parsing, auth relay, context injection.
-async fn handshake(
-    client: &mut ClientStream,
-    config: &Config,
-    upstream_tls: &Option<Arc<ClientConfig>>,
-    pool: &Option<Arc<Pool>>,
-    resolver_engine: &Option<Arc<ResolverEngine>>,
-    tenant_registry: &Option<Arc<TenantRegistry>>,
-    conn_id: u64,
-) -> Result<(HandshakeResult, Option<TenantGuard>), Box<dyn std::error::Error + Send + Sync>> {
-    // ─── Phase 1: Read StartupMessage ───────────────────────────────────
-
-    let mut buf = BytesMut::with_capacity(1024);
-
-    let startup = loop {
-        client.read_buf(&mut buf).await?;
-
-        match try_read_startup(&mut buf) {
-            Some(StartupType::SslRequest) => {
-                debug!(conn_id, "SSL request denied");
-                client.write_all(SSL_DENY).await?;
-                continue;
-            }
-            Some(StartupType::CancelRequest) => {
-                debug!(conn_id, "cancel request — closing");
-                return Ok((HandshakeResult::Done, None));
-            }
-            Some(StartupType::Startup(s)) => break s,
-            None => continue,
-        }
-    };
-
-    let raw_user = startup.params.get("user").cloned().unwrap_or_default();
-    if raw_user.is_empty() {
-        send_error(client, "FATAL", "08004", "no username in StartupMessage").await;
-        return Ok((HandshakeResult::Done, None));
+/// Count a client message toward `queries_total`. Parse ('P') is not counted:
+/// a parsed statement is counted each time it is Executed.
+fn count_frontend_message(metrics: &Metrics, msg_type: u8) {
+    if matches!(msg_type, b'Q' | b'E') {
+        Metrics::inc(&metrics.queries_total);
     }
+}
 
-    let database = startup
-        .params
-        .get("database")
-        .cloned()
-        .unwrap_or_else(|| "default".into());
+/// Count an upstream ErrorResponse toward `query_errors_total` and its
+/// SQLSTATE class.
+fn count_backend_error(metrics: &Metrics, fields: ErrorFields) {
+    metrics.record_query_error(fields.sqlstate_class());
+}
 
-    // ─── Superuser bypass (always passthrough, never pooled) ────────────
+/// Handshake states, in the order a connection moves through them. A
+/// superuser bypass or replication connection goes straight from
+/// `WaitingForStartup` to `Transparent`; a rejected connection stops
+/// wherever it was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Reading the StartupMessage and validating the tenant username.
+    WaitingForStartup,
+    /// Relaying upstream auth (passthrough) or authenticating the client (pool).
+    Authenticating,
+    /// Waiting for the upstream's ReadyForQuery (passthrough) or checking out
+    /// and resetting a pooled connection.
+    PostAuth,
+    /// Running resolvers to complete the context.
+    Resolving,
+    /// Sending the SET batch.
+    Injecting,
+    /// Handshake done — bytes are piped as-is.
+    Transparent,
+}
 
-    if config.superuser_bypass.contains(&raw_user) {
-        info!(conn_id, user = %raw_user, "superuser bypass");
-        let mut server = connect_upstream(config, upstream_tls).await?;
-        let original = build_startup_message(&startup.params);
-        server.write_all(&original).await?;
-        if !buf.is_empty() {
-            server.write_all(&buf).await?;
+impl ConnectionState {
+    pub const ALL: [Self; 6] = [
+        Self::WaitingForStartup,
+        Self::Authenticating,
+        Self::PostAuth,
+        Self::Resolving,
+        Self::Injecting,
+        Self::Transparent,
+    ];
+
+    /// Label used in logs and metrics.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::WaitingForStartup => "waiting_for_startup",
+            Self::Authenticating => "authenticating",
+            Self::PostAuth => "post_auth",
+            Self::Resolving => "resolving",
+            Self::Injecting => "injecting",
+            Self::Transparent => "transparent",
         }
-        return Ok((HandshakeResult::Passthrough(server), None));
     }
+}
 
-    // ─── Extract tenant context from username ───────────────────────────
+/// A state plus the data it needs — what each `ConnectionHandler` step
+/// consumes and returns.
+enum Phase {
+    Authenticating(TenantStartup),
+    PostAuth(TenantStartup, Authenticated),
+    Resolving(TenantStartup, UpstreamSession),
+    Injecting(
+        TenantStartup,
+        UpstreamSession,
+        HashMap<String, Option<String>>,
+    ),
+    Transparent(HandshakeResult, Option<TenantGuard>),
+    /// Handshake over without a pipe (rejected, cancel request, error sent).
+    Done,
+}
 
-    let sep_idx = match raw_user.find(&config.tenant_separator) {
-        Some(i) => i,
-        None => {
-            send_error(
-                client,
-                "FATAL",
-                "28000",
-                &format!(
-                    "username must contain context values separated by '{}'",
-                    config.tenant_separator
-                ),
-            )
-            .await;
-            return Ok((HandshakeResult::Done, None));
+impl Phase {
+    fn state(&self) -> Option<ConnectionState> {
+        match self {
+            Self::Authenticating(..) => Some(ConnectionState::Authenticating),
+            Self::PostAuth(..) => Some(ConnectionState::PostAuth),
+            Self::Resolving(..) => Some(ConnectionState::Resolving),
+            Self::Injecting(..) => Some(ConnectionState::Injecting),
+            Self::Transparent(..) => Some(ConnectionState::Transparent),
+            Self::Done => None,
         }
-    };
-
-    let actual_user = &raw_user[..sep_idx];
-    let tenant_payload = &raw_user[sep_idx + config.tenant_separator.len()..];
-
-    if actual_user.is_empty() || tenant_payload.is_empty() {
-        send_error(
-            client,
-            "FATAL",
-            "28000",
-            "empty role or context in username",
-        )
-        .await;
-        return Ok((HandshakeResult::Done, None));
     }
+}
 
-    let context_values: Vec<&str> = if config.context_variables.len() > 1 {
-        tenant_payload.split(&config.value_separator).collect()
-    } else {
-        vec![tenant_payload]
-    };
+/// A validated tenant StartupMessage, carried through every later state.
+struct TenantStartup {
+    params: HashMap<String, String>,
+    /// Client bytes read past the StartupMessage.
+    buf: BytesMut,
+    raw_user: String,
+    actual_user: String,
+    tenant: String,
+    database: String,
+    inject_comment: Option<String>,
+    tenant_guard: Option<TenantGuard>,
+}
 
-    if context_values.len() != config.context_variables.len() {
-        send_error(
-            client,
-            "FATAL",
-            "28000",
-            &format!(
-                "expected {} context value(s), got {}",
-                config.context_variables.len(),
-                context_values.len()
-            ),
-        )
-        .await;
-        return Ok((HandshakeResult::Done, None));
-    }
+/// Outcome of `Authenticating`.
+#[allow(clippy::large_enum_variant)]
+enum Authenticated {
+    /// Passthrough: the upstream accepted the client's credentials.
+    Direct {
+        server: UpstreamStream,
+        server_buf: BytesMut,
+    },
+    /// Pool mode: pgvpd accepted the client; no upstream connection yet.
+    Pooled {
+        pool: Arc<Pool>,
+        jwt_claims: Option<serde_json::Map<String, serde_json::Value>>,
+    },
+}
 
-    if context_values.iter().any(|v| v.is_empty()) {
-        send_error(client, "FATAL", "28000", "empty context value in username").await;
-        return Ok((HandshakeResult::Done, None));
-    }
+/// An upstream connection ready for context, carried through `Resolving`
+/// and `Injecting`.
+struct UpstreamSession {
+    server: UpstreamStream,
+    server_buf: BytesMut,
+    kind: SessionKind,
+}
 
-    info!(
-        conn_id,
-        role = actual_user,
-        database = %database,
-        "tenant connection"
-    );
+enum SessionKind {
+    /// The upstream's ReadyForQuery, held back until context is injected.
+    Direct {
+        buffered_ready: BackendMessage,
+    },
+    Pooled(PooledSession),
+}
 
-    // ─── Tenant isolation checks ────────────────────────────────────────
+/// A checked-out pooled connection, minus its stream.
+struct PooledSession {
+    pool: Arc<Pool>,
+    key: PoolKey,
+    created_at: Instant,
+    permit: CheckoutPermit,
+    param_statuses: Vec<BytesMut>,
+    backend_key_data: BytesMut,
+    /// Transaction status after the reset query.
+    ready_status: u8,
+    /// Checkout time (Unix ms) for `inject_conn_id`.
+    checkout_ms: u128,
+    /// Held from checkout until the context SETs complete.
+    injecting: InjectingGuard,
+    jwt_claims: Option<serde_json::Map<String, serde_json::Value>>,
+}
 
-    let tenant_guard = if let Some(registry) = tenant_registry {
-        if let Err(msg) = registry.check_access(tenant_payload) {
-            send_error(client, "FATAL", "28000", &msg).await;
-            return Ok((HandshakeResult::Done, None));
-        }
-        match registry.acquire(tenant_payload).await {
-            Ok(guard) => Some(guard),
-            Err(msg) => {
-                send_error(client, "FATAL", "53300", &msg).await;
-                return Ok((HandshakeResult::Done, None));
-            }
-        }
+/// The tenant payload split into one value per `context_variables` entry.
+fn split_context_values<'t>(config: &Config, tenant: &'t str) -> Vec<&'t str> {
+    if config.context_variables.len() > 1 {
+        tenant.split(&config.value_separator).collect()
     } else {
-        None
-    };
-
-    // ─── Branch: pool mode vs passthrough ───────────────────────────────
-
-    if config.pool_mode == PoolMode::Session
-        && let Some(pool) = pool
-    {
-        let (result, _) = handle_pooled(
-            client,
-            config,
-            pool,
-            actual_user,
-            &database,
-            &context_values,
-            resolver_engine,
-            conn_id,
-        )
-        .await?;
-        return Ok((result, tenant_guard));
+        vec![tenant]
     }
+}
 
-    // ─── Passthrough: connect and relay auth ────────────────────────────
+/// Whether the StartupMessage asks for a replication connection
+/// (`replication=true` or `replication=database`).
+fn is_replication(params: &HashMap<String, String>) -> bool {
+    params
+        .get("replication")
+        .is_some_and(|v| !matches!(v.to_lowercase().as_str(), "false" | "off" | "no" | "0"))
+}
 
-    let (result, _) = handle_passthrough(
-        client,
-        config,
-        upstream_tls,
-        &startup.params,
-        &mut buf,
-        actual_user,
-        &context_values,
-        resolver_engine,
-        conn_id,
-    )
-    .await?;
-    Ok((result, tenant_guard))
+/// Whether the replication connection is logical (`replication=database`),
+/// i.e. bound to the `database` parameter.
+fn is_logical_replication(params: &HashMap<String, String>) -> bool {
+    params
+        .get("replication")
+        .is_some_and(|v| v.eq_ignore_ascii_case("database"))
 }
 
-/// Passthrough mode — connect to upstream, relay auth, resolve context, inject.
+/// Run the handshake phases: startup parsing, auth relay, context injection.
 #[allow(clippy::too_many_arguments)]
-async fn handle_passthrough(
+async fn handshake(
     client: &mut ClientStream,
     config: &Config,
     upstream_tls: &Option<Arc<ClientConfig>>,
-    startup_params: &HashMap<String, String>,
-    buf: &mut BytesMut,
-    actual_user: &str,
-    context_values: &[&str],
+    dns: &DnsCache,
+    pool: &Option<Arc<Pool>>,
     resolver_engine: &Option<Arc<ResolverEngine>>,
+    database_resolvers: &HashMap<String, Arc<ResolverEngine>>,
+    tenant_registry: &Option<Arc<TenantRegistry>>,
+    metrics: &Metrics,
+    session: &mut SessionInfo,
     conn_id: u64,
 ) -> Result<(HandshakeResult, Option<TenantGuard>), Box<dyn std::error::Error + Send + Sync>> {
-    let mut server = connect_upstream(config, upstream_tls).await?;
-    debug!(
+    ConnectionHandler {
+        client,
+        config,
+        upstream_tls,
+        dns,
+        pool,
+        resolver_engine,
+        database_resolvers,
+        tenant_registry,
+        metrics,
+        session,
         conn_id,
-        host = %config.upstream_host,
-        port = config.upstream_port,
-        "connected to upstream"
-    );
-
-    // Send rewritten StartupMessage
-    let mut rewritten_params = startup_params.clone();
-    rewritten_params.insert("user".into(), actual_user.to_string());
-    let startup_msg = build_startup_message(&rewritten_params);
-    server.write_all(&startup_msg).await?;
-
-    if !buf.is_empty() {
-        server.write_all(buf).await?;
-        buf.clear();
+        state: ConnectionState::WaitingForStartup,
     }
+    .run()
+    .await
+}
 
-    // ─── Authentication relay ───────────────────────────────────────────
+/// Drives one client through the handshake, one method per state. Each step
+/// takes the data its state needs and returns the next `Phase`.
+struct ConnectionHandler<'a> {
+    client: &'a mut ClientStream,
+    config: &'a Config,
+    upstream_tls: &'a Option<Arc<ClientConfig>>,
+    dns: &'a DnsCache,
+    pool: &'a Option<Arc<Pool>>,
+    resolver_engine: &'a Option<Arc<ResolverEngine>>,
+    /// Engines that replace `resolver_engine` for their database.
+    database_resolvers: &'a HashMap<String, Arc<ResolverEngine>>,
+    tenant_registry: &'a Option<Arc<TenantRegistry>>,
+    metrics: &'a Metrics,
+    session: &'a mut SessionInfo,
+    conn_id: u64,
+    state: ConnectionState,
+}
 
-    let mut server_buf = BytesMut::with_capacity(4096);
-    let mut auth_done = false;
+impl ConnectionHandler<'_> {
+    async fn run(
+        mut self,
+    ) -> Result<(HandshakeResult, Option<TenantGuard>), Box<dyn std::error::Error + Send + Sync>>
+    {
+        let mut phase = self.wait_for_startup().await?;
+        loop {
+            if let Some(next) = phase.state() {
+                self.enter(next);
+            }
+            phase = match phase {
+                Phase::Authenticating(startup) => self.authenticate(startup).await?,
+                Phase::PostAuth(startup, authenticated) => {
+                    self.post_auth(startup, authenticated).await?
+                }
+                Phase::Resolving(startup, upstream) => self.resolve(startup, upstream).await?,
+                Phase::Injecting(startup, upstream, context) => {
+                    self.inject(startup, upstream, context).await?
+                }
+                Phase::Transparent(result, tenant_guard) => return Ok((result, tenant_guard)),
+                Phase::Done => return Ok((HandshakeResult::Done, None)),
+            };
+        }
+    }
 
-    while !auth_done {
-        server.read_buf(&mut server_buf).await?;
+    fn enter(&mut self, next: ConnectionState) {
+        debug!(
+            conn_id = self.conn_id,
+            from = self.state.as_str(),
+            to = next.as_str(),
+            "state transition"
+        );
+        self.metrics.record_state_transition(self.state, next);
+        self.state = next;
+    }
 
-        while let Some(msg) = try_read_backend_message(&mut server_buf) {
-            if msg.is_auth_ok() {
-                debug!(conn_id, "authentication OK");
-                client.write_all(&msg.raw).await?;
-                auth_done = true;
-                break;
+    // ─── WaitingForStartup ───────────────────────────────────────────────
+
+    /// Read the StartupMessage, split the tenant out of the username and run
+    /// the tenant isolation checks.
+    async fn wait_for_startup(
+        &mut self,
+    ) -> Result<Phase, Box<dyn std::error::Error + Send + Sync>> {
+        let config = self.config;
+        let conn_id = self.conn_id;
+        let mut buf = BytesMut::with_capacity(1024);
+
+        let startup = loop {
+            self.client.read_buf(&mut buf).await?;
+
+            match try_read_startup(&mut buf, config.max_startup_message_bytes) {
+                Some(StartupType::SslRequest) => {
+                    debug!(conn_id, "SSL request denied");
+                    self.client.write_all(SSL_DENY).await?;
+                    continue;
+                }
+                Some(StartupType::GssEncRequest) => {
+                    // No GSSAPI support; the client falls back to a plain StartupMessage
+                    debug!(conn_id, "GSSENC request denied");
+                    self.client.write_all(SSL_DENY).await?;
+                    continue;
+                }
+                Some(StartupType::CancelRequest) => {
+                    debug!(conn_id, "cancel request — closing");
+                    return Ok(Phase::Done);
+                }
+                Some(StartupType::Startup(s)) => break s,
+                None => continue,
             }
+        };
 
-            if msg.is_error_response() {
-                warn!(conn_id, error = %msg.error_message(), "auth error from server");
-            }
+        let raw_user = startup.params.get("user").cloned().unwrap_or_default();
+        if raw_user.is_empty() {
+            send_error(
+                self.client,
+                "FATAL",
+                "08004",
+                "no username in StartupMessage",
+            )
+            .await;
+            return Ok(Phase::Done);
+        }
 
-            client.write_all(&msg.raw).await?;
+        let database = startup
+            .params
+            .get("database")
+            .cloned()
+            .unwrap_or_else(|| "default".into());
+        self.session.database = Some(database.clone());
 
-            if msg.is_auth_challenge() {
-                let mut client_buf = BytesMut::with_capacity(1024);
-                client.read_buf(&mut client_buf).await?;
-                server.write_all(&client_buf).await?;
+        // ─── Replication (bypass users only — no tenant in the username) ──
+
+        if is_replication(&startup.params) {
+            let logical = is_logical_replication(&startup.params);
+            if !config.allows_replication(&raw_user, &database, logical) {
+                warn!(conn_id, user = %raw_user, "replication connection refused");
+                send_error(
+                    self.client,
+                    "FATAL",
+                    "28000",
+                    "replication connections are not allowed for this user",
+                )
+                .await;
+                return Ok(Phase::Done);
             }
+            info!(conn_id, user = %raw_user, "replication passthrough");
+            return self.bypass(&startup.params, &buf, raw_user).await;
         }
-    }
 
-    // ─── Post-auth — wait for ReadyForQuery ─────────────────────────────
+        // ─── Superuser bypass (always passthrough, never pooled) ─────────
 
-    let buffered_ready: BytesMut = loop {
-        if server_buf.is_empty() {
-            server.read_buf(&mut server_buf).await?;
+        if config.is_superuser_bypass(&raw_user, &database) {
+            info!(conn_id, user = %raw_user, "superuser bypass");
+            return self.bypass(&startup.params, &buf, raw_user).await;
         }
 
-        let mut ready_msg = None;
-        while let Some(msg) = try_read_backend_message(&mut server_buf) {
-            if msg.is_ready_for_query() {
-                debug!(
-                    conn_id,
-                    "ReadyForQuery buffered — resolving + injecting context"
-                );
-                ready_msg = Some(msg.raw);
-                break;
-            }
-
-            if msg.is_error_response() {
-                warn!(conn_id, error = %msg.error_message(), "post-auth error");
-            }
+        // ─── Extract tenant context from username ────────────────────────
 
-            client.write_all(&msg.raw).await?;
-        }
+        let Some((actual_user, tenant_payload)) = config.split_username(&raw_user) else {
+            let msg = if config.tenant_regex.is_some() {
+                "username does not match tenant_separator_regex".to_string()
+            } else {
+                format!(
+                    "username must contain context values separated by '{}'",
+                    config.tenant_separator
+                )
+            };
+            send_error(self.client, "FATAL", "28000", &msg).await;
+            return Ok(Phase::Done);
+        };
+        self.session.tenant = Some(tenant_payload.to_string());
+        self.session.role = Some(actual_user.to_string());
 
-        if let Some(raw) = ready_msg {
-            break raw;
+        if actual_user.is_empty() || tenant_payload.is_empty() {
+            send_error(
+                self.client,
+                "FATAL",
+                "28000",
+                "empty role or context in username",
+            )
+            .await;
+            return Ok(Phase::Done);
         }
-    };
 
-    // ─── Resolve context ────────────────────────────────────────────────
+        if config.forbidden_roles.iter().any(|r| r == actual_user) {
+            warn!(conn_id, role = actual_user, "forbidden role");
+            send_error(
+                self.client,
+                "FATAL",
+                "28000",
+                &format!("role \"{actual_user}\" is not allowed"),
+            )
+            .await;
+            return Ok(Phase::Done);
+        }
 
-    let mut context_map = build_static_context(config, context_values);
+        let context_values = split_context_values(config, tenant_payload);
 
-    if let Some(engine) = resolver_engine
-        && let Err(e) = engine
-            .resolve_context(&mut server, &mut server_buf, &mut context_map, conn_id)
-            .await
-    {
-        error!(conn_id, error = %e, "resolver failed — terminating connection");
-        send_error(client, "FATAL", "XX000", &format!("resolver failed: {e}")).await;
-        return Ok((HandshakeResult::Done, None));
-    }
+        if context_values.len() != config.context_variables.len() {
+            send_error(
+                self.client,
+                "FATAL",
+                "28000",
+                &format!(
+                    "expected {} context value(s), got {}",
+                    config.context_variables.len(),
+                    context_values.len()
+                ),
+            )
+            .await;
+            return Ok(Phase::Done);
+        }
 
-    // ─── Inject all context (static + resolved) ─────────────────────────
+        if context_values.iter().any(|v| v.is_empty()) {
+            send_error(
+                self.client,
+                "FATAL",
+                "28000",
+                "empty context value in username",
+            )
+            .await;
+            return Ok(Phase::Done);
+        }
 
-    let target_role = config.set_role.as_deref().unwrap_or(actual_user);
-    inject_context_from_map(
-        &mut server,
-        &mut server_buf,
-        client,
-        target_role,
-        &context_map,
-        &buffered_ready,
-        conn_id,
-    )
-    .await?;
+        let check_tenant_id = if config.tenant_id_allow_unicode {
+            escape_literal_unicode
+        } else {
+            escape_literal
+        };
+        if let Some(e) = context_values.iter().find_map(|v| check_tenant_id(v).err()) {
+            send_error(self.client, "FATAL", "28000", &e.to_string()).await;
+            return Ok(Phase::Done);
+        }
 
-    // Flush any remaining buffered server data
-    if !server_buf.is_empty() {
-        client.write_all(&server_buf).await?;
-    }
+        info!(
+            conn_id,
+            role = actual_user,
+            database = %database,
+            "tenant connection"
+        );
 
-    Ok((HandshakeResult::Passthrough(server), None))
-}
+        // ─── Tenant isolation checks ─────────────────────────────────────
 
-/// Pool mode — pgvpd authenticates client, checks out pooled connection,
-/// resets, resolves context, injects, then enters transparent pipe.
-#[allow(clippy::too_many_arguments)]
-async fn handle_pooled(
-    client: &mut ClientStream,
-    config: &Config,
-    pool: &Arc<Pool>,
-    actual_user: &str,
-    database: &str,
-    context_values: &[&str],
-    resolver_engine: &Option<Arc<ResolverEngine>>,
-    conn_id: u64,
-) -> Result<(HandshakeResult, Option<TenantGuard>), Box<dyn std::error::Error + Send + Sync>> {
-    // ─── Authenticate client ────────────────────────────────────────────
+        let tenant_guard = if let Some(registry) = self.tenant_registry {
+            if let Err(msg) = registry.check_access(tenant_payload) {
+                send_error(self.client, "FATAL", "28000", &msg).await;
+                return Ok(Phase::Done);
+            }
+            match registry.acquire(tenant_payload).await {
+                Ok(guard) => Some(guard),
+                Err(msg) => {
+                    send_error(self.client, "FATAL", "53300", &msg).await;
+                    return Ok(Phase::Done);
+                }
+            }
+        } else {
+            None
+        };
 
-    let pool_password = config.pool_password.as_deref().unwrap_or("");
-    if let Err(e) = auth::authenticate_client(client, pool_password, conn_id).await {
-        send_error(client, "FATAL", "28P01", &e).await;
-        return Ok((HandshakeResult::Done, None));
+        let inject_comment = config.render_inject_comment(
+            conn_id,
+            tenant_payload,
+            config.set_role.as_deref().unwrap_or(actual_user),
+            &database,
+        );
+        let actual_user = actual_user.to_string();
+        let tenant = tenant_payload.to_string();
+
+        Ok(Phase::Authenticating(TenantStartup {
+            params: startup.params,
+            buf,
+            raw_user,
+            actual_user,
+            tenant,
+            database,
+            inject_comment,
+            tenant_guard,
+        }))
     }
 
-    // ─── Checkout from pool ─────────────────────────────────────────────
+    /// Forward the original StartupMessage (and anything after it) to the
+    /// primary untouched and go straight to the transparent pipe.
+    async fn bypass(
+        &mut self,
+        params: &HashMap<String, String>,
+        buf: &BytesMut,
+        user: String,
+    ) -> Result<Phase, Box<dyn std::error::Error + Send + Sync>> {
+        self.session.role = Some(user);
+        let mut server =
+            connect_upstream(self.config, self.upstream_tls, self.dns, Upstream::Primary).await?;
+        server.write_all(&build_startup_message(params)).await?;
+        if !buf.is_empty() {
+            server.write_all(buf).await?;
+        }
+        Ok(Phase::Transparent(
+            HandshakeResult::Passthrough(server),
+            None,
+        ))
+    }
 
-    let key = PoolKey {
-        database: database.to_string(),
-        role: actual_user.to_string(),
-    };
+    // ─── Authenticating ──────────────────────────────────────────────────
+
+    async fn authenticate(
+        &mut self,
+        startup: TenantStartup,
+    ) -> Result<Phase, Box<dyn std::error::Error + Send + Sync>> {
+        if self.config.pool_mode == PoolMode::Session
+            && let Some(pool) = self.pool
+        {
+            let pool = Arc::clone(pool);
+            return Ok(
+                match self.authenticate_client(&startup.raw_user, pool).await? {
+                    Some(authenticated) => Phase::PostAuth(startup, authenticated),
+                    None => Phase::Done,
+                },
+            );
+        }
+        self.relay_upstream_auth(startup).await
+    }
 
-    let pooled = match pool.checkout(&key, conn_id).await {
-        Ok(c) => c,
-        Err(e) => {
-            send_error(
-                client,
-                "FATAL",
-                "53300",
-                &format!("pool checkout failed: {e}"),
-            )
-            .await;
-            return Ok((HandshakeResult::Done, None));
+    /// Pool mode — pgvpd authenticates the client itself (trust_ips, JWT or
+    /// `pool_password`). `None` once a failure has been reported to the client.
+    async fn authenticate_client(
+        &mut self,
+        raw_user: &str,
+        pool: Arc<Pool>,
+    ) -> Result<Option<Authenticated>, Box<dyn std::error::Error + Send + Sync>> {
+        let config = self.config;
+        let conn_id = self.conn_id;
+        let trusted = self
+            .session
+            .peer_addr
+            .is_some_and(|addr| config.is_trusted_ip(addr.ip()));
+        let mut jwt_claims = None;
+        if trusted {
+            debug!(conn_id, "trusted peer — skipping client auth");
+            Metrics::inc(&self.metrics.pool_trust_connections);
+            self.client.write_all(&build_auth_ok()).await?;
+        } else if config.pool_jwt_context {
+            let secret = config.pool_jwt_secret.as_deref().unwrap_or("");
+            match auth::authenticate_client_jwt(self.client, secret, conn_id).await {
+                Ok(claims) => jwt_claims = Some(claims),
+                Err(e) => {
+                    send_error(self.client, "FATAL", "28P01", &e).await;
+                    return Ok(None);
+                }
+            }
+        } else {
+            let pool_password = config.pool_password.as_deref().unwrap_or("");
+            let auth_result = match config.pool_auth_method {
+                PoolAuthMethod::Cleartext => {
+                    auth::authenticate_client(self.client, pool_password, conn_id).await
+                }
+                // libpq hashes with the user name from the StartupMessage (tenant suffix included)
+                PoolAuthMethod::Md5 => {
+                    auth::authenticate_client_md5(self.client, raw_user, pool_password, conn_id)
+                        .await
+                }
+            };
+            if let Err(e) = auth_result {
+                send_error(self.client, "FATAL", "28P01", &e).await;
+                return Ok(None);
+            }
         }
-    };
+        Ok(Some(Authenticated::Pooled { pool, jwt_claims }))
+    }
 
-    let mut server = pooled.stream;
-    let mut server_buf = BytesMut::with_capacity(4096);
+    /// Passthrough — connect to upstream, send the rewritten StartupMessage
+    /// and relay auth between client and upstream until AuthenticationOk.
+    async fn relay_upstream_auth(
+        &mut self,
+        mut startup: TenantStartup,
+    ) -> Result<Phase, Box<dyn std::error::Error + Send + Sync>> {
+        let config = self.config;
+        let conn_id = self.conn_id;
+        let router = ReadWriteRouter::new(config);
+        let upstream = router.route(&startup.params, conn_id);
+        let mut server = connect_upstream(config, self.upstream_tls, self.dns, upstream).await?;
+        let (host, port) = router.addr(upstream);
+        let local = server.local_addr().ok();
+        debug!(conn_id, host, port, %upstream, ?local, "connected to upstream");
+
+        // Send rewritten StartupMessage: apply rename/inject rules, drop parameters
+        // not on the allowlist
+        let (mut rewritten_params, dropped) = rewrite_startup_params(
+            &startup.params,
+            &config.startup_param_allowlist,
+            &config.startup_param_renames,
+            &config.startup_params,
+        );
+        for param in &dropped {
+            debug!(conn_id, param = %param, "dropping startup parameter");
+        }
+        let client_app = startup.params.get("application_name").map(String::as_str);
+        if let Some(app) = config.application_name(client_app, None) {
+            rewritten_params.insert("application_name".into(), app);
+        }
+        rewritten_params.insert("user".into(), startup.actual_user.clone());
+        let startup_msg = build_startup_message(&rewritten_params);
+        server.write_all(&startup_msg).await?;
 
-    // ─── Reset connection ───────────────────────────────────────────────
+        if !startup.buf.is_empty() {
+            server.write_all(&startup.buf).await?;
+            startup.buf.clear();
+        }
 
-    let reset_msg = build_query_message("DISCARD ALL;");
-    server.write_all(&reset_msg).await?;
+        let mut server_buf = BytesMut::with_capacity(4096);
+        let mut auth_done = false;
 
-    loop {
-        server.read_buf(&mut server_buf).await?;
-        let mut done = false;
-        while let Some(msg) = try_read_backend_message(&mut server_buf) {
-            if msg.is_error_response() {
-                error!(conn_id, error = %msg.error_message(), "pool: DISCARD ALL failed");
-                send_error(
-                    client,
-                    "FATAL",
-                    "XX000",
-                    &format!("DISCARD ALL failed: {}", msg.error_message()),
-                )
-                .await;
-                return Ok((HandshakeResult::Done, None));
-            }
-            if msg.is_ready_for_query() {
-                done = true;
-                break;
+        while !auth_done {
+            server.read_buf(&mut server_buf).await?;
+
+            while let Some(msg) = try_read_backend_message(&mut server_buf) {
+                if msg.is_auth_ok() {
+                    debug!(conn_id, "authentication OK");
+                    self.client.write_all(&msg.raw).await?;
+                    auth_done = true;
+                    break;
+                }
+
+                if msg.is_notice_response() {
+                    debug!(conn_id, "forwarding NoticeResponse during auth");
+                    self.client.write_all(&msg.raw).await?;
+                    continue;
+                }
+
+                // Sent before the auth request when the client asked for a newer
+                // minor protocol version or unknown `_pq_.` options.
+                if msg.is_negotiate_protocol_version() {
+                    if config.protocol_negotiate_passthrough {
+                        debug!(conn_id, "forwarding NegotiateProtocolVersion");
+                        self.client.write_all(&msg.raw).await?;
+                    } else {
+                        debug!(conn_id, "dropping NegotiateProtocolVersion");
+                    }
+                    continue;
+                }
+
+                if msg.is_error_response() {
+                    warn!(conn_id, sqlstate = %msg.parse_error_fields().sqlstate, error = %msg.error_message(), "auth error from server");
+                }
+
+                self.client.write_all(&msg.raw).await?;
+
+                if msg.is_auth_challenge() {
+                    let mut client_buf = BytesMut::with_capacity(1024);
+                    self.client.read_buf(&mut client_buf).await?;
+                    server.write_all(&client_buf).await?;
+                }
             }
         }
-        if done {
-            break;
+
+        Ok(Phase::PostAuth(
+            startup,
+            Authenticated::Direct { server, server_buf },
+        ))
+    }
+
+    // ─── PostAuth ────────────────────────────────────────────────────────
+
+    async fn post_auth(
+        &mut self,
+        startup: TenantStartup,
+        authenticated: Authenticated,
+    ) -> Result<Phase, Box<dyn std::error::Error + Send + Sync>> {
+        match authenticated {
+            Authenticated::Direct { server, server_buf } => {
+                self.await_ready(startup, server, server_buf).await
+            }
+            Authenticated::Pooled { pool, jwt_claims } => {
+                self.checkout(startup, pool, jwt_claims).await
+            }
         }
     }
 
-    // ─── Resolve context ────────────────────────────────────────────────
+    /// Passthrough — buffer the upstream's ReadyForQuery (forwarding anything
+    /// before it) so the client only sees it once context is injected.
+    async fn await_ready(
+        &mut self,
+        startup: TenantStartup,
+        mut server: UpstreamStream,
+        mut server_buf: BytesMut,
+    ) -> Result<Phase, Box<dyn std::error::Error + Send + Sync>> {
+        let conn_id = self.conn_id;
+        let buffered_ready: BackendMessage = loop {
+            if server_buf.is_empty() {
+                server.read_buf(&mut server_buf).await?;
+            }
 
-    let mut context_map = build_static_context(config, context_values);
+            let mut ready_msg = None;
+            while let Some(msg) = try_read_backend_message(&mut server_buf) {
+                if msg.is_ready_for_query() {
+                    debug!(
+                        conn_id,
+                        "ReadyForQuery buffered — resolving + injecting context"
+                    );
+                    ready_msg = Some(msg);
+                    break;
+                }
 
-    if let Some(engine) = resolver_engine
-        && let Err(e) = engine
-            .resolve_context(&mut server, &mut server_buf, &mut context_map, conn_id)
-            .await
-    {
-        error!(conn_id, error = %e, "resolver failed (pooled) — terminating");
-        send_error(client, "FATAL", "XX000", &format!("resolver failed: {e}")).await;
-        return Ok((HandshakeResult::Done, None));
-    }
+                if msg.is_notice_response() || msg.is_notification() {
+                    debug!(conn_id, msg_type = %(msg.msg_type as char), "forwarding post-auth async message");
+                    self.client.write_all(&msg.raw).await?;
+                    continue;
+                }
 
-    // ─── Inject context ─────────────────────────────────────────────────
+                if msg.is_error_response() {
+                    warn!(conn_id, sqlstate = %msg.parse_error_fields().sqlstate, error = %msg.error_message(), "post-auth error");
+                }
 
-    let mut set_clauses = Vec::new();
-    for (var, val) in &context_map {
-        match val {
-            Some(v) => {
-                let safe_val = escape_set_value(v);
-                set_clauses.push(format!("SET {var} = {safe_val}"));
+                self.client.write_all(&msg.raw).await?;
             }
-            None => {
-                set_clauses.push(format!("SET {var} = ''"));
+
+            if let Some(msg) = ready_msg {
+                break msg;
             }
-        }
+        };
+
+        Ok(Phase::Resolving(
+            startup,
+            UpstreamSession {
+                server,
+                server_buf,
+                kind: SessionKind::Direct { buffered_ready },
+            },
+        ))
     }
-    let target_role = config.set_role.as_deref().unwrap_or(actual_user);
-    set_clauses.push(format!("SET ROLE {}", quote_ident(target_role)?));
-    let sql = set_clauses.join("; ") + ";";
 
-    debug!(conn_id, sql = %sql, "pool: inject context");
-    let query_msg = build_query_message(&sql);
-    server.write_all(&query_msg).await?;
-
-    loop {
-        server.read_buf(&mut server_buf).await?;
-        let mut done = false;
-        while let Some(msg) = try_read_backend_message(&mut server_buf) {
-            if msg.is_error_response() {
-                error!(conn_id, error = %msg.error_message(), "pool: context injection failed");
+    /// Pool mode — check out a pooled connection and reset it. Checkin only
+    /// ran a light RESET; the full reset happens here, once per session
+    /// (unless pool_reset_query is reset_role or none).
+    async fn checkout(
+        &mut self,
+        startup: TenantStartup,
+        pool: Arc<Pool>,
+        jwt_claims: Option<serde_json::Map<String, serde_json::Value>>,
+    ) -> Result<Phase, Box<dyn std::error::Error + Send + Sync>> {
+        let config = self.config;
+        let conn_id = self.conn_id;
+        let key = PoolKey {
+            database: startup.database.clone(),
+            role: startup.actual_user.clone(),
+            upstream: ReadWriteRouter::new(config).route(&startup.params, conn_id),
+        };
+
+        let (pooled, permit) = match pool.checkout(&key, conn_id).await {
+            Ok(checked_out) => checked_out,
+            Err(e) => {
                 send_error(
-                    client,
+                    self.client,
                     "FATAL",
-                    "XX000",
-                    &format!("context injection failed: {}", msg.error_message()),
+                    "53300",
+                    &format!("pool checkout failed: {e}"),
                 )
                 .await;
-                return Ok((HandshakeResult::Done, None));
+                return Ok(Phase::Done);
             }
-            if msg.is_ready_for_query() {
-                done = true;
-                break;
+        };
+        let injecting = pool.begin_injecting(&key).await;
+        let checkout_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+
+        let mut server = pooled.stream;
+        let mut server_buf = BytesMut::with_capacity(4096);
+
+        let mut ready_status = b'I';
+        if let Some(reset_query) = config.pool_reset_query.checkout_query() {
+            server.write_all(&build_query_message(reset_query)).await?;
+            loop {
+                server.read_buf(&mut server_buf).await?;
+                let mut done = false;
+                while let Some(msg) = try_read_backend_message(&mut server_buf) {
+                    if msg.is_error_response() {
+                        error!(conn_id, sqlstate = %msg.parse_error_fields().sqlstate, error = %msg.error_message(), "pool: reset query failed");
+                        send_error(
+                            self.client,
+                            "FATAL",
+                            "XX000",
+                            &format!("pool reset query failed: {}", msg.error_message()),
+                        )
+                        .await;
+                        return Ok(Phase::Done);
+                    }
+                    if let Some(status) = msg.transaction_status() {
+                        ready_status = status;
+                        done = true;
+                        break;
+                    }
+                }
+                if done {
+                    break;
+                }
             }
         }
-        if done {
-            break;
+
+        Ok(Phase::Resolving(
+            startup,
+            UpstreamSession {
+                server,
+                server_buf,
+                kind: SessionKind::Pooled(PooledSession {
+                    pool,
+                    key,
+                    created_at: pooled.created_at,
+                    permit,
+                    param_statuses: pooled.param_statuses,
+                    backend_key_data: pooled.backend_key_data,
+                    ready_status,
+                    checkout_ms,
+                    injecting,
+                    jwt_claims,
+                }),
+            },
+        ))
+    }
+
+    // ─── Resolving ───────────────────────────────────────────────────────
+
+    /// Build the context map from the username (and JWT claims in pool mode),
+    /// then run the resolvers against the upstream: the database's own
+    /// `[[database_resolver]]` file if it has one, else the global one.
+    async fn resolve(
+        &mut self,
+        startup: TenantStartup,
+        mut upstream: UpstreamSession,
+    ) -> Result<Phase, Box<dyn std::error::Error + Send + Sync>> {
+        let config = self.config;
+        let conn_id = self.conn_id;
+        let context_values = split_context_values(config, &startup.tenant);
+        let mut context_map = build_static_context(config, &context_values);
+        if let SessionKind::Pooled(PooledSession {
+            jwt_claims: Some(claims),
+            ..
+        }) = &upstream.kind
+        {
+            let jwt = jwt_context(&config.pool_jwt_claims, claims);
+            if let Err(var) = merge_jwt_context(&mut context_map, jwt) {
+                warn!(conn_id, var = %var, "JWT claim does not match the username tenant");
+                send_error(
+                    self.client,
+                    "FATAL",
+                    "28000",
+                    &format!("JWT claim for {var} does not match the tenant in the username"),
+                )
+                .await;
+                return Ok(Phase::Done);
+            }
         }
+
+        let engine = self
+            .database_resolvers
+            .get(&startup.database)
+            .or(self.resolver_engine.as_ref());
+        if let Some(engine) = engine
+            && let Err(e) = engine
+                .resolve_context(
+                    &mut upstream.server,
+                    &mut upstream.server_buf,
+                    &mut *self.client,
+                    &mut context_map,
+                    &startup.params,
+                    conn_id,
+                )
+                .await
+        {
+            if matches!(upstream.kind, SessionKind::Pooled(_)) {
+                error!(conn_id, error = %e, "resolver failed (pooled) — terminating");
+            } else {
+                error!(conn_id, error = %e, "resolver failed — terminating connection");
+            }
+            send_error(
+                self.client,
+                "FATAL",
+                "XX000",
+                &format!("resolver failed: {e}"),
+            )
+            .await;
+            return Ok(Phase::Done);
+        }
+
+        Ok(Phase::Injecting(startup, upstream, context_map))
     }
 
-    // ─── Synthesize handshake to client ─────────────────────────────────
+    // ─── Injecting ───────────────────────────────────────────────────────
+
+    async fn inject(
+        &mut self,
+        startup: TenantStartup,
+        upstream: UpstreamSession,
+        context_map: HashMap<String, Option<String>>,
+    ) -> Result<Phase, Box<dyn std::error::Error + Send + Sync>> {
+        let UpstreamSession {
+            mut server,
+            mut server_buf,
+            kind,
+        } = upstream;
+        let target_role = match self.config.set_role_ident() {
+            Some(role) => role,
+            None => quote_ident(&startup.actual_user)?,
+        };
+        if self.config.require_role_exists {
+            let role = self.config.target_role(&startup.actual_user);
+            if let Err(e) = check_role(&mut server, role).await {
+                warn!(conn_id = self.conn_id, role, error = %e, "role check failed");
+                // No checkin follows, and the lookup may have stopped mid-reply
+                if let SessionKind::Pooled(pooled) = kind {
+                    pooled.pool.discard_checked_out(pooled.permit).await;
+                }
+                send_error(self.client, "FATAL", "28000", &e).await;
+                return Ok(Phase::Done);
+            }
+        }
+        match kind {
+            SessionKind::Direct { buffered_ready } => {
+                inject_context_from_map(
+                    &mut server,
+                    &mut server_buf,
+                    self.client,
+                    &target_role,
+                    &context_map,
+                    self.config,
+                    &buffered_ready,
+                    startup.inject_comment.as_deref(),
+                    self.conn_id,
+                )
+                .await?;
+
+                // Flush any remaining buffered server data
+                if !server_buf.is_empty() {
+                    self.client.write_all(&server_buf).await?;
+                }
 
-    for ps in &pooled.param_statuses {
-        client.write_all(ps).await?;
+                Ok(Phase::Transparent(
+                    HandshakeResult::Passthrough(server),
+                    startup.tenant_guard,
+                ))
+            }
+            SessionKind::Pooled(pooled) => {
+                self.inject_pooled(
+                    startup,
+                    server,
+                    server_buf,
+                    pooled,
+                    &context_map,
+                    &target_role,
+                )
+                .await
+            }
+        }
     }
-    client.write_all(&pooled.backend_key_data).await?;
-    let ready = build_ready_for_query();
-    client.write_all(&ready).await?;
 
-    let context_summary: String = context_map
-        .iter()
-        .map(|(k, v)| format!("{}={}", k, v.as_deref().unwrap_or("NULL")))
-        .collect::<Vec<_>>()
-        .join(", ");
+    /// Pool mode — inject the context, then synthesize the rest of the
+    /// startup handshake (cached ParameterStatus, BackendKeyData,
+    /// ReadyForQuery) for the client.
+    async fn inject_pooled(
+        &mut self,
+        startup: TenantStartup,
+        mut server: UpstreamStream,
+        mut server_buf: BytesMut,
+        pooled: PooledSession,
+        context_map: &HashMap<String, Option<String>>,
+        target_role: &str,
+    ) -> Result<Phase, Box<dyn std::error::Error + Send + Sync>> {
+        let config = self.config;
+        let conn_id = self.conn_id;
+        let application_name = config.application_name(
+            startup.params.get("application_name").map(String::as_str),
+            Some(startup.tenant.as_str()),
+        );
+
+        let mut set_clauses =
+            config.tenant_label_set_clauses(&startup.tenant, &startup.actual_user);
+        set_clauses.extend(config.conn_id_set_clauses(conn_id, Some(pooled.checkout_ms)));
+        // The reset query reverted application_name to the pool label; tag this
+        // session first so it shows in pg_stat_activity even if a later SET fails
+        if let Some(app) = &application_name {
+            set_clauses.push(format!("SET application_name = {}", escape_set_value(app)));
+        }
+        set_clauses.extend(context_set_clauses(context_map, &config.context_variables));
+        set_clauses.push(format!("SET ROLE {target_role}"));
+        let sql = wrap_in_transaction(
+            &set_clauses,
+            pooled.ready_status,
+            startup.inject_comment.as_deref(),
+        );
+
+        debug!(conn_id, sql = %sql, "pool: inject context");
+        let query_msg = build_query_message(&sql);
+        server.write_all(&query_msg).await?;
+
+        loop {
+            server.read_buf(&mut server_buf).await?;
+            let mut done = false;
+            while let Some(msg) = try_read_backend_message(&mut server_buf) {
+                if msg.is_error_response() {
+                    error!(conn_id, sqlstate = %msg.parse_error_fields().sqlstate, error = %msg.error_message(), "pool: context injection failed");
+                    send_error(
+                        self.client,
+                        "FATAL",
+                        "XX000",
+                        &format!("context injection failed: {}", msg.error_message()),
+                    )
+                    .await;
+                    return Ok(Phase::Done);
+                }
+                if msg.is_ready_for_query() {
+                    done = true;
+                    break;
+                }
+            }
+            if done {
+                break;
+            }
+        }
+        drop(pooled.injecting);
 
-    info!(
-        conn_id,
-        context = %context_summary,
-        role = target_role,
-        "context set (pooled)"
-    );
+        for ps in &pooled.param_statuses {
+            self.client.write_all(ps).await?;
+        }
+        self.client.write_all(&pooled.backend_key_data).await?;
+
+        let context_summary: String = context_map
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v.as_deref().unwrap_or("NULL")))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if config.send_context_notice {
+            let notice = context_notice_message(&context_summary, target_role);
+            self.client
+                .write_all(&build_notice_response("NOTICE", &notice))
+                .await?;
+        }
+        let ready = build_ready_for_query();
+        self.client.write_all(&ready).await?;
 
-    Ok((
-        HandshakeResult::Pooled {
-            stream: server,
-            key,
-            pool: Arc::clone(pool),
-        },
-        None,
-    ))
+        info!(
+            conn_id,
+            context = %context_summary,
+            role = target_role,
+            "context set (pooled)"
+        );
+
+        Ok(Phase::Transparent(
+            HandshakeResult::Pooled {
+                stream: server,
+                key: pooled.key,
+                pool: pooled.pool,
+                created_at: pooled.created_at,
+                permit: pooled.permit,
+            },
+            startup.tenant_guard,
+        ))
+    }
 }
 
 /// Build a context map from static (username-extracted) values.
@@ -731,37 +1541,109 @@ fn build_static_context(
     map
 }
 
-/// Inject context from a map of session_var → value. Sends SET statements + SET ROLE,
-/// consumes response, forwards buffered ReadyForQuery to client.
+/// Context values taken from JWT claims, per the `pool_jwt_claims` mapping.
+/// Strings are used as-is, other JSON values as their JSON text; a null claim
+/// maps to NULL. Missing claims are skipped, leaving the variable as it was.
+fn jwt_context(
+    mapping: &[(String, String)],
+    claims: &serde_json::Map<String, serde_json::Value>,
+) -> Vec<(String, Option<String>)> {
+    mapping
+        .iter()
+        .filter_map(|(claim, var)| {
+            let value = match claims.get(claim)? {
+                serde_json::Value::Null => None,
+                serde_json::Value::String(s) => Some(s.clone()),
+                other => Some(other.to_string()),
+            };
+            Some((var.clone(), value))
+        })
+        .collect()
+}
+
+/// Add JWT context values to `context`. A value for a variable the username
+/// already set must agree with it: the username tenant is what passed
+/// tenant_allow/deny and the registry's checks, and a claim may not swap in
+/// another one. Returns the first conflicting variable.
+fn merge_jwt_context(
+    context: &mut HashMap<String, Option<String>>,
+    jwt: Vec<(String, Option<String>)>,
+) -> Result<(), String> {
+    for (var, value) in jwt {
+        match context.get(&var) {
+            Some(existing) if *existing != value => return Err(var),
+            _ => {
+                context.insert(var, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Context entries in injection order: `context_variables` in config order,
+/// then everything else (resolver outputs, startup params) sorted by name, so
+/// the generated SQL is the same for the same context.
+fn ordered_context<'a>(
+    context: &'a HashMap<String, Option<String>>,
+    context_variables: &[String],
+) -> Vec<(&'a String, &'a Option<String>)> {
+    let mut ordered: Vec<_> = context_variables
+        .iter()
+        .filter_map(|var| context.get_key_value(var))
+        .collect();
+    let mut rest: Vec<_> = context
+        .iter()
+        .filter(|(var, _)| !context_variables.contains(var))
+        .collect();
+    rest.sort_by(|a, b| a.0.cmp(b.0));
+    ordered.extend(rest);
+    ordered
+}
+
+/// One `SET` per context entry, in `ordered_context` order. The map merges
+/// validated tenant IDs with resolver output, so every value goes through
+/// `escape_set_value`; NULLs become empty strings.
+fn context_set_clauses(
+    context: &HashMap<String, Option<String>>,
+    context_variables: &[String],
+) -> Vec<String> {
+    ordered_context(context, context_variables)
+        .into_iter()
+        .map(|(var, val)| match val {
+            Some(v) => format!("SET {var} = {}", escape_set_value(v)),
+            None => format!("SET {var} = ''"),
+        })
+        .collect()
+}
+
+/// Inject context from a map of session_var → value. Sends SET statements + SET ROLE
+/// (`role` is already a quoted identifier), consumes response, forwards buffered
+/// ReadyForQuery to client. With `send_context_notice`, a NoticeResponse listing
+/// the applied context precedes it.
+#[allow(clippy::too_many_arguments)]
 async fn inject_context_from_map(
     server: &mut UpstreamStream,
     server_buf: &mut BytesMut,
     client: &mut ClientStream,
-    actual_user: &str,
+    role: &str,
     context: &HashMap<String, Option<String>>,
-    buffered_ready: &[u8],
+    config: &Config,
+    buffered_ready: &BackendMessage,
+    inject_comment: Option<&str>,
     conn_id: u64,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut set_clauses = Vec::new();
-    for (var, val) in context {
-        match val {
-            Some(v) => {
-                // Use escape_literal for static context (validated tenant IDs),
-                // escape_set_value for everything else. Since the map merges both,
-                // use escape_set_value uniformly — it's safe for all values.
-                let safe_val = escape_set_value(v);
-                set_clauses.push(format!("SET {var} = {safe_val}"));
-            }
-            None => {
-                set_clauses.push(format!("SET {var} = ''"));
-            }
-        }
+    let context_variables = &config.context_variables;
+    let mut set_clauses = config.conn_id_set_clauses(conn_id, None);
+    set_clauses.extend(context_set_clauses(context, context_variables));
+    set_clauses.push(format!("SET ROLE {role}"));
+    let ready_status = buffered_ready.transaction_status().unwrap_or(b'I');
+    if ready_status != b'I' {
+        warn!(conn_id, status = %(ready_status as char), "upstream not idle before injection — rolling back");
     }
-    set_clauses.push(format!("SET ROLE {}", quote_ident(actual_user)?));
-    let sql = set_clauses.join("; ") + ";";
+    let sql = wrap_in_transaction(&set_clauses, ready_status, inject_comment);
 
-    let context_summary: String = context
-        .iter()
+    let context_summary: String = ordered_context(context, context_variables)
+        .into_iter()
         .map(|(k, v)| format!("{}={}", k, v.as_deref().unwrap_or("NULL")))
         .collect::<Vec<_>>()
         .join(", ");
@@ -776,7 +1658,7 @@ async fn inject_context_from_map(
         let mut injection_done = false;
         while let Some(msg) = try_read_backend_message(server_buf) {
             if msg.is_error_response() {
-                error!(conn_id, error = %msg.error_message(), "context injection failed");
+                error!(conn_id, sqlstate = %msg.parse_error_fields().sqlstate, error = %msg.error_message(), "context injection failed");
                 client.write_all(&msg.raw).await?;
                 return Err(msg.error_message().into());
             }
@@ -785,15 +1667,24 @@ async fn inject_context_from_map(
                 info!(
                     conn_id,
                     context = %context_summary,
-                    role = actual_user,
+                    role,
                     "context set"
                 );
-                client.write_all(buffered_ready).await?;
+                if config.send_context_notice {
+                    let notice = context_notice_message(&context_summary, role);
+                    client
+                        .write_all(&build_notice_response("NOTICE", &notice))
+                        .await?;
+                }
+                // Forward the post-COMMIT ReadyForQuery: it reflects the session's
+                // real (idle) status even if a stale transaction was rolled back.
+                client.write_all(&msg.raw).await?;
                 injection_done = true;
                 break;
             }
 
-            if msg.is_parameter_status() {
+            // Asynchronous messages can arrive mid-injection; don't swallow them
+            if msg.is_parameter_status() || msg.is_notice_response() || msg.is_notification() {
                 client.write_all(&msg.raw).await?;
             }
         }
@@ -806,6 +1697,26 @@ async fn inject_context_from_map(
     Ok(())
 }
 
+/// `require_role_exists`: `role` must exist upstream and not be a superuser.
+/// Fails closed if the lookup itself fails.
+async fn check_role(server: &mut UpstreamStream, role: &str) -> Result<(), String> {
+    let sql = format!(
+        "SELECT rolsuper FROM pg_roles WHERE rolname = {}",
+        escape_set_value(role)
+    );
+    match query_first_value(server, &sql).await {
+        Ok(None) => Err(format!("role \"{role}\" does not exist")),
+        Ok(Some(rolsuper)) if rolsuper == "t" => Err(format!("role \"{role}\" is a superuser")),
+        Ok(Some(_)) => Ok(()),
+        Err(e) => Err(format!("could not verify role \"{role}\": {e}")),
+    }
+}
+
+/// Text of the `send_context_notice` NoticeResponse.
+fn context_notice_message(context_summary: &str, role: &str) -> String {
+    format!("pgvpd: context set ({context_summary}), role {role}")
+}
+
 /// Build a ReadyForQuery ('Z') message with 'I' (idle) status.
 fn build_ready_for_query() -> BytesMut {
     use bytes::BufMut;
@@ -816,15 +1727,38 @@ fn build_ready_for_query() -> BytesMut {
     buf
 }
 
-/// Connect to upstream Postgres, optionally wrapping in TLS.
+/// Upper bound on the delay between upstream connect retries.
+const MAX_CONNECT_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Connect to the primary or a read replica, optionally wrapping in TLS. A failed
+/// TCP connect is retried `upstream_connect_retries` times with exponential backoff.
 pub async fn connect_upstream(
     config: &Config,
     upstream_tls: &Option<Arc<ClientConfig>>,
+    dns: &DnsCache,
+    upstream: Upstream,
 ) -> Result<UpstreamStream, Box<dyn std::error::Error + Send + Sync>> {
-    let tcp = TcpStream::connect((&*config.upstream_host, config.upstream_port)).await?;
+    let (host, port) = ReadWriteRouter::new(config).addr(upstream);
+    let dns_ttl = Duration::from_secs(config.upstream_dns_ttl_secs);
+    let tcp = retry_with_backoff(
+        config.upstream_connect_retries,
+        Duration::from_millis(config.upstream_connect_retry_delay_ms),
+        || async move {
+            if dns_ttl.is_zero() {
+                return TcpStream::connect((host, port)).await;
+            }
+            let result = connect_first(&dns.resolve(host, port, dns_ttl).await?).await;
+            if result.is_err() {
+                // The addresses may be stale; look them up again on the next attempt
+                dns.invalidate(host, port);
+            }
+            result
+        },
+    )
+    .await?;
 
     if let Some(tls_config) = upstream_tls {
-        let server_name = parse_server_name(&config.upstream_host)?;
+        let server_name = parse_server_name(host)?;
         let connector = tokio_rustls::TlsConnector::from(Arc::clone(tls_config));
         let tls_stream = connector.connect(server_name, tcp).await?;
         Ok(UpstreamStream::Tls(tls_stream))
@@ -833,9 +1767,748 @@ pub async fn connect_upstream(
     }
 }
 
+/// Connect to the first of `addrs` that accepts, trying them in order (as
+/// `TcpStream::connect` does for a host name). Returns the last error if
+/// none does.
+async fn connect_first(addrs: &[SocketAddr]) -> std::io::Result<TcpStream> {
+    let mut last_err = None;
+    for addr in addrs {
+        match TcpStream::connect(addr).await {
+            Ok(tcp) => return Ok(tcp),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "no upstream addresses")
+    }))
+}
+
+/// Check that the primary accepts connections. With `pool_upstream_user` set,
+/// logs in (with `upstream_password`) and runs an empty query; otherwise the
+/// server answering the StartupMessage with an authentication request counts
+/// as reachable. No retries — the caller applies its own timeout.
+pub async fn probe_upstream(
+    config: &Config,
+    upstream_tls: &Option<Arc<ClientConfig>>,
+    dns: &DnsCache,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let probe_config = Config {
+        upstream_connect_retries: 0,
+        ..config.clone()
+    };
+    let mut server = connect_upstream(&probe_config, upstream_tls, dns, Upstream::Primary).await?;
+    let user = config.pool_upstream_user.as_deref();
+    let params = HashMap::from([
+        ("user".to_string(), user.unwrap_or("pgvpd").to_string()),
+        ("database".to_string(), "postgres".to_string()),
+        ("application_name".to_string(), "pgvpd-health".to_string()),
+    ]);
+    server.write_all(&build_startup_message(&params)).await?;
+
+    let mut server_buf = BytesMut::with_capacity(1024);
+    let Some(user) = user else {
+        loop {
+            if server.read_buf(&mut server_buf).await? == 0 {
+                return Err("upstream closed the connection".into());
+            }
+            if let Some(msg) = try_read_backend_message(&mut server_buf) {
+                return if msg.is_error_response() {
+                    Err(msg.error_message().into())
+                } else {
+                    Ok(())
+                };
+            }
+        }
+    };
+
+    let password = config.upstream_password.as_deref().unwrap_or("");
+    auth::authenticate_upstream(
+        &mut server,
+        &mut server_buf,
+        user,
+        password,
+        config.upstream_auth_method,
+        config.scram_iterations(),
+        None,
+        0,
+    )
+    .await?;
+    let mut query_sent = false;
+    loop {
+        while let Some(msg) = try_read_backend_message(&mut server_buf) {
+            if msg.is_error_response() {
+                return Err(msg.error_message().into());
+            }
+            if msg.is_ready_for_query() {
+                if query_sent {
+                    return Ok(());
+                }
+                server.write_all(&build_query_message(";")).await?;
+                query_sent = true;
+            }
+        }
+        if server.read_buf(&mut server_buf).await? == 0 {
+            return Err("upstream closed the connection".into());
+        }
+    }
+}
+
+/// Run `attempt` until it succeeds or `retries` retries are used up, sleeping
+/// `delay` before the first retry and doubling it (up to 30s) after each.
+/// Returns the last error.
+async fn retry_with_backoff<T, F, Fut>(
+    retries: u32,
+    mut delay: Duration,
+    mut attempt: F,
+) -> std::io::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = std::io::Result<T>>,
+{
+    let mut tries = 0;
+    loop {
+        match attempt().await {
+            Ok(v) => return Ok(v),
+            Err(e) if tries < retries => {
+                tries += 1;
+                warn!(
+                    attempt = tries,
+                    max = retries,
+                    delay_ms = delay.as_millis() as u64,
+                    error = %e,
+                    "upstream connect failed — retrying"
+                );
+                tokio::time::sleep(delay).await;
+                delay = next_retry_delay(delay);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn next_retry_delay(delay: Duration) -> Duration {
+    (delay * 2).min(MAX_CONNECT_RETRY_DELAY)
+}
+
 async fn send_error(client: &mut ClientStream, severity: &str, sqlstate: &str, message: &str) {
     warn!(message, "rejecting connection");
     let msg = build_error_response(severity, sqlstate, message);
     let _ = client.write_all(&msg).await;
     let _ = client.shutdown().await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    // ─── Context injection SQL ───────────────────────────────────────────
+
+    #[test]
+    fn test_context_injection_sql_is_deterministic() {
+        let context_variables = vec!["app.tenant_id".to_string(), "app.user_id".to_string()];
+        let context: HashMap<String, Option<String>> = HashMap::from([
+            ("app.team_ids".to_string(), Some("t1,t2".to_string())),
+            ("app.user_id".to_string(), Some("u1".to_string())),
+            ("app.org_role".to_string(), None),
+            ("app.tenant_id".to_string(), Some("acme".to_string())),
+            ("app.org_id".to_string(), Some("o'1".to_string())),
+        ]);
+        let mut clauses = context_set_clauses(&context, &context_variables);
+        clauses.push("SET ROLE \"app_user\"".to_string());
+        assert_eq!(
+            wrap_in_transaction(&clauses, b'I', None),
+            "BEGIN; \
+             SET app.tenant_id = 'acme'; \
+             SET app.user_id = 'u1'; \
+             SET app.org_id = 'o''1'; \
+             SET app.org_role = ''; \
+             SET app.team_ids = 't1,t2'; \
+             SET ROLE \"app_user\"; \
+             COMMIT;"
+        );
+    }
+
+    #[test]
+    fn test_jwt_context_maps_claims() {
+        let mapping = vec![
+            ("tenant_id".to_string(), "app.tenant_id".to_string()),
+            ("teams".to_string(), "app.team_ids".to_string()),
+            ("missing".to_string(), "app.org_id".to_string()),
+        ];
+        let claims =
+            serde_json::json!({"tenant_id": "acme", "teams": [1, 2], "sub": "u1", "org": null});
+        let context = jwt_context(&mapping, claims.as_object().unwrap());
+        // The missing claim is skipped rather than set to NULL
+        assert_eq!(
+            context,
+            vec![
+                ("app.tenant_id".to_string(), Some("acme".to_string())),
+                ("app.team_ids".to_string(), Some("[1,2]".to_string())),
+            ]
+        );
+        let mapping = vec![("org".to_string(), "app.org_id".to_string())];
+        let context = jwt_context(&mapping, claims.as_object().unwrap());
+        assert_eq!(context, vec![("app.org_id".to_string(), None)]);
+    }
+
+    #[test]
+    fn test_jwt_context_cannot_change_the_username_tenant() {
+        let config = Config {
+            context_variables: vec!["app.tenant_id".into()],
+            ..Config::default()
+        };
+        let mut context = build_static_context(&config, &["acme"]);
+        let same = vec![
+            ("app.tenant_id".to_string(), Some("acme".to_string())),
+            ("app.user_id".to_string(), Some("u1".to_string())),
+        ];
+        assert_eq!(merge_jwt_context(&mut context, same), Ok(()));
+        assert_eq!(context["app.user_id"].as_deref(), Some("u1"));
+
+        for other in [Some("globex".to_string()), None] {
+            let jwt = vec![("app.tenant_id".to_string(), other)];
+            assert_eq!(
+                merge_jwt_context(&mut context, jwt),
+                Err("app.tenant_id".to_string())
+            );
+            assert_eq!(context["app.tenant_id"].as_deref(), Some("acme"));
+        }
+    }
+
+    // ─── Handshake state machine ─────────────────────────────────────────
+
+    /// Upstream that trusts any StartupMessage and answers the first query
+    /// with ReadyForQuery.
+    async fn trusting_upstream(listener: tokio::net::TcpListener) -> TcpStream {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let len = socket.read_u32().await.unwrap();
+        let mut startup = vec![0; len as usize - 4];
+        socket.read_exact(&mut startup).await.unwrap();
+        socket.write_all(&build_auth_ok()).await.unwrap();
+        socket.write_all(&build_ready_for_query()).await.unwrap();
+
+        assert_eq!(socket.read_u8().await.unwrap(), b'Q');
+        let len = socket.read_u32().await.unwrap();
+        let mut query = vec![0; len as usize - 4];
+        socket.read_exact(&mut query).await.unwrap();
+        socket.write_all(&build_ready_for_query()).await.unwrap();
+        socket
+    }
+
+    /// Proxy-side stream whose client has already sent a StartupMessage.
+    async fn client_with_startup(params: &[(&str, &str)]) -> (ClientStream, TcpStream) {
+        let proxy = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut app = TcpStream::connect(proxy.local_addr().unwrap())
+            .await
+            .unwrap();
+        let client = ClientStream::Plain(proxy.accept().await.unwrap().0);
+        let params: HashMap<String, String> = params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        app.write_all(&build_startup_message(&params))
+            .await
+            .unwrap();
+        (client, app)
+    }
+
+    #[tokio::test]
+    async fn replication_startup_bypasses_tenant_parsing() {
+        let upstream = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = Config {
+            upstream_host: "127.0.0.1".into(),
+            upstream_port: upstream.local_addr().unwrap().port(),
+            ..Config::default()
+        };
+        let upstream_task = tokio::spawn(async move {
+            let (mut socket, _) = upstream.accept().await.unwrap();
+            let len = socket.read_u32().await.unwrap();
+            let mut startup = vec![0; len as usize - 4];
+            socket.read_exact(&mut startup).await.unwrap();
+            startup
+        });
+
+        // No tenant separator in the username — would otherwise be rejected
+        let (mut client, _app) =
+            client_with_startup(&[("user", "postgres"), ("replication", "database")]).await;
+        let metrics = Metrics::new(Vec::new());
+        let mut session = SessionInfo::default();
+        let (result, _) = handshake(
+            &mut client,
+            &config,
+            &None,
+            &DnsCache::new(),
+            &None,
+            &None,
+            &HashMap::new(),
+            &None,
+            &metrics,
+            &mut session,
+            1,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(result, HandshakeResult::Passthrough(_)));
+        assert_eq!(session.tenant, None);
+        assert_eq!(session.role.as_deref(), Some("postgres"));
+
+        // The startup reaches upstream unmodified, replication flag included
+        let startup = upstream_task.await.unwrap();
+        assert!(
+            startup
+                .windows(b"replication\0database\0".len())
+                .any(|w| w == b"replication\0database\0")
+        );
+    }
+
+    #[tokio::test]
+    async fn replication_refused_for_other_users() {
+        let config = Config::default();
+        let (mut client, mut app) =
+            client_with_startup(&[("user", "app_user.acme"), ("replication", "true")]).await;
+        let metrics = Metrics::new(Vec::new());
+        let mut session = SessionInfo::default();
+        let (result, _) = handshake(
+            &mut client,
+            &config,
+            &None,
+            &DnsCache::new(),
+            &None,
+            &None,
+            &HashMap::new(),
+            &None,
+            &metrics,
+            &mut session,
+            1,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(result, HandshakeResult::Done));
+        assert_eq!(app.read_u8().await.unwrap(), b'E');
+    }
+
+    #[tokio::test]
+    async fn logical_replication_limited_to_bypass_databases() {
+        let config = Config {
+            superuser_bypass: vec![crate::config::SuperuserBypassRule {
+                user: "postgres".into(),
+                databases: vec!["postgres".into()],
+            }],
+            ..Config::default()
+        };
+        // replication=database connects to app_db, which postgres can't bypass
+        let (mut client, mut app) = client_with_startup(&[
+            ("user", "postgres"),
+            ("database", "app_db"),
+            ("replication", "database"),
+        ])
+        .await;
+        let metrics = Metrics::new(Vec::new());
+        let mut session = SessionInfo::default();
+        let (result, _) = handshake(
+            &mut client,
+            &config,
+            &None,
+            &DnsCache::new(),
+            &None,
+            &None,
+            &HashMap::new(),
+            &None,
+            &metrics,
+            &mut session,
+            1,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(result, HandshakeResult::Done));
+        assert_eq!(app.read_u8().await.unwrap(), b'E');
+    }
+
+    #[tokio::test]
+    async fn forbidden_role_refused() {
+        let config = Config {
+            forbidden_roles: vec!["postgres".into()],
+            ..Config::default()
+        };
+        let (mut client, mut app) =
+            client_with_startup(&[("user", "postgres.acme"), ("database", "app")]).await;
+        let metrics = Metrics::new(Vec::new());
+        let mut session = SessionInfo::default();
+        let (result, _) = handshake(
+            &mut client,
+            &config,
+            &None,
+            &DnsCache::new(),
+            &None,
+            &None,
+            &HashMap::new(),
+            &None,
+            &metrics,
+            &mut session,
+            1,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(result, HandshakeResult::Done));
+        assert_eq!(app.read_u8().await.unwrap(), b'E');
+    }
+
+    /// Upstream that answers one query with `rows` single-column DataRows.
+    async fn role_lookup(
+        rows: &'static [&'static str],
+    ) -> (UpstreamStream, tokio::task::JoinHandle<String>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let task = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            assert_eq!(socket.read_u8().await.unwrap(), b'Q');
+            let len = socket.read_u32().await.unwrap();
+            let mut query = vec![0; len as usize - 4];
+            socket.read_exact(&mut query).await.unwrap();
+            for value in rows {
+                let mut row = vec![b'D'];
+                row.extend_from_slice(&(10 + value.len() as u32).to_be_bytes());
+                row.extend_from_slice(&1u16.to_be_bytes());
+                row.extend_from_slice(&(value.len() as u32).to_be_bytes());
+                row.extend_from_slice(value.as_bytes());
+                socket.write_all(&row).await.unwrap();
+            }
+            socket.write_all(&build_ready_for_query()).await.unwrap();
+            String::from_utf8_lossy(&query).into_owned()
+        });
+        (UpstreamStream::Plain(server), task)
+    }
+
+    #[tokio::test]
+    async fn check_role_requires_existing_non_superuser() {
+        let (mut server, task) = role_lookup(&["f"]).await;
+        assert_eq!(check_role(&mut server, "app_user").await, Ok(()));
+        assert!(task.await.unwrap().contains("rolname = 'app_user'"));
+
+        let (mut server, _) = role_lookup(&[]).await;
+        assert_eq!(
+            check_role(&mut server, "ghost").await.unwrap_err(),
+            "role \"ghost\" does not exist"
+        );
+
+        let (mut server, _) = role_lookup(&["t"]).await;
+        assert_eq!(
+            check_role(&mut server, "postgres").await.unwrap_err(),
+            "role \"postgres\" is a superuser"
+        );
+    }
+
+    #[tokio::test]
+    async fn passthrough_handshake_walks_every_state() {
+        let upstream = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = Config {
+            upstream_host: "127.0.0.1".into(),
+            upstream_port: upstream.local_addr().unwrap().port(),
+            ..Config::default()
+        };
+        let upstream_task = tokio::spawn(trusting_upstream(upstream));
+
+        let proxy = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut app = TcpStream::connect(proxy.local_addr().unwrap())
+            .await
+            .unwrap();
+        let mut client = ClientStream::Plain(proxy.accept().await.unwrap().0);
+        let params = HashMap::from([
+            ("user".to_string(), "app_user.acme".to_string()),
+            ("database".to_string(), "app".to_string()),
+        ]);
+        app.write_all(&build_startup_message(&params))
+            .await
+            .unwrap();
+
+        let metrics = Metrics::new(Vec::new());
+        let mut session = SessionInfo::default();
+        let (result, _) = handshake(
+            &mut client,
+            &config,
+            &None,
+            &DnsCache::new(),
+            &None,
+            &None,
+            &HashMap::new(),
+            &None,
+            &metrics,
+            &mut session,
+            1,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(result, HandshakeResult::Passthrough(_)));
+        assert_eq!(session.tenant.as_deref(), Some("acme"));
+
+        use ConnectionState::*;
+        let path = [
+            WaitingForStartup,
+            Authenticating,
+            PostAuth,
+            Resolving,
+            Injecting,
+            Transparent,
+        ];
+        for from in ConnectionState::ALL {
+            for to in ConnectionState::ALL {
+                let taken = path.windows(2).any(|w| *w == [from, to]);
+                assert_eq!(
+                    metrics.state_transitions(from, to),
+                    u64::from(taken),
+                    "{from:?} -> {to:?}"
+                );
+            }
+        }
+        upstream_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn passthrough_forwards_backend_key_data() {
+        let upstream = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = Config {
+            upstream_host: "127.0.0.1".into(),
+            upstream_port: upstream.local_addr().unwrap().port(),
+            ..Config::default()
+        };
+        let upstream_task = tokio::spawn(async move {
+            let (mut socket, _) = upstream.accept().await.unwrap();
+            let len = socket.read_u32().await.unwrap();
+            let mut startup = vec![0; len as usize - 4];
+            socket.read_exact(&mut startup).await.unwrap();
+            socket.write_all(&build_auth_ok()).await.unwrap();
+            // BackendKeyData: pid 4242, secret 0xdeadbeef
+            let mut key_data = vec![b'K', 0, 0, 0, 12];
+            key_data.extend_from_slice(&4242u32.to_be_bytes());
+            key_data.extend_from_slice(&0xdead_beefu32.to_be_bytes());
+            socket.write_all(&key_data).await.unwrap();
+            socket.write_all(&build_ready_for_query()).await.unwrap();
+
+            assert_eq!(socket.read_u8().await.unwrap(), b'Q');
+            let len = socket.read_u32().await.unwrap();
+            let mut query = vec![0; len as usize - 4];
+            socket.read_exact(&mut query).await.unwrap();
+            socket.write_all(&build_ready_for_query()).await.unwrap();
+            socket
+        });
+
+        let (mut client, mut app) =
+            client_with_startup(&[("user", "app_user.acme"), ("database", "app")]).await;
+        let metrics = Metrics::new(Vec::new());
+        let mut session = SessionInfo::default();
+        let (result, _) = handshake(
+            &mut client,
+            &config,
+            &None,
+            &DnsCache::new(),
+            &None,
+            &None,
+            &HashMap::new(),
+            &None,
+            &metrics,
+            &mut session,
+            1,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(result, HandshakeResult::Passthrough(_)));
+
+        // Everything the client sees up to ReadyForQuery
+        let mut key_data = None;
+        loop {
+            let msg_type = app.read_u8().await.unwrap();
+            let len = app.read_u32().await.unwrap();
+            let mut body = vec![0; len as usize - 4];
+            app.read_exact(&mut body).await.unwrap();
+            match msg_type {
+                b'K' => key_data = Some(body),
+                b'Z' => break,
+                _ => {}
+            }
+        }
+        let key_data = key_data.expect("BackendKeyData not forwarded");
+        assert_eq!(&key_data[..4], &4242u32.to_be_bytes());
+        assert_eq!(&key_data[4..], &0xdead_beefu32.to_be_bytes());
+        upstream_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn passthrough_half_close_keeps_the_other_direction_open() {
+        let proxy = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut app = TcpStream::connect(proxy.local_addr().unwrap())
+            .await
+            .unwrap();
+        let mut client = ClientStream::Plain(proxy.accept().await.unwrap().0);
+        let upstream = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut server = UpstreamStream::Plain(
+            TcpStream::connect(upstream.local_addr().unwrap())
+                .await
+                .unwrap(),
+        );
+        let (mut db, _) = upstream.accept().await.unwrap();
+
+        let metrics = Metrics::new(Vec::new());
+        let mut stats = PipeStats::default();
+        let pipe = pipe_passthrough(&mut client, &mut server, 1, None, &metrics, &mut stats);
+        let peers = async {
+            app.write_all(b"request").await.unwrap();
+            app.shutdown().await.unwrap();
+            // The client's EOF reaches upstream as a shutdown of its write half
+            let mut received = Vec::new();
+            db.read_to_end(&mut received).await.unwrap();
+            assert_eq!(received, b"request");
+            // ...while the response still flows back to the client
+            db.write_all(b"response").await.unwrap();
+            db.shutdown().await.unwrap();
+            let mut response = Vec::new();
+            app.read_to_end(&mut response).await.unwrap();
+            assert_eq!(response, b"response");
+        };
+        let (result, ()) = tokio::join!(pipe, peers);
+        result.unwrap();
+        assert_eq!(stats.bytes_received, 7);
+        assert_eq!(stats.bytes_sent, 8);
+    }
+
+    #[tokio::test]
+    async fn pooled_pipe_rejects_malformed_message_length() {
+        let proxy = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut app = TcpStream::connect(proxy.local_addr().unwrap())
+            .await
+            .unwrap();
+        let mut client = ClientStream::Plain(proxy.accept().await.unwrap().0);
+        let upstream = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut server = UpstreamStream::Plain(
+            TcpStream::connect(upstream.local_addr().unwrap())
+                .await
+                .unwrap(),
+        );
+        let (mut db, _) = upstream.accept().await.unwrap();
+
+        let query = build_query_message("SELECT 1");
+        let mut sent = query.to_vec();
+        sent.extend_from_slice(&[b'Q', 0, 0, 0, 2]);
+        app.write_all(&sent).await.unwrap();
+
+        let metrics = Metrics::new(Vec::new());
+        let mut stats = PipeStats::default();
+        let err = pipe_pooled(
+            &mut client,
+            &mut server,
+            &mut BytesMut::new(),
+            &mut BytesMut::new(),
+            1,
+            None,
+            None,
+            &metrics,
+            &mut stats,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // The client is told why, with a protocol-violation SQLSTATE
+        let mut buf = BytesMut::new();
+        let error = loop {
+            app.read_buf(&mut buf).await.unwrap();
+            if let Some(msg) = try_read_backend_message(&mut buf) {
+                break msg;
+            }
+        };
+        assert!(error.is_error_response());
+        assert_eq!(error.parse_error_fields().sqlstate, "08P01");
+
+        // Only the well-formed Query reached upstream
+        drop(server);
+        let mut received = Vec::new();
+        db.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, query.to_vec());
+    }
+
+    // ─── Upstream connect retry ──────────────────────────────────────────
+
+    fn refused() -> std::io::Error {
+        std::io::Error::from(std::io::ErrorKind::ConnectionRefused)
+    }
+
+    #[tokio::test]
+    async fn retry_with_backoff_recovers_after_failures() {
+        let calls = AtomicU32::new(0);
+        let result = retry_with_backoff(3, Duration::from_millis(1), || async {
+            if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(refused())
+            } else {
+                Ok("connected")
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), "connected");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_with_backoff_returns_last_error() {
+        let calls = AtomicU32::new(0);
+        let result: std::io::Result<()> =
+            retry_with_backoff(2, Duration::from_millis(1), || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(refused())
+            })
+            .await;
+        assert_eq!(
+            result.unwrap_err().kind(),
+            std::io::ErrorKind::ConnectionRefused
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 3); // first try + 2 retries
+    }
+
+    #[tokio::test]
+    async fn retry_with_backoff_zero_retries() {
+        let calls = AtomicU32::new(0);
+        let result: std::io::Result<()> =
+            retry_with_backoff(0, Duration::from_secs(60), || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(refused())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn retry_delay_doubles_and_caps() {
+        assert_eq!(
+            next_retry_delay(Duration::from_millis(500)),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            next_retry_delay(Duration::from_secs(20)),
+            MAX_CONNECT_RETRY_DELAY
+        );
+        assert_eq!(
+            next_retry_delay(MAX_CONNECT_RETRY_DELAY),
+            MAX_CONNECT_RETRY_DELAY
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_first_falls_through_to_the_next_address() {
+        // A port nothing listens on: bind, note it, and close
+        let dead = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let live = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp = connect_first(&[dead, live.local_addr().unwrap()])
+            .await
+            .unwrap();
+        assert_eq!(tcp.peer_addr().unwrap(), live.local_addr().unwrap());
+
+        assert!(connect_first(&[dead]).await.is_err());
+        assert!(connect_first(&[]).await.is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Upstream address cache — avoids a DNS lookup on every upstream connect.
+//!
+//! Every address a lookup returns is kept, in order, so the connector can
+//! fall through to the next one. They are kept for `upstream_dns_ttl_secs`. A failed connect
+//! drops the entry so the next attempt re-resolves, and SIGHUP flushes the
+//! whole cache (e.g. after a failover moved the upstream's DNS record).
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Shared `host:port` → address cache. Clones share the same entries.
+#[derive(Clone, Default)]
+pub struct DnsCache {
+    entries: Arc<Mutex<HashMap<(String, u16), (Vec<SocketAddr>, Instant)>>>,
+}
+
+impl DnsCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Addresses for `host:port`, in lookup order, from the cache if resolved
+    /// less than `ttl` ago, otherwise via `lookup_host`. Never empty.
+    pub async fn resolve(
+        &self,
+        host: &str,
+        port: u16,
+        ttl: Duration,
+    ) -> io::Result<Vec<SocketAddr>> {
+        let key = cache_key(host, port);
+        if let Some(addrs) = self.get(&key, ttl) {
+            return Ok(addrs);
+        }
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no addresses found for {host}"),
+            ));
+        }
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key, (addrs.clone(), Instant::now()));
+        Ok(addrs)
+    }
+
+    /// Drop the cached addresses for `host:port`.
+    pub fn invalidate(&self, host: &str, port: u16) {
+        self.entries.lock().unwrap().remove(&cache_key(host, port));
+    }
+
+    /// Every cached `(host, port, addresses)`, expired or not.
+    pub fn snapshot(&self) -> Vec<(String, u16, Vec<SocketAddr>)> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((host, port), (addrs, _))| (host.clone(), *port, addrs.clone()))
+            .collect()
+    }
+
+    /// Drop every cached entry.
+    pub fn flush(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Number of cached `host:port` entries (for metrics).
+    pub fn size(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    fn get(&self, key: &(String, u16), ttl: Duration) -> Option<Vec<SocketAddr>> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((addrs, resolved)) if resolved.elapsed() < ttl => Some(addrs.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+fn cache_key(host: &str, port: u16) -> (String, u16) {
+    (host.to_string(), port)
+}
+
+// ─── Tests ──────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(30);
+
+    #[tokio::test]
+    async fn resolve_caches_address() {
+        let cache = DnsCache::new();
+        let addrs = cache.resolve("127.0.0.1", 5432, TTL).await.unwrap();
+        assert_eq!(addrs, vec!["127.0.0.1:5432".parse().unwrap()]);
+        assert_eq!(cache.size(), 1);
+
+        // A second lookup is served from the cache
+        cache.resolve("127.0.0.1", 5432, TTL).await.unwrap();
+        assert_eq!(cache.size(), 1);
+
+        // Different ports are separate entries
+        cache.resolve("127.0.0.1", 6543, TTL).await.unwrap();
+        assert_eq!(cache.size(), 2);
+    }
+
+    #[tokio::test]
+    async fn cached_entry_is_returned_until_ttl() {
+        let cache = DnsCache::new();
+        let stale: Vec<SocketAddr> = vec![
+            "10.0.0.1:5432".parse().unwrap(),
+            "10.0.0.2:5432".parse().unwrap(),
+        ];
+        cache.entries.lock().unwrap().insert(
+            cache_key("db.internal", 5432),
+            (stale.clone(), Instant::now()),
+        );
+        assert_eq!(
+            cache.resolve("db.internal", 5432, TTL).await.unwrap(),
+            stale
+        );
+
+        // Past its TTL the entry is dropped rather than served
+        assert_eq!(
+            cache.get(&cache_key("db.internal", 5432), Duration::ZERO),
+            None
+        );
+        assert_eq!(cache.size(), 0);
+    }
+
+    #[tokio::test]
+    async fn invalidate_and_flush() {
+        let cache = DnsCache::new();
+        cache.resolve("127.0.0.1", 5432, TTL).await.unwrap();
+        cache.resolve("127.0.0.1", 5433, TTL).await.unwrap();
+        cache.invalidate("127.0.0.1", 5432);
+        assert_eq!(cache.size(), 1);
+        assert_eq!(
+            cache.snapshot(),
+            vec![(
+                "127.0.0.1".to_string(),
+                5433,
+                vec!["127.0.0.1:5433".parse().unwrap()]
+            )]
+        );
+        cache.flush();
+        assert_eq!(cache.size(), 0);
+    }
+}
@@ -1,17 +1,38 @@
 mod admin;
+mod audit;
 mod auth;
+mod buffer;
 mod config;
 mod connection;
+mod dns;
 mod metrics;
+#[cfg(feature = "otlp")]
+mod otlp;
 mod pool;
 mod protocol;
 mod proxy;
+mod proxy_protocol;
 mod resolver;
+mod router;
+mod statsd;
 mod stream;
 mod tenant;
+#[cfg(test)]
+mod testing;
 mod tls;
 
-use tracing_subscriber::EnvFilter;
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::Instant;
+use tracing::Subscriber;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{InitError, RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer, fmt, reload};
+
+use config::{Config, LogFormat, LogRotation};
 
 const BANNER: &str = r#"
   ╔══════════════════════════════════════════════════╗
@@ -21,23 +42,92 @@ const BANNER: &str = r#"
   ╚══════════════════════════════════════════════════╝
 "#;
 
+/// Process start time, for uptime reporting.
+static STARTED_AT: LazyLock<Instant> = LazyLock::new(Instant::now);
+
 #[tokio::main]
 async fn main() {
-    let config = config::Config::load();
+    LazyLock::force(&STARTED_AT);
+    let (config, config_warnings) = Config::load();
 
-    // Set up tracing with the configured log level
+    // Set up tracing with the configured log level (reloadable on SIGHUP)
     let filter = EnvFilter::try_new(&config.log_level).unwrap_or_else(|_| EnvFilter::new("info"));
+    let (filter, log_filter) = reload::Layer::new(filter);
+
+    let mut layers = vec![log_layer(&config, std::io::stderr, true)];
+    let log_guard = match config.log_file.as_deref() {
+        Some(path) => match log_file_writer(path, config.log_rotation) {
+            Ok((writer, guard)) => {
+                layers.push(log_layer(&config, writer, false));
+                Some(guard)
+            }
+            Err(e) => {
+                eprintln!("fatal: cannot open log_file '{path}': {e}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
 
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_target(false)
-        .with_timer(tracing_subscriber::fmt::time::uptime())
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(layers)
         .init();
+    for warning in &config_warnings {
+        tracing::warn!(key = %warning.key, "{warning}");
+    }
 
     eprintln!("{BANNER}");
 
-    if let Err(e) = proxy::run(config).await {
-        eprintln!("fatal: {e}");
+    if let Err(e) = proxy::run(config, log_filter).await {
+        tracing::error!("fatal: {e}");
+        // exit() skips destructors; flush buffered log_file output first
+        drop(log_guard);
         std::process::exit(1);
     }
 }
+
+/// One formatting layer per log destination. Text keeps the uptime timer;
+/// JSON uses wall-clock time, which is what aggregators index on.
+fn log_layer<S, W>(config: &Config, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_target(false).with_writer(writer);
+    match (config.log_format, config.log_timestamps) {
+        (LogFormat::Text, true) => layer
+            .with_ansi(ansi)
+            .with_timer(fmt::time::uptime())
+            .boxed(),
+        (LogFormat::Text, false) => layer.with_ansi(ansi).without_time().boxed(),
+        (LogFormat::Json, true) => layer.json().with_ansi(false).boxed(),
+        (LogFormat::Json, false) => layer.json().with_ansi(false).without_time().boxed(),
+    }
+}
+
+/// Non-blocking writer for `log_file`. The guard flushes buffered lines on
+/// drop, so it must live until `main` returns. Fails if the directory can't
+/// be created or the file can't be opened.
+fn log_file_writer(
+    path: &str,
+    rotation: LogRotation,
+) -> Result<(tracing_appender::non_blocking::NonBlocking, WorkerGuard), InitError> {
+    let path = Path::new(path);
+    let dir = path
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let file_name = path.file_name().unwrap_or(path.as_os_str());
+    let rotation = match rotation {
+        LogRotation::Never => Rotation::NEVER,
+        LogRotation::Minutely => Rotation::MINUTELY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+    };
+    let appender = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(file_name.to_string_lossy())
+        .build(dir)?;
+    Ok(tracing_appender::non_blocking(appender))
+}
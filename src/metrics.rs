@@ -3,13 +3,213 @@
 //! Wrapped in `Arc<Metrics>` and passed to pool, resolver, and connection handler.
 //! No external crate needed — we format Prometheus exposition text manually.
 
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::connection::ConnectionState;
+
+/// Upper bounds (seconds) for connection duration histograms.
+pub const DURATION_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0];
+
+/// Upper bounds (seconds) for pool connection age and lifetime histograms.
+/// Tenants given their own connection duration histogram. Tenant names come
+/// from clients, so later tenants share the [`OTHER_TENANT`] series instead
+/// of growing the map (and every exporter's copy of it) without bound.
+pub const MAX_TENANT_DURATION_SERIES: usize = 1000;
+
+/// Label for tenants beyond `MAX_TENANT_DURATION_SERIES`.
+pub const OTHER_TENANT: &str = "other";
+
+pub const POOL_AGE_BUCKETS: &[f64] = &[1.0, 5.0, 30.0, 60.0, 300.0, 600.0];
+
+/// Accessor for one of the scalar atomics in `Metrics`.
+pub type Field = for<'a> fn(&'a Metrics) -> &'a AtomicU64;
+
+/// Scalar counters for the push exporters (OTLP, StatsD): (name without the
+/// `pgvpd_` prefix or `_total` suffix, description, source). `/metrics`
+/// renders its own, with labels.
+pub const COUNTERS: &[(&str, &str, Field)] = &[
+    ("connections", "Total client connections accepted", |m| {
+        &m.connections_total
+    }),
+    (
+        "connections_rejected",
+        "Connections refused at max_total_connections",
+        |m| &m.connections_rejected_max_total,
+    ),
+    (
+        "client_idle_timeouts",
+        "Client connections closed by client_idle_timeout",
+        |m| &m.client_idle_timeouts,
+    ),
+    ("queries", "Queries forwarded to upstream", |m| {
+        &m.queries_total
+    }),
+    (
+        "query_errors",
+        "ErrorResponses forwarded from upstream",
+        |m| &m.query_errors_total,
+    ),
+    ("pool_checkouts", "Pool checkouts", |m| &m.pool_checkouts),
+    (
+        "pool_reuses",
+        "Pool checkouts served by an idle connection",
+        |m| &m.pool_reuses,
+    ),
+    ("pool_creates", "Pooled upstream connections created", |m| {
+        &m.pool_creates
+    }),
+    ("pool_checkins", "Connections returned to the pool", |m| {
+        &m.pool_checkins
+    }),
+    ("pool_discards", "Pooled connections discarded", |m| {
+        &m.pool_discards
+    }),
+    ("pool_timeouts", "Pool checkouts that timed out", |m| {
+        &m.pool_timeouts
+    }),
+    (
+        "pool_wait_wakeups",
+        "Waiting checkouts woken by a checkin",
+        |m| &m.pool_wait_wakeups,
+    ),
+    (
+        "pool_topup_attempts",
+        "Connections created to reach pool_min_size",
+        |m| &m.pool_topup_attempts_total,
+    ),
+    (
+        "pool_topup_failures",
+        "Failed pool_min_size top-up connects",
+        |m| &m.pool_topup_failures_total,
+    ),
+    (
+        "pool_keepalive_pings",
+        "Keepalive pings sent to idle pooled connections",
+        |m| &m.pool_keepalive_pings_total,
+    ),
+    (
+        "pool_keepalive_failures",
+        "Keepalive pings that failed",
+        |m| &m.pool_keepalive_failures_total,
+    ),
+    (
+        "pool_trust_ips_connections",
+        "Pool clients admitted via trust_ips",
+        |m| &m.pool_trust_connections,
+    ),
+    ("replica_checkouts", "Pool checkouts from replicas", |m| {
+        &m.replica_checkouts_total
+    }),
+    ("resolver_cache_hits", "Resolver cache hits", |m| {
+        &m.resolver_cache_hits
+    }),
+    ("resolver_cache_misses", "Resolver cache misses", |m| {
+        &m.resolver_cache_misses
+    }),
+    (
+        "scram_cache_hits",
+        "Upstream SCRAM logins that reused a cached SaltedPassword",
+        |m| &m.scram_cache_hits_total,
+    ),
+    ("tenant_timeouts", "Tenant query timeouts", |m| {
+        &m.tenant_timeouts
+    }),
+    ("config_reloads", "Successful SIGHUP config reloads", |m| {
+        &m.config_reloads_total
+    }),
+    (
+        "config_reload_errors",
+        "SIGHUP config reloads rejected (current config kept)",
+        |m| &m.config_reload_errors_total,
+    ),
+];
+
+/// Scalar gauges for the push exporters, in the same shape as `COUNTERS`.
+pub const GAUGES: &[(&str, &str, Field)] = &[
+    (
+        "connections_active",
+        "Client connections currently open",
+        |m| &m.connections_active,
+    ),
+    (
+        "upstream_health_last_check_secs",
+        "Unix time of the last upstream health probe",
+        |m| &m.upstream_health_last_check_secs,
+    ),
+    (
+        "upstream_max_connections",
+        "Upstream max_connections setting (0 = not read yet)",
+        |m| &m.upstream_max_connections,
+    ),
+    (
+        "config_last_reload_timestamp",
+        "Unix time of the last successful config reload (0 = never)",
+        |m| &m.config_last_reload_timestamp,
+    ),
+];
+
+/// Fixed-bucket histogram with lock-free observation.
+pub struct Histogram {
+    bounds: &'static [f64],
+    /// Per-bucket (non-cumulative) counts; the last slot is `+Inf`.
+    counts: Vec<AtomicU64>,
+    /// Sum of observed values in millionths.
+    sum_micros: AtomicU64,
+}
+
+/// Point-in-time view of a Histogram, in Prometheus shape.
+#[derive(Debug)]
+pub struct HistogramSnapshot {
+    /// `(upper bound, cumulative count)`; the last bound is `f64::INFINITY`.
+    pub buckets: Vec<(f64, u64)>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = self
+            .bounds
+            .iter()
+            .copied()
+            .chain(std::iter::once(f64::INFINITY))
+            .zip(&self.counts)
+            .map(|(bound, c)| {
+                cumulative += c.load(Ordering::Relaxed);
+                (bound, cumulative)
+            })
+            .collect();
+        HistogramSnapshot {
+            buckets,
+            sum: self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+            count: cumulative,
+        }
+    }
+}
 
 /// Shared metrics counters, all lock-free via AtomicU64.
 pub struct Metrics {
     // ─── Connections ─────────────────────────────────────────────────────
     pub connections_total: AtomicU64,
     pub connections_active: AtomicU64,
+    /// Connections refused because `max_total_connections` was reached.
+    pub connections_rejected_max_total: AtomicU64,
+    /// Client connections closed by `client_idle_timeout`.
+    pub client_idle_timeouts: AtomicU64,
+    /// Handshake state transitions, indexed by `transition_index(from, to)`.
+    pub state_transitions_total: Vec<AtomicU64>,
 
     // ─── Pool ────────────────────────────────────────────────────────────
     pub pool_checkouts: AtomicU64,
@@ -18,10 +218,32 @@ pub struct Metrics {
     pub pool_checkins: AtomicU64,
     pub pool_discards: AtomicU64,
     pub pool_timeouts: AtomicU64,
+    /// Checkouts woken by a checkin while waiting on a full pool.
+    pub pool_wait_wakeups: AtomicU64,
+    /// Pool-mode clients admitted without a password via `trust_ips`.
+    pub pool_trust_connections: AtomicU64,
+    /// Pool checkouts from replica buckets (`routing_mode = replica_reads`).
+    pub replica_checkouts_total: AtomicU64,
+    /// Connections the idle reaper tried to create to reach `pool_min_size`.
+    pub pool_topup_attempts_total: AtomicU64,
+    /// Top-up attempts that failed (retried on the next reaper cycle).
+    pub pool_topup_failures_total: AtomicU64,
+    /// Keepalive pings sent to idle pooled connections (`pool_keepalive_secs`).
+    pub pool_keepalive_pings_total: AtomicU64,
+    /// Keepalive pings that failed; the connection was discarded.
+    pub pool_keepalive_failures_total: AtomicU64,
+    /// Upstream SCRAM logins that skipped PBKDF2 via the pool's cache.
+    pub scram_cache_hits_total: AtomicU64,
+    /// Unix time of the last `/health?check=upstream` probe (0 = never).
+    pub upstream_health_last_check_secs: AtomicU64,
+    /// The upstream's `max_connections` (0 = not read yet).
+    pub upstream_max_connections: AtomicU64,
 
     // ─── Resolvers ───────────────────────────────────────────────────────
     pub resolver_cache_hits: AtomicU64,
     pub resolver_cache_misses: AtomicU64,
+    /// Per-resolver cache hit counts (indexed by resolver order).
+    pub resolver_cache_hits_by_resolver: Vec<AtomicU64>,
     /// Per-resolver execution counts (indexed by resolver order).
     pub resolver_executions: Vec<AtomicU64>,
     /// Per-resolver error counts (indexed by resolver order).
@@ -34,6 +256,27 @@ pub struct Metrics {
     pub tenant_rejected_limit: AtomicU64,
     pub tenant_rejected_rate: AtomicU64,
     pub tenant_timeouts: AtomicU64,
+    /// Per-tenant connection duration, keyed by tenant payload. Only tenants
+    /// admitted by the registry are recorded, at most
+    /// `MAX_TENANT_DURATION_SERIES` of them plus `other`.
+    pub tenant_connection_duration: Mutex<HashMap<String, Arc<Histogram>>>,
+
+    // ─── Queries ─────────────────────────────────────────────────────────
+    /// Simple Query ('Q') and extended-protocol Execute ('E') messages forwarded.
+    pub queries_total: AtomicU64,
+    /// ErrorResponses forwarded from upstream after the handshake.
+    pub query_errors_total: AtomicU64,
+    /// The same errors, keyed by SQLSTATE class (`23`, `53`, ...; `unknown`
+    /// when the code is missing or malformed).
+    pub query_errors_by_class: Mutex<HashMap<String, u64>>,
+
+    // ─── Config reload ───────────────────────────────────────────────────
+    pub config_reloads_total: AtomicU64,
+    pub config_reload_errors_total: AtomicU64,
+    /// Unix time of the last successful reload (0 = never reloaded).
+    pub config_last_reload_timestamp: AtomicU64,
+    /// Why the last reload failed; cleared by a successful one.
+    pub config_last_reload_error: Mutex<Option<String>>,
 }
 
 impl Metrics {
@@ -44,14 +287,30 @@ impl Metrics {
         Self {
             connections_total: AtomicU64::new(0),
             connections_active: AtomicU64::new(0),
+            connections_rejected_max_total: AtomicU64::new(0),
+            client_idle_timeouts: AtomicU64::new(0),
+            state_transitions_total: (0..ConnectionState::ALL.len().pow(2))
+                .map(|_| AtomicU64::new(0))
+                .collect(),
             pool_checkouts: AtomicU64::new(0),
             pool_reuses: AtomicU64::new(0),
             pool_creates: AtomicU64::new(0),
             pool_checkins: AtomicU64::new(0),
             pool_discards: AtomicU64::new(0),
             pool_timeouts: AtomicU64::new(0),
+            pool_wait_wakeups: AtomicU64::new(0),
+            pool_trust_connections: AtomicU64::new(0),
+            replica_checkouts_total: AtomicU64::new(0),
+            pool_topup_attempts_total: AtomicU64::new(0),
+            pool_topup_failures_total: AtomicU64::new(0),
+            pool_keepalive_pings_total: AtomicU64::new(0),
+            pool_keepalive_failures_total: AtomicU64::new(0),
+            scram_cache_hits_total: AtomicU64::new(0),
+            upstream_health_last_check_secs: AtomicU64::new(0),
+            upstream_max_connections: AtomicU64::new(0),
             resolver_cache_hits: AtomicU64::new(0),
             resolver_cache_misses: AtomicU64::new(0),
+            resolver_cache_hits_by_resolver: (0..n).map(|_| AtomicU64::new(0)).collect(),
             resolver_executions: (0..n).map(|_| AtomicU64::new(0)).collect(),
             resolver_errors: (0..n).map(|_| AtomicU64::new(0)).collect(),
             resolver_names,
@@ -59,9 +318,72 @@ impl Metrics {
             tenant_rejected_limit: AtomicU64::new(0),
             tenant_rejected_rate: AtomicU64::new(0),
             tenant_timeouts: AtomicU64::new(0),
+            tenant_connection_duration: Mutex::new(HashMap::new()),
+            queries_total: AtomicU64::new(0),
+            query_errors_total: AtomicU64::new(0),
+            query_errors_by_class: Mutex::new(HashMap::new()),
+            config_reloads_total: AtomicU64::new(0),
+            config_reload_errors_total: AtomicU64::new(0),
+            config_last_reload_timestamp: AtomicU64::new(0),
+            config_last_reload_error: Mutex::new(None),
         }
     }
 
+    /// Count an ErrorResponse forwarded from upstream under its SQLSTATE class.
+    pub fn record_query_error(&self, sqlstate_class: Option<&str>) {
+        Self::inc(&self.query_errors_total);
+        let class = sqlstate_class.unwrap_or("unknown");
+        let mut by_class = self.query_errors_by_class.lock().unwrap();
+        *by_class.entry(class.to_string()).or_insert(0) += 1;
+    }
+
+    /// Count a successful config reload and stamp its time.
+    pub fn record_config_reload(&self) {
+        Self::inc(&self.config_reloads_total);
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.config_last_reload_timestamp
+            .store(now, Ordering::Relaxed);
+        *self.config_last_reload_error.lock().unwrap() = None;
+    }
+
+    /// Count a rejected config reload and keep its reason for `/status`.
+    pub fn record_config_reload_error(&self, error: &str) {
+        Self::inc(&self.config_reload_errors_total);
+        *self.config_last_reload_error.lock().unwrap() = Some(error.to_string());
+    }
+
+    /// The connection duration histogram for `tenant`, created on first use.
+    /// Once `MAX_TENANT_DURATION_SERIES` tenants have one, new tenants get the
+    /// shared `other` histogram.
+    pub fn tenant_duration_histogram(&self, tenant: &str) -> Arc<Histogram> {
+        let mut map = self.tenant_connection_duration.lock().unwrap();
+        if let Some(histogram) = map.get(tenant) {
+            return Arc::clone(histogram);
+        }
+        let key = if map.len() < MAX_TENANT_DURATION_SERIES {
+            tenant
+        } else {
+            OTHER_TENANT
+        };
+        Arc::clone(
+            map.entry(key.to_string())
+                .or_insert_with(|| Arc::new(Histogram::new(DURATION_BUCKETS))),
+        )
+    }
+
+    /// Count one handshake transition from `from` to `to`.
+    pub fn record_state_transition(&self, from: ConnectionState, to: ConnectionState) {
+        Self::inc(&self.state_transitions_total[transition_index(from, to)]);
+    }
+
+    /// How many handshakes moved from `from` to `to`.
+    pub fn state_transitions(&self, from: ConnectionState, to: ConnectionState) -> u64 {
+        self.state_transitions_total[transition_index(from, to)].load(Ordering::Relaxed)
+    }
+
     /// Increment a counter by 1 and return the previous value.
     #[inline]
     pub fn inc(counter: &AtomicU64) -> u64 {
@@ -73,4 +395,21 @@ impl Metrics {
     pub fn dec(counter: &AtomicU64) {
         counter.fetch_sub(1, Ordering::Relaxed);
     }
+
+    /// Record one observation in a histogram.
+    pub fn observe(histogram: &Histogram, value: f64) {
+        let idx = histogram
+            .bounds
+            .iter()
+            .position(|&bound| value <= bound)
+            .unwrap_or(histogram.bounds.len());
+        histogram.counts[idx].fetch_add(1, Ordering::Relaxed);
+        histogram
+            .sum_micros
+            .fetch_add((value * 1_000_000.0) as u64, Ordering::Relaxed);
+    }
+}
+
+fn transition_index(from: ConnectionState, to: ConnectionState) -> usize {
+    from as usize * ConnectionState::ALL.len() + to as usize
 }
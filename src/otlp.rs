@@ -0,0 +1,182 @@
+//! OpenTelemetry metrics export — pushes the `Metrics` counters and gauges to
+//! an OTLP/gRPC collector (`otlp_endpoint`), alongside the Prometheus scrape.
+//!
+//! Only built with the `otlp` cargo feature. Every instrument is observable:
+//! the SDK's periodic reader reads the atomics each `otlp_export_interval_secs`
+//! and exports them, so nothing changes on the connection path. Names match
+//! `/metrics` without the `_total` suffix, which collectors add back for
+//! counters. Histograms are left to the Prometheus endpoint.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Meter, MeterProvider as _};
+use opentelemetry_otlp::{MetricExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::runtime;
+
+use crate::connection::ConnectionState;
+use crate::metrics::{COUNTERS, GAUGES, Metrics};
+
+/// Keeps the OTLP pipeline running; dropping it flushes and stops the export.
+pub struct OtlpExporter {
+    _provider: SdkMeterProvider,
+}
+
+impl OtlpExporter {
+    /// Connect to `endpoint` and register every instrument. The first export
+    /// happens after `interval`, then repeats every `interval`.
+    pub fn start(
+        endpoint: &str,
+        interval: Duration,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let exporter = MetricExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()?;
+        let reader = PeriodicReader::builder(exporter, runtime::Tokio)
+            .with_interval(interval)
+            .build();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(reader)
+            .with_resource(Resource::new([
+                KeyValue::new("service.name", "pgvpd"),
+                KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+            ]))
+            .build();
+        register(&provider.meter("pgvpd"), &metrics);
+        Ok(Self {
+            _provider: provider,
+        })
+    }
+}
+
+fn register(meter: &Meter, metrics: &Arc<Metrics>) {
+    for &(name, description, field) in COUNTERS {
+        let m = Arc::clone(metrics);
+        meter
+            .u64_observable_counter(format!("pgvpd_{name}"))
+            .with_description(description)
+            .with_callback(move |observer| observer.observe(load(field(&m)), &[]))
+            .build();
+    }
+    for &(name, description, field) in GAUGES {
+        let m = Arc::clone(metrics);
+        meter
+            .u64_observable_gauge(format!("pgvpd_{name}"))
+            .with_description(description)
+            .with_callback(move |observer| observer.observe(load(field(&m)), &[]))
+            .build();
+    }
+
+    let m = Arc::clone(metrics);
+    meter
+        .u64_observable_counter("pgvpd_tenant_rejected")
+        .with_description("Tenant connections rejected, by reason")
+        .with_callback(move |observer| {
+            for (reason, counter) in [
+                ("deny", &m.tenant_rejected_deny),
+                ("limit", &m.tenant_rejected_limit),
+                ("rate", &m.tenant_rejected_rate),
+            ] {
+                observer.observe(load(counter), &[KeyValue::new("reason", reason)]);
+            }
+        })
+        .build();
+
+    register_per_resolver(
+        meter,
+        metrics,
+        "pgvpd_resolver_executions",
+        "Resolver executions",
+        |m| &m.resolver_executions,
+    );
+    register_per_resolver(
+        meter,
+        metrics,
+        "pgvpd_resolver_errors",
+        "Resolver errors",
+        |m| &m.resolver_errors,
+    );
+
+    let m = Arc::clone(metrics);
+    meter
+        .u64_observable_counter("pgvpd_state_transitions")
+        .with_description("Handshake state transitions")
+        .with_callback(move |observer| {
+            for from in ConnectionState::ALL {
+                for to in ConnectionState::ALL {
+                    let count = m.state_transitions(from, to);
+                    if count > 0 {
+                        observer.observe(
+                            count,
+                            &[
+                                KeyValue::new("from", from.as_str()),
+                                KeyValue::new("to", to.as_str()),
+                            ],
+                        );
+                    }
+                }
+            }
+        })
+        .build();
+}
+
+/// A counter with one series per resolver, labelled by resolver name.
+fn register_per_resolver(
+    meter: &Meter,
+    metrics: &Arc<Metrics>,
+    name: &'static str,
+    description: &'static str,
+    counters: for<'a> fn(&'a Metrics) -> &'a [AtomicU64],
+) {
+    let m = Arc::clone(metrics);
+    meter
+        .u64_observable_counter(name)
+        .with_description(description)
+        .with_callback(move |observer| {
+            for (resolver, counter) in m.resolver_names.iter().zip(counters(&m)) {
+                observer.observe(
+                    load(counter),
+                    &[KeyValue::new("resolver", resolver.clone())],
+                );
+            }
+        })
+        .build();
+}
+
+fn load(counter: &AtomicU64) -> u64 {
+    counter.load(Ordering::Relaxed)
+}
+
+// ─── Tests ──────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn instrument_names_are_unique_and_unsuffixed() {
+        let mut seen = HashSet::new();
+        for (name, _, _) in COUNTERS.iter().chain(GAUGES) {
+            assert!(seen.insert(*name), "duplicate instrument {name}");
+            assert!(!name.ends_with("_total"), "{name} keeps its _total suffix");
+        }
+    }
+
+    #[test]
+    fn fields_read_the_matching_counter() {
+        let m = Metrics::new(Vec::new());
+        Metrics::inc(&m.pool_discards);
+        let (_, _, field) = COUNTERS
+            .iter()
+            .find(|(name, _, _)| *name == "pool_discards")
+            .unwrap();
+        assert_eq!(load(field(&m)), 1);
+    }
+}
@@ -1,22 +1,33 @@
 //! Connection Pool — session pooling for upstream Postgres connections.
 //!
-//! Pool key is `(database, role)`. Each bucket holds up to `pool_size` connections.
-//! Idle connections are reaped after `pool_idle_timeout` seconds.
+//! Pool key is `(database, role, upstream)`; the upstream is the primary unless
+//! replica routing sends a read-only session to a replica. Each bucket holds up
+//! to `pool_size` connections.
+//! Idle connections are reaped after `pool_idle_timeout` seconds (keeping
+//! `pool_min_size` per bucket, which the reaper also tops back up), and every
+//! checkout runs `pool_health_check_query` first, discarding connections that fail it.
+//! With `pool_keepalive_secs` set, the reaper also pings idle connections so
+//! upstream idle timeouts don't close them unnoticed.
 
 use bytes::BytesMut;
 use rustls::ClientConfig;
 use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::sync::Mutex;
-use tracing::{debug, info, warn};
+use tokio::sync::{Mutex, Notify, OwnedSemaphorePermit, Semaphore, watch};
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, warn};
 
-use crate::auth;
+use crate::auth::{self, ScramCache};
+use crate::buffer::BufferPool;
 use crate::config::Config;
 use crate::connection::connect_upstream;
-use crate::metrics::Metrics;
+use crate::dns::DnsCache;
+use crate::metrics::{Histogram, HistogramSnapshot, Metrics, POOL_AGE_BUCKETS};
 use crate::protocol::{build_query_message, build_startup_message, try_read_backend_message};
+use crate::router::Upstream;
 use crate::stream::UpstreamStream;
 
 /// Pool key — identifies a bucket of reusable connections.
@@ -24,6 +35,7 @@ use crate::stream::UpstreamStream;
 pub struct PoolKey {
     pub database: String,
     pub role: String,
+    pub upstream: Upstream,
 }
 
 /// A pooled upstream connection with cached handshake data.
@@ -32,33 +44,141 @@ pub struct PooledConn {
     pub stream: UpstreamStream,
     pub created_at: Instant,
     pub last_used: Instant,
+    /// Last time the connection was known to be alive: created, checked in,
+    /// or answered a keepalive ping.
+    pub last_keepalive: Instant,
     /// Cached ParameterStatus messages from the initial handshake.
     pub param_statuses: Vec<BytesMut>,
     /// Cached BackendKeyData message from the initial handshake.
     pub backend_key_data: BytesMut,
 }
 
+/// Per-bucket counters, mirroring the pool-wide ones in `Metrics`.
+struct BucketStats {
+    checkouts: AtomicU64,
+    reuses: AtomicU64,
+    creates: AtomicU64,
+    checkins: AtomicU64,
+    discards: AtomicU64,
+    /// Summed time from checkout start to a connection being handed out.
+    total_wait_us: AtomicU64,
+    /// Checked-out connections still running the context injection (see
+    /// `InjectingGuard`).
+    injecting: AtomicU32,
+    /// Time since last use of connections evicted by the idle reaper.
+    age_histogram: Histogram,
+    /// Time since creation of every connection closed by the pool.
+    lifetime_histogram: Histogram,
+}
+
+impl Default for BucketStats {
+    fn default() -> Self {
+        Self {
+            checkouts: AtomicU64::new(0),
+            reuses: AtomicU64::new(0),
+            creates: AtomicU64::new(0),
+            checkins: AtomicU64::new(0),
+            discards: AtomicU64::new(0),
+            total_wait_us: AtomicU64::new(0),
+            injecting: AtomicU32::new(0),
+            age_histogram: Histogram::new(POOL_AGE_BUCKETS),
+            lifetime_histogram: Histogram::new(POOL_AGE_BUCKETS),
+        }
+    }
+}
+
 struct PoolBucket {
     idle: VecDeque<PooledConn>,
     total: u32,
+    stats: Arc<BucketStats>,
     /// Cached ParameterStatus messages from the first connection's handshake.
     /// Reused for all subsequent connections in this bucket.
     cached_param_statuses: Option<Vec<BytesMut>>,
     /// Cached BackendKeyData from the first connection's handshake.
     cached_backend_key_data: Option<BytesMut>,
+    /// `pool_size` checkout slots. Waiters get them in arrival order, so a
+    /// returned connection goes to the longest-waiting client.
+    permits: Arc<Semaphore>,
+    /// Woken when a connection is returned or capacity frees up, for a
+    /// permit holder that finds neither (e.g. the only idle connection is
+    /// out for a keepalive ping, or a top-up connect is still in flight).
+    notify: Arc<Notify>,
+    /// `drain_bucket()` calls waiting for checkins. While non-zero, checkouts
+    /// are refused and returning connections are closed instead of idled.
+    draining: u32,
+    /// Set by `drain()` and `drain_bucket()`. Connections created before it
+    /// are discarded when they come back instead of returning to idle; their
+    /// slot stays in `total` until then.
+    drained_at: Option<Instant>,
 }
 
 impl PoolBucket {
-    fn new() -> Self {
+    fn new(pool_size: u32) -> Self {
         Self {
             idle: VecDeque::new(),
             total: 0,
+            stats: Arc::new(BucketStats::default()),
             cached_param_statuses: None,
             cached_backend_key_data: None,
+            permits: Arc::new(Semaphore::new(pool_size as usize)),
+            notify: Arc::new(Notify::new()),
+            draining: 0,
+            drained_at: None,
+        }
+    }
+
+    /// Whether a connection created at `created_at` predates the last drain.
+    fn is_drained(&self, created_at: Instant) -> bool {
+        self.drained_at.is_some_and(|drained| created_at <= drained)
+    }
+}
+
+/// A bucket's checkout slot, held from `checkout` until the connection is
+/// handed back to `checkin` or `discard_checked_out`. Dropping it instead
+/// (e.g. when a reset, resolver, role check or injection fails and the
+/// session ends) counts the connection as discarded and frees its place in
+/// the bucket's `total`, so the next waiter can open a new one.
+pub struct CheckoutPermit {
+    _permit: OwnedSemaphorePermit,
+    /// `None` once checkin or discard has settled the connection.
+    slot: Option<CheckedOutSlot>,
+}
+
+/// What a dropped `CheckoutPermit` needs to release its connection's slot.
+struct CheckedOutSlot {
+    buckets: Buckets,
+    metrics: Arc<Metrics>,
+    key: PoolKey,
+    created_at: Instant,
+}
+
+impl Drop for CheckoutPermit {
+    fn drop(&mut self) {
+        let Some(slot) = self.slot.take() else {
+            return;
+        };
+        // The bucket lock is async; release the slot from a task. A waiter
+        // that gets the semaphore permit first is woken by its notify.
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                discard_slot(&slot.buckets, &slot.metrics, &slot.key, slot.created_at).await;
+            });
         }
     }
 }
 
+type Buckets = Arc<Mutex<HashMap<PoolKey, PoolBucket>>>;
+
+/// Counts one checked-out connection as injecting context until dropped, so
+/// every exit from the handshake (including errors) releases it.
+pub struct InjectingGuard(Arc<BucketStats>);
+
+impl Drop for InjectingGuard {
+    fn drop(&mut self) {
+        self.0.injecting.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 /// Snapshot of pool state for the admin /status and /metrics endpoints.
 #[derive(Debug)]
 pub struct PoolSnapshot {
@@ -70,61 +190,188 @@ pub struct PoolSnapshot {
 pub struct PoolBucketSnapshot {
     pub database: String,
     pub role: String,
+    pub upstream: Upstream,
     pub total: u32,
     pub idle: u32,
+    /// Checked out but still resolving / injecting context.
+    pub injecting: u32,
+    pub checkouts: u64,
+    pub reuses: u64,
+    pub creates: u64,
+    pub checkins: u64,
+    pub discards: u64,
+    pub total_wait_us: u64,
+    pub age: HistogramSnapshot,
+    pub lifetime: HistogramSnapshot,
+}
+
+/// What `Pool::drain_bucket` did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BucketDrainStats {
+    pub drained_idle: u32,
+    /// Connections still checked out when the wait gave up.
+    pub waiting_for_checkin: u32,
+}
+
+/// What `Pool::rebalance` closed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RebalanceStats {
+    pub evicted: u32,
+    pub buckets_affected: u32,
 }
 
 /// Connection pool for upstream Postgres connections.
 pub struct Pool {
-    buckets: Mutex<HashMap<PoolKey, PoolBucket>>,
+    buckets: Buckets,
     config: Arc<Config>,
     upstream_tls: Option<Arc<ClientConfig>>,
+    dns: DnsCache,
     metrics: Arc<Metrics>,
+    /// Read buffers for `pipe_pooled`, recycled across sessions.
+    buffers: BufferPool,
+    /// Limits concurrent `pool_min_size` top-up connects.
+    topup_permits: Semaphore,
+    /// SaltedPassword cache for upstream SCRAM logins (`None` when disabled).
+    scram_cache: Option<ScramCache>,
+    /// Upstream `max_connections`, read on the first connection that can.
+    max_connections: watch::Sender<Option<u32>>,
+    /// Set while (or once) a connection is reading `max_connections`.
+    max_connections_queried: AtomicBool,
 }
 
+/// Size of each pooled-pipe read buffer.
+const PIPE_BUFFER_SIZE: usize = 8192;
+
+/// Upstream connects the top-up may have in flight at once.
+const TOPUP_CONCURRENCY: usize = 4;
+
+/// Checkout error while `drain_bucket()` runs on the bucket.
+const BUCKET_DRAINING: &str = "pool bucket is draining";
+
 impl Pool {
     pub fn new(
         config: Arc<Config>,
         upstream_tls: Option<Arc<ClientConfig>>,
+        dns: DnsCache,
         metrics: Arc<Metrics>,
     ) -> Self {
+        // Two buffers (client + server) per checked-out connection
+        let buffers = BufferPool::new(PIPE_BUFFER_SIZE, 2 * config.pool_size as usize);
+        let scram_cache = (config.scram_cache_max_entries > 0)
+            .then(|| ScramCache::new(config.scram_cache_max_entries, metrics.clone()));
         Self {
-            buckets: Mutex::new(HashMap::new()),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
             config,
             upstream_tls,
+            dns,
             metrics,
+            buffers,
+            topup_permits: Semaphore::new(TOPUP_CONCURRENCY),
+            scram_cache,
+            max_connections: watch::Sender::new(None),
+            max_connections_queried: AtomicBool::new(false),
         }
     }
 
+    pub fn buffers(&self) -> &BufferPool {
+        &self.buffers
+    }
+
+    /// The upstream's `max_connections`, waiting for the first pooled
+    /// connection to read it (pool connections need a client's database and
+    /// role, so there is none before the first checkout).
+    pub async fn upstream_max_connections(&self) -> u32 {
+        let mut rx = self.max_connections.subscribe();
+        let value = *rx
+            .wait_for(Option::is_some)
+            .await
+            .expect("sender lives in the pool");
+        value.unwrap_or_default()
+    }
+
     /// Snapshot of current pool state (for admin API).
     pub async fn snapshot(&self) -> PoolSnapshot {
         let buckets = self.buckets.lock().await;
         let mut result = Vec::with_capacity(buckets.len());
         for (key, bucket) in buckets.iter() {
+            let stats = &bucket.stats;
             result.push(PoolBucketSnapshot {
                 database: key.database.clone(),
                 role: key.role.clone(),
+                upstream: key.upstream,
                 total: bucket.total,
                 idle: bucket.idle.len() as u32,
+                injecting: stats.injecting.load(Ordering::Relaxed),
+                checkouts: stats.checkouts.load(Ordering::Relaxed),
+                reuses: stats.reuses.load(Ordering::Relaxed),
+                creates: stats.creates.load(Ordering::Relaxed),
+                checkins: stats.checkins.load(Ordering::Relaxed),
+                discards: stats.discards.load(Ordering::Relaxed),
+                total_wait_us: stats.total_wait_us.load(Ordering::Relaxed),
+                age: stats.age_histogram.snapshot(),
+                lifetime: stats.lifetime_histogram.snapshot(),
             });
         }
         PoolSnapshot { buckets: result }
     }
 
     /// Check out a connection from the pool. Reuses an idle connection if available,
-    /// otherwise creates a new one (if under pool_size). Waits if pool is full.
+    /// otherwise creates a new one (if under pool_size). Waits if pool is full;
+    /// waiters are served first come, first served. The permit goes back to
+    /// `checkin` with the connection; dropping it discards the connection.
     pub async fn checkout(
         &self,
         key: &PoolKey,
         conn_id: u64,
-    ) -> Result<PooledConn, Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<(PooledConn, CheckoutPermit), Box<dyn std::error::Error + Send + Sync>> {
+        let started = Instant::now();
         let timeout = Duration::from_secs(self.config.pool_checkout_timeout);
-        let deadline = Instant::now() + timeout;
+        let deadline = started + timeout;
+
+        let permits = {
+            let mut buckets = self.buckets.lock().await;
+            let bucket = buckets
+                .entry(key.clone())
+                .or_insert_with(|| PoolBucket::new(self.config.pool_size));
+            if bucket.draining > 0 {
+                return Err(BUCKET_DRAINING.into());
+            }
+            Arc::clone(&bucket.permits)
+        };
+        let permit = match Arc::clone(&permits).try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => match tokio::time::timeout(timeout, permits.acquire_owned()).await {
+                Ok(Ok(permit)) => {
+                    Metrics::inc(&self.metrics.pool_wait_wakeups);
+                    permit
+                }
+                _ => {
+                    Metrics::inc(&self.metrics.pool_timeouts);
+                    return Err("pool checkout timeout: all connections in use".into());
+                }
+            },
+        };
 
         loop {
+            // Register for wakeups before checking the bucket, so a checkin that
+            // lands between the check and the wait is not missed.
+            let notify = {
+                let mut buckets = self.buckets.lock().await;
+                let bucket = buckets
+                    .entry(key.clone())
+                    .or_insert_with(|| PoolBucket::new(self.config.pool_size));
+                Arc::clone(&bucket.notify)
+            };
+            let notified = notify.notified();
+
             {
                 let mut buckets = self.buckets.lock().await;
-                let bucket = buckets.entry(key.clone()).or_insert_with(PoolBucket::new);
+                let bucket = buckets
+                    .entry(key.clone())
+                    .or_insert_with(|| PoolBucket::new(self.config.pool_size));
+                if bucket.draining > 0 {
+                    return Err(BUCKET_DRAINING.into());
+                }
 
                 // Try to pop an idle connection
                 if let Some(mut conn) = bucket.idle.pop_front() {
@@ -140,19 +387,30 @@ impl Pool {
                     {
                         conn.backend_key_data = cached.clone();
                     }
+                    let stats = Arc::clone(&bucket.stats);
+                    drop(buckets); // Release lock for the health check
+                    if let Err(e) = self.health_check(&mut conn.stream, conn_id).await {
+                        warn!(conn_id, error = %e, "pool: idle connection failed health check, discarding");
+                        self.discard(key, conn.created_at).await;
+                        continue;
+                    }
                     Metrics::inc(&self.metrics.pool_reuses);
-                    Metrics::inc(&self.metrics.pool_checkouts);
+                    self.count_checkout(key);
+                    Metrics::inc(&stats.reuses);
+                    record_checkout(&stats, started);
                     debug!(conn_id, database = %key.database, role = %key.role, "pool: reusing idle connection");
-                    return Ok(conn);
+                    return Ok(self.checked_out(key, conn, permit));
                 }
 
                 // Create new if under limit
                 if bucket.total < self.config.pool_size {
                     bucket.total += 1;
+                    let stats = Arc::clone(&bucket.stats);
                     drop(buckets); // Release lock before connecting
                     Metrics::inc(&self.metrics.pool_creates);
+                    Metrics::inc(&stats.creates);
                     debug!(conn_id, database = %key.database, role = %key.role, "pool: creating new connection");
-                    match self.create_connection(key, conn_id).await {
+                    match self.create_checked_connection(key, conn_id).await {
                         Ok(conn) => {
                             // Cache handshake data on first connection for this bucket
                             let mut buckets = self.buckets.lock().await;
@@ -163,39 +421,83 @@ impl Pool {
                                 bucket.cached_backend_key_data =
                                     Some(conn.backend_key_data.clone());
                             }
-                            Metrics::inc(&self.metrics.pool_checkouts);
-                            return Ok(conn);
+                            self.count_checkout(key);
+                            record_checkout(&stats, started);
+                            return Ok(self.checked_out(key, conn, permit));
                         }
                         Err(e) => {
                             // Decrement total on failure
-                            let mut buckets = self.buckets.lock().await;
-                            if let Some(bucket) = buckets.get_mut(key) {
-                                bucket.total = bucket.total.saturating_sub(1);
-                            }
+                            self.decrement_total(key).await;
                             return Err(e);
                         }
                     }
                 }
             }
 
-            // Pool is full — wait and retry
-            if Instant::now() >= deadline {
+            // Holding a permit but nothing to hand out yet — wait and retry
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || tokio::time::timeout(remaining, notified).await.is_err() {
                 Metrics::inc(&self.metrics.pool_timeouts);
                 return Err("pool checkout timeout: all connections in use".into());
             }
-            tokio::time::sleep(Duration::from_millis(50)).await;
+            Metrics::inc(&self.metrics.pool_wait_wakeups);
         }
     }
 
+    /// Pair a connection being handed out with its checkout permit.
+    fn checked_out(
+        &self,
+        key: &PoolKey,
+        conn: PooledConn,
+        permit: OwnedSemaphorePermit,
+    ) -> (PooledConn, CheckoutPermit) {
+        let slot = CheckedOutSlot {
+            buckets: Arc::clone(&self.buckets),
+            metrics: Arc::clone(&self.metrics),
+            key: key.clone(),
+            created_at: conn.created_at,
+        };
+        let permit = CheckoutPermit {
+            _permit: permit,
+            slot: Some(slot),
+        };
+        (conn, permit)
+    }
+
+    /// Count a connection just checked out from `key`'s bucket as injecting
+    /// until the guard is dropped. Slow resolvers show up here as connections
+    /// that are checked out but not yet serving queries.
+    pub async fn begin_injecting(&self, key: &PoolKey) -> InjectingGuard {
+        let mut buckets = self.buckets.lock().await;
+        let bucket = buckets
+            .entry(key.clone())
+            .or_insert_with(|| PoolBucket::new(self.config.pool_size));
+        bucket.stats.injecting.fetch_add(1, Ordering::Relaxed);
+        InjectingGuard(Arc::clone(&bucket.stats))
+    }
+
     /// Return a connection to the pool after use.
-    /// Sends ROLLBACK; DISCARD ALL; to reset state, then pushes to idle.
-    pub async fn checkin(&self, key: PoolKey, mut stream: UpstreamStream, conn_id: u64) {
+    /// Sends ROLLBACK, then RESET ROLE; RESET ALL, and pushes to idle. The full
+    /// `pool_reset_query` (DISCARD ALL by default) runs on the next checkout, so
+    /// checkin only drops the tenant's role and settings while the connection idles.
+    /// With `pool_reset_query = reset_role` only RESET ROLE follows the ROLLBACK,
+    /// and with `none` nothing does. `permit` is released once the connection
+    /// is back in idle (or discarded), so the next waiter finds it there.
+    pub async fn checkin(
+        &self,
+        key: PoolKey,
+        mut stream: UpstreamStream,
+        created_at: Instant,
+        mut permit: CheckoutPermit,
+        conn_id: u64,
+    ) {
+        // Every path below returns the connection to idle or discards it
+        permit.slot = None;
         // Reset the connection in two steps:
         // 1. ROLLBACK — ends any open transaction (no-op if idle)
-        // 2. DISCARD ALL — resets all session state
-        // These MUST be separate SimpleQuery messages because PostgreSQL
-        // wraps multi-statement queries in an implicit transaction, and
-        // DISCARD ALL refuses to run inside a transaction block.
+        // 2. RESET ROLE; RESET ALL — drops the tenant role and session settings
+        // ROLLBACK is sent on its own because PostgreSQL wraps multi-statement
+        // queries in an implicit transaction.
         let mut buf = BytesMut::with_capacity(1024);
         let reset_timeout = Duration::from_secs(5);
 
@@ -204,30 +506,39 @@ impl Pool {
             if !Self::send_and_drain(&mut stream, "ROLLBACK", &mut buf, conn_id).await {
                 return false;
             }
-            // Step 2: DISCARD ALL
-            Self::send_and_drain(&mut stream, "DISCARD ALL", &mut buf, conn_id).await
+            // Step 2: RESET ROLE; RESET ALL
+            match self.config.pool_reset_query.checkin_query() {
+                Some(sql) => Self::send_and_drain(&mut stream, sql, &mut buf, conn_id).await,
+                None => true,
+            }
         })
         .await
         {
             Ok(true) => {
+                let mut buckets = self.buckets.lock().await;
+                if buckets
+                    .get(&key)
+                    .is_some_and(|bucket| bucket.draining > 0 || bucket.is_drained(created_at))
+                {
+                    drop(buckets);
+                    debug!(conn_id, "pool: connection predates drain, discarding");
+                    self.discard(&key, created_at).await;
+                    return;
+                }
                 // Connection is clean — return to pool
                 Metrics::inc(&self.metrics.pool_checkins);
-                let mut buckets = self.buckets.lock().await;
                 if let Some(bucket) = buckets.get_mut(&key) {
-                    // Re-create a minimal PooledConn for the idle queue
-                    // (param_statuses and backend_key_data are preserved from creation)
-                    // We need to store them separately, but for simplicity we store
-                    // a placeholder — they were cached at creation time.
-                    // Actually, we need to preserve the original cached data.
-                    // The approach: store param_statuses/backend_key_data on the bucket level.
-                    // For now, push with empty caches — checkout will use whatever was cached.
+                    Metrics::inc(&bucket.stats.checkins);
+                    // Handshake data lives on the bucket; checkout re-attaches it
                     bucket.idle.push_back(PooledConn {
                         stream,
-                        created_at: Instant::now(), // Not ideal, but functional
+                        created_at,
                         last_used: Instant::now(),
+                        last_keepalive: Instant::now(),
                         param_statuses: Vec::new(),
                         backend_key_data: BytesMut::new(),
                     });
+                    bucket.notify.notify_waiters();
                     debug!(conn_id, database = %key.database, role = %key.role, "pool: connection returned");
                 } else {
                     // Bucket disappeared — discard
@@ -235,10 +546,204 @@ impl Pool {
                 }
             }
             _ => {
-                Metrics::inc(&self.metrics.pool_discards);
                 warn!(conn_id, "pool: reset failed or timed out, discarding");
-                self.decrement_total(&key).await;
+                self.discard(&key, created_at).await;
+            }
+        }
+        drop(permit);
+    }
+
+    /// Stop reusing every existing connection, e.g. after the upstream moved.
+    /// Idle connections are closed now; checked-out ones finish their session
+    /// and are discarded at checkin, holding their slot until then so the
+    /// bucket never exceeds `pool_size`. New connections are created as usual.
+    pub async fn drain(&self) {
+        let now = Instant::now();
+        let mut closed = 0u32;
+        let mut buckets = self.buckets.lock().await;
+        for bucket in buckets.values_mut() {
+            bucket.drained_at = Some(now);
+            for conn in std::mem::take(&mut bucket.idle) {
+                self.evict_idle(bucket, conn);
+                closed += 1;
             }
+            // Freed slots — let waiting checkouts create fresh connections
+            bucket.notify.notify_waiters();
+        }
+        let in_use: u32 = buckets.values().map(|bucket| bucket.total).sum();
+        info!(closed, in_use, "pool: drained");
+    }
+
+    /// Drain the buckets for `database` and `role` (primary and replicas), e.g.
+    /// before upgrading that database. Connections created before the drain
+    /// are closed: idle ones right away, checked-out ones at checkin. New
+    /// checkouts are refused until none is checked out or `timeout` passes,
+    /// whichever comes first; the bucket then serves fresh connections again.
+    /// Concurrent drains of the same bucket each hold it until they finish.
+    pub async fn drain_bucket(
+        &self,
+        database: &str,
+        role: &str,
+        timeout: Duration,
+    ) -> BucketDrainStats {
+        let started = Instant::now();
+        let mut keys = Vec::new();
+        {
+            let mut buckets = self.buckets.lock().await;
+            for (key, bucket) in buckets.iter_mut() {
+                if key.database == database && key.role == role {
+                    bucket.draining += 1;
+                    bucket.drained_at = Some(started);
+                    keys.push(key.clone());
+                }
+            }
+        }
+
+        let deadline = started + timeout;
+        let mut drained_idle = 0u32;
+        let waiting_for_checkin = loop {
+            let mut checked_out = 0u32;
+            {
+                let mut buckets = self.buckets.lock().await;
+                for key in &keys {
+                    let Some(bucket) = buckets.get_mut(key) else {
+                        continue;
+                    };
+                    // Connections that reach idle without a checkin (keepalive
+                    // pings, top-ups) are caught on a later pass
+                    let (stale, fresh): (Vec<_>, VecDeque<_>) = std::mem::take(&mut bucket.idle)
+                        .into_iter()
+                        .partition(|conn| bucket.is_drained(conn.created_at));
+                    bucket.idle = fresh;
+                    for conn in stale {
+                        self.evict_idle(bucket, conn);
+                        drained_idle += 1;
+                    }
+                    // `total` includes idle connections; only the rest are out
+                    checked_out += bucket.total.saturating_sub(bucket.idle.len() as u32);
+                }
+            }
+            if checked_out == 0 || Instant::now() >= deadline {
+                break checked_out;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        };
+
+        let mut buckets = self.buckets.lock().await;
+        for key in &keys {
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.draining = bucket.draining.saturating_sub(1);
+                bucket.notify.notify_waiters();
+            }
+        }
+        info!(
+            database,
+            role, drained_idle, waiting_for_checkin, "pool: bucket drained"
+        );
+        BucketDrainStats {
+            drained_idle,
+            waiting_for_checkin,
+        }
+    }
+
+    /// Close idle connections in buckets holding well over their share of the
+    /// pool. With `n` buckets the share is `pool_size / n` (never below
+    /// `pool_min_size`); a bucket with more than 1.5× that many idle is
+    /// trimmed back to it, least recently used first. Best effort: checked-out
+    /// connections are never touched.
+    pub async fn rebalance(&self) -> RebalanceStats {
+        let mut stats = RebalanceStats::default();
+        let mut buckets = self.buckets.lock().await;
+        if buckets.is_empty() {
+            return stats;
+        }
+        let target = (self.config.pool_size as usize / buckets.len())
+            .max(self.config.pool_min_size as usize);
+        for (key, bucket) in buckets.iter_mut() {
+            let idle = bucket.idle.len();
+            if 2 * idle <= 3 * target {
+                continue;
+            }
+            let excess: Vec<_> = bucket.idle.drain(..idle - target).collect();
+            for conn in excess {
+                debug!(
+                    database = %key.database,
+                    role = %key.role,
+                    idle_secs = conn.last_used.elapsed().as_secs(),
+                    "pool: rebalance evicted idle connection"
+                );
+                self.evict_idle(bucket, conn);
+                stats.evicted += 1;
+            }
+            stats.buckets_affected += 1;
+            // Freed slots — let waiting checkouts create fresh connections
+            bucket.notify.notify_waiters();
+        }
+        info!(
+            evicted = stats.evicted,
+            buckets_affected = stats.buckets_affected,
+            "pool: rebalanced"
+        );
+        stats
+    }
+
+    /// Close a connection already taken out of `bucket.idle`: count the
+    /// discard and its lifetime, and free its slot. The caller wakes waiters.
+    fn evict_idle(&self, bucket: &mut PoolBucket, conn: PooledConn) {
+        Metrics::inc(&self.metrics.pool_discards);
+        Metrics::inc(&bucket.stats.discards);
+        Metrics::observe(
+            &bucket.stats.lifetime_histogram,
+            conn.created_at.elapsed().as_secs_f64(),
+        );
+        bucket.total = bucket.total.saturating_sub(1);
+    }
+
+    /// Discard a checked-out connection that can't go back through `checkin`.
+    pub async fn discard_checked_out(&self, mut permit: CheckoutPermit) {
+        if let Some(slot) = permit.slot.take() {
+            self.discard(&slot.key, slot.created_at).await;
+        }
+    }
+
+    /// Count a connection as discarded and free its slot in the bucket.
+    async fn discard(&self, key: &PoolKey, created_at: Instant) {
+        discard_slot(&self.buckets, &self.metrics, key, created_at).await;
+    }
+
+    /// Run `pool_health_check_query` on a connection about to be handed out.
+    /// With `pool_upstream_min_version` set, the first column of the first row
+    /// must be a server version at least that new.
+    async fn health_check(&self, stream: &mut UpstreamStream, conn_id: u64) -> Result<(), String> {
+        let sql = self.config.pool_health_check_query.as_str();
+        if sql.trim().is_empty() {
+            return Ok(());
+        }
+        let first_value = query_first_value(stream, sql).await?;
+
+        let Some(min) = self.config.pool_upstream_min_version else {
+            return Ok(());
+        };
+        let version = first_value.unwrap_or_default();
+        match parse_major_version(&version) {
+            Some(major) if major >= min => Ok(()),
+            Some(major) => {
+                error!(
+                    conn_id,
+                    server_version = %version,
+                    min_version = min,
+                    "pool: upstream is older than pool_upstream_min_version"
+                );
+                Err(format!("server version {major} is below minimum {min}"))
+            }
+            None => Err(format!("could not parse server version from '{version}'")),
+        }
+    }
+
+    fn count_checkout(&self, key: &PoolKey) {
+        Metrics::inc(&self.metrics.pool_checkouts);
+        if key.upstream != Upstream::Primary {
+            Metrics::inc(&self.metrics.replica_checkouts_total);
         }
     }
 
@@ -256,7 +761,7 @@ impl Pool {
             return false;
         }
         loop {
-            if stream.read_buf(buf).await.is_err() {
+            if !matches!(stream.read_buf(buf).await, Ok(n) if n > 0) {
                 return false;
             }
             while let Some(msg) = try_read_backend_message(buf) {
@@ -271,32 +776,94 @@ impl Pool {
         }
     }
 
+    /// Create a new connection and run the health check on it.
+    async fn create_checked_connection(
+        &self,
+        key: &PoolKey,
+        conn_id: u64,
+    ) -> Result<PooledConn, Box<dyn std::error::Error + Send + Sync>> {
+        let mut conn = self.create_connection(key, conn_id).await?;
+        self.health_check(&mut conn.stream, conn_id)
+            .await
+            .map_err(|e| format!("new connection failed health check: {e}"))?;
+        if !self.max_connections_queried.swap(true, Ordering::AcqRel) {
+            self.read_max_connections(&mut conn.stream)
+                .await
+                .map_err(|e| format!("new connection failed reading max_connections: {e}"))?;
+        }
+        Ok(conn)
+    }
+
+    /// Read the upstream's `max_connections` for `upstream_max_connections`.
+    /// On failure the next new connection tries again. An error means the
+    /// query did not run to ReadyForQuery, so the connection must not be used.
+    async fn read_max_connections(&self, stream: &mut UpstreamStream) -> Result<(), String> {
+        let value = query_first_value(stream, "SHOW max_connections").await;
+        match value.map(|v| v.and_then(|v| v.parse::<u32>().ok())) {
+            Ok(Some(max)) => {
+                self.max_connections.send_replace(Some(max));
+                Ok(())
+            }
+            Ok(None) => {
+                debug!("pool: upstream max_connections is not numeric");
+                self.max_connections_queried.store(false, Ordering::Release);
+                Ok(())
+            }
+            Err(e) => {
+                debug!(error = %e, "pool: could not read upstream max_connections");
+                self.max_connections_queried.store(false, Ordering::Release);
+                Err(e)
+            }
+        }
+    }
+
     /// Create a new upstream connection, authenticate, and cache handshake data.
     async fn create_connection(
         &self,
         key: &PoolKey,
         conn_id: u64,
     ) -> Result<PooledConn, Box<dyn std::error::Error + Send + Sync>> {
-        let mut server = connect_upstream(&self.config, &self.upstream_tls).await?;
+        let mut server =
+            connect_upstream(&self.config, &self.upstream_tls, &self.dns, key.upstream).await?;
 
-        // Send StartupMessage with the pool role
+        // Send StartupMessage as the pool user (the bucket's role unless
+        // pool_upstream_user is set; SET ROLE is injected per client either way)
+        let upstream_user = self
+            .config
+            .pool_upstream_user
+            .as_deref()
+            .unwrap_or(&key.role);
         let mut params = std::collections::HashMap::new();
-        params.insert("user".into(), key.role.clone());
+        params.insert("user".into(), upstream_user.to_string());
         params.insert("database".into(), key.database.clone());
+        for (name, value) in &self.config.startup_params {
+            params.insert(name.clone(), value.clone());
+        }
+        if let Some(app) = self.config.pool_application_name(&key.database, &key.role) {
+            params.insert("application_name".into(), app);
+        }
         let startup_msg = build_startup_message(&params);
         server.write_all(&startup_msg).await?;
 
         // Authenticate to upstream
         let mut server_buf = BytesMut::with_capacity(4096);
         let upstream_password = self.config.upstream_password.as_deref().unwrap_or("");
-        auth::authenticate_upstream(
+        let method = auth::authenticate_upstream(
             &mut server,
             &mut server_buf,
-            &key.role,
+            upstream_user,
             upstream_password,
+            self.config.upstream_auth_method,
+            self.config.scram_iterations(),
+            self.scram_cache.as_ref(),
             conn_id,
         )
         .await?;
+        debug!(
+            conn_id,
+            method = method.map_or_else(|| "trust".to_string(), |m| m.to_string()),
+            "pool connection authenticated upstream"
+        );
 
         // Collect ParameterStatus, BackendKeyData, ReadyForQuery
         let mut param_statuses = Vec::new();
@@ -333,6 +900,7 @@ impl Pool {
             stream: server,
             created_at: now,
             last_used: now,
+            last_keepalive: now,
             param_statuses,
             backend_key_data,
         })
@@ -341,7 +909,12 @@ impl Pool {
     /// Background task: evict connections idle longer than pool_idle_timeout.
     pub async fn idle_reaper(self: Arc<Self>) {
         let idle_timeout = Duration::from_secs(self.config.pool_idle_timeout);
-        let interval = Duration::from_secs(30); // check every 30s
+        let keepalive =
+            Some(Duration::from_secs(self.config.pool_keepalive_secs)).filter(|k| !k.is_zero());
+        // Check every 30s, or every keepalive interval if that is shorter
+        let interval =
+            keepalive.map_or(Duration::from_secs(30), |k| k.min(Duration::from_secs(30)));
+        let min_idle = self.config.pool_min_size as usize;
 
         loop {
             tokio::time::sleep(interval).await;
@@ -351,9 +924,22 @@ impl Pool {
 
             for (key, bucket) in buckets.iter_mut() {
                 let before = bucket.idle.len();
-                bucket
-                    .idle
-                    .retain(|conn| conn.last_used.elapsed() < idle_timeout);
+                let stats = &bucket.stats;
+                // Never reap below pool_min_size
+                let mut reapable = before.saturating_sub(min_idle);
+                bucket.idle.retain(|conn| {
+                    let idle = conn.last_used.elapsed();
+                    if idle < idle_timeout || reapable == 0 {
+                        return true;
+                    }
+                    reapable -= 1;
+                    Metrics::observe(&stats.age_histogram, idle.as_secs_f64());
+                    Metrics::observe(
+                        &stats.lifetime_histogram,
+                        conn.created_at.elapsed().as_secs_f64(),
+                    );
+                    false
+                });
                 let reaped = before - bucket.idle.len();
                 if reaped > 0 {
                     bucket.total = bucket.total.saturating_sub(reaped as u32);
@@ -374,6 +960,131 @@ impl Pool {
             if total_reaped > 0 {
                 info!(reaped = total_reaped, "pool: idle reaper cycle");
             }
+            drop(buckets);
+
+            if let Some(keepalive) = keepalive {
+                self.keepalive(keepalive).await;
+            }
+            self.top_up().await;
+        }
+    }
+
+    /// Ping idle connections that have gone `interval` without use or a ping.
+    /// They leave the idle list (still counted in `total`) while the pings run
+    /// without the lock; live ones go back, failures are discarded.
+    async fn keepalive(self: &Arc<Self>, interval: Duration) {
+        let mut due = Vec::new();
+        {
+            let mut buckets = self.buckets.lock().await;
+            for (key, bucket) in buckets.iter_mut() {
+                let (ping, keep): (VecDeque<_>, VecDeque<_>) = bucket
+                    .idle
+                    .drain(..)
+                    .partition(|conn| conn.last_keepalive.elapsed() >= interval);
+                bucket.idle = keep;
+                due.extend(ping.into_iter().map(|conn| (key.clone(), conn)));
+            }
+        }
+
+        let mut tasks = JoinSet::new();
+        for (key, mut conn) in due {
+            let pool = Arc::clone(self);
+            tasks.spawn(async move {
+                Metrics::inc(&pool.metrics.pool_keepalive_pings_total);
+                let mut buf = BytesMut::with_capacity(256);
+                let alive = tokio::time::timeout(
+                    Duration::from_secs(5),
+                    Self::send_and_drain(&mut conn.stream, ";", &mut buf, 0),
+                )
+                .await
+                .unwrap_or(false);
+                if !alive {
+                    Metrics::inc(&pool.metrics.pool_keepalive_failures_total);
+                    warn!(
+                        database = %key.database,
+                        role = %key.role,
+                        "pool: keepalive ping failed, discarding connection"
+                    );
+                    pool.discard(&key, conn.created_at).await;
+                    return;
+                }
+                conn.last_keepalive = Instant::now();
+                let mut buckets = pool.buckets.lock().await;
+                if let Some(bucket) = buckets.get_mut(&key) {
+                    if bucket.is_drained(conn.created_at) {
+                        drop(buckets);
+                        pool.discard(&key, conn.created_at).await;
+                        return;
+                    }
+                    bucket.idle.push_back(conn);
+                    bucket.notify.notify_waiters();
+                }
+            });
+        }
+        while tasks.join_next().await.is_some() {}
+    }
+
+    /// Create connections until every bucket has `pool_min_size` idle (within
+    /// `pool_size`). Failures are logged and left for the next reaper cycle.
+    async fn top_up(self: &Arc<Self>) {
+        let min_idle = self.config.pool_min_size;
+        if min_idle == 0 {
+            return;
+        }
+
+        let mut tasks = JoinSet::new();
+        {
+            let mut buckets = self.buckets.lock().await;
+            for (key, bucket) in buckets
+                .iter_mut()
+                .filter(|(_, bucket)| bucket.draining == 0)
+            {
+                let missing = min_idle.saturating_sub(bucket.idle.len() as u32);
+                let available = self.config.pool_size.saturating_sub(bucket.total);
+                let count = missing.min(available);
+                // Reserve the slots now so checkouts can't overshoot pool_size
+                bucket.total += count;
+                for _ in 0..count {
+                    let pool = Arc::clone(self);
+                    let key = key.clone();
+                    tasks.spawn(async move { pool.top_up_one(key).await });
+                }
+            }
+        }
+        while tasks.join_next().await.is_some() {}
+    }
+
+    /// Create one top-up connection for a slot already reserved in `key`'s bucket.
+    async fn top_up_one(&self, key: PoolKey) {
+        let Ok(_permit) = self.topup_permits.acquire().await else {
+            self.decrement_total(&key).await;
+            return;
+        };
+        Metrics::inc(&self.metrics.pool_topup_attempts_total);
+        match self.create_checked_connection(&key, 0).await {
+            Ok(conn) => {
+                let mut buckets = self.buckets.lock().await;
+                let Some(bucket) = buckets.get_mut(&key) else {
+                    return; // bucket reaped meanwhile; the connection is dropped
+                };
+                Metrics::inc(&bucket.stats.creates);
+                if bucket.cached_param_statuses.is_none() {
+                    bucket.cached_param_statuses = Some(conn.param_statuses.clone());
+                    bucket.cached_backend_key_data = Some(conn.backend_key_data.clone());
+                }
+                bucket.idle.push_back(conn);
+                bucket.notify.notify_waiters();
+            }
+            Err(e) => {
+                Metrics::inc(&self.metrics.pool_topup_failures_total);
+                warn!(
+                    database = %key.database,
+                    role = %key.role,
+                    error = %e,
+                    "pool: top-up connection failed, retrying next cycle"
+                );
+                self.decrement_total(&key).await;
+            }
         }
     }
 
@@ -381,6 +1092,694 @@ impl Pool {
         let mut buckets = self.buckets.lock().await;
         if let Some(bucket) = buckets.get_mut(key) {
             bucket.total = bucket.total.saturating_sub(1);
+            // A slot freed up — let a waiting checkout create a new connection
+            bucket.notify.notify_waiters();
+        }
+    }
+}
+
+/// Count a connection as discarded and free its slot in `key`'s bucket.
+async fn discard_slot(buckets: &Buckets, metrics: &Metrics, key: &PoolKey, created_at: Instant) {
+    Metrics::inc(&metrics.pool_discards);
+    let mut buckets = buckets.lock().await;
+    if let Some(bucket) = buckets.get_mut(key) {
+        Metrics::inc(&bucket.stats.discards);
+        Metrics::observe(
+            &bucket.stats.lifetime_histogram,
+            created_at.elapsed().as_secs_f64(),
+        );
+        bucket.total = bucket.total.saturating_sub(1);
+        // A slot freed up — let a waiting checkout create a new connection
+        bucket.notify.notify_waiters();
+    }
+}
+
+/// Run `sql` and return the first column of its first row, if any. Gives up
+/// after 5 seconds.
+pub async fn query_first_value(
+    stream: &mut UpstreamStream,
+    sql: &str,
+) -> Result<Option<String>, String> {
+    let mut buf = BytesMut::with_capacity(1024);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        stream
+            .write_all(&build_query_message(sql))
+            .await
+            .map_err(|e| format!("write failed: {e}"))?;
+        let mut first_value = None;
+        loop {
+            let n = stream
+                .read_buf(&mut buf)
+                .await
+                .map_err(|e| format!("read failed: {e}"))?;
+            if n == 0 {
+                return Err("connection closed".to_string());
+            }
+            while let Some(msg) = try_read_backend_message(&mut buf) {
+                if msg.is_error_response() {
+                    return Err(msg.error_message());
+                }
+                if msg.is_data_row() && first_value.is_none() {
+                    first_value = Some(first_column(&msg.payload).unwrap_or_default());
+                }
+                if msg.is_ready_for_query() {
+                    return Ok(first_value);
+                }
+            }
         }
+    })
+    .await
+    .map_err(|_| "timed out".to_string())?
+}
+
+/// Count a successful checkout and the time spent getting it.
+fn record_checkout(stats: &BucketStats, started: Instant) {
+    Metrics::inc(&stats.checkouts);
+    stats
+        .total_wait_us
+        .fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+}
+
+/// Text of the first column of a DataRow payload (None if NULL or truncated).
+fn first_column(payload: &[u8]) -> Option<String> {
+    let len = i32::from_be_bytes(payload.get(2..6)?.try_into().ok()?);
+    let len = usize::try_from(len).ok()?;
+    let value = payload.get(6..6 + len)?;
+    Some(String::from_utf8_lossy(value).into_owned())
+}
+
+/// Major version from a `server_version` string such as `16.2 (Debian 16.2-1)`
+/// or `17beta1`.
+fn parse_major_version(version: &str) -> Option<u32> {
+    let digits: String = version
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+// ─── Tests ──────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::build_auth_ok;
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// A minimal upstream: accepts any login and answers every query with
+    /// EmptyQueryResponse + ReadyForQuery, except `SHOW max_connections`,
+    /// which returns 100.
+    async fn fake_upstream() -> u16 {
+        fake_upstream_with_show(b"D\0\0\0\x0d\0\x01\0\0\0\x03100").await
+    }
+
+    /// `fake_upstream`, sending `show_reply` ahead of the usual response to
+    /// `SHOW max_connections`.
+    async fn fake_upstream_with_show(show_reply: &'static [u8]) -> u16 {
+        const READY: &[u8] = b"Z\0\0\0\x05I";
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let len = socket.read_u32().await.unwrap() as usize;
+                    let mut startup = vec![0; len - 4];
+                    socket.read_exact(&mut startup).await.unwrap();
+                    socket.write_all(&build_auth_ok()).await.unwrap();
+                    socket.write_all(READY).await.unwrap();
+                    while let Ok(msg_type) = socket.read_u8().await {
+                        let len = socket.read_u32().await.unwrap() as usize;
+                        let mut payload = vec![0; len - 4];
+                        socket.read_exact(&mut payload).await.unwrap();
+                        if msg_type == b'X' {
+                            break;
+                        }
+                        if payload.starts_with(b"SHOW max_connections") {
+                            socket.write_all(show_reply).await.unwrap();
+                        }
+                        socket.write_all(b"I\0\0\0\x04").await.unwrap();
+                        socket.write_all(READY).await.unwrap();
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn top_up_fills_bucket_to_min_size() {
+        let config = Config {
+            upstream_port: fake_upstream().await,
+            upstream_password: Some("secret".into()),
+            pool_size: 4,
+            pool_min_size: 3,
+            ..Config::default()
+        };
+        let metrics = Arc::new(Metrics::new(Vec::new()));
+        let pool = Arc::new(Pool::new(
+            Arc::new(config),
+            None,
+            DnsCache::new(),
+            Arc::clone(&metrics),
+        ));
+        let key = PoolKey {
+            database: "app".into(),
+            role: "app_user".into(),
+            upstream: Upstream::Primary,
+        };
+
+        // First use creates the bucket; return the connection to it
+        let (conn, permit) = pool.checkout(&key, 1).await.unwrap();
+        pool.checkin(key.clone(), conn.stream, conn.created_at, permit, 1)
+            .await;
+
+        pool.top_up().await;
+        let snap = pool.snapshot().await;
+        assert_eq!(snap.buckets.len(), 1);
+        assert_eq!(snap.buckets[0].idle, 3);
+        assert_eq!(snap.buckets[0].total, 3);
+        assert_eq!(metrics.pool_topup_attempts_total.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.pool_topup_failures_total.load(Ordering::Relaxed), 0);
+
+        // Already at the minimum: nothing more to do
+        pool.top_up().await;
+        assert_eq!(pool.snapshot().await.buckets[0].total, 3);
+
+        // Pre-warmed connections are handed out like any other
+        let (conn, _permit) = pool.checkout(&key, 2).await.unwrap();
+        assert_eq!(pool.snapshot().await.buckets[0].idle, 2);
+        drop(conn);
+    }
+
+    #[tokio::test]
+    async fn checkout_and_checkin_update_metrics() {
+        let config = Config {
+            upstream_port: fake_upstream().await,
+            upstream_password: Some("secret".into()),
+            pool_size: 1,
+            pool_checkout_timeout: 0,
+            ..Config::default()
+        };
+        let metrics = Arc::new(Metrics::new(Vec::new()));
+        let pool = Pool::new(
+            Arc::new(config),
+            None,
+            DnsCache::new(),
+            Arc::clone(&metrics),
+        );
+        let key = PoolKey {
+            database: "app".into(),
+            role: "app_user".into(),
+            upstream: Upstream::Primary,
+        };
+        let count = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+        let (conn, permit) = pool.checkout(&key, 1).await.unwrap();
+        assert_eq!(count(&metrics.pool_checkouts), 1);
+        assert_eq!(count(&metrics.pool_creates), 1);
+        assert_eq!(count(&metrics.pool_reuses), 0);
+
+        // The only slot is taken, and there is no time to wait for it
+        assert!(pool.checkout(&key, 2).await.is_err());
+        assert_eq!(count(&metrics.pool_timeouts), 1);
+        assert_eq!(count(&metrics.pool_checkouts), 1);
+
+        pool.checkin(key.clone(), conn.stream, conn.created_at, permit, 1)
+            .await;
+        assert_eq!(count(&metrics.pool_checkins), 1);
+
+        let (conn, permit) = pool.checkout(&key, 3).await.unwrap();
+        assert_eq!(count(&metrics.pool_checkouts), 2);
+        assert_eq!(count(&metrics.pool_reuses), 1);
+        assert_eq!(count(&metrics.pool_creates), 1);
+
+        pool.drain().await;
+        pool.checkin(key.clone(), conn.stream, conn.created_at, permit, 3)
+            .await;
+        assert_eq!(count(&metrics.pool_discards), 1);
+        assert_eq!(count(&metrics.pool_checkins), 1);
+    }
+
+    #[tokio::test]
+    async fn waiters_are_served_in_arrival_order() {
+        let config = Config {
+            upstream_port: fake_upstream().await,
+            upstream_password: Some("secret".into()),
+            pool_size: 1,
+            pool_checkout_timeout: 5,
+            ..Config::default()
+        };
+        let metrics = Arc::new(Metrics::new(Vec::new()));
+        let pool = Arc::new(Pool::new(
+            Arc::new(config),
+            None,
+            DnsCache::new(),
+            Arc::clone(&metrics),
+        ));
+        let key = PoolKey {
+            database: "app".into(),
+            role: "app_user".into(),
+            upstream: Upstream::Primary,
+        };
+        let waiter = |conn_id| {
+            let pool = Arc::clone(&pool);
+            let key = key.clone();
+            tokio::spawn(async move { pool.checkout(&key, conn_id).await })
+        };
+
+        let (conn, permit) = pool.checkout(&key, 1).await.unwrap();
+        let first = waiter(2);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let second = waiter(3);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        pool.checkin(key.clone(), conn.stream, conn.created_at, permit, 1)
+            .await;
+        let (conn, permit) = first.await.unwrap().unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!second.is_finished());
+
+        pool.checkin(key.clone(), conn.stream, conn.created_at, permit, 2)
+            .await;
+        let (conn, _permit) = second.await.unwrap().unwrap();
+        drop(conn);
+        assert_eq!(metrics.pool_wait_wakeups.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.pool_timeouts.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn injecting_guard_counts_until_dropped() {
+        let config = Config {
+            upstream_port: fake_upstream().await,
+            upstream_password: Some("secret".into()),
+            ..Config::default()
+        };
+        let pool = Pool::new(
+            Arc::new(config),
+            None,
+            DnsCache::new(),
+            Arc::new(Metrics::new(Vec::new())),
+        );
+        let key = PoolKey {
+            database: "app".into(),
+            role: "app_user".into(),
+            upstream: Upstream::Primary,
+        };
+
+        let (conn, _permit) = pool.checkout(&key, 1).await.unwrap();
+        let guard = pool.begin_injecting(&key).await;
+        assert_eq!(pool.snapshot().await.buckets[0].injecting, 1);
+        drop(guard);
+        assert_eq!(pool.snapshot().await.buckets[0].injecting, 0);
+        drop(conn);
+    }
+
+    #[tokio::test]
+    async fn first_connection_reads_upstream_max_connections() {
+        let config = Config {
+            upstream_port: fake_upstream().await,
+            upstream_password: Some("secret".into()),
+            ..Config::default()
+        };
+        let pool = Pool::new(
+            Arc::new(config),
+            None,
+            DnsCache::new(),
+            Arc::new(Metrics::new(Vec::new())),
+        );
+        let key = PoolKey {
+            database: "app".into(),
+            role: "app_user".into(),
+            upstream: Upstream::Primary,
+        };
+        assert_eq!(*pool.max_connections.borrow(), None);
+
+        let (conn, _permit) = pool.checkout(&key, 1).await.unwrap();
+        assert_eq!(pool.upstream_max_connections().await, 100);
+        assert!(pool.max_connections_queried.load(Ordering::Relaxed));
+        drop(conn);
+    }
+
+    #[tokio::test]
+    async fn dropped_checkouts_release_their_slots() {
+        let config = Config {
+            upstream_port: fake_upstream().await,
+            upstream_password: Some("secret".into()),
+            pool_size: 2,
+            pool_checkout_timeout: 2,
+            ..Config::default()
+        };
+        let metrics = Arc::new(Metrics::new(Vec::new()));
+        let pool = Pool::new(
+            Arc::new(config),
+            None,
+            DnsCache::new(),
+            Arc::clone(&metrics),
+        );
+        let key = PoolKey {
+            database: "app".into(),
+            role: "app_user".into(),
+            upstream: Upstream::Primary,
+        };
+
+        // Sessions that fail after checkout (reset, resolver, injection) drop
+        // the connection without checkin; each must give its slot back
+        for conn_id in 1..=3 {
+            let (conn, permit) = pool.checkout(&key, conn_id).await.unwrap();
+            drop(conn);
+            drop(permit);
+        }
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while pool.snapshot().await.buckets[0].total > 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(metrics.pool_discards.load(Ordering::Relaxed), 3);
+
+        // Checkin and discard_checked_out settle the slot themselves
+        let (conn, permit) = pool.checkout(&key, 4).await.unwrap();
+        pool.checkin(key.clone(), conn.stream, conn.created_at, permit, 4)
+            .await;
+        let (_conn, permit) = pool.checkout(&key, 5).await.unwrap();
+        pool.discard_checked_out(permit).await;
+        tokio::task::yield_now().await;
+        let snap = pool.snapshot().await;
+        assert_eq!((snap.buckets[0].total, snap.buckets[0].idle), (0, 0));
+        assert_eq!(metrics.pool_discards.load(Ordering::Relaxed), 4);
+    }
+
+    #[tokio::test]
+    async fn failed_max_connections_read_discards_the_connection() {
+        // An ErrorResponse leaves the query's ReadyForQuery unread
+        let config = Config {
+            upstream_port: fake_upstream_with_show(b"E\0\0\0\x0cSERROR\0\0").await,
+            upstream_password: Some("secret".into()),
+            ..Config::default()
+        };
+        let pool = Pool::new(
+            Arc::new(config),
+            None,
+            DnsCache::new(),
+            Arc::new(Metrics::new(Vec::new())),
+        );
+        let key = PoolKey {
+            database: "app".into(),
+            role: "app_user".into(),
+            upstream: Upstream::Primary,
+        };
+
+        let err = pool.checkout(&key, 1).await.unwrap_err();
+        assert!(err.to_string().contains("max_connections"));
+        assert_eq!(pool.snapshot().await.buckets[0].total, 0);
+        assert_eq!(*pool.max_connections.borrow(), None);
+        // The next new connection tries again
+        assert!(!pool.max_connections_queried.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn drain_keeps_checked_out_slots_until_checkin() {
+        let config = Config {
+            upstream_port: fake_upstream().await,
+            upstream_password: Some("secret".into()),
+            pool_size: 2,
+            ..Config::default()
+        };
+        let metrics = Arc::new(Metrics::new(Vec::new()));
+        let pool = Pool::new(
+            Arc::new(config),
+            None,
+            DnsCache::new(),
+            Arc::clone(&metrics),
+        );
+        let key = PoolKey {
+            database: "app".into(),
+            role: "app_user".into(),
+            upstream: Upstream::Primary,
+        };
+        let (in_use, in_use_permit) = pool.checkout(&key, 1).await.unwrap();
+        let (idle, idle_permit) = pool.checkout(&key, 2).await.unwrap();
+        pool.checkin(key.clone(), idle.stream, idle.created_at, idle_permit, 2)
+            .await;
+
+        // The idle connection goes now; the checked-out one keeps its slot
+        pool.drain().await;
+        let snap = pool.snapshot().await;
+        assert_eq!(snap.buckets[0].idle, 0);
+        assert_eq!(snap.buckets[0].total, 1);
+
+        // A fresh connection fits in the freed slot and is reused normally
+        let (fresh, fresh_permit) = pool.checkout(&key, 3).await.unwrap();
+        assert_eq!(pool.snapshot().await.buckets[0].total, 2);
+        pool.checkin(key.clone(), fresh.stream, fresh.created_at, fresh_permit, 3)
+            .await;
+        assert_eq!(pool.snapshot().await.buckets[0].idle, 1);
+
+        // The pre-drain connection is discarded when its session ends
+        pool.checkin(
+            key.clone(),
+            in_use.stream,
+            in_use.created_at,
+            in_use_permit,
+            1,
+        )
+        .await;
+        let snap = pool.snapshot().await;
+        assert_eq!(snap.buckets[0].idle, 1);
+        assert_eq!(snap.buckets[0].total, 1);
+        assert_eq!(metrics.pool_discards.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn drain_bucket_refuses_checkouts_until_connections_return() {
+        let config = Config {
+            upstream_port: fake_upstream().await,
+            upstream_password: Some("secret".into()),
+            pool_size: 2,
+            ..Config::default()
+        };
+        let metrics = Arc::new(Metrics::new(Vec::new()));
+        let pool = Arc::new(Pool::new(
+            Arc::new(config),
+            None,
+            DnsCache::new(),
+            Arc::clone(&metrics),
+        ));
+        let key = PoolKey {
+            database: "app".into(),
+            role: "app_user".into(),
+            upstream: Upstream::Primary,
+        };
+        let other = PoolKey {
+            role: "reporting".into(),
+            ..key.clone()
+        };
+        let (in_use, in_use_permit) = pool.checkout(&key, 1).await.unwrap();
+        let (idle, idle_permit) = pool.checkout(&key, 2).await.unwrap();
+        pool.checkin(key.clone(), idle.stream, idle.created_at, idle_permit, 2)
+            .await;
+
+        let drain = tokio::spawn({
+            let pool = Arc::clone(&pool);
+            async move {
+                pool.drain_bucket("app", "app_user", Duration::from_secs(5))
+                    .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let err = pool.checkout(&key, 3).await.err().unwrap();
+        assert_eq!(err.to_string(), BUCKET_DRAINING);
+        // Other buckets are unaffected
+        let (conn, _permit) = pool.checkout(&other, 4).await.unwrap();
+        drop(conn);
+
+        // A second, shorter drain gives up on the checked-out connection but
+        // leaves the bucket draining for the first
+        let stats = pool
+            .drain_bucket("app", "app_user", Duration::from_millis(200))
+            .await;
+        assert_eq!(
+            stats,
+            BucketDrainStats {
+                drained_idle: 0,
+                waiting_for_checkin: 1
+            }
+        );
+        assert!(pool.checkout(&key, 5).await.is_err());
+
+        pool.checkin(
+            key.clone(),
+            in_use.stream,
+            in_use.created_at,
+            in_use_permit,
+            1,
+        )
+        .await;
+        assert_eq!(
+            drain.await.unwrap(),
+            BucketDrainStats {
+                drained_idle: 1,
+                waiting_for_checkin: 0
+            }
+        );
+        assert_eq!(metrics.pool_discards.load(Ordering::Relaxed), 2);
+
+        // Serving again, with a fresh connection that survives a checkin
+        let (conn, permit) = pool.checkout(&key, 6).await.unwrap();
+        pool.checkin(key.clone(), conn.stream, conn.created_at, permit, 6)
+            .await;
+        let snap = pool.snapshot().await;
+        let bucket = snap.buckets.iter().find(|b| b.role == "app_user").unwrap();
+        assert_eq!((bucket.idle, bucket.total), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn rebalance_trims_buckets_over_their_share() {
+        let config = Config {
+            upstream_port: fake_upstream().await,
+            upstream_password: Some("secret".into()),
+            pool_size: 4,
+            ..Config::default()
+        };
+        let metrics = Arc::new(Metrics::new(Vec::new()));
+        let pool = Pool::new(
+            Arc::new(config),
+            None,
+            DnsCache::new(),
+            Arc::clone(&metrics),
+        );
+        let key = |role: &str| PoolKey {
+            database: "app".into(),
+            role: role.into(),
+            upstream: Upstream::Primary,
+        };
+        // "busy" idles all 4 of its connections, "quiet" just 1
+        let mut conns = Vec::new();
+        for conn_id in 0..4 {
+            conns.push((
+                key("busy"),
+                pool.checkout(&key("busy"), conn_id).await.unwrap(),
+            ));
+        }
+        conns.push((key("quiet"), pool.checkout(&key("quiet"), 4).await.unwrap()));
+        for (conn_id, (key, (conn, permit))) in conns.into_iter().enumerate() {
+            pool.checkin(key, conn.stream, conn.created_at, permit, conn_id as u64)
+                .await;
+        }
+
+        // Share is 4 / 2 = 2: "busy" (4 > 3) is trimmed to 2, "quiet" is left
+        let stats = pool.rebalance().await;
+        assert_eq!(
+            stats,
+            RebalanceStats {
+                evicted: 2,
+                buckets_affected: 1
+            }
+        );
+        let snap = pool.snapshot().await;
+        let bucket = |role: &str| snap.buckets.iter().find(|b| b.role == role).unwrap();
+        assert_eq!((bucket("busy").idle, bucket("busy").total), (2, 2));
+        assert_eq!((bucket("quiet").idle, bucket("quiet").total), (1, 1));
+        assert_eq!(metrics.pool_discards.load(Ordering::Relaxed), 2);
+
+        // Already balanced: nothing more to do
+        assert_eq!(pool.rebalance().await, RebalanceStats::default());
+    }
+
+    #[tokio::test]
+    async fn keepalive_pings_idle_connections_and_discards_dead_ones() {
+        let config = Config {
+            upstream_port: fake_upstream().await,
+            upstream_password: Some("secret".into()),
+            pool_keepalive_secs: 60,
+            ..Config::default()
+        };
+        let metrics = Arc::new(Metrics::new(Vec::new()));
+        let pool = Arc::new(Pool::new(
+            Arc::new(config),
+            None,
+            DnsCache::new(),
+            Arc::clone(&metrics),
+        ));
+        let key = PoolKey {
+            database: "app".into(),
+            role: "app_user".into(),
+            upstream: Upstream::Primary,
+        };
+        let (conn, permit) = pool.checkout(&key, 1).await.unwrap();
+        pool.checkin(key.clone(), conn.stream, conn.created_at, permit, 1)
+            .await;
+
+        // Recently checked in: not due yet
+        pool.keepalive(Duration::from_secs(60)).await;
+        assert_eq!(
+            metrics.pool_keepalive_pings_total.load(Ordering::Relaxed),
+            0
+        );
+
+        // Add a connection whose upstream has gone away
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        drop(listener.accept().await.unwrap());
+        {
+            let mut buckets = pool.buckets.lock().await;
+            let bucket = buckets.get_mut(&key).unwrap();
+            let now = Instant::now();
+            bucket.idle.push_back(PooledConn {
+                stream: UpstreamStream::Plain(stream),
+                created_at: now,
+                last_used: now,
+                last_keepalive: now,
+                param_statuses: Vec::new(),
+                backend_key_data: BytesMut::new(),
+            });
+            bucket.total += 1;
+        }
+
+        pool.keepalive(Duration::ZERO).await;
+        assert_eq!(
+            metrics.pool_keepalive_pings_total.load(Ordering::Relaxed),
+            2
+        );
+        assert_eq!(
+            metrics
+                .pool_keepalive_failures_total
+                .load(Ordering::Relaxed),
+            1
+        );
+        let snap = pool.snapshot().await;
+        assert_eq!(snap.buckets[0].idle, 1);
+        assert_eq!(snap.buckets[0].total, 1);
+
+        // The surviving connection is still usable
+        let (conn, _permit) = pool.checkout(&key, 2).await.unwrap();
+        drop(conn);
+    }
+
+    #[test]
+    fn major_version_parsing() {
+        assert_eq!(
+            parse_major_version("16.2 (Debian 16.2-1.pgdg120+2)"),
+            Some(16)
+        );
+        assert_eq!(parse_major_version("17beta1"), Some(17));
+        assert_eq!(parse_major_version("9.6.24"), Some(9));
+        assert_eq!(parse_major_version(""), None);
+        assert_eq!(parse_major_version("unknown"), None);
+    }
+
+    #[test]
+    fn first_column_of_data_row() {
+        // 1 field, length 4, "16.2"
+        let payload = [&[0, 1, 0, 0, 0, 4][..], b"16.2"].concat();
+        assert_eq!(first_column(&payload).as_deref(), Some("16.2"));
+        // NULL
+        assert_eq!(first_column(&[0, 1, 0xff, 0xff, 0xff, 0xff]), None);
+        // Truncated
+        assert_eq!(first_column(&[0, 1, 0, 0, 0, 9, b'1']), None);
     }
 }
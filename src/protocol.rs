@@ -21,9 +21,15 @@ const SSL_REQUEST_CODE: i32 = 80877103;
 /// CancelRequest magic number
 const CANCEL_REQUEST_CODE: i32 = 80877102;
 
-/// Single byte denying SSL
+/// GSSENCRequest magic number (PostgreSQL 12+)
+const GSSENC_REQUEST_CODE: i32 = 80877104;
+
+/// Single byte denying SSL (also used to deny GSSAPI encryption)
 pub const SSL_DENY: &[u8] = b"N";
 
+/// Default cap on StartupMessage length (`max_startup_message_bytes`)
+pub const DEFAULT_MAX_STARTUP_MESSAGE_BYTES: usize = 10240;
+
 /// Backend message types we care about
 pub mod backend {
     pub const AUTHENTICATION: u8 = b'R';
@@ -35,6 +41,9 @@ pub mod backend {
     pub const ROW_DESCRIPTION: u8 = b'T';
     pub const DATA_ROW: u8 = b'D';
     pub const EMPTY_QUERY_RESPONSE: u8 = b'I';
+    pub const NOTICE_RESPONSE: u8 = b'N';
+    pub const NEGOTIATE_PROTOCOL_VERSION: u8 = b'v';
+    pub const NOTIFICATION_RESPONSE: u8 = b'A';
 }
 
 /// Authentication subtypes
@@ -55,6 +64,8 @@ pub enum StartupType {
     SslRequest,
     /// CancelRequest — client wants to cancel a query.
     CancelRequest,
+    /// GSSENCRequest — client wants GSSAPI transport encryption.
+    GssEncRequest,
     /// Normal StartupMessage with parameters.
     Startup(StartupMessage),
 }
@@ -104,11 +115,35 @@ impl BackendMessage {
         self.msg_type == backend::READY_FOR_QUERY
     }
 
+    /// Transaction status from a ReadyForQuery: `b'I'` idle, `b'T'` in a
+    /// transaction block, `b'E'` in a failed transaction block.
+    pub fn transaction_status(&self) -> Option<u8> {
+        if !self.is_ready_for_query() {
+            return None;
+        }
+        self.payload.first().copied()
+    }
+
     /// Is this ErrorResponse?
     pub fn is_error_response(&self) -> bool {
         self.msg_type == backend::ERROR_RESPONSE
     }
 
+    /// Is this NoticeResponse?
+    pub fn is_notice_response(&self) -> bool {
+        self.msg_type == backend::NOTICE_RESPONSE
+    }
+
+    /// Is this NotificationResponse (LISTEN/NOTIFY)?
+    pub fn is_notification(&self) -> bool {
+        self.msg_type == backend::NOTIFICATION_RESPONSE
+    }
+
+    /// Is this NegotiateProtocolVersion?
+    pub fn is_negotiate_protocol_version(&self) -> bool {
+        self.msg_type == backend::NEGOTIATE_PROTOCOL_VERSION
+    }
+
     /// Is this ParameterStatus?
     pub fn is_parameter_status(&self) -> bool {
         self.msg_type == backend::PARAMETER_STATUS
@@ -150,9 +185,49 @@ impl BackendMessage {
         if !self.is_error_response() {
             return String::from("not an error");
         }
-        let mut parts = Vec::new();
+        let fields = self.parse_error_fields();
+        match (fields.message.is_empty(), fields.detail) {
+            (true, None) => String::from("unknown error"),
+            (true, Some(detail)) => detail,
+            (false, None) => fields.message,
+            (false, Some(detail)) => format!("{}: {detail}", fields.message),
+        }
+    }
+
+    /// Parse the fields of an ErrorResponse. Anything else yields empty fields.
+    pub fn parse_error_fields(&self) -> ErrorFields {
+        if !self.is_error_response() {
+            return ErrorFields::default();
+        }
+        ErrorFields::parse(&self.payload)
+    }
+}
+
+/// The fields of an ErrorResponse that pgvpd looks at. Missing fields are
+/// left empty (or `None`); unknown field types are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorFields {
+    /// `S`: ERROR, FATAL, PANIC (localized).
+    pub severity: String,
+    /// `C`: the five-character SQLSTATE code.
+    pub sqlstate: String,
+    /// `M`: the primary message.
+    pub message: String,
+    /// `D`: optional secondary message.
+    pub detail: Option<String>,
+    /// `H`: optional suggestion for fixing the problem.
+    pub hint: Option<String>,
+    /// `P`: 1-based character offset into the query string.
+    pub position: Option<u32>,
+}
+
+impl ErrorFields {
+    /// Parse an ErrorResponse (or NoticeResponse) payload: a sequence of
+    /// type-byte + null-terminated string fields, ending with a zero byte.
+    /// A truncated payload yields whatever fields it does contain.
+    pub fn parse(data: &[u8]) -> Self {
+        let mut fields = Self::default();
         let mut offset = 0;
-        let data = &self.payload;
 
         while offset < data.len() {
             let field_type = data[offset];
@@ -172,16 +247,28 @@ impl BackendMessage {
             offset = str_end + 1;
 
             match field_type {
-                b'M' => parts.insert(0, value), // Message
-                b'D' => parts.push(value),      // Detail
+                b'S' => fields.severity = value,
+                b'C' => fields.sqlstate = value,
+                b'M' => fields.message = value,
+                b'D' => fields.detail = Some(value),
+                b'H' => fields.hint = Some(value),
+                b'P' => fields.position = value.parse().ok(),
                 _ => {}
             }
         }
 
-        if parts.is_empty() {
-            String::from("unknown error")
+        fields
+    }
+
+    /// The SQLSTATE class: the first two characters of the code (`23` for
+    /// integrity constraint violations, `53` for insufficient resources).
+    /// `None` if the code is missing or malformed.
+    pub fn sqlstate_class(&self) -> Option<&str> {
+        let code = self.sqlstate.as_bytes();
+        if code.len() == 5 && code.iter().all(u8::is_ascii_alphanumeric) {
+            Some(&self.sqlstate[..2])
         } else {
-            parts.join(": ")
+            None
         }
     }
 }
@@ -191,14 +278,15 @@ impl BackendMessage {
 /// Try to read a complete startup-phase message from the buffer.
 ///
 /// Startup messages have no type byte — they start with Int32 length.
-/// Returns `None` if not enough data. Consumes the message from `buf` on success.
-pub fn try_read_startup(buf: &mut BytesMut) -> Option<StartupType> {
+/// Returns `None` if not enough data, or if the length exceeds `max_size`.
+/// Consumes the message from `buf` on success.
+pub fn try_read_startup(buf: &mut BytesMut, max_size: usize) -> Option<StartupType> {
     if buf.len() < 8 {
         return None;
     }
 
     let length = i32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
-    if !(8..=10240).contains(&length) {
+    if !(8..=max_size).contains(&length) {
         return None; // sanity check
     }
     if buf.len() < length {
@@ -211,6 +299,7 @@ pub fn try_read_startup(buf: &mut BytesMut) -> Option<StartupType> {
     match version {
         v if v == SSL_REQUEST_CODE => Some(StartupType::SslRequest),
         v if v == CANCEL_REQUEST_CODE => Some(StartupType::CancelRequest),
+        v if v == GSSENC_REQUEST_CODE => Some(StartupType::GssEncRequest),
         _ => {
             // Parse key-value pairs
             let mut params = HashMap::new();
@@ -256,16 +345,20 @@ pub fn try_read_backend_message(buf: &mut BytesMut) -> Option<BackendMessage> {
     }
 
     let msg_type = buf[0];
-    let length = i32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+    let length = i32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]);
+    if length < 4 {
+        return None; // malformed: length must cover itself
+    }
+    let length = length as usize;
     let total_length = 1 + length; // type byte + length value
 
     if buf.len() < total_length {
         return None;
     }
 
-    let raw = BytesMut::from(&buf[..total_length]);
-    let payload = BytesMut::from(&buf[5..total_length]);
-    buf.advance(total_length);
+    // `raw` takes over the bytes without copying; only the payload is copied
+    let raw = buf.split_to(total_length);
+    let payload = BytesMut::from(&raw[5..]);
 
     Some(BackendMessage {
         msg_type,
@@ -274,6 +367,165 @@ pub fn try_read_backend_message(buf: &mut BytesMut) -> Option<BackendMessage> {
     })
 }
 
+/// Most of an ErrorResponse body `MessageScanner::scan_backend` keeps. The
+/// fields it reports (severity, SQLSTATE) come first in practice.
+const ERROR_CAPTURE_LIMIT: usize = 1024;
+
+/// Follows message boundaries in a typed message stream (either direction,
+/// post-startup) that is forwarded in arbitrary chunks, reporting each
+/// message's type byte without buffering the message itself.
+#[derive(Debug, Default)]
+pub struct MessageScanner {
+    header: [u8; 5],
+    header_len: usize,
+    /// Body bytes of the current message still to skip.
+    remaining: usize,
+    /// The ErrorResponse body collected so far, when `scan_backend` is in one.
+    error_body: Option<Vec<u8>>,
+}
+
+impl MessageScanner {
+    /// Feed the next chunk of the stream; `on_message` is called with the type
+    /// byte of every message whose header completes inside `data`.
+    pub fn scan(&mut self, data: &[u8], on_message: impl FnMut(u8)) {
+        self.scan_inner(data, false, on_message, |_| {});
+    }
+
+    /// Like `scan`, for the backend → client direction. Also calls `on_error`
+    /// with the fields of each ErrorResponse once its body is complete; only
+    /// the first `ERROR_CAPTURE_LIMIT` bytes of the body are kept.
+    pub fn scan_backend(
+        &mut self,
+        data: &[u8],
+        on_message: impl FnMut(u8),
+        on_error: impl FnMut(ErrorFields),
+    ) {
+        self.scan_inner(data, true, on_message, on_error);
+    }
+
+    fn scan_inner(
+        &mut self,
+        mut data: &[u8],
+        errors: bool,
+        mut on_message: impl FnMut(u8),
+        mut on_error: impl FnMut(ErrorFields),
+    ) {
+        while !data.is_empty() {
+            if self.remaining > 0 {
+                let n = self.remaining.min(data.len());
+                if let Some(body) = &mut self.error_body {
+                    let keep = n.min(ERROR_CAPTURE_LIMIT.saturating_sub(body.len()));
+                    body.extend_from_slice(&data[..keep]);
+                }
+                self.remaining -= n;
+                data = &data[n..];
+                if self.remaining == 0
+                    && let Some(body) = self.error_body.take()
+                {
+                    on_error(ErrorFields::parse(&body));
+                }
+                continue;
+            }
+            let n = (5 - self.header_len).min(data.len());
+            self.header[self.header_len..self.header_len + n].copy_from_slice(&data[..n]);
+            self.header_len += n;
+            data = &data[n..];
+            if self.header_len == 5 {
+                let h = self.header;
+                let length = i32::from_be_bytes([h[1], h[2], h[3], h[4]]);
+                on_message(h[0]);
+                // Malformed lengths (< 4) are treated as empty bodies
+                self.remaining = length.max(4) as usize - 4;
+                self.header_len = 0;
+                if errors && h[0] == backend::ERROR_RESPONSE {
+                    if self.remaining == 0 {
+                        on_error(ErrorFields::default());
+                    } else {
+                        self.error_body = Some(Vec::new());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Type byte of the frontend message at the start of `buf`, as a char,
+/// without consuming it. `None` if `buf` is empty.
+pub fn peek_frontend_message_type(buf: &[u8]) -> Option<char> {
+    buf.first().map(|&b| b as char)
+}
+
+/// Name of a frontend (client → server) message type, for logging.
+pub fn frontend_message_type_name(msg_type: u8) -> &'static str {
+    match msg_type {
+        b'Q' => "Query",
+        b'P' => "Parse",
+        b'B' => "Bind",
+        b'E' => "Execute",
+        b'D' => "Describe",
+        b'C' => "Close",
+        b'S' => "Sync",
+        b'H' => "Flush",
+        b'F' => "FunctionCall",
+        b'd' => "CopyData",
+        b'c' => "CopyDone",
+        b'f' => "CopyFail",
+        b'p' => "PasswordMessage",
+        b'X' => "Terminate",
+        _ => "Unknown",
+    }
+}
+
+/// Apply configured rename rules and extra parameters to a client's StartupMessage
+/// parameters, then filter the rest through `allowlist`.
+///
+/// Renamed parameters are kept regardless of the allowlist (the rename is the
+/// operator's opt-in). `extra` parameters are merged last and override anything
+/// the client sent. Returns the rewritten map and the names of dropped parameters.
+pub fn rewrite_startup_params(
+    params: &HashMap<String, String>,
+    allowlist: &[String],
+    renames: &[(String, String)],
+    extra: &[(String, String)],
+) -> (HashMap<String, String>, Vec<String>) {
+    let mut remaining = params.clone();
+    let mut renamed = HashMap::new();
+    for (from, to) in renames {
+        if let Some(value) = remaining.remove(from) {
+            renamed.insert(to.clone(), value);
+        }
+    }
+    let (mut kept, dropped) = filter_startup_params(&remaining, allowlist);
+    kept.extend(renamed);
+    for (name, value) in extra {
+        kept.insert(name.clone(), value.clone());
+    }
+    (kept, dropped)
+}
+
+/// Split StartupMessage parameters into those on `allowlist` and the names of
+/// those dropped. Names match case-insensitively; `user` and `database` are
+/// always kept.
+pub fn filter_startup_params(
+    params: &HashMap<String, String>,
+    allowlist: &[String],
+) -> (HashMap<String, String>, Vec<String>) {
+    let mut kept = HashMap::new();
+    let mut dropped = Vec::new();
+    for (key, value) in params {
+        let allowed = key == "user"
+            || key == "database"
+            || allowlist.iter().any(|a| a.eq_ignore_ascii_case(key));
+        if allowed {
+            kept.insert(key.clone(), value.clone());
+        } else {
+            dropped.push(key.clone());
+        }
+    }
+    dropped.sort();
+    (kept, dropped)
+}
+
 // ─── Building ───────────────────────────────────────────────────────────────
 
 /// Build a StartupMessage with the given parameters.
@@ -302,6 +554,32 @@ pub fn build_startup_message(params: &HashMap<String, String>) -> BytesMut {
     buf
 }
 
+/// Join `statements` into a single `BEGIN; ...; COMMIT;` batch so they apply
+/// atomically. If `ready_status` shows the session is already inside a
+/// transaction (`T`) or a failed one (`E`), a `ROLLBACK` is sent first.
+/// A `comment` (already rendered, e.g. `/* pgvpd ... */`) leads the batch.
+pub fn wrap_in_transaction(
+    statements: &[String],
+    ready_status: u8,
+    comment: Option<&str>,
+) -> String {
+    let mut sql = String::new();
+    if let Some(comment) = comment {
+        sql.push_str(comment);
+        sql.push(' ');
+    }
+    if ready_status == b'T' || ready_status == b'E' {
+        sql.push_str("ROLLBACK; ");
+    }
+    sql.push_str("BEGIN; ");
+    for stmt in statements {
+        sql.push_str(stmt);
+        sql.push_str("; ");
+    }
+    sql.push_str("COMMIT;");
+    sql
+}
+
 /// Build a SimpleQuery ('Q') message.
 pub fn build_query_message(sql: &str) -> BytesMut {
     let msg_len = 4 + sql.len() + 1; // length field + sql + null
@@ -317,6 +595,17 @@ pub fn build_query_message(sql: &str) -> BytesMut {
 
 /// Build an ErrorResponse ('E') message.
 pub fn build_error_response(severity: &str, sqlstate: &str, message: &str) -> BytesMut {
+    build_fields_message(backend::ERROR_RESPONSE, severity, sqlstate, message)
+}
+
+/// Build a NoticeResponse ('N') message. Severity is NOTICE, INFO, DEBUG, etc.
+pub fn build_notice_response(severity: &str, message: &str) -> BytesMut {
+    // 00000 = successful_completion, which is what Postgres uses for plain notices
+    build_fields_message(backend::NOTICE_RESPONSE, severity, "00000", message)
+}
+
+/// ErrorResponse and NoticeResponse share a body: typed, null-terminated fields.
+fn build_fields_message(msg_type: u8, severity: &str, sqlstate: &str, message: &str) -> BytesMut {
     let fields: Vec<(u8, &str)> = vec![
         (b'S', severity),
         (b'V', severity),
@@ -329,7 +618,7 @@ pub fn build_error_response(severity: &str, sqlstate: &str, message: &str) -> By
     let msg_len = 4 + fields_len;
 
     let mut buf = BytesMut::with_capacity(1 + msg_len);
-    buf.put_u8(b'E');
+    buf.put_u8(msg_type);
     buf.put_i32(msg_len as i32);
 
     for (field_type, value) in &fields {
@@ -352,6 +641,17 @@ pub fn build_auth_cleartext_request() -> BytesMut {
     buf
 }
 
+/// Build an AuthenticationMD5Password request (server → client).
+pub fn build_auth_md5_request(salt: &[u8; 4]) -> BytesMut {
+    // 'R' | int32 len(12) | int32 subtype(5) | salt[4]
+    let mut buf = BytesMut::with_capacity(13);
+    buf.put_u8(backend::AUTHENTICATION);
+    buf.put_i32(12); // length: 4 (len field) + 4 (subtype) + 4 (salt)
+    buf.put_i32(auth::MD5_PASSWORD);
+    buf.put_slice(salt);
+    buf
+}
+
 /// Build an AuthenticationOk message (server → client).
 pub fn build_auth_ok() -> BytesMut {
     let mut buf = BytesMut::with_capacity(9);
@@ -425,11 +725,21 @@ pub fn build_sasl_response(data: &[u8]) -> BytesMut {
 
 /// Escape a value as a SQL single-quoted literal.
 /// Rejects characters that have no business in a tenant ID.
-#[allow(dead_code)]
 pub fn escape_literal(value: &str) -> io::Result<String> {
+    escape_tenant_id(value, |c| c.is_ascii_alphanumeric())
+}
+
+/// Like `escape_literal()`, but letters and digits may be any Unicode
+/// alphanumeric (`char::is_alphanumeric`), e.g. CJK workspace slugs. Symbols
+/// such as emoji, whitespace and punctuation other than `_-.` are still rejected.
+pub fn escape_literal_unicode(value: &str) -> io::Result<String> {
+    escape_tenant_id(value, char::is_alphanumeric)
+}
+
+fn escape_tenant_id(value: &str, alphanumeric: impl Fn(char) -> bool) -> io::Result<String> {
     if !value
         .chars()
-        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+        .all(|c| alphanumeric(c) || c == '_' || c == '-' || c == '.')
     {
         return Err(io::Error::new(
             io::ErrorKind::InvalidInput,
@@ -488,7 +798,7 @@ mod tests {
             PROTOCOL_VERSION_30,
             &[("user", "admin"), ("database", "mydb")],
         );
-        match try_read_startup(&mut buf) {
+        match try_read_startup(&mut buf, DEFAULT_MAX_STARTUP_MESSAGE_BYTES) {
             Some(StartupType::Startup(msg)) => {
                 assert_eq!(msg.params.get("user").unwrap(), "admin");
                 assert_eq!(msg.params.get("database").unwrap(), "mydb");
@@ -504,11 +814,30 @@ mod tests {
         buf.put_i32(8);
         buf.put_i32(SSL_REQUEST_CODE);
         assert!(matches!(
-            try_read_startup(&mut buf),
+            try_read_startup(&mut buf, DEFAULT_MAX_STARTUP_MESSAGE_BYTES),
             Some(StartupType::SslRequest)
         ));
     }
 
+    #[test]
+    fn parse_gssenc_request() {
+        let mut buf = BytesMut::new();
+        buf.put_i32(8);
+        buf.put_i32(GSSENC_REQUEST_CODE);
+        // A plain StartupMessage typically follows once the request is denied
+        buf.extend_from_slice(&build_raw_startup(PROTOCOL_VERSION_30, &[("user", "u")]));
+        assert!(matches!(
+            try_read_startup(&mut buf, DEFAULT_MAX_STARTUP_MESSAGE_BYTES),
+            Some(StartupType::GssEncRequest)
+        ));
+        // Denied with the same single byte as SSL
+        assert_eq!(SSL_DENY, b"N");
+        assert!(matches!(
+            try_read_startup(&mut buf, DEFAULT_MAX_STARTUP_MESSAGE_BYTES),
+            Some(StartupType::Startup(_))
+        ));
+    }
+
     #[test]
     fn parse_cancel_request() {
         let mut buf = BytesMut::new();
@@ -517,7 +846,7 @@ mod tests {
         buf.put_i32(1234); // pid
         buf.put_i32(5678); // secret key
         assert!(matches!(
-            try_read_startup(&mut buf),
+            try_read_startup(&mut buf, DEFAULT_MAX_STARTUP_MESSAGE_BYTES),
             Some(StartupType::CancelRequest)
         ));
     }
@@ -526,7 +855,7 @@ mod tests {
     fn truncated_startup_returns_none() {
         // Only 4 bytes — need at least 8
         let mut buf = BytesMut::from(&[0u8, 0, 0, 8][..]);
-        assert!(try_read_startup(&mut buf).is_none());
+        assert!(try_read_startup(&mut buf, DEFAULT_MAX_STARTUP_MESSAGE_BYTES).is_none());
     }
 
     #[test]
@@ -537,24 +866,42 @@ mod tests {
         buf.put_i32(PROTOCOL_VERSION_30);
         buf.put_u8(0);
         buf.put_u8(0);
-        assert!(try_read_startup(&mut buf).is_none());
+        assert!(try_read_startup(&mut buf, DEFAULT_MAX_STARTUP_MESSAGE_BYTES).is_none());
     }
 
     #[test]
     fn oversized_startup_returns_none() {
-        // Length > 10240 sanity check
+        // Length > default max_size sanity check
         let mut buf = BytesMut::new();
         buf.put_i32(20000);
         buf.put_i32(PROTOCOL_VERSION_30);
         buf.extend_from_slice(&vec![0u8; 20000]);
-        assert!(try_read_startup(&mut buf).is_none());
+        assert!(try_read_startup(&mut buf, DEFAULT_MAX_STARTUP_MESSAGE_BYTES).is_none());
+    }
+
+    #[test]
+    fn large_startup_respects_max_size() {
+        // ~15KB of options, as sent by clients with many -c GUCs
+        let options = "-c search_path=public ".repeat(700);
+        let mut buf =
+            build_raw_startup(PROTOCOL_VERSION_30, &[("user", "u"), ("options", &options)]);
+        assert!(buf.len() > 15000);
+
+        let mut small = buf.clone();
+        assert!(try_read_startup(&mut small, DEFAULT_MAX_STARTUP_MESSAGE_BYTES).is_none());
+
+        match try_read_startup(&mut buf, 65536) {
+            Some(StartupType::Startup(msg)) => assert_eq!(msg.params["options"], options),
+            _ => panic!("expected Startup"),
+        }
+        assert!(buf.is_empty());
     }
 
     #[test]
     fn startup_with_empty_params() {
         // Just version + terminal null, no key-value pairs
         let mut buf = build_raw_startup(PROTOCOL_VERSION_30, &[]);
-        match try_read_startup(&mut buf) {
+        match try_read_startup(&mut buf, DEFAULT_MAX_STARTUP_MESSAGE_BYTES) {
             Some(StartupType::Startup(msg)) => {
                 assert!(msg.params.is_empty());
             }
@@ -590,6 +937,117 @@ mod tests {
         assert!(msg.is_auth_ok());
     }
 
+    #[test]
+    fn parse_backend_message_notification() {
+        // pid | channel | payload
+        let mut payload = BytesMut::new();
+        payload.put_i32(4242);
+        payload.put_slice(b"tenant_events\0");
+        payload.put_slice(b"refresh\0");
+        let mut buf = build_raw_backend_message(backend::NOTIFICATION_RESPONSE, &payload);
+        let msg = try_read_backend_message(&mut buf).unwrap();
+        assert!(msg.is_notification());
+        assert!(!msg.is_notice_response());
+        assert_eq!(msg.raw.len(), 1 + 4 + payload.len());
+    }
+
+    #[test]
+    fn parse_backend_message_negotiate_protocol_version() {
+        // Newest supported minor version + one unrecognized option
+        let mut payload = BytesMut::new();
+        payload.put_i32(0);
+        payload.put_i32(1);
+        payload.put_slice(b"_pq_.unknown\0");
+        let mut buf = build_raw_backend_message(backend::NEGOTIATE_PROTOCOL_VERSION, &payload);
+        let msg = try_read_backend_message(&mut buf).unwrap();
+        assert!(msg.is_negotiate_protocol_version());
+        assert!(!msg.is_auth_challenge());
+        assert!(!msg.is_error_response());
+    }
+
+    // ─── MessageScanner ──────────────────────────────────────────────────
+
+    #[test]
+    fn message_scanner_whole_messages() {
+        let mut data = build_query_message("SELECT 1");
+        data.extend_from_slice(&build_error_response("ERROR", "42601", "syntax error"));
+        data.extend_from_slice(&build_raw_backend_message(backend::READY_FOR_QUERY, b"I"));
+        let mut seen = Vec::new();
+        MessageScanner::default().scan(&data, |t| seen.push(t));
+        assert_eq!(seen, vec![b'Q', b'E', b'Z']);
+    }
+
+    #[test]
+    fn message_scanner_split_across_chunks() {
+        let mut data = build_query_message("SELECT 1");
+        data.extend_from_slice(&build_query_message("SELECT 2"));
+        // Every split point, including mid-header and mid-body
+        for split in 0..=data.len() {
+            let mut scanner = MessageScanner::default();
+            let mut seen = Vec::new();
+            scanner.scan(&data[..split], |t| seen.push(t));
+            scanner.scan(&data[split..], |t| seen.push(t));
+            assert_eq!(seen, vec![b'Q', b'Q'], "split at {split}");
+        }
+    }
+
+    #[test]
+    fn message_scanner_reports_error_fields() {
+        let mut data = build_raw_backend_message(backend::READY_FOR_QUERY, b"I");
+        data.extend_from_slice(&build_error_response("ERROR", "23505", "duplicate key"));
+        data.extend_from_slice(&build_raw_backend_message(backend::ERROR_RESPONSE, b""));
+        data.extend_from_slice(&build_error_response("FATAL", "53300", "too many clients"));
+        for split in 0..=data.len() {
+            let mut scanner = MessageScanner::default();
+            let mut seen = Vec::new();
+            let mut errors = Vec::new();
+            for chunk in [&data[..split], &data[split..]] {
+                scanner.scan_backend(chunk, |t| seen.push(t), |e| errors.push(e.sqlstate));
+            }
+            assert_eq!(seen, vec![b'Z', b'E', b'E', b'E'], "split at {split}");
+            assert_eq!(errors, vec!["23505", "", "53300"], "split at {split}");
+        }
+    }
+
+    #[test]
+    fn message_scanner_caps_error_body() {
+        let long = "x".repeat(ERROR_CAPTURE_LIMIT * 2);
+        let mut data = build_error_response("ERROR", "XX000", &long);
+        data.extend_from_slice(&build_raw_backend_message(backend::READY_FOR_QUERY, b"I"));
+        let mut scanner = MessageScanner::default();
+        let mut seen = Vec::new();
+        let mut errors = Vec::new();
+        scanner.scan_backend(&data, |t| seen.push(t), |e| errors.push(e));
+        assert_eq!(seen, vec![b'E', b'Z']);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].sqlstate, "XX000");
+        assert!(errors[0].message.len() < ERROR_CAPTURE_LIMIT);
+    }
+
+    #[test]
+    fn message_scanner_byte_at_a_time() {
+        let data = build_error_response("ERROR", "XX000", "boom");
+        let mut scanner = MessageScanner::default();
+        let mut count = 0;
+        for b in data.iter() {
+            scanner.scan(std::slice::from_ref(b), |_| count += 1);
+        }
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn backend_message_with_short_length_returns_none() {
+        // Length fields below 4 (including negative) can't cover themselves
+        for length in [0i32, 3, -1, i32::MIN] {
+            let mut buf = BytesMut::new();
+            buf.put_u8(b'Z');
+            buf.put_i32(length);
+            buf.put_slice(&[0; 8]);
+            assert!(try_read_backend_message(&mut buf).is_none());
+            assert_eq!(buf.len(), 13);
+        }
+    }
+
     #[test]
     fn truncated_backend_message_returns_none() {
         // Only 3 bytes — need at least 5 (1 type + 4 length)
@@ -687,6 +1145,158 @@ mod tests {
         let mut buf = build_raw_backend_message(backend::READY_FOR_QUERY, &[b'I']);
         let msg = try_read_backend_message(&mut buf).unwrap();
         assert_eq!(msg.error_message(), "not an error");
+        assert_eq!(msg.parse_error_fields(), ErrorFields::default());
+    }
+
+    #[test]
+    fn error_fields_parsing() {
+        let mut payload = BytesMut::new();
+        for (field_type, value) in [
+            (b'S', "ERROR"),
+            (b'V', "ERROR"),
+            (b'C', "23505"),
+            (
+                b'M',
+                "duplicate key value violates unique constraint \"users_pkey\"",
+            ),
+            (b'D', "Key (id)=(1) already exists."),
+            (b'H', "Use ON CONFLICT."),
+            (b'P', "15"),
+            (b'n', "users_pkey"),
+        ] {
+            payload.put_u8(field_type);
+            payload.put_slice(value.as_bytes());
+            payload.put_u8(0);
+        }
+        payload.put_u8(0);
+
+        let mut buf = build_raw_backend_message(backend::ERROR_RESPONSE, &payload);
+        let msg = try_read_backend_message(&mut buf).unwrap();
+        let fields = msg.parse_error_fields();
+        assert_eq!(fields.severity, "ERROR");
+        assert_eq!(fields.sqlstate, "23505");
+        assert_eq!(
+            fields.message,
+            "duplicate key value violates unique constraint \"users_pkey\""
+        );
+        assert_eq!(
+            fields.detail.as_deref(),
+            Some("Key (id)=(1) already exists.")
+        );
+        assert_eq!(fields.hint.as_deref(), Some("Use ON CONFLICT."));
+        assert_eq!(fields.position, Some(15));
+        assert_eq!(fields.sqlstate_class(), Some("23"));
+    }
+
+    #[test]
+    fn error_fields_missing_and_malformed() {
+        let fields = ErrorFields::parse(b"SFATAL\0C53\0Pabc\0\0");
+        assert_eq!(fields.severity, "FATAL");
+        assert_eq!(fields.message, "");
+        assert_eq!(fields.detail, None);
+        assert_eq!(fields.hint, None);
+        assert_eq!(fields.position, None);
+        assert_eq!(fields.sqlstate_class(), None);
+
+        // Truncated mid-field: keep what is there
+        let fields = ErrorFields::parse(b"SERROR\0C53300\0Mtoo many conn");
+        assert_eq!(fields.sqlstate_class(), Some("53"));
+        assert_eq!(fields.message, "too many conn");
+    }
+
+    #[test]
+    fn error_message_from_built_response() {
+        let mut buf = build_error_response("ERROR", "42601", "syntax error");
+        let msg = try_read_backend_message(&mut buf).unwrap();
+        assert_eq!(msg.error_message(), "syntax error");
+        let fields = msg.parse_error_fields();
+        assert_eq!(fields.severity, "ERROR");
+        assert_eq!(fields.sqlstate, "42601");
+    }
+
+    // ─── Startup parameter filtering ─────────────────────────────────────
+
+    fn default_allowlist() -> Vec<String> {
+        [
+            "application_name",
+            "client_encoding",
+            "DateStyle",
+            "TimeZone",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn filter_drops_options_param() {
+        let mut buf = build_raw_startup(
+            PROTOCOL_VERSION_30,
+            &[
+                ("user", "app_user.acme"),
+                ("database", "mydb"),
+                ("application_name", "psql"),
+                ("options", "-c search_path=evil"),
+            ],
+        );
+        let Some(StartupType::Startup(msg)) =
+            try_read_startup(&mut buf, DEFAULT_MAX_STARTUP_MESSAGE_BYTES)
+        else {
+            panic!("expected Startup");
+        };
+        let (kept, dropped) = filter_startup_params(&msg.params, &default_allowlist());
+        assert_eq!(dropped, vec!["options".to_string()]);
+        assert!(!kept.contains_key("options"));
+        assert_eq!(kept.get("application_name").unwrap(), "psql");
+        assert_eq!(kept.len(), 3);
+    }
+
+    #[test]
+    fn filter_always_keeps_user_and_database() {
+        let mut params = HashMap::new();
+        params.insert("user".to_string(), "u".to_string());
+        params.insert("database".to_string(), "d".to_string());
+        params.insert("search_path".to_string(), "evil".to_string());
+        let (kept, dropped) = filter_startup_params(&params, &[]);
+        assert!(kept.contains_key("user"));
+        assert!(kept.contains_key("database"));
+        assert_eq!(dropped, vec!["search_path".to_string()]);
+    }
+
+    #[test]
+    fn filter_matches_case_insensitively() {
+        let mut params = HashMap::new();
+        params.insert("datestyle".to_string(), "ISO".to_string());
+        params.insert("TIMEZONE".to_string(), "UTC".to_string());
+        let (kept, dropped) = filter_startup_params(&params, &default_allowlist());
+        assert_eq!(kept.len(), 2);
+        assert!(dropped.is_empty());
+    }
+
+    #[test]
+    fn rewrite_renames_and_injects() {
+        let mut params = HashMap::new();
+        params.insert("user".to_string(), "app_user".to_string());
+        params.insert("myapp_tenant".to_string(), "acme".to_string());
+        params.insert("options".to_string(), "-c x=y".to_string());
+        let renames = vec![("myapp_tenant".to_string(), "app.tenant".to_string())];
+        let extra = vec![("search_path".to_string(), "acme, public".to_string())];
+        let (kept, dropped) =
+            rewrite_startup_params(&params, &default_allowlist(), &renames, &extra);
+        assert_eq!(kept.get("app.tenant").unwrap(), "acme");
+        assert!(!kept.contains_key("myapp_tenant"));
+        assert_eq!(kept.get("search_path").unwrap(), "acme, public");
+        assert_eq!(kept.get("user").unwrap(), "app_user");
+        assert_eq!(dropped, vec!["options".to_string()]);
+    }
+
+    #[test]
+    fn rewrite_extra_overrides_client_value() {
+        let mut params = HashMap::new();
+        params.insert("application_name".to_string(), "client".to_string());
+        let extra = vec![("application_name".to_string(), "forced".to_string())];
+        let (kept, _) = rewrite_startup_params(&params, &default_allowlist(), &[], &extra);
+        assert_eq!(kept.get("application_name").unwrap(), "forced");
     }
 
     // ─── Message building ────────────────────────────────────────────────
@@ -697,7 +1307,7 @@ mod tests {
         params.insert("user".to_string(), "app_user".to_string());
         params.insert("database".to_string(), "mydb".to_string());
         let mut buf = build_startup_message(&params);
-        match try_read_startup(&mut buf) {
+        match try_read_startup(&mut buf, DEFAULT_MAX_STARTUP_MESSAGE_BYTES) {
             Some(StartupType::Startup(msg)) => {
                 assert_eq!(msg.params.get("user").unwrap(), "app_user");
                 assert_eq!(msg.params.get("database").unwrap(), "mydb");
@@ -706,6 +1316,50 @@ mod tests {
         }
     }
 
+    #[test]
+    fn wrap_in_transaction_idle() {
+        let stmts = vec!["SET a = '1'".to_string(), "SET ROLE \"r\"".to_string()];
+        assert_eq!(
+            wrap_in_transaction(&stmts, b'I', None),
+            "BEGIN; SET a = '1'; SET ROLE \"r\"; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn wrap_in_transaction_rolls_back_open_transaction() {
+        let stmts = vec!["SET a = '1'".to_string()];
+        assert_eq!(
+            wrap_in_transaction(&stmts, b'T', None),
+            "ROLLBACK; BEGIN; SET a = '1'; COMMIT;"
+        );
+        assert!(wrap_in_transaction(&stmts, b'E', None).starts_with("ROLLBACK; "));
+    }
+
+    #[test]
+    fn wrap_in_transaction_prepends_comment() {
+        let stmts = vec!["SET app.current_tenant_id = 'acme'".to_string()];
+        let sql = wrap_in_transaction(&stmts, b'I', Some("/* pgvpd conn_id=7 */"));
+        assert_eq!(
+            sql,
+            "/* pgvpd conn_id=7 */ BEGIN; SET app.current_tenant_id = 'acme'; COMMIT;"
+        );
+    }
+
+    #[test]
+    fn transaction_status_from_ready_for_query() {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'Z');
+        buf.put_i32(5);
+        buf.put_u8(b'T');
+        let msg = try_read_backend_message(&mut buf).unwrap();
+        assert_eq!(msg.transaction_status(), Some(b'T'));
+
+        let mut buf = build_query_message("SELECT 1");
+        buf[0] = b'C';
+        let msg = try_read_backend_message(&mut buf).unwrap();
+        assert_eq!(msg.transaction_status(), None);
+    }
+
     #[test]
     fn build_query_message_format() {
         let buf = build_query_message("SELECT 1");
@@ -715,6 +1369,30 @@ mod tests {
         assert_eq!(buf[buf.len() - 1], 0); // null terminator
     }
 
+    #[test]
+    fn build_notice_response_format() {
+        let mut buf = build_notice_response("NOTICE", "context set");
+        assert_eq!(buf[0], b'N');
+        let len = i32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+        assert_eq!(len, buf.len() - 1);
+        let msg = try_read_backend_message(&mut buf).unwrap();
+        assert!(msg.is_notice_response());
+        assert!(buf.is_empty());
+        let body = String::from_utf8_lossy(&msg.payload);
+        assert!(body.contains("SNOTICE\0"));
+        assert!(body.contains("C00000\0"));
+        assert!(body.contains("Mcontext set\0"));
+    }
+
+    #[test]
+    fn build_auth_md5_request_format() {
+        let mut buf = build_auth_md5_request(&[0xDE, 0xAD, 0xBE, 0xEF]);
+        let msg = try_read_backend_message(&mut buf).unwrap();
+        assert_eq!(msg.auth_subtype(), Some(auth::MD5_PASSWORD));
+        assert!(msg.is_auth_challenge());
+        assert_eq!(&msg.payload[4..], &[0xDE, 0xAD, 0xBE, 0xEF]);
+    }
+
     #[test]
     fn build_and_parse_password_roundtrip() {
         let mut buf = build_password_message(b"secret123");
@@ -742,6 +1420,25 @@ mod tests {
         assert!(try_read_password_message(&mut buf).is_none());
     }
 
+    // ─── Frontend message types ──────────────────────────────────────────
+
+    #[test]
+    fn peek_frontend_message_type_does_not_consume() {
+        let msg = build_query_message("SELECT 1");
+        assert_eq!(peek_frontend_message_type(&msg), Some('Q'));
+        assert_eq!(msg.len(), 14);
+        assert_eq!(peek_frontend_message_type(&[]), None);
+    }
+
+    #[test]
+    fn frontend_message_type_names() {
+        assert_eq!(frontend_message_type_name(b'Q'), "Query");
+        assert_eq!(frontend_message_type_name(b'P'), "Parse");
+        assert_eq!(frontend_message_type_name(b'B'), "Bind");
+        assert_eq!(frontend_message_type_name(b'X'), "Terminate");
+        assert_eq!(frontend_message_type_name(b'Z'), "Unknown");
+    }
+
     // ─── SQL escaping ────────────────────────────────────────────────────
 
     #[test]
@@ -761,6 +1458,34 @@ mod tests {
         assert!(escape_literal("{a,b}").is_err()); // braces
     }
 
+    #[test]
+    fn escape_literal_rejects_unicode() {
+        assert!(escape_literal("株式会社").is_err());
+        assert!(escape_literal("café").is_err());
+    }
+
+    #[test]
+    fn escape_literal_unicode_accepts_cjk() {
+        assert_eq!(escape_literal_unicode("株式会社").unwrap(), "'株式会社'");
+        assert_eq!(
+            escape_literal_unicode("데브팀-2.서울").unwrap(),
+            "'데브팀-2.서울'"
+        );
+        assert_eq!(escape_literal_unicode("café_ñ").unwrap(), "'café_ñ'");
+        assert_eq!(escape_literal_unicode("tenant_a").unwrap(), "'tenant_a'");
+    }
+
+    #[test]
+    fn escape_literal_unicode_rejects_symbols() {
+        // Emoji are symbols, not alphanumerics
+        assert!(escape_literal_unicode("team-🚀").is_err());
+        assert!(escape_literal_unicode("🏢").is_err());
+        assert!(escape_literal_unicode("株式 会社").is_err()); // space
+        assert!(escape_literal_unicode("株式\u{3000}会社").is_err()); // ideographic space
+        assert!(escape_literal_unicode("'; DROP TABLE--").is_err());
+        assert!(escape_literal_unicode("tenant\u{200b}id").is_err()); // zero-width space
+    }
+
     #[test]
     fn escape_set_value_allows_anything() {
         assert_eq!(escape_set_value("simple"), "'simple'");
@@ -782,6 +1507,50 @@ mod tests {
         assert!(quote_ident("a;b").is_err()); // semicolon
     }
 
+    // ─── SQL escaping properties ─────────────────────────────────────────
+
+    /// Undo single-quote literal escaping; `None` if `quoted` isn't well formed.
+    fn unquote_literal(quoted: &str) -> Option<String> {
+        let inner = quoted.strip_prefix('\'')?.strip_suffix('\'')?;
+        let mut out = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            if c == '\'' && chars.next()? != '\'' {
+                return None; // lone quote would end the literal early
+            }
+            out.push(c);
+        }
+        Some(out)
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn escape_set_value_roundtrips(value in ".*") {
+            let escaped = escape_set_value(&value);
+            proptest::prop_assert_eq!(unquote_literal(&escaped), Some(value));
+        }
+
+        #[test]
+        fn escape_literal_accepts_only_safe_chars(value in ".*") {
+            let safe = value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+            match escape_literal(&value) {
+                Ok(escaped) => {
+                    proptest::prop_assert!(safe);
+                    proptest::prop_assert_eq!(escaped, format!("'{value}'"));
+                }
+                Err(_) => proptest::prop_assert!(!safe),
+            }
+        }
+
+        #[test]
+        fn escape_literal_roundtrips_tenant_ids(value in "[A-Za-z0-9_.-]{0,64}") {
+            let escaped = escape_literal(&value).unwrap();
+            proptest::prop_assert_eq!(unquote_literal(&escaped), Some(value));
+        }
+    }
+
     // ─── Multiple messages in buffer ─────────────────────────────────────
 
     #[test]
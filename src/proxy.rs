@@ -2,52 +2,99 @@
 //! Supports both plain and TLS listeners.
 
 use rustls::ClientConfig;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv6Addr, SocketAddr};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
-use tokio::net::TcpListener;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::signal::unix::{SignalKind, signal};
 use tokio_rustls::TlsAcceptor;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::{EnvFilter, Registry, reload};
 
 use crate::admin::{self, AdminState};
+use crate::audit::AuditLogger;
 use crate::config::{Config, PoolMode};
 use crate::connection;
+use crate::dns::DnsCache;
 use crate::metrics::Metrics;
 use crate::pool::Pool;
-use crate::resolver::{self, ResolverEngine};
+use crate::protocol::build_error_response;
+use crate::proxy_protocol;
+use crate::resolver::{self, DatabaseResolvers, ResolverEngine, SharedResolver};
+use crate::statsd::StatsdExporter;
 use crate::stream::ClientStream;
 use crate::tenant::TenantRegistry;
-use crate::tls;
+use crate::tls::{self, SharedTlsAcceptor};
 
 static CONN_COUNTER: AtomicU64 = AtomicU64::new(0);
 
+/// Handle for swapping the tracing filter when `log_level` is reloaded.
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
 /// Start the Pgvpd proxy server.
-pub async fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn run(
+    config: Config,
+    log_filter: LogFilterHandle,
+) -> Result<(), Box<dyn std::error::Error>> {
     config
         .validate()
         .map_err(|e| -> Box<dyn std::error::Error> { e.into() })?;
 
+    match &config.config_file {
+        Some(path) => info!(file = %path.display(), "config file loaded"),
+        None => info!("no config file — using environment and flags only"),
+    }
+
     // ─── Build TLS state once at startup ────────────────────────────────
 
+    let tls_policy = tls::TlsPolicy::from_config(&config);
+
     // TLS termination (client → Pgvpd)
-    let tls_acceptor = match (&config.tls_port, &config.tls_cert, &config.tls_key) {
-        (Some(_), Some(cert), Some(key)) => {
-            let server_config = tls::build_server_config(cert, key)?;
+    let tls_acceptor = match (
+        &config.tls_port,
+        &config.tls_cert_pkcs12,
+        &config.tls_cert,
+        &config.tls_key,
+    ) {
+        (Some(_), Some(bundle), _, _) => {
+            let password = config.tls_cert_pkcs12_password.as_deref().unwrap_or("");
+            let server_config = tls::build_server_config_pkcs12(
+                bundle,
+                password,
+                &config.tls_sni_certs,
+                &tls_policy,
+            )?;
+            Some(TlsAcceptor::from(server_config))
+        }
+        (Some(_), None, Some(cert), Some(key)) => {
+            let server_config =
+                tls::build_server_config(cert, key, &config.tls_sni_certs, &tls_policy)?;
             Some(TlsAcceptor::from(server_config))
         }
         _ => None,
-    };
+    }
+    .map(SharedTlsAcceptor::new);
 
     // TLS origination (Pgvpd → upstream)
     let upstream_tls: Option<Arc<ClientConfig>> = if config.upstream_tls {
         Some(tls::build_client_config(
             config.upstream_tls_verify,
             config.upstream_tls_ca.as_deref(),
+            config.upstream_tls_pin_pubkey.as_deref(),
+            &tls_policy,
         )?)
     } else {
         None
     };
 
+    // Resolved upstream addresses, shared by passthrough and the pool
+    let dns = DnsCache::new();
+
     // ─── Context resolvers (if configured) ──────────────────────────────
     // We need resolver names before creating Metrics, so we load resolvers
     // first (without metrics), then create Metrics, then set metrics on the engine.
@@ -55,7 +102,7 @@ pub async fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
     // Peek at resolver names for Metrics initialization
     let resolver_names: Vec<String> = match &config.resolvers {
         Some(path) => {
-            let engine = resolver::load_resolvers(path, None)
+            let engine = resolver::load_resolvers(path, &config.context_variables, None)
                 .map_err(|e| -> Box<dyn std::error::Error> { e.into() })?;
             engine.resolvers.iter().map(|r| r.name.clone()).collect()
         }
@@ -67,47 +114,52 @@ pub async fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
     let metrics = Arc::new(Metrics::new(resolver_names));
 
     // Now load resolvers for real (with metrics)
-    let resolver_engine: Option<Arc<ResolverEngine>> = match &config.resolvers {
-        Some(path) => {
-            let engine = resolver::load_resolvers(path, Some(Arc::clone(&metrics)))
-                .map_err(|e| -> Box<dyn std::error::Error> { e.into() })?;
+    let resolver = SharedResolver::new(
+        load_resolver_engine(&config, Some(Arc::clone(&metrics)))
+            .map_err(|e| -> Box<dyn std::error::Error> { e.into() })?,
+    );
+
+    // Per-resolver metrics follow the global file, so per-database engines
+    // run without them
+    let database_resolvers =
+        load_database_resolvers(&config).map_err(|e| -> Box<dyn std::error::Error> { e.into() })?;
+
+    // ─── OTLP export (if configured) ────────────────────────────────────
+    // The SDK's periodic reader runs in the background until `_otlp` drops.
+
+    #[cfg(feature = "otlp")]
+    let _otlp = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let interval = Duration::from_secs(config.otlp_export_interval_secs);
+            let exporter =
+                crate::otlp::OtlpExporter::start(endpoint, interval, Arc::clone(&metrics))?;
             info!(
-                resolvers = engine.resolvers.len(),
-                file = %path,
-                "context resolvers loaded"
+                endpoint = %endpoint,
+                interval_secs = config.otlp_export_interval_secs,
+                "OTLP metrics export"
             );
-            for r in &engine.resolvers {
-                let inject_vars: Vec<&str> = r.inject.iter().map(|(k, _)| k.as_str()).collect();
-                info!(
-                    name = %r.name,
-                    params = ?r.params,
-                    inject = ?inject_vars,
-                    required = r.required,
-                    cache_ttl = r.cache_ttl.as_secs(),
-                    "  resolver"
-                );
-            }
-            let engine = Arc::new(engine);
-
-            // Spawn cache evictor if any resolver uses caching
-            if engine
-                .resolvers
-                .iter()
-                .any(|r| r.cache_ttl > Duration::ZERO)
-            {
-                let evictor = Arc::clone(&engine);
-                tokio::spawn(async move {
-                    loop {
-                        tokio::time::sleep(Duration::from_secs(60)).await;
-                        evictor.evict_expired().await;
-                    }
-                });
-            }
-
-            Some(engine)
+            Some(exporter)
         }
         None => None,
     };
+    #[cfg(not(feature = "otlp"))]
+    if config.otlp_endpoint.is_some() {
+        warn!("otlp_endpoint is set but pgvpd was built without the otlp feature — ignored");
+    }
+
+    // ─── StatsD export (if configured) ───────────────────────────────────
+
+    if let (Some(host), Some(port)) = (&config.statsd_host, config.statsd_port) {
+        let exporter =
+            StatsdExporter::new(host, port, &config.statsd_prefix, Arc::clone(&metrics))?;
+        info!(
+            addr = %format!("{host}:{port}"),
+            prefix = %config.statsd_prefix,
+            interval_ms = config.statsd_flush_interval_ms,
+            "StatsD metrics export"
+        );
+        tokio::spawn(exporter.run(Duration::from_millis(config.statsd_flush_interval_ms)));
+    }
 
     // ─── Connection pool (if configured) ────────────────────────────────
 
@@ -117,12 +169,18 @@ pub async fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
         let pool = Arc::new(Pool::new(
             Arc::clone(&config),
             upstream_tls.clone(),
+            dns.clone(),
             Arc::clone(&metrics),
         ));
         let reaper_pool = Arc::clone(&pool);
         tokio::spawn(async move {
             reaper_pool.idle_reaper().await;
         });
+        tokio::spawn(check_upstream_capacity(
+            Arc::clone(&config),
+            Arc::clone(&pool),
+            Arc::clone(&metrics),
+        ));
         info!(
             pool_mode = %config.pool_mode,
             pool_size = config.pool_size,
@@ -130,6 +188,13 @@ pub async fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
             checkout_timeout = config.pool_checkout_timeout,
             "connection pool"
         );
+        if !config.trust_ips.is_empty() {
+            let ranges: Vec<String> = config.trust_ips.iter().map(|n| n.to_string()).collect();
+            warn!(
+                trust_ips = %ranges.join(","),
+                "trust mode enabled — clients from these ranges skip password auth"
+            );
+        }
         Some(pool)
     } else {
         None
@@ -144,21 +209,24 @@ pub async fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
         None
     };
 
-    // ─── Plain listener (always starts) ─────────────────────────────────
+    // ─── Audit log (if configured) ───────────────────────────────────────
 
-    let plain_addr = format!("{}:{}", config.listen_host, config.listen_port);
-    let plain_listener = TcpListener::bind(&plain_addr).await?;
-
-    info!(
-        addr = %plain_addr,
-        upstream = %format!("{}:{}", config.upstream_host, config.upstream_port),
-        separator = %config.tenant_separator,
-        context_vars = %config.context_variables.join(", "),
-        "plain listener"
-    );
+    let audit_logger: Option<Arc<AuditLogger>> = match &config.audit_log_file {
+        Some(path) => {
+            let logger = AuditLogger::open(path, config.audit_log_max_bytes).await?;
+            info!(file = %path, max_bytes = config.audit_log_max_bytes, "audit log");
+            Some(Arc::new(logger))
+        }
+        None => None,
+    };
 
     if !config.superuser_bypass.is_empty() {
-        info!(bypass = %config.superuser_bypass.join(", "), "superuser bypass");
+        let rules: Vec<String> = config
+            .superuser_bypass
+            .iter()
+            .map(|r| r.to_string())
+            .collect();
+        info!(bypass = %rules.join(", "), "superuser bypass");
     }
 
     if upstream_tls.is_some() {
@@ -173,54 +241,162 @@ pub async fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
     // ─── Admin API (if configured) ──────────────────────────────────────
 
     if let Some(admin_port) = config.admin_port {
+        // Logged here rather than in validate(), which also runs on every reload
+        if config.admin_host_is_wildcard() {
+            warn!(
+                "admin API is bound to {} -- consider restricting with admin_host",
+                config.admin_host
+            );
+        }
         let admin_state = AdminState {
             metrics: Arc::clone(&metrics),
             pool: pool.clone(),
-            resolver: resolver_engine.clone(),
+            resolver: resolver.clone(),
+            database_resolvers: Arc::clone(&database_resolvers),
+            dns: dns.clone(),
+            config: Arc::clone(&config),
+            upstream_tls: upstream_tls.clone(),
+            upstream_health: Default::default(),
+            tenant: tenant_registry.clone(),
         };
-        tokio::spawn(admin::serve(admin_state, admin_port));
+        tokio::spawn(admin::serve(
+            admin_state,
+            config.admin_host.clone(),
+            admin_port,
+        ));
     }
 
-    // ─── TLS listener (if configured) ───────────────────────────────────
+    // ─── Config reload on SIGHUP ────────────────────────────────────────
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut reloader = ConfigReloader {
+        active: (*config).clone(),
+        log_filter,
+        tenant_registry: tenant_registry.clone(),
+        resolver: resolver.clone(),
+        metrics: Arc::clone(&metrics),
+        dns: dns.clone(),
+        pool: pool.clone(),
+    };
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("SIGHUP received — reloading config");
+            reloader.reload();
+        }
+    });
+
+    // ─── Listeners ──────────────────────────────────────────────────────
+    // Bound last, once everything above has succeeded: until then clients get
+    // connection refused rather than sitting in the accept backlog of a proxy
+    // that may still fail to start. Every port is bound before any is served,
+    // so a bind failure exits without having accepted a connection.
+
+    let plain_addr = format!("{}:{}", config.listen_host, config.listen_port);
+    let plain_listener = TcpListener::bind(&plain_addr).await?;
+
+    info!(
+        addr = %plain_addr,
+        upstream = %format!("{}:{}", config.upstream_host, config.upstream_port),
+        separator = %config.tenant_separator,
+        context_vars = %config.context_variables.join(", "),
+        "plain listener"
+    );
+
+    let v6_listener = if config.listen_ipv6 {
+        let ip: Ipv6Addr = config
+            .listen_host_v6
+            .parse()
+            .map_err(|_| format!("invalid listen_host_v6 '{}'", config.listen_host_v6))?;
+        let addr = SocketAddr::new(ip.into(), config.listen_port);
+        let listener = bind_v6_only(addr)?;
+        info!(addr = %addr, "plain listener (IPv6)");
+        Some(listener)
+    } else {
+        None
+    };
+
+    let tls_listener = match (config.tls_port, tls_acceptor) {
+        (Some(tls_port), Some(acceptor)) => {
+            let tls_addr = format!("{}:{}", config.listen_host, tls_port);
+            let listener = TcpListener::bind(&tls_addr).await?;
+            info!(addr = %tls_addr, "TLS listener");
+            Some((listener, acceptor))
+        }
+        _ => None,
+    };
 
-    if let (Some(tls_port), Some(acceptor)) = (config.tls_port, tls_acceptor) {
-        let tls_addr = format!("{}:{}", config.listen_host, tls_port);
-        let tls_listener = TcpListener::bind(&tls_addr).await?;
-        info!(addr = %tls_addr, "TLS listener");
+    // ─── TLS accept loop (if configured) ────────────────────────────────
+
+    if let Some((tls_listener, acceptor)) = tls_listener {
+        // PEM files are watched for renewals; a PKCS#12 bundle needs a restart
+        if config.tls_watch_interval_secs > 0
+            && let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key)
+        {
+            let watcher = tls::CertWatcher::new(
+                cert.clone(),
+                key.clone(),
+                config.tls_sni_certs.clone(),
+                tls_policy.clone(),
+                acceptor.clone(),
+            );
+            tokio::spawn(watcher.run(Duration::from_secs(config.tls_watch_interval_secs)));
+        }
 
         let tls_config = Arc::clone(&config);
         let tls_upstream = upstream_tls.clone();
+        let tls_dns = dns.clone();
         let tls_pool = pool.clone();
-        let tls_resolver = resolver_engine.clone();
+        let tls_resolver = resolver.clone();
+        let tls_database_resolvers = Arc::clone(&database_resolvers);
         let tls_metrics = Arc::clone(&metrics);
         let tls_tenant = tenant_registry.clone();
+        let tls_audit = audit_logger.clone();
 
         tokio::spawn(async move {
             loop {
                 match tls_listener.accept().await {
-                    Ok((socket, _)) => {
+                    Ok((mut socket, _)) => {
+                        if at_capacity(&tls_config, &tls_metrics) {
+                            tokio::spawn(reject_over_capacity(socket, Arc::clone(&tls_metrics)));
+                            continue;
+                        }
                         let config = Arc::clone(&tls_config);
                         let upstream = tls_upstream.clone();
+                        let dns = tls_dns.clone();
                         let pool = tls_pool.clone();
-                        let resolver = tls_resolver.clone();
+                        let resolver = tls_resolver.current();
+                        let database_resolvers = Arc::clone(&tls_database_resolvers);
                         let tenant = tls_tenant.clone();
-                        let acceptor = acceptor.clone();
+                        let audit = tls_audit.clone();
+                        let acceptor = acceptor.current();
                         let m = Arc::clone(&tls_metrics);
                         let conn_id = CONN_COUNTER.fetch_add(1, Ordering::Relaxed) + 1;
 
                         tokio::spawn(async move {
                             Metrics::inc(&m.connections_total);
                             Metrics::inc(&m.connections_active);
+                            let proxied = match read_proxy_header(&mut socket, &config).await {
+                                Ok(addr) => addr,
+                                Err(e) => {
+                                    debug!(conn_id, error = %e, "PROXY header rejected");
+                                    Metrics::dec(&m.connections_active);
+                                    return;
+                                }
+                            };
                             match acceptor.accept(socket).await {
                                 Ok(tls_stream) => {
                                     let client = ClientStream::Tls(tls_stream);
                                     connection::handle_connection(
                                         client,
+                                        proxied,
                                         config,
                                         upstream,
+                                        dns,
                                         pool,
                                         resolver,
+                                        database_resolvers,
                                         tenant,
+                                        audit,
                                         Arc::clone(&m),
                                         conn_id,
                                     )
@@ -241,29 +417,100 @@ pub async fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
         });
     }
 
+    // ─── IPv6 accept loop (if configured) ───────────────────────────────
+
+    if let Some(listener) = v6_listener {
+        let accept = accept_plain(
+            listener,
+            Arc::clone(&config),
+            upstream_tls.clone(),
+            dns.clone(),
+            pool.clone(),
+            resolver.clone(),
+            Arc::clone(&database_resolvers),
+            tenant_registry.clone(),
+            audit_logger.clone(),
+            Arc::clone(&metrics),
+        );
+        tokio::spawn(async move {
+            if let Err(e) = accept.await {
+                error!(error = %e, "IPv6 accept error — listener stopped");
+            }
+        });
+    }
+
     // ─── Plain accept loop (runs on main task) ──────────────────────────
 
+    accept_plain(
+        plain_listener,
+        config,
+        upstream_tls,
+        dns,
+        pool,
+        resolver,
+        database_resolvers,
+        tenant_registry,
+        audit_logger,
+        metrics,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Accept plain connections on `listener` until accepting fails.
+#[allow(clippy::too_many_arguments)]
+async fn accept_plain(
+    listener: TcpListener,
+    config: Arc<Config>,
+    upstream_tls: Option<Arc<ClientConfig>>,
+    dns: DnsCache,
+    pool: Option<Arc<Pool>>,
+    resolver: SharedResolver,
+    database_resolvers: DatabaseResolvers,
+    tenant_registry: Option<Arc<TenantRegistry>>,
+    audit_logger: Option<Arc<AuditLogger>>,
+    metrics: Arc<Metrics>,
+) -> io::Result<()> {
     loop {
-        let (socket, _) = plain_listener.accept().await?;
+        let (mut socket, _) = listener.accept().await?;
+        if at_capacity(&config, &metrics) {
+            tokio::spawn(reject_over_capacity(socket, Arc::clone(&metrics)));
+            continue;
+        }
         let config = Arc::clone(&config);
         let upstream = upstream_tls.clone();
+        let dns = dns.clone();
         let pool = pool.clone();
-        let resolver = resolver_engine.clone();
+        let resolver = resolver.current();
+        let database_resolvers = Arc::clone(&database_resolvers);
         let tenant = tenant_registry.clone();
+        let audit = audit_logger.clone();
         let m = Arc::clone(&metrics);
         let conn_id = CONN_COUNTER.fetch_add(1, Ordering::Relaxed) + 1;
 
         tokio::spawn(async move {
             Metrics::inc(&m.connections_total);
             Metrics::inc(&m.connections_active);
+            let proxied = match read_proxy_header(&mut socket, &config).await {
+                Ok(addr) => addr,
+                Err(e) => {
+                    debug!(conn_id, error = %e, "PROXY header rejected");
+                    Metrics::dec(&m.connections_active);
+                    return;
+                }
+            };
             let client = ClientStream::Plain(socket);
             connection::handle_connection(
                 client,
+                proxied,
                 config,
                 upstream,
+                dns,
                 pool,
                 resolver,
+                database_resolvers,
                 tenant,
+                audit,
                 Arc::clone(&m),
                 conn_id,
             )
@@ -272,3 +519,294 @@ pub async fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
         });
     }
 }
+
+/// Bind an IPv6-only listener, so IPv4-mapped connections are left to the
+/// IPv4 listener even when both use the same port.
+fn bind_v6_only(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_only_v6(true)?;
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Once the pool has read the upstream's `max_connections`, record it and warn
+/// if the pool could exhaust it. `pool_size` applies per (database, role)
+/// bucket, so the estimate uses the buckets open at that point. Informational
+/// only: nothing is enforced.
+async fn check_upstream_capacity(config: Arc<Config>, pool: Arc<Pool>, metrics: Arc<Metrics>) {
+    let max_connections = pool.upstream_max_connections().await;
+    metrics
+        .upstream_max_connections
+        .store(u64::from(max_connections), Ordering::Relaxed);
+
+    let buckets = pool.snapshot().await.buckets.len().max(1) as u64;
+    let needed = u64::from(config.pool_size) * buckets;
+    if needed >= u64::from(max_connections) {
+        warn!(
+            pool_size = config.pool_size,
+            buckets,
+            max_connections,
+            "pool_size x buckets reaches the upstream's max_connections — the pool may exhaust it"
+        );
+    } else {
+        info!(
+            pool_size = config.pool_size,
+            buckets, max_connections, "upstream capacity checked"
+        );
+    }
+}
+
+/// Whether a new connection would exceed `max_total_connections`.
+fn at_capacity(config: &Config, metrics: &Metrics) -> bool {
+    config.max_total_connections > 0
+        && metrics.connections_active.load(Ordering::Relaxed) >= config.max_total_connections
+}
+
+/// Consume the PROXY header `proxy_protocol_mode` calls for, if the peer is
+/// in `proxy_protocol_trusted_sources`, giving up after
+/// `handshake_timeout_secs`. An error means the connection should be dropped.
+async fn read_proxy_header(
+    socket: &mut TcpStream,
+    config: &Config,
+) -> io::Result<Option<SocketAddr>> {
+    let timeout = Duration::from_secs(config.handshake_timeout_secs);
+    tokio::time::timeout(
+        timeout,
+        proxy_protocol::read_header(
+            socket,
+            config.proxy_protocol_mode,
+            &config.proxy_protocol_trusted_sources,
+        ),
+    )
+    .await
+    .unwrap_or_else(|_| {
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "timed out waiting for PROXY header",
+        ))
+    })
+}
+
+/// Refuse a connection over `max_total_connections` with SQLSTATE 53300
+/// (too_many_connections) before reading anything from it.
+async fn reject_over_capacity(mut socket: TcpStream, metrics: Arc<Metrics>) {
+    Metrics::inc(&metrics.connections_rejected_max_total);
+    warn!(
+        peer = ?socket.peer_addr().ok(),
+        "max_total_connections reached — rejecting connection"
+    );
+    let msg = build_error_response("FATAL", "53300", "too many connections");
+    let _ = socket.write_all(&msg).await;
+    let _ = socket.shutdown().await;
+}
+
+/// Load the global `resolvers` file, if configured.
+fn load_resolver_engine(
+    config: &Config,
+    metrics: Option<Arc<Metrics>>,
+) -> Result<Option<Arc<ResolverEngine>>, String> {
+    let Some(path) = &config.resolvers else {
+        return Ok(None);
+    };
+    load_resolver_file(path, config, metrics, None).map(Some)
+}
+
+/// Load each `[[database_resolver]]` file, keyed by database.
+fn load_database_resolvers(config: &Config) -> Result<DatabaseResolvers, String> {
+    let mut engines = HashMap::new();
+    for entry in &config.database_resolvers {
+        let engine = load_resolver_file(&entry.resolvers, config, None, Some(&entry.database))?;
+        engines.insert(entry.database.clone(), engine);
+    }
+    Ok(Arc::new(engines))
+}
+
+/// Load one resolver file, log its contents, and start a cache evictor for
+/// it. The evictor exits once the engine is dropped.
+fn load_resolver_file(
+    path: &str,
+    config: &Config,
+    metrics: Option<Arc<Metrics>>,
+    database: Option<&str>,
+) -> Result<Arc<ResolverEngine>, String> {
+    let engine = resolver::load_resolvers(path, &config.context_variables, metrics)?;
+    info!(
+        resolvers = engine.resolvers.len(),
+        file = %path,
+        database = database.unwrap_or("*"),
+        "context resolvers loaded"
+    );
+    for r in &engine.resolvers {
+        let inject_vars: Vec<&str> = r.inject.iter().map(|(k, _)| k.as_str()).collect();
+        info!(
+            name = %r.name,
+            params = ?r.params,
+            inject = ?inject_vars,
+            required = r.required,
+            cache_ttl = r.cache_ttl.as_secs(),
+            "  resolver"
+        );
+    }
+    let engine = Arc::new(engine);
+
+    // Spawn cache evictor if any resolver uses caching
+    if engine
+        .resolvers
+        .iter()
+        .any(|r| r.cache_ttl > Duration::ZERO)
+    {
+        let evictor = Arc::downgrade(&engine);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                let Some(engine) = evictor.upgrade() else {
+                    break; // replaced by a reload
+                };
+                engine.evict_expired().await;
+            }
+        });
+    }
+
+    Ok(engine)
+}
+
+// ─── Config Reload ──────────────────────────────────────────────────────────
+
+/// Applies runtime-safe settings from a re-read config: log level, tenant
+/// limits and resolvers. Anything else is reported as needing a restart.
+/// Every reload also flushes the upstream DNS cache; a successful one drains
+/// the pool if an upstream host now resolves to different addresses.
+struct ConfigReloader {
+    /// The settings currently in effect.
+    active: Config,
+    log_filter: LogFilterHandle,
+    tenant_registry: Option<Arc<TenantRegistry>>,
+    resolver: SharedResolver,
+    metrics: Arc<Metrics>,
+    dns: DnsCache,
+    pool: Option<Arc<Pool>>,
+}
+
+impl ConfigReloader {
+    fn reload(&mut self) {
+        // Re-resolve upstream hosts from here on (e.g. after a DNS failover)
+        let previous = self.dns.snapshot();
+        self.dns.flush();
+
+        let new = match Config::try_load().and_then(|(c, w)| c.validate().map(|_| (c, w))) {
+            Ok((c, warnings)) => {
+                for warning in &warnings {
+                    warn!(key = %warning.key, "{warning}");
+                }
+                c
+            }
+            Err(e) => {
+                error!(error = %e, "config reload failed — keeping current config");
+                self.metrics.record_config_reload_error(&e);
+                return;
+            }
+        };
+        self.metrics.record_config_reload();
+        self.drain_if_upstream_moved(previous);
+
+        for field in self.active.restart_required_changes(&new) {
+            warn!(field, "config change requires restart — ignored");
+        }
+
+        // ─── Log level ──────────────────────────────────────────────────
+        if new.log_level != self.active.log_level {
+            match EnvFilter::try_new(&new.log_level) {
+                Ok(filter) => match self.log_filter.reload(filter) {
+                    Ok(()) => {
+                        info!(log_level = %new.log_level, "log level updated");
+                        self.active.log_level = new.log_level.clone();
+                    }
+                    Err(e) => error!(error = %e, "failed to update log level"),
+                },
+                Err(e) => {
+                    warn!(log_level = %new.log_level, error = %e, "invalid log_level — ignored")
+                }
+            }
+        }
+
+        // ─── Tenant limits ──────────────────────────────────────────────
+        match &self.tenant_registry {
+            Some(registry) => {
+                registry.update_config(&new);
+                self.active.tenant_allow = new.tenant_allow.clone();
+                self.active.tenant_deny = new.tenant_deny.clone();
+                self.active.tenant_allowlist_only = new.tenant_allowlist_only;
+                self.active.tenant_max_connections = new.tenant_max_connections;
+                self.active.tenant_max_connections_map = new.tenant_max_connections_map.clone();
+                self.active.tenant_rate_limit = new.tenant_rate_limit;
+                self.active.tenant_rate_limit_window_secs = new.tenant_rate_limit_window_secs;
+                info!("tenant limits updated");
+            }
+            None if new.has_tenant_limits() => {
+                warn!("enabling tenant isolation requires restart — ignored");
+            }
+            None => {}
+        }
+
+        // ─── Resolvers ──────────────────────────────────────────────────
+        // Resolvers are validated against the running context_variables, since
+        // connections keep using those until restart.
+        let mut candidate = new.clone();
+        candidate.context_variables = self.active.context_variables.clone();
+        match self.reload_resolvers(&candidate) {
+            Ok(engine) => {
+                self.resolver.replace(engine);
+                self.active.resolvers = new.resolvers;
+            }
+            Err(e) => error!(error = %e, "resolver reload failed — keeping current resolvers"),
+        }
+    }
+
+    /// Re-resolve the hosts that were cached before the reload and drain the
+    /// pool if any of them moved, so pooled connections stop going to the old
+    /// addresses. Lookup order is ignored: round-robin DNS reorders answers.
+    fn drain_if_upstream_moved(&self, previous: Vec<(String, u16, Vec<SocketAddr>)>) {
+        let Some(pool) = self.pool.clone() else {
+            return;
+        };
+        let dns = self.dns.clone();
+        let ttl = Duration::from_secs(self.active.upstream_dns_ttl_secs);
+        tokio::spawn(async move {
+            for (host, port, mut before) in previous {
+                let Ok(mut now) = dns.resolve(&host, port, ttl).await else {
+                    continue;
+                };
+                before.sort();
+                now.sort();
+                if now != before {
+                    info!(host = %host, port, "upstream addresses changed — draining pool");
+                    pool.drain().await;
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Per-resolver metrics are sized at startup, so they are only attached
+    /// when the reloaded resolver set has the same names in the same order.
+    fn reload_resolvers(&self, config: &Config) -> Result<Option<Arc<ResolverEngine>>, String> {
+        let Some(path) = &config.resolvers else {
+            if self.active.resolvers.is_some() {
+                info!("context resolvers disabled");
+            }
+            return Ok(None);
+        };
+        let peek = resolver::load_resolvers(path, &config.context_variables, None)?;
+        let names: Vec<&String> = peek.resolvers.iter().map(|r| &r.name).collect();
+        let metrics = if names.iter().copied().eq(self.metrics.resolver_names.iter()) {
+            Some(Arc::clone(&self.metrics))
+        } else {
+            warn!("resolver set changed — resolver metrics disabled until restart");
+            None
+        };
+        load_resolver_engine(config, metrics)
+    }
+}
@@ -0,0 +1,349 @@
+//! PROXY protocol (v1 text and v2 binary) on client connections.
+//!
+//! When pgvpd sits behind HAProxy or a cloud load balancer, the TCP peer is
+//! the balancer. The header it prepends carries the real client address,
+//! which then stands in for the peer in logs, the audit log and `trust_ips`.
+//!
+//! The header is read with `peek` first, so in `detect` mode a connection
+//! without one is handed on with its first bytes untouched.
+//!
+//! Only TCP peers in `proxy_protocol_trusted_sources` get their header
+//! parsed. Anyone else could otherwise claim an address inside `trust_ips`
+//! and skip pool-mode auth.
+
+use ipnetwork::IpNetwork;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+use crate::config::ProxyProtocolMode;
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V2_SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+/// Longest legal v1 line, CRLF included.
+const V1_MAX_LEN: usize = 107;
+const V2_HEADER_LEN: usize = 16;
+/// Back-off between peeks while a header prefix is still arriving.
+const PEEK_RETRY: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Header {
+    V1,
+    V2,
+    Absent,
+}
+
+/// Consume the PROXY header at the start of `socket` if `mode` expects one,
+/// and return the client address it carries. `Ok(None)` means the TCP peer
+/// address applies: mode is off, no header in detect mode, or the header
+/// itself carries no address (v1 `UNKNOWN`, v2 `LOCAL`).
+///
+/// A peer outside `trusted_sources` is refused in required mode; in detect
+/// mode it is treated as a direct client and any header it sends is left
+/// unread, to fail as a malformed startup packet.
+pub async fn read_header(
+    socket: &mut TcpStream,
+    mode: ProxyProtocolMode,
+    trusted_sources: &[IpNetwork],
+) -> io::Result<Option<SocketAddr>> {
+    if mode == ProxyProtocolMode::Off {
+        return Ok(None);
+    }
+    let peer = socket.peer_addr()?.ip().to_canonical();
+    if !trusted_sources.iter().any(|net| net.contains(peer)) {
+        return match mode {
+            ProxyProtocolMode::Required => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{peer} is not in proxy_protocol_trusted_sources"),
+            )),
+            _ => Ok(None),
+        };
+    }
+
+    let mut buf = [0u8; V1_MAX_LEN];
+    match peek_until(socket, &mut buf[..V2_HEADER_LEN], detect).await? {
+        Header::V1 => {
+            let len = peek_until(socket, &mut buf, v1_line_len)
+                .await?
+                .ok_or_else(|| invalid("PROXY v1 header exceeds 107 bytes"))?;
+            let mut line = vec![0u8; len];
+            socket.read_exact(&mut line).await?;
+            parse_v1(&line)
+        }
+        Header::V2 => {
+            let mut header = [0u8; V2_HEADER_LEN];
+            socket.read_exact(&mut header).await?;
+            let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+            let mut body = vec![0u8; len];
+            socket.read_exact(&mut body).await?;
+            parse_v2(&header, &body)
+        }
+        Header::Absent if mode == ProxyProtocolMode::Required => {
+            Err(invalid("missing PROXY protocol header"))
+        }
+        Header::Absent => Ok(None),
+    }
+}
+
+/// Peek into `buf` until `decide` can tell from the bytes so far.
+async fn peek_until<T>(
+    socket: &TcpStream,
+    buf: &mut [u8],
+    decide: impl Fn(&[u8]) -> Option<T>,
+) -> io::Result<T> {
+    loop {
+        let n = socket.peek(buf).await?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed before PROXY header",
+            ));
+        }
+        if let Some(decision) = decide(&buf[..n]) {
+            return Ok(decision);
+        }
+        // peek returns at once while unread data is buffered — wait for more
+        tokio::time::sleep(PEEK_RETRY).await;
+    }
+}
+
+/// Which header `prefix` starts with; `None` while it could still be either.
+fn detect(prefix: &[u8]) -> Option<Header> {
+    let v1 = prefix.len().min(V1_PREFIX.len());
+    if prefix[..v1] == V1_PREFIX[..v1] {
+        return (v1 == V1_PREFIX.len()).then_some(Header::V1);
+    }
+    let v2 = prefix.len().min(V2_SIGNATURE.len());
+    if prefix[..v2] == V2_SIGNATURE[..v2] {
+        return (v2 == V2_SIGNATURE.len()).then_some(Header::V2);
+    }
+    Some(Header::Absent)
+}
+
+/// Length of the v1 line including CRLF, `Some(None)` if it is too long.
+fn v1_line_len(buf: &[u8]) -> Option<Option<usize>> {
+    match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(pos) => Some(Some(pos + 2)),
+        None if buf.len() >= V1_MAX_LEN => Some(None),
+        None => None,
+    }
+}
+
+/// `PROXY TCP4 <src> <dst> <sport> <dport>\r\n` (or TCP6, or UNKNOWN).
+fn parse_v1(line: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line).map_err(|_| invalid("PROXY v1 header is not ASCII"))?;
+    let fields: Vec<&str> = line.trim_end_matches("\r\n").split(' ').collect();
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", proto @ ("TCP4" | "TCP6"), src, _dst, sport, _dport] => {
+            let ip: IpAddr = src
+                .parse()
+                .map_err(|_| invalid("PROXY v1 header has a bad source address"))?;
+            if ip.is_ipv4() != (*proto == "TCP4") {
+                return Err(invalid("PROXY v1 source address does not match protocol"));
+            }
+            let port: u16 = sport
+                .parse()
+                .map_err(|_| invalid("PROXY v1 header has a bad source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("malformed PROXY v1 header")),
+    }
+}
+
+/// The 16-byte v2 header (signature, version/command, family, length) and
+/// the address block that follows it.
+fn parse_v2(header: &[u8; V2_HEADER_LEN], body: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if header[12] >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    match header[12] & 0x0F {
+        // LOCAL — the balancer's own health check; no client behind it
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => return Err(invalid("unsupported PROXY v2 command")),
+    }
+    match header[13] >> 4 {
+        // AF_INET: src(4) dst(4) sport(2) dport(2)
+        0x1 if body.len() >= 12 => {
+            let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            let port = u16::from_be_bytes([body[8], body[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        // AF_INET6: src(16) dst(16) sport(2) dport(2)
+        0x2 if body.len() >= 36 => {
+            let octets: [u8; 16] = body[..16].try_into().unwrap();
+            let port = u16::from_be_bytes([body[32], body[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port)))
+        }
+        0x1 | 0x2 => Err(invalid("truncated PROXY v2 address block")),
+        // AF_UNSPEC / AF_UNIX — nothing usable as a peer address
+        _ => Ok(None),
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// ─── Tests ──────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    /// Accepted server side of a loopback connection whose client has
+    /// already written `bytes`.
+    async fn socket_with(bytes: &[u8]) -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        client.write_all(bytes).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (server, client)
+    }
+
+    fn loopback() -> Vec<IpNetwork> {
+        vec!["127.0.0.0/8".parse().unwrap()]
+    }
+
+    fn v2_header(command: u8, family: u8, body: &[u8]) -> Vec<u8> {
+        let mut out = V2_SIGNATURE.to_vec();
+        out.push(0x20 | command);
+        out.push(family);
+        out.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        out.extend_from_slice(body);
+        out
+    }
+
+    #[test]
+    fn detect_waits_for_an_unambiguous_prefix() {
+        assert_eq!(detect(b"PRO"), None);
+        assert_eq!(detect(b"PROXY TCP4"), Some(Header::V1));
+        assert_eq!(detect(&V2_SIGNATURE[..5]), None);
+        assert_eq!(detect(&V2_SIGNATURE), Some(Header::V2));
+        // A StartupMessage begins with its length
+        assert_eq!(detect(&[0, 0, 0, 8, 4, 210, 22, 47]), Some(Header::Absent));
+        assert_eq!(detect(b"PRY"), Some(Header::Absent));
+    }
+
+    #[test]
+    fn v1_parsing() {
+        assert_eq!(
+            parse_v1(b"PROXY TCP4 203.0.113.7 10.0.0.1 51234 6432\r\n").unwrap(),
+            Some("203.0.113.7:51234".parse().unwrap())
+        );
+        assert_eq!(
+            parse_v1(b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 6432\r\n").unwrap(),
+            Some("[2001:db8::1]:4000".parse().unwrap())
+        );
+        assert_eq!(parse_v1(b"PROXY UNKNOWN\r\n").unwrap(), None);
+        assert!(parse_v1(b"PROXY TCP6 203.0.113.7 10.0.0.1 1 2\r\n").is_err());
+        assert!(parse_v1(b"PROXY TCP4 203.0.113.7 10.0.0.1 99999 2\r\n").is_err());
+        assert!(parse_v1(b"PROXY TCP4 203.0.113.7\r\n").is_err());
+    }
+
+    #[test]
+    fn v2_parsing() {
+        let header = |bytes: &[u8]| -> [u8; 16] { bytes[..16].try_into().unwrap() };
+
+        let body = [203, 0, 113, 7, 10, 0, 0, 1, 0xC8, 0x22, 0x19, 0x20];
+        let msg = v2_header(0x1, 0x11, &body);
+        assert_eq!(
+            parse_v2(&header(&msg), &body).unwrap(),
+            Some("203.0.113.7:51234".parse().unwrap())
+        );
+
+        let mut body6 = [0u8; 36];
+        body6[..16].copy_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        body6[32..34].copy_from_slice(&4000u16.to_be_bytes());
+        let msg = v2_header(0x1, 0x21, &body6);
+        assert_eq!(
+            parse_v2(&header(&msg), &body6).unwrap(),
+            Some("[2001:db8::1]:4000".parse().unwrap())
+        );
+
+        let local = v2_header(0x0, 0x00, &[]);
+        assert_eq!(parse_v2(&header(&local), &[]).unwrap(), None);
+
+        let short = v2_header(0x1, 0x11, &body[..6]);
+        assert!(parse_v2(&header(&short), &body[..6]).is_err());
+    }
+
+    #[tokio::test]
+    async fn header_is_consumed_and_rest_left_for_postgres() {
+        let mut bytes = b"PROXY TCP4 203.0.113.7 10.0.0.1 51234 6432\r\n".to_vec();
+        bytes.extend_from_slice(&[0, 0, 0, 8, 4, 210, 22, 47]);
+        let (mut server, _client) = socket_with(&bytes).await;
+
+        let addr = read_header(&mut server, ProxyProtocolMode::Required, &loopback())
+            .await
+            .unwrap();
+        assert_eq!(addr, Some("203.0.113.7:51234".parse().unwrap()));
+        let mut rest = [0u8; 8];
+        server.read_exact(&mut rest).await.unwrap();
+        assert_eq!(rest, [0, 0, 0, 8, 4, 210, 22, 47]);
+    }
+
+    #[tokio::test]
+    async fn v2_header_is_read_from_the_socket() {
+        let body = [203, 0, 113, 7, 10, 0, 0, 1, 0xC8, 0x22, 0x19, 0x20];
+        let (mut server, _client) = socket_with(&v2_header(0x1, 0x11, &body)).await;
+        let addr = read_header(&mut server, ProxyProtocolMode::Detect, &loopback())
+            .await
+            .unwrap();
+        assert_eq!(addr, Some("203.0.113.7:51234".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn detect_leaves_plain_connections_untouched() {
+        let startup = [0, 0, 0, 8, 4, 210, 22, 47];
+        let (mut server, _client) = socket_with(&startup).await;
+
+        let addr = read_header(&mut server, ProxyProtocolMode::Detect, &loopback())
+            .await
+            .unwrap();
+        assert_eq!(addr, None);
+        let mut first = [0u8; 8];
+        server.read_exact(&mut first).await.unwrap();
+        assert_eq!(first, startup);
+    }
+
+    #[tokio::test]
+    async fn required_rejects_plain_connections() {
+        let (mut server, _client) = socket_with(&[0, 0, 0, 8, 4, 210, 22, 47]).await;
+        let err = read_header(&mut server, ProxyProtocolMode::Required, &loopback())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn untrusted_peers_cannot_supply_an_address() {
+        let header = b"PROXY TCP4 10.0.0.5 10.0.0.1 51234 6432\r\n";
+        let elsewhere: Vec<IpNetwork> = vec!["10.0.0.0/8".parse().unwrap()];
+
+        let (mut server, _client) = socket_with(header).await;
+        let err = read_header(&mut server, ProxyProtocolMode::Required, &elsewhere)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        // Detect mode ignores the header, leaving it for the startup parser
+        let (mut server, _client) = socket_with(header).await;
+        let addr = read_header(&mut server, ProxyProtocolMode::Detect, &elsewhere)
+            .await
+            .unwrap();
+        assert_eq!(addr, None);
+        let mut first = [0u8; 6];
+        server.read_exact(&mut first).await.unwrap();
+        assert_eq!(&first, V1_PREFIX);
+    }
+}
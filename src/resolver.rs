@@ -11,13 +11,12 @@ use std::hash::{Hash, Hasher};
 use std::io;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::sync::Mutex;
 use tracing::{debug, error, info};
 
 use crate::metrics::Metrics;
 use crate::protocol::{backend, build_query_message, escape_set_value, try_read_backend_message};
-use crate::stream::UpstreamStream;
 
 // ─── TOML Deserialization ───────────────────────────────────────────────────
 
@@ -34,13 +33,24 @@ pub struct ResolverToml {
     pub query: String,
     #[serde(default)]
     pub params: Vec<String>,
+    /// Entries of `params` that bind as NULL when missing or NULL, instead
+    /// of failing the connection or skipping the resolver.
+    #[serde(default)]
+    pub param_optional: Vec<String>,
     pub inject: HashMap<String, String>, // session_var -> column_name
     #[serde(default)]
     pub required: bool,
     #[serde(default)]
     pub depends_on: Vec<String>,
+    /// Session variables this resolver may re-inject after a resolver it
+    /// depends on has set them.
+    #[serde(default)]
+    pub overrides: Vec<String>,
     #[serde(default)]
     pub cache_ttl: u64, // seconds, 0 = no caching
+    /// StartupMessage parameters made available as resolver inputs.
+    #[serde(default)]
+    pub from_startup_params: Vec<String>,
 }
 
 // ─── Validated Definitions ──────────────────────────────────────────────────
@@ -51,10 +61,13 @@ pub struct ResolverDef {
     pub name: String,
     pub query: String,
     pub params: Vec<String>,
+    pub param_optional: Vec<String>,
     pub inject: Vec<(String, String)>, // (session_var, column_name) ordered
     pub required: bool,
     pub depends_on: Vec<String>,
+    pub overrides: Vec<String>,
     pub cache_ttl: Duration,
+    pub from_startup_params: Vec<String>,
 }
 
 // ─── Cache ──────────────────────────────────────────────────────────────────
@@ -65,6 +78,20 @@ struct CacheEntry {
     expires_at: Instant,
 }
 
+/// Redacted view of one cache entry for the debug admin endpoint.
+/// Values are reduced to their lengths so tenant data never leaves the proxy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntryDebug {
+    pub resolver_name: String,
+    /// Hash of the resolver's input values (the cache key).
+    pub key_hash: u64,
+    /// `(column name, value length)`, sorted by column; `None` for NULL.
+    pub columns: Vec<(String, Option<usize>)>,
+    /// Number of non-NULL values.
+    pub value_count: usize,
+    pub ttl_remaining_secs: u64,
+}
+
 // ─── Resolver Engine ────────────────────────────────────────────────────────
 
 /// The resolver engine: holds ordered resolvers and a shared result cache.
@@ -74,21 +101,53 @@ pub struct ResolverEngine {
     metrics: Option<Arc<Metrics>>,
 }
 
+/// The active resolver engine, replaced when the config is reloaded.
+/// Connections take a snapshot at accept time, so a reload never changes the
+/// resolvers of a handshake already in progress.
+#[derive(Clone, Default)]
+pub struct SharedResolver(Arc<std::sync::RwLock<Option<Arc<ResolverEngine>>>>);
+
+impl SharedResolver {
+    pub fn new(engine: Option<Arc<ResolverEngine>>) -> Self {
+        Self(Arc::new(std::sync::RwLock::new(engine)))
+    }
+
+    pub fn current(&self) -> Option<Arc<ResolverEngine>> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn replace(&self, engine: Option<Arc<ResolverEngine>>) {
+        *self.0.write().unwrap() = engine;
+    }
+}
+
+/// Per-database resolver engines (`[[database_resolver]]`), keyed by database
+/// name. Loaded once at startup, so changing them needs a restart.
+pub type DatabaseResolvers = Arc<HashMap<String, Arc<ResolverEngine>>>;
+
 /// Load resolvers from a TOML file, validate, and topologically sort.
-pub fn load_resolvers(path: &str, metrics: Option<Arc<Metrics>>) -> Result<ResolverEngine, String> {
+/// `context_variables` are the username-derived variables; startup parameter
+/// inputs may not shadow them.
+pub fn load_resolvers(
+    path: &str,
+    context_variables: &[String],
+    metrics: Option<Arc<Metrics>>,
+) -> Result<ResolverEngine, String> {
     let content = std::fs::read_to_string(path)
         .map_err(|e| format!("cannot read resolver file '{}': {}", path, e))?;
 
-    let parsed: ResolverFile =
-        toml::from_str(&content).map_err(|e| format!("invalid TOML in '{}': {}", path, e))?;
-
-    if parsed.resolver.is_empty() {
+    let problems = validate_resolver_file(&content, context_variables);
+    if !problems.is_empty() {
         return Err(format!(
-            "resolver file '{}' contains no [[resolver]] blocks",
-            path
+            "invalid resolver file '{}':\n  - {}",
+            path,
+            problems.join("\n  - ")
         ));
     }
 
+    let parsed: ResolverFile =
+        toml::from_str(&content).map_err(|e| format!("invalid TOML in '{}': {}", path, e))?;
+
     let defs: Vec<ResolverDef> = parsed
         .resolver
         .into_iter()
@@ -96,28 +155,32 @@ pub fn load_resolvers(path: &str, metrics: Option<Arc<Metrics>>) -> Result<Resol
             name: r.name,
             query: r.query,
             params: r.params,
+            param_optional: r.param_optional,
             inject: r.inject.into_iter().collect(),
             required: r.required,
             depends_on: r.depends_on,
+            overrides: r.overrides,
             cache_ttl: Duration::from_secs(r.cache_ttl),
+            from_startup_params: r.from_startup_params,
         })
         .collect();
 
-    // Validate: no duplicate names
-    let mut names: std::collections::HashSet<&str> = std::collections::HashSet::new();
+    // Validate: startup param inputs don't shadow username context or resolver outputs
     for def in &defs {
-        if !names.insert(&def.name) {
-            return Err(format!("duplicate resolver name: '{}'", def.name));
-        }
-    }
-
-    // Validate: depends_on references exist
-    for def in &defs {
-        for dep in &def.depends_on {
-            if !names.contains(dep.as_str()) {
+        for param in &def.from_startup_params {
+            if context_variables.contains(param) {
                 return Err(format!(
-                    "resolver '{}' depends on '{}' which does not exist",
-                    def.name, dep
+                    "resolver '{}': startup param '{}' collides with a context variable",
+                    def.name, param
+                ));
+            }
+            if defs
+                .iter()
+                .any(|d| d.inject.iter().any(|(var, _)| var == param))
+            {
+                return Err(format!(
+                    "resolver '{}': startup param '{}' collides with a resolver output",
+                    def.name, param
                 ));
             }
         }
@@ -129,6 +192,7 @@ pub fn load_resolvers(path: &str, metrics: Option<Arc<Metrics>>) -> Result<Resol
     }
 
     let sorted = topological_sort(&defs)?;
+    check_inject_conflicts(&sorted)?;
 
     Ok(ResolverEngine {
         resolvers: sorted,
@@ -137,6 +201,147 @@ pub fn load_resolvers(path: &str, metrics: Option<Arc<Metrics>>) -> Result<Resol
     })
 }
 
+// ─── File Validation ────────────────────────────────────────────────────────
+
+const RESOLVER_KEYS: &[&str] = &[
+    "name",
+    "query",
+    "params",
+    "param_optional",
+    "inject",
+    "required",
+    "depends_on",
+    "overrides",
+    "cache_ttl",
+    "from_startup_params",
+];
+
+/// Semantic checks on a resolver file, run before deserializing so a typo
+/// like `injectx` gets a pointed message instead of a serde error. Returns
+/// every violation found; empty means the file is usable.
+pub fn validate_resolver_file(content: &str, context_variables: &[String]) -> Vec<String> {
+    let table: toml::Table = match content.parse() {
+        Ok(t) => t,
+        Err(e) => return vec![format!("invalid TOML: {}", e)],
+    };
+    let Some(blocks) = table.get("resolver").and_then(|v| v.as_array()) else {
+        return vec!["no [[resolver]] blocks".into()];
+    };
+    if blocks.is_empty() {
+        return vec!["no [[resolver]] blocks".into()];
+    }
+
+    let mut problems = Vec::new();
+    let str_list = |block: &toml::Table, key: &str| -> Vec<String> {
+        block
+            .get(key)
+            .and_then(|v| v.as_array())
+            .map(|a| {
+                a.iter()
+                    .filter_map(|v| v.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    // First pass: names and outputs, so references can point forward
+    let mut names = Vec::new();
+    let mut available: Vec<String> = context_variables.to_vec();
+    for block in blocks.iter().filter_map(|b| b.as_table()) {
+        if let Some(name) = block.get("name").and_then(|v| v.as_str()) {
+            names.push(name.to_string());
+        }
+        if let Some(inject) = block.get("inject").and_then(|v| v.as_table()) {
+            available.extend(inject.keys().cloned());
+        }
+        available.extend(str_list(block, "from_startup_params"));
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    for (i, block) in blocks.iter().enumerate() {
+        let Some(block) = block.as_table() else {
+            problems.push(format!("resolver #{}: not a table", i + 1));
+            continue;
+        };
+        let label = match block.get("name").and_then(|v| v.as_str()) {
+            Some(name) if !name.is_empty() => format!("resolver '{}'", name),
+            _ => {
+                problems.push(format!("resolver #{}: missing or empty 'name'", i + 1));
+                format!("resolver #{}", i + 1)
+            }
+        };
+        if let Some(name) = block.get("name").and_then(|v| v.as_str())
+            && !name.is_empty()
+            && !seen.insert(name)
+        {
+            problems.push(format!("duplicate resolver name: '{}'", name));
+        }
+
+        for key in block.keys() {
+            if !RESOLVER_KEYS.contains(&key.as_str()) {
+                problems.push(format!("{}: unknown key '{}'", label, key));
+            }
+        }
+
+        match block.get("query").and_then(|v| v.as_str()) {
+            Some(q) if !q.trim().is_empty() => {}
+            _ => problems.push(format!("{}: missing or empty 'query'", label)),
+        }
+
+        match block.get("inject").and_then(|v| v.as_table()) {
+            Some(inject) if !inject.is_empty() => {
+                for (var, col) in inject {
+                    if !col.as_str().is_some_and(|c| !c.is_empty()) {
+                        problems.push(format!(
+                            "{}: inject '{}' must map to a non-empty column name",
+                            label, var
+                        ));
+                    }
+                }
+            }
+            _ => problems.push(format!("{}: missing or empty 'inject'", label)),
+        }
+
+        let params = str_list(block, "params");
+        for param in &params {
+            if !available.contains(param) {
+                problems.push(format!(
+                    "{}: param '{}' is not a context variable, startup param, or resolver output",
+                    label, param
+                ));
+            }
+        }
+        for param in str_list(block, "param_optional") {
+            if !params.contains(&param) {
+                problems.push(format!(
+                    "{}: param_optional '{}' is not listed in 'params'",
+                    label, param
+                ));
+            }
+        }
+
+        for dep in str_list(block, "depends_on") {
+            if !names.contains(&dep) {
+                problems.push(format!(
+                    "{} depends on '{}' which does not exist",
+                    label, dep
+                ));
+            }
+        }
+
+        if let Some(ttl) = block.get("cache_ttl")
+            && !ttl.as_integer().is_some_and(|n| n >= 0)
+        {
+            problems.push(format!(
+                "{}: 'cache_ttl' must be a non-negative integer (seconds)",
+                label
+            ));
+        }
+    }
+
+    problems
+}
+
 // ─── Topological Sort ───────────────────────────────────────────────────────
 
 fn topological_sort(defs: &[ResolverDef]) -> Result<Vec<ResolverDef>, String> {
@@ -183,6 +388,51 @@ fn topological_sort(defs: &[ResolverDef]) -> Result<Vec<ResolverDef>, String> {
     Ok(order.into_iter().map(|i| defs[i].clone()).collect())
 }
 
+// ─── Inject Conflicts ───────────────────────────────────────────────────────
+
+/// Reject two resolvers injecting the same session variable, which would
+/// leave the value up to execution order. A resolver may replace a variable
+/// set by one of its (transitive) `depends_on` resolvers only when it lists
+/// the variable in `overrides`. `defs` must be topologically sorted.
+fn check_inject_conflicts(defs: &[ResolverDef]) -> Result<(), String> {
+    let mut ancestors: HashMap<&str, std::collections::HashSet<&str>> = HashMap::new();
+    let mut injected_by: HashMap<&str, &str> = HashMap::new(); // session_var -> resolver
+
+    for def in defs {
+        let mut mine = std::collections::HashSet::new();
+        for dep in &def.depends_on {
+            mine.insert(dep.as_str());
+            mine.extend(ancestors[dep.as_str()].iter().copied());
+        }
+
+        for var in &def.overrides {
+            if !injected_by
+                .get(var.as_str())
+                .is_some_and(|owner| mine.contains(owner))
+            {
+                return Err(format!(
+                    "resolver '{}' overrides '{}' which no resolver it depends on injects",
+                    def.name, var
+                ));
+            }
+        }
+
+        for (var, _) in &def.inject {
+            if let Some(owner) = injected_by.insert(var, &def.name) {
+                let allowed = mine.contains(owner) && def.overrides.contains(var);
+                if !allowed {
+                    return Err(format!(
+                        "resolver '{}' injects into '{}' which is already injected by resolver '{}'",
+                        def.name, var, owner
+                    ));
+                }
+            }
+        }
+        ancestors.insert(&def.name, mine);
+    }
+    Ok(())
+}
+
 // ─── Resolver Execution ─────────────────────────────────────────────────────
 
 impl ResolverEngine {
@@ -191,27 +441,84 @@ impl ResolverEngine {
         self.cache.lock().await.len()
     }
 
+    /// Redacted snapshot of live cache entries (for the debug admin endpoint).
+    pub async fn cache_entries_debug(&self) -> Vec<CacheEntryDebug> {
+        let cache = self.cache.lock().await;
+        let now = Instant::now();
+        let mut entries: Vec<CacheEntryDebug> = cache
+            .iter()
+            .filter(|(_, entry)| entry.expires_at > now)
+            .map(|((name, hash), entry)| {
+                let mut columns: Vec<(String, Option<usize>)> = entry
+                    .values
+                    .iter()
+                    .map(|(col, val)| (col.clone(), val.as_ref().map(|v| v.len())))
+                    .collect();
+                columns.sort();
+                CacheEntryDebug {
+                    resolver_name: name.clone(),
+                    key_hash: *hash,
+                    value_count: columns.iter().filter(|(_, len)| len.is_some()).count(),
+                    columns,
+                    ttl_remaining_secs: entry.expires_at.duration_since(now).as_secs(),
+                }
+            })
+            .collect();
+        entries.sort_by(|a, b| (&a.resolver_name, a.key_hash).cmp(&(&b.resolver_name, b.key_hash)));
+        entries
+    }
+
+    /// Values of every `from_startup_params` name, taken from the client's
+    /// StartupMessage. Parameters the client didn't send map to NULL.
+    pub fn startup_context(
+        &self,
+        startup_params: &HashMap<String, String>,
+    ) -> HashMap<String, Option<String>> {
+        self.resolvers
+            .iter()
+            .flat_map(|def| &def.from_startup_params)
+            .map(|name| (name.clone(), startup_params.get(name).cloned()))
+            .collect()
+    }
+
     /// Execute all resolvers in order, populating `context` with resolved values.
-    /// `context` comes in with static context from username extraction.
-    pub async fn resolve_context(
+    /// `context` comes in with static context from username extraction; any
+    /// `from_startup_params` are added from `startup_params` as extra inputs
+    /// (and removed again afterwards, so they are not injected). Notices raised
+    /// by resolver queries are forwarded to `client`.
+    pub async fn resolve_context<S, C>(
         &self,
-        server: &mut UpstreamStream,
+        server: &mut S,
         server_buf: &mut BytesMut,
+        client: &mut C,
         context: &mut HashMap<String, Option<String>>,
+        startup_params: &HashMap<String, String>,
         conn_id: u64,
-    ) -> Result<(), io::Error> {
+    ) -> Result<(), io::Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        C: AsyncWrite + Unpin,
+    {
+        let from_startup = self.startup_context(startup_params);
+        for (name, value) in &from_startup {
+            context.insert(name.clone(), value.clone());
+        }
+
         for (resolver_idx, def) in self.resolvers.iter().enumerate() {
             // Collect input param values
             let mut skip = false;
             let mut input_values: Vec<Option<String>> = Vec::with_capacity(def.params.len());
 
             for param_name in &def.params {
+                let optional = def.param_optional.contains(param_name);
                 match context.get(param_name) {
                     Some(Some(val)) => input_values.push(Some(val.clone())),
                     Some(None) => {
                         input_values.push(None);
-                        skip = true;
+                        skip |= !optional;
                     }
+                    // Not in the context (yet): optional params bind as NULL
+                    None if optional => input_values.push(None),
                     None => {
                         error!(
                             conn_id,
@@ -230,7 +537,7 @@ impl ResolverEngine {
                 }
             }
 
-            // If any input is NULL, skip this resolver
+            // If any required input is NULL, skip this resolver
             if skip {
                 debug!(conn_id, resolver = %def.name, "skipping — input param is NULL");
                 for (session_var, _) in &def.inject {
@@ -248,6 +555,9 @@ impl ResolverEngine {
                 {
                     if let Some(m) = &self.metrics {
                         Metrics::inc(&m.resolver_cache_hits);
+                        if let Some(counter) = m.resolver_cache_hits_by_resolver.get(resolver_idx) {
+                            Metrics::inc(counter);
+                        }
                     }
                     debug!(conn_id, resolver = %def.name, "cache hit");
                     for (session_var, col_name) in &def.inject {
@@ -270,7 +580,9 @@ impl ResolverEngine {
                 }
             }
             let result =
-                match execute_resolver(server, server_buf, def, &input_values, conn_id).await {
+                match execute_resolver(server, server_buf, client, def, &input_values, conn_id)
+                    .await
+                {
                     Ok(r) => r,
                     Err(e) => {
                         if let Some(m) = &self.metrics
@@ -334,6 +646,11 @@ impl ResolverEngine {
             }
         }
 
+        // Startup params are resolver inputs only — never SET them on the session
+        for name in from_startup.keys() {
+            context.remove(name);
+        }
+
         Ok(())
     }
 
@@ -355,21 +672,27 @@ impl ResolverEngine {
 }
 
 /// Execute a single resolver query. Returns Ok(Some(row)) for first row,
-/// Ok(None) for zero rows, or Err on SQL error.
-async fn execute_resolver(
-    server: &mut UpstreamStream,
+/// Ok(None) for zero rows, or Err on SQL error. NoticeResponses raised by
+/// the query are forwarded to `client`.
+async fn execute_resolver<S, C>(
+    server: &mut S,
     server_buf: &mut BytesMut,
+    client: &mut C,
     def: &ResolverDef,
     input_values: &[Option<String>],
     conn_id: u64,
-) -> Result<Option<HashMap<String, String>>, io::Error> {
+) -> Result<Option<HashMap<String, String>>, io::Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    C: AsyncWrite + Unpin,
+{
     let sql = substitute_params(&def.query, input_values)?;
     debug!(conn_id, resolver = %def.name, sql = %sql, "executing resolver");
 
     let query_msg = build_query_message(&sql);
     server.write_all(&query_msg).await?;
 
-    let mut column_names: Vec<String> = Vec::new();
+    let mut fields: Vec<FieldDescriptor> = Vec::new();
     let mut first_row: Option<HashMap<String, String>> = None;
 
     loop {
@@ -386,12 +709,12 @@ async fn execute_resolver(
         while let Some(msg) = try_read_backend_message(server_buf) {
             match msg.msg_type {
                 backend::ROW_DESCRIPTION => {
-                    column_names = parse_row_description(&msg.payload);
-                    debug!(conn_id, resolver = %def.name, columns = ?column_names, "RowDescription");
+                    fields = parse_row_description(&msg.payload);
+                    debug!(conn_id, resolver = %def.name, columns = ?fields, "RowDescription");
                 }
                 backend::DATA_ROW => {
                     if first_row.is_none() {
-                        first_row = Some(parse_data_row(&msg.payload, &column_names));
+                        first_row = Some(parse_data_row(&msg.payload, &fields));
                     }
                     // Ignore subsequent rows
                 }
@@ -408,15 +731,19 @@ async fn execute_resolver(
                         def.name, err_msg
                     )));
                 }
-                _ => {} // NoticeResponse, etc.
+                backend::NOTICE_RESPONSE => {
+                    debug!(conn_id, resolver = %def.name, "forwarding NoticeResponse");
+                    client.write_all(&msg.raw).await?;
+                }
+                _ => {} // ParameterStatus, etc.
             }
         }
     }
 }
 
 /// Consume messages until ReadyForQuery (used after ErrorResponse).
-async fn drain_to_ready(
-    server: &mut UpstreamStream,
+async fn drain_to_ready<S: AsyncRead + AsyncWrite + Unpin>(
+    server: &mut S,
     server_buf: &mut BytesMut,
 ) -> Result<(), io::Error> {
     loop {
@@ -439,18 +766,32 @@ async fn drain_to_ready(
 
 // ─── Parameter Substitution ─────────────────────────────────────────────────
 
-/// Replace $1, $2, ... in SQL with escaped literal values.
-/// Replaces in reverse order so $10 doesn't collide with $1.
+/// Replace $1, $2, ... in SQL with escaped literal values; `None` (a NULL or
+/// absent optional param) becomes a bare `NULL`, while a value that is the
+/// string "NULL" stays a quoted literal.
+/// Single pass over `sql`, so `$10` is never read as `$1` followed by `0`,
+/// and a value that itself contains `$2` is not substituted again.
+/// Placeholders beyond `values` are left as written.
 fn substitute_params(sql: &str, values: &[Option<String>]) -> Result<String, io::Error> {
-    let mut result = sql.to_string();
-    for i in (0..values.len()).rev() {
-        let placeholder = format!("${}", i + 1);
-        let replacement = match &values[i] {
-            Some(val) => escape_set_value(val),
-            None => "NULL".to_string(),
-        };
-        result = result.replace(&placeholder, &replacement);
+    let mut result = String::with_capacity(sql.len());
+    let mut rest = sql;
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let digits = after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let value = after[..digits]
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|i| values.get(i));
+        match value {
+            Some(Some(val)) => result.push_str(&escape_set_value(val)),
+            Some(None) => result.push_str("NULL"),
+            None => result.push_str(&rest[pos..pos + 1 + digits]),
+        }
+        rest = &after[digits..];
     }
+    result.push_str(rest);
     Ok(result)
 }
 
@@ -466,13 +807,26 @@ fn make_cache_key(resolver_name: &str, input_values: &[Option<String>]) -> (Stri
 
 // ─── Wire Protocol Parsing ──────────────────────────────────────────────────
 
-/// Parse column names from a RowDescription message payload.
-fn parse_row_description(payload: &[u8]) -> Vec<String> {
+/// One column of a RowDescription message. Type information is not used
+/// yet (values are always read as text) but is kept for binary format support.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
+struct FieldDescriptor {
+    name: String,
+    type_oid: u32,
+    type_modifier: i32,
+    /// 0 = text, 1 = binary. Resolver queries use the simple protocol,
+    /// so this is always 0 today.
+    format_code: i16,
+}
+
+/// Parse field descriptors from a RowDescription message payload.
+fn parse_row_description(payload: &[u8]) -> Vec<FieldDescriptor> {
     if payload.len() < 2 {
         return Vec::new();
     }
     let field_count = i16::from_be_bytes([payload[0], payload[1]]) as usize;
-    let mut names = Vec::with_capacity(field_count);
+    let mut fields = Vec::with_capacity(field_count);
     let mut offset = 2;
 
     for _ in 0..field_count {
@@ -486,17 +840,26 @@ fn parse_row_description(payload: &[u8]) -> Vec<String> {
             .unwrap_or(payload.len());
         let name = String::from_utf8_lossy(&payload[offset..name_end]).to_string();
         offset = name_end + 1;
-        // Skip: table_oid(4) + col_num(2) + type_oid(4) + type_size(2) + type_modifier(4) + format_code(2) = 18
+        // table_oid(4) + col_num(2) + type_oid(4) + type_size(2) + type_modifier(4) + format_code(2) = 18
+        if offset + 18 > payload.len() {
+            break;
+        }
+        let field = &payload[offset..offset + 18];
+        fields.push(FieldDescriptor {
+            name,
+            type_oid: u32::from_be_bytes([field[6], field[7], field[8], field[9]]),
+            type_modifier: i32::from_be_bytes([field[12], field[13], field[14], field[15]]),
+            format_code: i16::from_be_bytes([field[16], field[17]]),
+        });
         offset += 18;
-        names.push(name);
     }
 
-    names
+    fields
 }
 
 /// Parse a DataRow message payload into a map of column_name -> value.
 /// NULL values (length = -1) are omitted from the map.
-fn parse_data_row(payload: &[u8], column_names: &[String]) -> HashMap<String, String> {
+fn parse_data_row(payload: &[u8], fields: &[FieldDescriptor]) -> HashMap<String, String> {
     let mut map = HashMap::new();
     if payload.len() < 2 {
         return map;
@@ -527,8 +890,8 @@ fn parse_data_row(payload: &[u8], column_names: &[String]) -> HashMap<String, St
         let value = String::from_utf8_lossy(&payload[offset..offset + len]).to_string();
         offset += len;
 
-        if i < column_names.len() {
-            map.insert(column_names[i].clone(), value);
+        if let Some(field) = fields.get(i) {
+            map.insert(field.name.clone(), value);
         }
     }
 
@@ -540,19 +903,305 @@ fn parse_data_row(payload: &[u8], column_names: &[String]) -> HashMap<String, St
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::MockStreamBuilder;
 
     fn make_def(name: &str, deps: &[&str]) -> ResolverDef {
         ResolverDef {
             name: name.to_string(),
             query: String::new(),
             params: vec![],
+            param_optional: vec![],
             inject: vec![],
             required: false,
             depends_on: deps.iter().map(|s| s.to_string()).collect(),
+            overrides: vec![],
             cache_ttl: Duration::ZERO,
+            from_startup_params: vec![],
         }
     }
 
+    fn write_resolver_file(name: &str, toml: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "pgvpd-resolvers-{}-{name}.toml",
+            std::process::id()
+        ));
+        std::fs::write(&path, toml).unwrap();
+        path
+    }
+
+    // ─── from_startup_params ─────────────────────────────────────────────
+
+    #[test]
+    fn test_startup_param_reaches_resolver_query() {
+        use crate::protocol::{
+            DEFAULT_MAX_STARTUP_MESSAGE_BYTES, StartupType, build_startup_message, try_read_startup,
+        };
+
+        let path = write_resolver_file(
+            "startup",
+            r#"
+[[resolver]]
+name = "deployment"
+query = "SELECT region FROM deployments WHERE id = $1 AND tenant = $2"
+params = ["myapp.deployment_id", "app.current_tenant_id"]
+from_startup_params = ["myapp.deployment_id"]
+inject = { "app.region" = "region" }
+"#,
+        );
+        let engine = load_resolvers(
+            path.to_str().unwrap(),
+            &["app.current_tenant_id".to_string()],
+            None,
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut params = HashMap::new();
+        params.insert("user".to_string(), "app_user.acme".to_string());
+        params.insert("myapp.deployment_id".to_string(), "d-42".to_string());
+        let mut buf = build_startup_message(&params);
+        let Some(StartupType::Startup(msg)) =
+            try_read_startup(&mut buf, DEFAULT_MAX_STARTUP_MESSAGE_BYTES)
+        else {
+            panic!("expected Startup");
+        };
+
+        let startup = engine.startup_context(&msg.params);
+        assert_eq!(
+            startup.get("myapp.deployment_id"),
+            Some(&Some("d-42".to_string()))
+        );
+
+        // The resolver's bind params see the startup value alongside static context
+        let mut context: HashMap<String, Option<String>> = startup;
+        context.insert("app.current_tenant_id".into(), Some("acme".into()));
+        let def = &engine.resolvers[0];
+        let inputs: Vec<Option<String>> = def.params.iter().map(|p| context[p].clone()).collect();
+        let sql = substitute_params(&def.query, &inputs).unwrap();
+        assert_eq!(
+            sql,
+            "SELECT region FROM deployments WHERE id = 'd-42' AND tenant = 'acme'"
+        );
+    }
+
+    #[test]
+    fn test_startup_param_missing_is_null() {
+        let mut def = make_def("r", &[]);
+        def.from_startup_params = vec!["myapp.deployment_id".into()];
+        let engine = ResolverEngine {
+            resolvers: vec![def],
+            cache: Mutex::new(HashMap::new()),
+            metrics: None,
+        };
+        let ctx = engine.startup_context(&HashMap::new());
+        assert_eq!(ctx.get("myapp.deployment_id"), Some(&None));
+    }
+
+    #[test]
+    fn test_startup_param_colliding_with_context_variable_rejected() {
+        let path = write_resolver_file(
+            "collide",
+            r#"
+[[resolver]]
+name = "r"
+query = "SELECT 1 AS x"
+from_startup_params = ["app.current_tenant_id"]
+inject = { "app.x" = "x" }
+"#,
+        );
+        let err = load_resolvers(
+            path.to_str().unwrap(),
+            &["app.current_tenant_id".to_string()],
+            None,
+        )
+        .err()
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.contains("collides with a context variable"));
+    }
+
+    // ─── param_optional ──────────────────────────────────────────────────
+
+    #[test]
+    fn test_param_optional_parsed() {
+        let path = write_resolver_file(
+            "optional",
+            r#"
+[[resolver]]
+name = "scope"
+query = "SELECT scope FROM scopes WHERE tenant = $1 AND team = $2"
+params = ["app.current_tenant_id", "app.team_id"]
+param_optional = ["app.team_id"]
+inject = { "app.scope" = "scope" }
+"#,
+        );
+        let engine = load_resolvers(
+            path.to_str().unwrap(),
+            &[
+                "app.current_tenant_id".to_string(),
+                "app.team_id".to_string(),
+            ],
+            None,
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(engine.resolvers[0].param_optional, vec!["app.team_id"]);
+    }
+
+    #[test]
+    fn test_param_optional_must_be_a_param() {
+        let problems = validate_resolver_file(
+            r#"
+[[resolver]]
+name = "scope"
+query = "SELECT scope FROM scopes WHERE tenant = $1"
+params = ["app.current_tenant_id"]
+param_optional = ["app.team_id"]
+inject = { "app.scope" = "scope" }
+"#,
+            &["app.current_tenant_id".to_string()],
+        );
+        assert_eq!(
+            problems,
+            vec!["resolver 'scope': param_optional 'app.team_id' is not listed in 'params'"]
+        );
+    }
+
+    fn scope_engine() -> ResolverEngine {
+        ResolverEngine {
+            resolvers: vec![ResolverDef {
+                query: "SELECT scope FROM scopes WHERE tenant = $1 AND team = $2".to_string(),
+                params: vec!["app.tenant".to_string(), "app.team".to_string()],
+                param_optional: vec!["app.team".to_string()],
+                inject: vec![("app.scope".to_string(), "scope".to_string())],
+                ..make_def("scope", &[])
+            }],
+            cache: Mutex::new(HashMap::new()),
+            metrics: None,
+        }
+    }
+
+    async fn resolve_scope(context: &mut HashMap<String, Option<String>>) -> String {
+        let mut server = MockStreamBuilder::new()
+            .row_description(&["scope"])
+            .data_row(&[Some("s-1")])
+            .command_complete("SELECT 1")
+            .ready_for_query()
+            .build();
+        scope_engine()
+            .resolve_context(
+                &mut server,
+                &mut BytesMut::new(),
+                &mut Vec::<u8>::new(),
+                context,
+                &HashMap::new(),
+                1,
+            )
+            .await
+            .unwrap();
+        String::from_utf8_lossy(&server.written).into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_optional_param_absent_binds_null() {
+        let mut context = HashMap::from([("app.tenant".to_string(), Some("acme".to_string()))]);
+        let sql = resolve_scope(&mut context).await;
+        assert!(sql.contains("tenant = 'acme' AND team = NULL"), "{sql}");
+        assert_eq!(context["app.scope"].as_deref(), Some("s-1"));
+
+        // NULL is the same as absent
+        let mut context = HashMap::from([
+            ("app.tenant".to_string(), Some("acme".to_string())),
+            ("app.team".to_string(), None),
+        ]);
+        let sql = resolve_scope(&mut context).await;
+        assert!(sql.contains("team = NULL"), "{sql}");
+    }
+
+    #[tokio::test]
+    async fn test_optional_param_present_binds_value() {
+        let mut context = HashMap::from([
+            ("app.tenant".to_string(), Some("acme".to_string())),
+            ("app.team".to_string(), Some("red".to_string())),
+        ]);
+        let sql = resolve_scope(&mut context).await;
+        assert!(sql.contains("tenant = 'acme' AND team = 'red'"), "{sql}");
+    }
+
+    #[tokio::test]
+    async fn test_required_param_null_still_skips() {
+        let mut context = HashMap::from([("app.tenant".to_string(), None)]);
+        let sql = resolve_scope(&mut context).await;
+        assert!(sql.is_empty(), "{sql}");
+        assert_eq!(context["app.scope"], None);
+    }
+
+    // ─── validate_resolver_file ──────────────────────────────────────────
+
+    #[test]
+    fn test_validate_resolver_file_accepts_valid_chain() {
+        let problems = validate_resolver_file(
+            r#"
+[[resolver]]
+name = "org"
+query = "SELECT org_id FROM m WHERE user_id = $1"
+params = ["app.user_id"]
+inject = { "app.org_id" = "org_id" }
+
+[[resolver]]
+name = "team"
+query = "SELECT team_ids FROM t WHERE org_id = $1"
+params = ["app.org_id"]
+inject = { "app.team_ids" = "team_ids" }
+depends_on = ["org"]
+cache_ttl = 60
+"#,
+            &["app.user_id".to_string()],
+        );
+        assert!(problems.is_empty(), "{problems:?}");
+    }
+
+    #[test]
+    fn test_validate_resolver_file_reports_all_problems() {
+        let problems = validate_resolver_file(
+            r#"
+[[resolver]]
+query = ""
+injectx = { "app.x" = "x" }
+
+[[resolver]]
+name = "r"
+query = "SELECT 1 AS x"
+params = ["app.nope"]
+inject = { "app.y" = "" }
+depends_on = ["ghost"]
+cache_ttl = -5
+"#,
+            &["app.user_id".to_string()],
+        );
+        let expected = [
+            "resolver #1: missing or empty 'name'",
+            "resolver #1: unknown key 'injectx'",
+            "resolver #1: missing or empty 'query'",
+            "resolver #1: missing or empty 'inject'",
+            "resolver 'r': inject 'app.y' must map to a non-empty column name",
+            "resolver 'r': param 'app.nope' is not a context variable",
+            "resolver 'r' depends on 'ghost' which does not exist",
+            "resolver 'r': 'cache_ttl' must be a non-negative integer",
+        ];
+        assert_eq!(problems.len(), expected.len(), "{problems:?}");
+        for (problem, want) in problems.iter().zip(expected) {
+            assert!(problem.starts_with(want), "{problem} vs {want}");
+        }
+    }
+
+    #[test]
+    fn test_validate_resolver_file_syntax_error() {
+        let problems = validate_resolver_file("[[resolver]\nname = ", &[]);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("invalid TOML"));
+    }
+
     #[test]
     fn test_topological_sort_simple_chain() {
         let defs = vec![
@@ -578,6 +1227,83 @@ mod tests {
         assert_eq!(sorted.len(), 2);
     }
 
+    // ─── check_inject_conflicts ──────────────────────────────────────────
+
+    fn injecting(name: &str, deps: &[&str], vars: &[&str]) -> ResolverDef {
+        ResolverDef {
+            inject: vars
+                .iter()
+                .map(|v| (v.to_string(), "col".to_string()))
+                .collect(),
+            ..make_def(name, deps)
+        }
+    }
+
+    #[test]
+    fn test_inject_conflict_between_independent_resolvers() {
+        let defs = vec![
+            injecting("a", &[], &["app.org_id"]),
+            injecting("b", &[], &["app.org_id", "app.team"]),
+        ];
+        assert_eq!(
+            check_inject_conflicts(&defs).unwrap_err(),
+            "resolver 'b' injects into 'app.org_id' which is already injected by resolver 'a'"
+        );
+    }
+
+    #[test]
+    fn test_inject_conflict_in_chain_needs_overrides() {
+        let mut defs = vec![
+            injecting("a", &[], &["app.org_id"]),
+            injecting("b", &["a"], &["app.team"]),
+            injecting("c", &["b"], &["app.org_id"]),
+        ];
+        assert!(check_inject_conflicts(&defs).is_err());
+
+        // An explicit override of a (transitive) parent's output is fine
+        defs[2].overrides = vec!["app.org_id".into()];
+        assert!(check_inject_conflicts(&defs).is_ok());
+    }
+
+    #[test]
+    fn test_overrides_must_name_a_parent_output() {
+        let mut defs = vec![
+            injecting("a", &[], &["app.org_id"]),
+            injecting("b", &[], &["app.org_id"]),
+        ];
+        defs[1].overrides = vec!["app.org_id".into()];
+        assert!(
+            check_inject_conflicts(&defs)
+                .unwrap_err()
+                .contains("which no resolver it depends on injects")
+        );
+    }
+
+    #[test]
+    fn test_load_resolvers_rejects_inject_conflict() {
+        let path = write_resolver_file(
+            "conflict",
+            r#"
+[[resolver]]
+name = "org"
+query = "SELECT org_id FROM m WHERE user_id = $1"
+params = ["app.user_id"]
+inject = { "app.org_id" = "org_id" }
+
+[[resolver]]
+name = "legacy_org"
+query = "SELECT org_id FROM legacy WHERE user_id = $1"
+params = ["app.user_id"]
+inject = { "app.org_id" = "org_id" }
+"#,
+        );
+        let err = load_resolvers(path.to_str().unwrap(), &["app.user_id".to_string()], None)
+            .err()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.contains("already injected by resolver"), "{err}");
+    }
+
     #[test]
     fn test_substitute_params() {
         let sql = "SELECT * FROM t WHERE a = $1 AND b = $2";
@@ -594,6 +1320,34 @@ mod tests {
         assert_eq!(result, "SELECT * FROM t WHERE a = 'it''s'");
     }
 
+    #[test]
+    fn test_substitute_params_null() {
+        let sql = "SELECT * FROM t WHERE a = $1 AND b = $2";
+        let vals = vec![None, Some("NULL".to_string())];
+        let result = substitute_params(sql, &vals).unwrap();
+        assert_eq!(result, "SELECT * FROM t WHERE a = NULL AND b = 'NULL'");
+    }
+
+    #[test]
+    fn test_substitute_params_values_not_resubstituted() {
+        // A value containing a placeholder must stay inside its own literal
+        let sql = "SELECT * FROM t WHERE a = $1 AND b = $2";
+        let vals = vec![Some("x' OR true --".to_string()), Some("$1".to_string())];
+        let result = substitute_params(sql, &vals).unwrap();
+        assert_eq!(
+            result,
+            "SELECT * FROM t WHERE a = 'x'' OR true --' AND b = '$1'"
+        );
+    }
+
+    #[test]
+    fn test_substitute_params_two_digit_placeholders() {
+        let sql = "SELECT $1, $10, $11, $$body$$, $";
+        let vals: Vec<_> = (1..=10).map(|i| Some(format!("v{i}"))).collect();
+        let result = substitute_params(sql, &vals).unwrap();
+        assert_eq!(result, "SELECT 'v1', 'v10', $11, $$body$$, $");
+    }
+
     #[test]
     fn test_substitute_params_array_literal() {
         let sql = "SELECT * FROM t WHERE a = ANY($1::uuid[])";
@@ -608,8 +1362,8 @@ mod tests {
         payload.extend_from_slice(&1i16.to_be_bytes()); // 1 field
         payload.extend_from_slice(b"org_id\0");
         payload.extend_from_slice(&[0u8; 18]); // field descriptor
-        let names = parse_row_description(&payload);
-        assert_eq!(names, vec!["org_id"]);
+        let fields = parse_row_description(&payload);
+        assert_eq!(fields, vec![text_field("org_id")]);
     }
 
     #[test]
@@ -620,17 +1374,63 @@ mod tests {
         payload.extend_from_slice(&[0u8; 18]);
         payload.extend_from_slice(b"role\0");
         payload.extend_from_slice(&[0u8; 18]);
-        let names = parse_row_description(&payload);
+        let fields = parse_row_description(&payload);
+        let names: Vec<&str> = fields.iter().map(|f| f.name.as_str()).collect();
         assert_eq!(names, vec!["org_id", "role"]);
     }
 
+    fn text_field(name: &str) -> FieldDescriptor {
+        FieldDescriptor {
+            name: name.to_string(),
+            type_oid: 0,
+            type_modifier: 0,
+            format_code: 0,
+        }
+    }
+
+    fn push_field(payload: &mut Vec<u8>, name: &str, type_oid: u32, type_modifier: i32) {
+        payload.extend_from_slice(name.as_bytes());
+        payload.push(0);
+        payload.extend_from_slice(&16384u32.to_be_bytes()); // table oid
+        payload.extend_from_slice(&1i16.to_be_bytes()); // column number
+        payload.extend_from_slice(&type_oid.to_be_bytes());
+        payload.extend_from_slice(&(-1i16).to_be_bytes()); // type size
+        payload.extend_from_slice(&type_modifier.to_be_bytes());
+        payload.extend_from_slice(&0i16.to_be_bytes()); // text format
+    }
+
+    #[test]
+    fn test_parse_row_description_type_info() {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&2i16.to_be_bytes());
+        push_field(&mut payload, "org_id", 23, -1); // int4
+        push_field(&mut payload, "label", 1043, 68); // varchar(64): typmod = 64 + 4
+        let fields = parse_row_description(&payload);
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].type_oid, 23);
+        assert_eq!(fields[0].type_modifier, -1);
+        assert_eq!(fields[1].name, "label");
+        assert_eq!(fields[1].type_oid, 1043);
+        assert_eq!(fields[1].type_modifier, 68);
+        assert_eq!(fields[1].format_code, 0);
+    }
+
+    #[test]
+    fn test_parse_row_description_truncated_field() {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&1i16.to_be_bytes());
+        payload.extend_from_slice(b"org_id\0");
+        payload.extend_from_slice(&[0u8; 10]); // descriptor cut short
+        assert!(parse_row_description(&payload).is_empty());
+    }
+
     #[test]
     fn test_parse_data_row() {
         let mut payload = Vec::new();
         payload.extend_from_slice(&1i16.to_be_bytes()); // 1 field
         payload.extend_from_slice(&3i32.to_be_bytes()); // length 3
         payload.extend_from_slice(b"abc");
-        let cols = vec!["org_id".to_string()];
+        let cols = vec![text_field("org_id")];
         let row = parse_data_row(&payload, &cols);
         assert_eq!(row.get("org_id").unwrap(), "abc");
     }
@@ -640,7 +1440,7 @@ mod tests {
         let mut payload = Vec::new();
         payload.extend_from_slice(&1i16.to_be_bytes());
         payload.extend_from_slice(&(-1i32).to_be_bytes()); // NULL
-        let cols = vec!["org_id".to_string()];
+        let cols = vec![text_field("org_id")];
         let row = parse_data_row(&payload, &cols);
         assert!(row.get("org_id").is_none());
     }
@@ -653,9 +1453,212 @@ mod tests {
         payload.extend_from_slice(b"org-1");
         payload.extend_from_slice(&5i32.to_be_bytes());
         payload.extend_from_slice(b"admin");
-        let cols = vec!["org_id".to_string(), "role".to_string()];
+        let cols = vec![text_field("org_id"), text_field("role")];
         let row = parse_data_row(&payload, &cols);
         assert_eq!(row.get("org_id").unwrap(), "org-1");
         assert_eq!(row.get("role").unwrap(), "admin");
     }
+
+    // ─── execute_resolver ────────────────────────────────────────────────
+
+    fn org_resolver() -> ResolverDef {
+        ResolverDef {
+            query: "SELECT org_id FROM members WHERE user_id = $1".to_string(),
+            params: vec!["app.user_id".to_string()],
+            ..make_def("org", &[])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_resolver_zero_rows() {
+        let mut server = MockStreamBuilder::new()
+            .row_description(&["org_id"])
+            .command_complete("SELECT 0")
+            .ready_for_query()
+            .build();
+        let mut buf = BytesMut::new();
+        let mut client = Vec::new();
+        let vals = vec![Some("u1".to_string())];
+        let row = execute_resolver(
+            &mut server,
+            &mut buf,
+            &mut client,
+            &org_resolver(),
+            &vals,
+            1,
+        )
+        .await
+        .unwrap();
+        assert!(row.is_none());
+        // Query message carries the substituted SQL
+        assert_eq!(server.written[0], b'Q');
+        let sql = String::from_utf8_lossy(&server.written[5..]);
+        assert!(sql.contains("user_id = 'u1'"));
+    }
+
+    #[tokio::test]
+    async fn test_execute_resolver_one_row() {
+        let mut server = MockStreamBuilder::new()
+            .row_description(&["org_id"])
+            .data_row(&[Some("org-1")])
+            .command_complete("SELECT 1")
+            .ready_for_query()
+            .build();
+        let mut buf = BytesMut::new();
+        let mut client = Vec::new();
+        let row = execute_resolver(
+            &mut server,
+            &mut buf,
+            &mut client,
+            &org_resolver(),
+            &[None],
+            1,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(row.get("org_id").unwrap(), "org-1");
+    }
+
+    #[tokio::test]
+    async fn test_execute_resolver_takes_first_of_many_rows() {
+        let mut server = MockStreamBuilder::new()
+            .row_description(&["org_id"])
+            .data_row(&[Some("org-1")])
+            .data_row(&[Some("org-2")])
+            .command_complete("SELECT 2")
+            .ready_for_query()
+            .build();
+        let mut buf = BytesMut::new();
+        let mut client = Vec::new();
+        let row = execute_resolver(
+            &mut server,
+            &mut buf,
+            &mut client,
+            &org_resolver(),
+            &[None],
+            1,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(row.get("org_id").unwrap(), "org-1");
+        assert!(buf.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_execute_resolver_sql_error() {
+        let mut server = MockStreamBuilder::new()
+            .error_response("42P01", "relation \"members\" does not exist")
+            .ready_for_query()
+            .build();
+        let mut buf = BytesMut::new();
+        let mut client = Vec::new();
+        let err = execute_resolver(
+            &mut server,
+            &mut buf,
+            &mut client,
+            &org_resolver(),
+            &[None],
+            1,
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("relation \"members\" does not exist")
+        );
+        // Drained through ReadyForQuery so the connection stays usable
+        assert!(buf.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_execute_resolver_upstream_eof() {
+        let mut server = MockStreamBuilder::new()
+            .row_description(&["org_id"])
+            .build();
+        let mut buf = BytesMut::new();
+        let mut client = Vec::new();
+        let err = execute_resolver(
+            &mut server,
+            &mut buf,
+            &mut client,
+            &org_resolver(),
+            &[None],
+            1,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn test_execute_resolver_forwards_notice() {
+        let mut server = MockStreamBuilder::new()
+            .notice_response("resolver notice")
+            .row_description(&["org_id"])
+            .data_row(&[Some("org-1")])
+            .command_complete("SELECT 1")
+            .ready_for_query()
+            .build();
+        let mut buf = BytesMut::new();
+        let mut client = Vec::new();
+        let row = execute_resolver(
+            &mut server,
+            &mut buf,
+            &mut client,
+            &org_resolver(),
+            &[None],
+            1,
+        )
+        .await
+        .unwrap();
+        assert!(row.is_some());
+        // Only the notice reaches the client
+        assert_eq!(client[0], backend::NOTICE_RESPONSE);
+        let len = i32::from_be_bytes([client[1], client[2], client[3], client[4]]) as usize;
+        assert_eq!(client.len(), 1 + len);
+        assert!(String::from_utf8_lossy(&client).contains("resolver notice"));
+    }
+
+    #[tokio::test]
+    async fn test_cache_entries_debug_redacts_values() {
+        let engine = ResolverEngine {
+            resolvers: vec![],
+            cache: Mutex::new(HashMap::new()),
+            metrics: None,
+        };
+        {
+            let mut cache = engine.cache.lock().await;
+            cache.insert(
+                ("org".into(), 42),
+                CacheEntry {
+                    values: HashMap::from([
+                        ("org_id".to_string(), Some("org-123".to_string())),
+                        ("role".to_string(), None),
+                    ]),
+                    expires_at: Instant::now() + Duration::from_secs(60),
+                },
+            );
+            cache.insert(
+                ("stale".into(), 7),
+                CacheEntry {
+                    values: HashMap::new(),
+                    expires_at: Instant::now() - Duration::from_secs(1),
+                },
+            );
+        }
+
+        let entries = engine.cache_entries_debug().await;
+        assert_eq!(entries.len(), 1);
+        let e = &entries[0];
+        assert_eq!(e.resolver_name, "org");
+        assert_eq!(e.key_hash, 42);
+        assert_eq!(
+            e.columns,
+            vec![("org_id".to_string(), Some(7)), ("role".to_string(), None)]
+        );
+        assert_eq!(e.value_count, 1);
+        assert!(e.ttl_remaining_secs > 50 && e.ttl_remaining_secs <= 60);
+    }
 }
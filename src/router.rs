@@ -0,0 +1,184 @@
+//! Read/write routing — picks the upstream server for a session.
+//!
+//! With `routing_mode = replica_reads`, sessions that ask for read-only
+//! transactions in their StartupMessage (`default_transaction_read_only=on`,
+//! directly or through `options`) are spread round-robin across `upstream_replica_hosts`; everything else
+//! goes to the primary. Routing is per session, not per statement: a session's
+//! SET ROLE and injected context live on one upstream connection, so the
+//! whole session has to stay there.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::config::{Config, RoutingMode};
+
+/// The upstream server a session (or pool bucket) is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Upstream {
+    Primary,
+    /// Index into `upstream_replica_hosts`.
+    Replica(usize),
+}
+
+impl fmt::Display for Upstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Primary => write!(f, "primary"),
+            Self::Replica(i) => write!(f, "replica{i}"),
+        }
+    }
+}
+
+/// Routes sessions between the primary and read replicas.
+pub struct ReadWriteRouter<'a> {
+    config: &'a Config,
+}
+
+impl<'a> ReadWriteRouter<'a> {
+    pub fn new(config: &'a Config) -> Self {
+        Self { config }
+    }
+
+    /// Upstream for a new session. Replicas are picked by `conn_id`, which
+    /// round-robins without shared state.
+    pub fn route(&self, startup_params: &HashMap<String, String>, conn_id: u64) -> Upstream {
+        let replicas = self.config.upstream_replica_hosts.len();
+        if self.config.routing_mode == RoutingMode::PrimaryOnly
+            || replicas == 0
+            || !wants_read_only(startup_params)
+        {
+            return Upstream::Primary;
+        }
+        Upstream::Replica((conn_id % replicas as u64) as usize)
+    }
+
+    /// Host and port of `upstream`. Unknown replicas fall back to the primary.
+    pub fn addr(&self, upstream: Upstream) -> (&'a str, u16) {
+        let primary = (
+            self.config.upstream_host.as_str(),
+            self.config.upstream_port,
+        );
+        match upstream {
+            Upstream::Primary => primary,
+            Upstream::Replica(i) => self
+                .config
+                .upstream_replica_hosts
+                .get(i)
+                .and_then(|entry| parse_host_port(entry, self.config.upstream_port))
+                .unwrap_or(primary),
+        }
+    }
+}
+
+/// Whether the client asked for a read-only session, either as its own
+/// startup parameter or via `options` (`-c default_transaction_read_only=on`,
+/// which is how libpq clients have to send it).
+fn wants_read_only(startup_params: &HashMap<String, String>) -> bool {
+    let is_on = |v: &str| matches!(v.to_lowercase().as_str(), "on" | "true" | "yes" | "1");
+    if let Some(v) = startup_params.get("default_transaction_read_only") {
+        return is_on(v);
+    }
+    let Some(options) = startup_params.get("options") else {
+        return false;
+    };
+    options.split_whitespace().any(|token| {
+        let setting = token
+            .strip_prefix("--")
+            .or_else(|| token.strip_prefix("-c"))
+            .unwrap_or(token);
+        setting.split_once('=').is_some_and(|(name, value)| {
+            name.replace('-', "_") == "default_transaction_read_only" && is_on(value)
+        })
+    })
+}
+
+/// Split `host[:port]` (IPv6 as `[addr]:port`). Returns None for a bad port.
+pub fn parse_host_port(entry: &str, default_port: u16) -> Option<(&str, u16)> {
+    match entry.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            let port = port.parse().ok()?;
+            (!host.is_empty()).then_some((host, port))
+        }
+        _ => {
+            let host = entry.trim_start_matches('[').trim_end_matches(']');
+            (!host.is_empty()).then_some((host, default_port))
+        }
+    }
+}
+
+// ─── Tests ──────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replica_config() -> Config {
+        Config {
+            routing_mode: RoutingMode::ReplicaReads,
+            upstream_replica_hosts: vec!["replica1:5433".into(), "replica2".into()],
+            ..Config::default()
+        }
+    }
+
+    fn read_only_params() -> HashMap<String, String> {
+        HashMap::from([("default_transaction_read_only".into(), "on".into())])
+    }
+
+    #[test]
+    fn read_only_sessions_round_robin_replicas() {
+        let config = replica_config();
+        let router = ReadWriteRouter::new(&config);
+        assert_eq!(router.route(&read_only_params(), 4), Upstream::Replica(0));
+        assert_eq!(router.route(&read_only_params(), 5), Upstream::Replica(1));
+        assert_eq!(router.addr(Upstream::Replica(0)), ("replica1", 5433));
+        assert_eq!(router.addr(Upstream::Replica(1)), ("replica2", 5432));
+    }
+
+    #[test]
+    fn read_only_via_options() {
+        let config = replica_config();
+        let router = ReadWriteRouter::new(&config);
+        for options in [
+            "-c default_transaction_read_only=on",
+            "-cdefault_transaction_read_only=true",
+            "--default-transaction-read-only=on",
+        ] {
+            let params = HashMap::from([("options".to_string(), options.to_string())]);
+            assert_eq!(router.route(&params, 0), Upstream::Replica(0), "{options}");
+        }
+        let params = HashMap::from([(
+            "options".to_string(),
+            "-c default_transaction_read_only=off".to_string(),
+        )]);
+        assert_eq!(router.route(&params, 0), Upstream::Primary);
+    }
+
+    #[test]
+    fn read_write_sessions_use_primary() {
+        let config = replica_config();
+        let router = ReadWriteRouter::new(&config);
+        assert_eq!(router.route(&HashMap::new(), 1), Upstream::Primary);
+        assert_eq!(router.addr(Upstream::Primary), ("127.0.0.1", 5432));
+    }
+
+    #[test]
+    fn primary_only_ignores_read_only_hint() {
+        let config = Config {
+            routing_mode: RoutingMode::PrimaryOnly,
+            ..replica_config()
+        };
+        let router = ReadWriteRouter::new(&config);
+        assert_eq!(router.route(&read_only_params(), 1), Upstream::Primary);
+    }
+
+    #[test]
+    fn parse_host_port_forms() {
+        assert_eq!(parse_host_port("db", 5432), Some(("db", 5432)));
+        assert_eq!(parse_host_port("db:6543", 5432), Some(("db", 6543)));
+        assert_eq!(parse_host_port("::1", 5432), Some(("::1", 5432)));
+        assert_eq!(parse_host_port("[::1]:6543", 5432), Some(("::1", 6543)));
+        assert_eq!(parse_host_port("db:x", 5432), None);
+        assert_eq!(parse_host_port(":5432", 5432), None);
+    }
+}
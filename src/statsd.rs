@@ -0,0 +1,317 @@
+//! StatsD / DogStatsD export — sends the `Metrics` counters and gauges to a
+//! UDP agent (`statsd_host`, `statsd_port`) every `statsd_flush_interval_ms`.
+//!
+//! Names are `<statsd_prefix>.<name>` with underscores turned into dots.
+//! Counters end in `.total` and carry the change since the previous flush;
+//! labelled series use DogStatsD tags (`|#tenant:acme`). Sends are
+//! fire-and-forget — with no agent listening, the datagrams are just lost.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::debug;
+
+use crate::connection::ConnectionState;
+use crate::metrics::{COUNTERS, GAUGES, Histogram, Metrics};
+
+/// Datagrams stay under a typical Ethernet MTU.
+const MAX_DATAGRAM: usize = 1432;
+
+pub struct StatsdExporter {
+    socket: UdpSocket,
+    prefix: String,
+    metrics: Arc<Metrics>,
+    /// Counter values at the previous flush, keyed by name and tags.
+    last_counters: HashMap<String, u64>,
+    /// Per-tenant duration histogram `(count, sum)` at the previous flush.
+    last_durations: HashMap<String, (u64, f64)>,
+}
+
+impl StatsdExporter {
+    /// Resolve the agent address once and connect a UDP socket to it.
+    pub fn new(host: &str, port: u16, prefix: &str, metrics: Arc<Metrics>) -> io::Result<Self> {
+        let target = (host, port).to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("statsd_host '{host}' did not resolve"),
+            )
+        })?;
+        let local: SocketAddr = if target.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(target)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            prefix: prefix.to_string(),
+            metrics,
+            last_counters: HashMap::new(),
+            last_durations: HashMap::new(),
+        })
+    }
+
+    /// Flush every `interval`, forever.
+    pub async fn run(mut self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await; // the first tick completes immediately
+        loop {
+            ticker.tick().await;
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        let lines = self.collect();
+        for datagram in pack(&lines) {
+            if let Err(e) = self.socket.send(datagram.as_bytes()) {
+                debug!(error = %e, "statsd: send failed");
+            }
+        }
+    }
+
+    /// One line per series with something to report since the last flush.
+    fn collect(&mut self) -> Vec<String> {
+        let m = Arc::clone(&self.metrics);
+        let mut lines = Vec::new();
+
+        for &(name, _, field) in COUNTERS {
+            self.counter(&mut lines, name, &[], load(field(&m)));
+        }
+        for &(name, _, field) in GAUGES {
+            lines.push(format!("{}:{}|g", self.metric_name(name), load(field(&m))));
+        }
+
+        for (reason, counter) in [
+            ("deny", &m.tenant_rejected_deny),
+            ("limit", &m.tenant_rejected_limit),
+            ("rate", &m.tenant_rejected_rate),
+        ] {
+            self.counter(
+                &mut lines,
+                "tenant_rejected",
+                &[("reason", reason)],
+                load(counter),
+            );
+        }
+        for (name, counters) in [
+            ("resolver_executions", &m.resolver_executions),
+            ("resolver_errors", &m.resolver_errors),
+        ] {
+            for (resolver, counter) in m.resolver_names.iter().zip(counters) {
+                self.counter(
+                    &mut lines,
+                    name,
+                    &[("resolver", resolver.as_str())],
+                    load(counter),
+                );
+            }
+        }
+        for from in ConnectionState::ALL {
+            for to in ConnectionState::ALL {
+                self.counter(
+                    &mut lines,
+                    "state_transitions",
+                    &[("from", from.as_str()), ("to", to.as_str())],
+                    m.state_transitions(from, to),
+                );
+            }
+        }
+
+        let durations: Vec<(String, Arc<Histogram>)> = m
+            .tenant_connection_duration
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(tenant, histogram)| (tenant.clone(), Arc::clone(histogram)))
+            .collect();
+        for (tenant, histogram) in durations {
+            let snap = histogram.snapshot();
+            self.counter(
+                &mut lines,
+                "tenant_connections_closed",
+                &[("tenant", tenant.as_str())],
+                snap.count,
+            );
+            let (count, sum) = self
+                .last_durations
+                .insert(tenant.clone(), (snap.count, snap.sum))
+                .unwrap_or((0, 0.0));
+            let n = snap.count.saturating_sub(count);
+            if n == 0 {
+                continue;
+            }
+            // Only bucketed totals are kept, not the observations, so send the
+            // mean of the connections closed since the last flush as a gauge.
+            // A sampled histogram would make the agent invent n identical
+            // values and skew its percentiles.
+            let mean_ms = (snap.sum - sum) / n as f64 * 1000.0;
+            lines.push(format!(
+                "{}:{mean_ms:.3}|g{}",
+                self.metric_name("tenant_connection_duration_ms"),
+                format_tags(&[("tenant", tenant.as_str())]),
+            ));
+        }
+        lines
+    }
+
+    /// Add a counter line for the change since the last flush, if any.
+    fn counter(&mut self, lines: &mut Vec<String>, name: &str, tags: &[(&str, &str)], value: u64) {
+        let tags = format_tags(tags);
+        let previous = self
+            .last_counters
+            .insert(format!("{name}{tags}"), value)
+            .unwrap_or(0);
+        let delta = value.saturating_sub(previous);
+        if delta > 0 {
+            lines.push(format!("{}.total:{delta}|c{tags}", self.metric_name(name)));
+        }
+    }
+
+    fn metric_name(&self, name: &str) -> String {
+        format!("{}.{}", self.prefix, name.replace('_', "."))
+    }
+}
+
+fn load(counter: &AtomicU64) -> u64 {
+    counter.load(Ordering::Relaxed)
+}
+
+/// `|#key:value,...`, or nothing without tags. Characters that would end the
+/// tag list are replaced.
+fn format_tags(tags: &[(&str, &str)]) -> String {
+    if tags.is_empty() {
+        return String::new();
+    }
+    let tags: Vec<String> = tags
+        .iter()
+        .map(|(key, value)| {
+            let value: String = value
+                .chars()
+                .map(|c| {
+                    if matches!(c, ',' | '|' | '#' | '\n') {
+                        '_'
+                    } else {
+                        c
+                    }
+                })
+                .collect();
+            format!("{key}:{value}")
+        })
+        .collect();
+    format!("|#{}", tags.join(","))
+}
+
+/// Join lines into newline-separated datagrams of at most `MAX_DATAGRAM`
+/// bytes (a single longer line gets a datagram of its own).
+fn pack(lines: &[String]) -> Vec<String> {
+    let mut datagrams = Vec::new();
+    let mut current = String::new();
+    for line in lines {
+        if !current.is_empty() && current.len() + 1 + line.len() > MAX_DATAGRAM {
+            datagrams.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(line);
+    }
+    if !current.is_empty() {
+        datagrams.push(current);
+    }
+    datagrams
+}
+
+// ─── Tests ──────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exporter(metrics: Arc<Metrics>) -> (StatsdExporter, UdpSocket) {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        agent
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let port = agent.local_addr().unwrap().port();
+        let exporter = StatsdExporter::new("127.0.0.1", port, "pgvpd", metrics).unwrap();
+        (exporter, agent)
+    }
+
+    #[test]
+    fn counters_send_deltas_and_gauges_current_values() {
+        let metrics = Arc::new(Metrics::new(vec!["org".into()]));
+        let (mut exporter, _agent) = exporter(Arc::clone(&metrics));
+        Metrics::inc(&metrics.connections_total);
+        Metrics::inc(&metrics.connections_total);
+        Metrics::inc(&metrics.connections_active);
+        Metrics::inc(&metrics.tenant_rejected_rate);
+        Metrics::inc(&metrics.resolver_executions[0]);
+
+        let lines = exporter.collect();
+        assert!(lines.contains(&"pgvpd.connections.total:2|c".to_string()));
+        assert!(lines.contains(&"pgvpd.connections.active:1|g".to_string()));
+        assert!(lines.contains(&"pgvpd.tenant.rejected.total:1|c|#reason:rate".to_string()));
+        assert!(lines.contains(&"pgvpd.resolver.executions.total:1|c|#resolver:org".to_string()));
+
+        Metrics::inc(&metrics.connections_total);
+        let lines = exporter.collect();
+        assert!(lines.contains(&"pgvpd.connections.total:1|c".to_string()));
+        // Unchanged counters are skipped; gauges are always sent
+        assert!(!lines.iter().any(|l| l.starts_with("pgvpd.tenant.rejected")));
+        assert!(lines.contains(&"pgvpd.connections.active:1|g".to_string()));
+    }
+
+    #[test]
+    fn tenant_durations_are_tagged_gauges() {
+        let metrics = Arc::new(Metrics::new(Vec::new()));
+        let (mut exporter, _agent) = exporter(Arc::clone(&metrics));
+        let histogram = metrics.tenant_duration_histogram("acme,corp");
+        Metrics::observe(&histogram, 0.25);
+        Metrics::observe(&histogram, 0.75);
+
+        let lines = exporter.collect();
+        assert!(lines.contains(
+            &"pgvpd.tenant.connection.duration.ms:500.000|g|#tenant:acme_corp".to_string()
+        ));
+        assert!(
+            lines.contains(
+                &"pgvpd.tenant.connections.closed.total:2|c|#tenant:acme_corp".to_string()
+            )
+        );
+        assert!(
+            !exporter
+                .collect()
+                .iter()
+                .any(|l| l.contains("connection.duration"))
+        );
+    }
+
+    #[test]
+    fn flush_reaches_the_agent() {
+        let metrics = Arc::new(Metrics::new(Vec::new()));
+        let (mut exporter, agent) = exporter(Arc::clone(&metrics));
+        Metrics::inc(&metrics.queries_total);
+        exporter.flush();
+
+        let mut buf = [0u8; MAX_DATAGRAM];
+        let n = agent.recv(&mut buf).unwrap();
+        let datagram = std::str::from_utf8(&buf[..n]).unwrap();
+        assert!(datagram.lines().any(|l| l == "pgvpd.queries.total:1|c"));
+    }
+
+    #[test]
+    fn pack_splits_at_datagram_size() {
+        let line = "x".repeat(600);
+        let lines = vec![line.clone(), line.clone(), line.clone()];
+        let datagrams = pack(&lines);
+        assert_eq!(datagrams.len(), 2);
+        assert_eq!(datagrams[0], format!("{line}\n{line}"));
+        assert!(datagrams.iter().all(|d| d.len() <= MAX_DATAGRAM));
+    }
+}
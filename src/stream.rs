@@ -1,6 +1,6 @@
 //! Stream abstraction — plain TCP or TLS on both client and upstream sides.
 
-use std::io;
+use std::io::{self, IoSlice};
 use std::net::SocketAddr;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -24,6 +24,14 @@ impl ClientStream {
             Self::Tls(s) => s.get_ref().0.peer_addr(),
         }
     }
+
+    /// The listener address this connection arrived on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Self::Plain(s) => s.local_addr(),
+            Self::Tls(s) => s.get_ref().0.local_addr(),
+        }
+    }
 }
 
 impl AsyncRead for ClientStream {
@@ -51,6 +59,24 @@ impl AsyncWrite for ClientStream {
         }
     }
 
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+            Self::Tls(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Self::Plain(s) => s.is_write_vectored(),
+            Self::Tls(s) => s.is_write_vectored(),
+        }
+    }
+
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         match self.get_mut() {
             Self::Plain(s) => Pin::new(s).poll_flush(cx),
@@ -76,6 +102,16 @@ pub enum UpstreamStream {
     Tls(ClientTlsStream<TcpStream>),
 }
 
+impl UpstreamStream {
+    /// The local end of the upstream connection.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Self::Plain(s) => s.local_addr(),
+            Self::Tls(s) => s.get_ref().0.local_addr(),
+        }
+    }
+}
+
 impl AsyncRead for UpstreamStream {
     fn poll_read(
         self: Pin<&mut Self>,
@@ -101,6 +137,24 @@ impl AsyncWrite for UpstreamStream {
         }
     }
 
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+            Self::Tls(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Self::Plain(s) => s.is_write_vectored(),
+            Self::Tls(s) => s.is_write_vectored(),
+        }
+    }
+
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         match self.get_mut() {
             Self::Plain(s) => Pin::new(s).poll_flush(cx),
@@ -117,3 +171,46 @@ impl AsyncWrite for UpstreamStream {
 }
 
 impl Unpin for UpstreamStream {}
+
+// ─── Tests ──────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn plain_streams_expose_both_addresses() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listen_addr = listener.local_addr().unwrap();
+
+        let (dialed, accepted) = tokio::join!(TcpStream::connect(listen_addr), listener.accept());
+        let upstream = UpstreamStream::Plain(dialed.unwrap());
+        let client = ClientStream::Plain(accepted.unwrap().0);
+
+        assert_eq!(client.local_addr().unwrap(), listen_addr);
+        assert_eq!(client.peer_addr().unwrap(), upstream.local_addr().unwrap());
+    }
+
+    #[tokio::test]
+    async fn plain_streams_write_vectored() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (dialed, accepted) = tokio::join!(
+            TcpStream::connect(listener.local_addr().unwrap()),
+            listener.accept()
+        );
+        let mut upstream = UpstreamStream::Plain(dialed.unwrap());
+        let mut client = ClientStream::Plain(accepted.unwrap().0);
+        assert!(upstream.is_write_vectored());
+        assert!(client.is_write_vectored());
+
+        let bufs = [IoSlice::new(b"SET a = 1; "), IoSlice::new(b"SET b = 2;")];
+        let n = upstream.write_vectored(&bufs).await.unwrap();
+        assert_eq!(n, 21);
+        let mut received = [0u8; 21];
+        client.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"SET a = 1; SET b = 2;");
+    }
+}
@@ -3,12 +3,12 @@
 //! The TenantRegistry is shared across all connection tasks. It tracks per-tenant
 //! runtime state (active connections, rate window) and enforces limits configured
 //! in pgvpd.conf. TenantGuard is an RAII guard that decrements the active connection
-//! count when the connection ends.
+//! count and records the connection's duration when the connection ends.
 
-use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::time::Instant;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
 use crate::config::Config;
@@ -17,23 +17,98 @@ use crate::metrics::Metrics;
 /// Per-tenant runtime state, created on first connection for that tenant.
 struct TenantState {
     active_connections: AtomicU32,
-    /// Rate limit: sliding window start time and count.
-    rate_window: Mutex<(Instant, u32)>,
+    /// Rate limit: admission times within the current sliding window.
+    rate_window: Mutex<VecDeque<Instant>>,
+}
+
+/// Point-in-time view of one tenant's runtime state, for the admin API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantSnapshot {
+    pub tenant_id: String,
+    pub active_connections: u32,
+    /// Admissions within the current rate-limit window.
+    pub rate_window_count: u32,
+    /// Age of the oldest admission in the window (0 if none).
+    pub rate_window_age_ms: u64,
+}
+
+/// An allow or deny list. Plain entries are looked up exactly; entries with
+/// glob metacharacters (`*`, `?`, `[...]`) are matched as patterns.
+struct TenantList {
+    literals: HashSet<String>,
+    patterns: Vec<glob::Pattern>,
+}
+
+impl TenantList {
+    fn new(entries: &[String]) -> Self {
+        let mut list = TenantList {
+            literals: HashSet::new(),
+            patterns: Vec::new(),
+        };
+        for entry in entries {
+            match is_glob(entry).then(|| glob::Pattern::new(entry)) {
+                Some(Ok(pattern)) => list.patterns.push(pattern),
+                // Invalid patterns are rejected by Config::validate
+                _ => {
+                    list.literals.insert(entry.clone());
+                }
+            }
+        }
+        list
+    }
+
+    fn contains(&self, tenant_id: &str) -> bool {
+        self.literals.contains(tenant_id) || self.patterns.iter().any(|p| p.matches(tenant_id))
+    }
+}
+
+/// Whether a tenant list entry should be compiled as a glob pattern.
+pub fn is_glob(entry: &str) -> bool {
+    entry.contains(['*', '?', '['])
+}
+
+/// Configured limits, replaceable at runtime via `TenantRegistry::update_config`.
+struct TenantLimits {
+    /// Deny tenants not in `allow` (explicit `tenant_allowlist_only`, or
+    /// implied by an allow list).
+    allowlist_only: bool,
+    allow: Option<TenantList>,
+    deny: Option<TenantList>,
+    max_connections: Option<u32>,
+    /// Per-tenant `max_connections` overrides from `[tenant_limits]`.
+    max_connections_overrides: HashMap<String, u32>,
+    rate_limit: Option<u32>,
+    rate_window: Duration,
+}
+
+impl TenantLimits {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            allowlist_only: config.tenant_allowlist_only || config.tenant_allow.is_some(),
+            allow: config.tenant_allow.as_deref().map(TenantList::new),
+            deny: config.tenant_deny.as_deref().map(TenantList::new),
+            max_connections: config.tenant_max_connections,
+            max_connections_overrides: config.tenant_max_connections_map.clone(),
+            rate_limit: config.tenant_rate_limit,
+            rate_window: Duration::from_secs(config.tenant_rate_limit_window_secs),
+        }
+    }
 }
 
 /// Registry of per-tenant state, shared across all connection tasks.
 pub struct TenantRegistry {
     tenants: Mutex<HashMap<String, Arc<TenantState>>>,
-    allow: Option<HashSet<String>>,
-    deny: Option<HashSet<String>>,
-    max_connections: Option<u32>,
-    rate_limit: Option<u32>,
+    limits: RwLock<TenantLimits>,
     metrics: Arc<Metrics>,
 }
 
-/// RAII guard that decrements active_connections on drop.
+/// RAII guard that decrements active_connections and observes the
+/// per-tenant connection duration on drop.
 pub struct TenantGuard {
     state: Arc<TenantState>,
+    tenant: String,
+    metrics: Arc<Metrics>,
+    start_time: Instant,
 }
 
 impl Drop for TenantGuard {
@@ -41,6 +116,8 @@ impl Drop for TenantGuard {
         self.state
             .active_connections
             .fetch_sub(1, Ordering::Relaxed);
+        let histogram = self.metrics.tenant_duration_histogram(&self.tenant);
+        Metrics::observe(&histogram, self.start_time.elapsed().as_secs_f64());
     }
 }
 
@@ -48,31 +125,27 @@ impl TenantRegistry {
     pub fn new(config: &Config, metrics: Arc<Metrics>) -> Self {
         Self {
             tenants: Mutex::new(HashMap::new()),
-            allow: config
-                .tenant_allow
-                .as_ref()
-                .map(|v| v.iter().cloned().collect()),
-            deny: config
-                .tenant_deny
-                .as_ref()
-                .map(|v| v.iter().cloned().collect()),
-            max_connections: config.tenant_max_connections,
-            rate_limit: config.tenant_rate_limit,
+            limits: RwLock::new(TenantLimits::from_config(config)),
             metrics,
         }
     }
 
+    /// Replace allow/deny lists and limits (config reload). Existing
+    /// connections are unaffected; new ones are checked against the new limits.
+    pub fn update_config(&self, config: &Config) {
+        *self.limits.write().unwrap() = TenantLimits::from_config(config);
+    }
+
     /// Check allow/deny list. Returns Err with message if denied.
     pub fn check_access(&self, tenant_id: &str) -> Result<(), String> {
-        if let Some(ref deny) = self.deny
+        let limits = self.limits.read().unwrap();
+        if let Some(ref deny) = limits.deny
             && deny.contains(tenant_id)
         {
             Metrics::inc(&self.metrics.tenant_rejected_deny);
             return Err(format!("tenant '{}' is denied", tenant_id));
         }
-        if let Some(ref allow) = self.allow
-            && !allow.contains(tenant_id)
-        {
+        if limits.allowlist_only && !limits.allow.as_ref().is_some_and(|a| a.contains(tenant_id)) {
             Metrics::inc(&self.metrics.tenant_rejected_deny);
             return Err(format!("tenant '{}' is not in allow list", tenant_id));
         }
@@ -83,10 +156,19 @@ impl TenantRegistry {
     /// Returns a TenantGuard that decrements the count on drop.
     /// Returns Err if connection limit or rate limit exceeded.
     pub async fn acquire(&self, tenant_id: &str) -> Result<TenantGuard, String> {
+        let (max_connections, rate_limit, rate_window) = {
+            let limits = self.limits.read().unwrap();
+            let max_connections = limits
+                .max_connections_overrides
+                .get(tenant_id)
+                .copied()
+                .or(limits.max_connections);
+            (max_connections, limits.rate_limit, limits.rate_window)
+        };
         let state = self.get_or_create(tenant_id).await;
 
         // Check connection limit
-        if let Some(max) = self.max_connections {
+        if let Some(max) = max_connections {
             let current = state.active_connections.load(Ordering::Relaxed);
             if current >= max {
                 Metrics::inc(&self.metrics.tenant_rejected_limit);
@@ -98,21 +180,16 @@ impl TenantRegistry {
         }
 
         // Check rate limit
-        if let Some(limit) = self.rate_limit {
+        if let Some(limit) = rate_limit {
             let mut window = state.rate_window.lock().await;
-            let now = Instant::now();
-            let elapsed = now.duration_since(window.0);
-            if elapsed.as_secs() >= 1 {
-                // New window
-                *window = (now, 1);
-            } else if window.1 >= limit {
+            if !admit(&mut window, Instant::now(), limit, rate_window) {
                 Metrics::inc(&self.metrics.tenant_rejected_rate);
                 return Err(format!(
-                    "tenant '{}' rate limit exceeded ({}/s)",
-                    tenant_id, limit
+                    "tenant '{}' rate limit exceeded ({}/{}s)",
+                    tenant_id,
+                    limit,
+                    rate_window.as_secs()
                 ));
-            } else {
-                window.1 += 1;
             }
         }
 
@@ -120,9 +197,45 @@ impl TenantRegistry {
         state.active_connections.fetch_add(1, Ordering::Relaxed);
         Ok(TenantGuard {
             state: Arc::clone(&state),
+            tenant: tenant_id.to_string(),
+            metrics: Arc::clone(&self.metrics),
+            start_time: Instant::now(),
         })
     }
 
+    /// Every tenant seen so far, sorted by ID. Admissions that have aged out
+    /// of the rate window (but not been pruned yet) are not counted.
+    pub async fn snapshot(&self) -> Vec<TenantSnapshot> {
+        let window = self.limits.read().unwrap().rate_window;
+        let tenants: Vec<(String, Arc<TenantState>)> = self
+            .tenants
+            .lock()
+            .await
+            .iter()
+            .map(|(id, state)| (id.clone(), Arc::clone(state)))
+            .collect();
+
+        let now = Instant::now();
+        let mut snapshot = Vec::with_capacity(tenants.len());
+        for (tenant_id, state) in tenants {
+            let recent = state.rate_window.lock().await;
+            let in_window: Vec<&Instant> = recent
+                .iter()
+                .filter(|&&t| now.duration_since(t) < window)
+                .collect();
+            snapshot.push(TenantSnapshot {
+                tenant_id,
+                active_connections: state.active_connections.load(Ordering::Relaxed),
+                rate_window_count: in_window.len() as u32,
+                rate_window_age_ms: in_window
+                    .first()
+                    .map_or(0, |&&t| now.duration_since(t).as_millis() as u64),
+            });
+        }
+        snapshot.sort_by(|a, b| a.tenant_id.cmp(&b.tenant_id));
+        snapshot
+    }
+
     async fn get_or_create(&self, tenant_id: &str) -> Arc<TenantState> {
         let mut tenants = self.tenants.lock().await;
         if let Some(state) = tenants.get(tenant_id) {
@@ -130,7 +243,7 @@ impl TenantRegistry {
         } else {
             let state = Arc::new(TenantState {
                 active_connections: AtomicU32::new(0),
-                rate_window: Mutex::new((Instant::now(), 0)),
+                rate_window: Mutex::new(VecDeque::new()),
             });
             tenants.insert(tenant_id.to_string(), Arc::clone(&state));
             state
@@ -138,11 +251,28 @@ impl TenantRegistry {
     }
 }
 
+/// Sliding-window rate check: forget admissions older than `window`, then
+/// admit (and record `now`) if fewer than `limit` remain.
+fn admit(recent: &mut VecDeque<Instant>, now: Instant, limit: u32, window: Duration) -> bool {
+    while recent
+        .front()
+        .is_some_and(|&t| now.duration_since(t) >= window)
+    {
+        recent.pop_front();
+    }
+    if recent.len() >= limit as usize {
+        return false;
+    }
+    recent.push_back(now);
+    true
+}
+
 // ─── Tests ──────────────────────────────────────────────────────────────────
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::metrics::{MAX_TENANT_DURATION_SERIES, OTHER_TENANT};
 
     fn make_config(
         allow: Option<Vec<&str>>,
@@ -179,6 +309,62 @@ mod tests {
         assert!(reg.check_access("gamma").is_err());
     }
 
+    #[test]
+    fn test_glob_star_and_question() {
+        let config = make_config(Some(vec!["prod_*", "qa?"]), None, None, None);
+        let reg = TenantRegistry::new(&config, make_metrics());
+        assert!(reg.check_access("prod_acme").is_ok());
+        assert!(reg.check_access("prod_").is_ok());
+        assert!(reg.check_access("qa1").is_ok());
+        assert!(reg.check_access("qa12").is_err());
+        assert!(reg.check_access("staging_acme").is_err());
+    }
+
+    #[test]
+    fn test_glob_brackets_in_deny_list() {
+        let config = make_config(None, Some(vec!["tenant_[abc]"]), None, None);
+        let reg = TenantRegistry::new(&config, make_metrics());
+        assert!(reg.check_access("tenant_a").is_err());
+        assert!(reg.check_access("tenant_c").is_err());
+        assert!(reg.check_access("tenant_d").is_ok());
+    }
+
+    #[test]
+    fn test_glob_mixed_with_literals() {
+        let config = make_config(None, Some(vec!["staging_*", "blocked", "x.y"]), None, None);
+        let reg = TenantRegistry::new(&config, make_metrics());
+        assert!(reg.check_access("staging_eu").is_err());
+        assert!(reg.check_access("blocked").is_err());
+        assert!(reg.check_access("x.y").is_err());
+        // Literals are exact: no prefix or wildcard semantics
+        assert!(reg.check_access("blocked2").is_ok());
+        assert!(reg.check_access("xzy").is_ok());
+        assert!(reg.check_access("prod_eu").is_ok());
+    }
+
+    #[test]
+    fn test_deny_wildcard_blocks_everyone() {
+        let config = make_config(None, Some(vec!["*"]), None, None);
+        let reg = TenantRegistry::new(&config, make_metrics());
+        assert!(reg.check_access("acme").is_err());
+        assert!(reg.check_access("prod_1").is_err());
+    }
+
+    #[test]
+    fn test_allowlist_only() {
+        // Without an allow list, allowlist-only mode admits nobody
+        let mut config = make_config(None, None, None, None);
+        config.tenant_allowlist_only = true;
+        let reg = TenantRegistry::new(&config, make_metrics());
+        assert!(reg.check_access("acme").is_err());
+
+        config.tenant_allow = Some(vec!["acme".into(), "prod_*".into()]);
+        reg.update_config(&config);
+        assert!(reg.check_access("acme").is_ok());
+        assert!(reg.check_access("prod_eu").is_ok());
+        assert!(reg.check_access("other").is_err());
+    }
+
     #[test]
     fn test_no_lists_allows_all() {
         let config = make_config(None, None, None, None);
@@ -209,6 +395,80 @@ mod tests {
         assert!(g5.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_snapshot_lists_tenants_sorted() {
+        let config = make_config(None, None, None, Some(10));
+        let reg = TenantRegistry::new(&config, make_metrics());
+        assert!(reg.snapshot().await.is_empty());
+
+        let _b1 = reg.acquire("beta").await.unwrap();
+        let _b2 = reg.acquire("beta").await.unwrap();
+        drop(reg.acquire("alpha").await.unwrap());
+
+        let snap = reg.snapshot().await;
+        let ids: Vec<&str> = snap.iter().map(|t| t.tenant_id.as_str()).collect();
+        assert_eq!(ids, ["alpha", "beta"]);
+        assert_eq!(snap[0].active_connections, 0);
+        assert_eq!(snap[0].rate_window_count, 1);
+        assert_eq!(snap[1].active_connections, 2);
+        assert_eq!(snap[1].rate_window_count, 2);
+        assert!(snap[1].rate_window_age_ms < 60_000);
+    }
+
+    #[tokio::test]
+    async fn test_per_tenant_connection_override() {
+        let mut config = make_config(None, None, Some(1), None);
+        config.tenant_max_connections_map.insert("big".into(), 3);
+        let reg = TenantRegistry::new(&config, make_metrics());
+
+        let _s1 = reg.acquire("small").await.unwrap();
+        assert!(reg.acquire("small").await.is_err());
+
+        let _b1 = reg.acquire("big").await.unwrap();
+        let _b2 = reg.acquire("big").await.unwrap();
+        let _b3 = reg.acquire("big").await.unwrap();
+        assert!(reg.acquire("big").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_guard_records_duration() {
+        let config = make_config(None, None, Some(5), None);
+        let metrics = make_metrics();
+        let reg = TenantRegistry::new(&config, Arc::clone(&metrics));
+
+        let guard = reg.acquire("t1").await.unwrap();
+        assert!(
+            metrics
+                .tenant_connection_duration
+                .lock()
+                .unwrap()
+                .is_empty()
+        );
+        drop(guard);
+
+        let snap = metrics.tenant_duration_histogram("t1").snapshot();
+        assert_eq!(snap.count, 1);
+        // A just-dropped guard lands in the smallest bucket
+        assert_eq!(snap.buckets[0], (0.1, 1));
+        assert_eq!(snap.buckets.last().unwrap().1, 1);
+        assert_eq!(metrics.tenant_duration_histogram("t2").snapshot().count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_duration_series_are_capped() {
+        let config = make_config(None, None, None, None);
+        let metrics = make_metrics();
+        let reg = TenantRegistry::new(&config, Arc::clone(&metrics));
+
+        for i in 0..MAX_TENANT_DURATION_SERIES + 5 {
+            drop(reg.acquire(&format!("t{i}")).await.unwrap());
+        }
+        let map = metrics.tenant_connection_duration.lock().unwrap();
+        assert_eq!(map.len(), MAX_TENANT_DURATION_SERIES + 1);
+        assert_eq!(map[OTHER_TENANT].snapshot().count, 5);
+        assert_eq!(map["t0"].snapshot().count, 1);
+    }
+
     #[tokio::test]
     async fn test_rate_limit() {
         let config = make_config(None, None, None, Some(3));
@@ -223,4 +483,82 @@ mod tests {
         let g4 = reg.acquire("t1").await;
         assert!(g4.is_err());
     }
+
+    #[test]
+    fn test_sliding_window_spans_fixed_boundaries() {
+        let start = Instant::now();
+        let window = Duration::from_secs(1);
+        let mut recent = VecDeque::new();
+
+        // Burst of 3 at t=0.9s fills the window
+        let t = start + Duration::from_millis(900);
+        for _ in 0..3 {
+            assert!(admit(&mut recent, t, 3, window));
+        }
+        // A fixed window would reset at t=1.0s; the sliding window still sees the burst
+        assert!(!admit(
+            &mut recent,
+            start + Duration::from_millis(1100),
+            3,
+            window
+        ));
+        // Rejected attempts aren't recorded
+        assert_eq!(recent.len(), 3);
+        // Once the burst is a full window old, capacity returns
+        assert!(admit(
+            &mut recent,
+            start + Duration::from_millis(1900),
+            3,
+            window
+        ));
+        assert_eq!(recent.len(), 1);
+    }
+
+    #[test]
+    fn test_sliding_window_partial_expiry() {
+        let start = Instant::now();
+        let window = Duration::from_secs(10);
+        let mut recent = VecDeque::new();
+        assert!(admit(&mut recent, start, 2, window));
+        assert!(admit(
+            &mut recent,
+            start + Duration::from_secs(6),
+            2,
+            window
+        ));
+        assert!(!admit(
+            &mut recent,
+            start + Duration::from_secs(9),
+            2,
+            window
+        ));
+        // First admission expires at t=10s, second still counts
+        assert!(admit(
+            &mut recent,
+            start + Duration::from_secs(10),
+            2,
+            window
+        ));
+        assert!(!admit(
+            &mut recent,
+            start + Duration::from_secs(15),
+            2,
+            window
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_update_config_replaces_limits() {
+        let config = make_config(None, Some(vec!["bad"]), Some(1), None);
+        let reg = TenantRegistry::new(&config, make_metrics());
+        let _g1 = reg.acquire("t1").await.unwrap();
+        assert!(reg.acquire("t1").await.is_err());
+
+        reg.update_config(&make_config(Some(vec!["bad", "t1"]), None, Some(2), None));
+        assert!(reg.check_access("bad").is_ok());
+        assert!(reg.check_access("other").is_err());
+        // Active connections carry over; only the limit changed
+        let _g2 = reg.acquire("t1").await.unwrap();
+        assert!(reg.acquire("t1").await.is_err());
+    }
 }
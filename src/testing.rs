@@ -0,0 +1,160 @@
+//! Test helpers — an in-memory upstream that replays canned backend messages.
+//!
+//! `MockUpstreamStream` stands in for `UpstreamStream` in unit tests: reads
+//! return the queued messages one at a time, then EOF; writes are captured
+//! so tests can inspect what the proxy sent.
+
+use bytes::{BufMut, BytesMut};
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::protocol::{backend, build_auth_ok, build_error_response};
+
+pub struct MockUpstreamStream {
+    chunks: VecDeque<BytesMut>,
+    /// Everything the code under test wrote to the "server".
+    pub written: Vec<u8>,
+}
+
+impl MockUpstreamStream {
+    pub fn new(chunks: Vec<BytesMut>) -> Self {
+        Self {
+            chunks: chunks.into(),
+            written: Vec::new(),
+        }
+    }
+}
+
+impl AsyncRead for MockUpstreamStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Some(chunk) = this.chunks.front_mut() {
+            let n = chunk.len().min(buf.remaining());
+            buf.put_slice(&chunk.split_to(n));
+            if chunk.is_empty() {
+                this.chunks.pop_front();
+            }
+        }
+        // No chunks left: leave `buf` untouched, which signals EOF.
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for MockUpstreamStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().written.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Builds the message sequence a `MockUpstreamStream` plays back.
+#[derive(Default)]
+pub struct MockStreamBuilder {
+    chunks: Vec<BytesMut>,
+}
+
+impl MockStreamBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a backend message with the given type and payload.
+    pub fn message(mut self, msg_type: u8, payload: &[u8]) -> Self {
+        let mut buf = BytesMut::with_capacity(5 + payload.len());
+        buf.put_u8(msg_type);
+        buf.put_i32(4 + payload.len() as i32);
+        buf.put_slice(payload);
+        self.chunks.push(buf);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn auth_ok(mut self) -> Self {
+        self.chunks.push(build_auth_ok());
+        self
+    }
+
+    /// ReadyForQuery with transaction status 'I' (idle).
+    pub fn ready_for_query(self) -> Self {
+        self.message(backend::READY_FOR_QUERY, b"I")
+    }
+
+    /// RowDescription with text-typed columns.
+    pub fn row_description(self, cols: &[&str]) -> Self {
+        let mut payload = BytesMut::new();
+        payload.put_i16(cols.len() as i16);
+        for col in cols {
+            payload.put_slice(col.as_bytes());
+            payload.put_u8(0);
+            payload.put_u32(0); // table oid
+            payload.put_i16(0); // column number
+            payload.put_u32(25); // text
+            payload.put_i16(-1); // type size
+            payload.put_i32(-1); // type modifier
+            payload.put_i16(0); // text format
+        }
+        self.message(backend::ROW_DESCRIPTION, &payload)
+    }
+
+    /// DataRow; `None` encodes SQL NULL.
+    pub fn data_row(self, values: &[Option<&str>]) -> Self {
+        let mut payload = BytesMut::new();
+        payload.put_i16(values.len() as i16);
+        for value in values {
+            match value {
+                Some(v) => {
+                    payload.put_i32(v.len() as i32);
+                    payload.put_slice(v.as_bytes());
+                }
+                None => payload.put_i32(-1),
+            }
+        }
+        self.message(backend::DATA_ROW, &payload)
+    }
+
+    pub fn command_complete(self, tag: &str) -> Self {
+        let mut payload = tag.as_bytes().to_vec();
+        payload.push(0);
+        self.message(backend::COMMAND_COMPLETE, &payload)
+    }
+
+    pub fn error_response(mut self, sqlstate: &str, message: &str) -> Self {
+        self.chunks
+            .push(build_error_response("ERROR", sqlstate, message));
+        self
+    }
+
+    pub fn notice_response(self, message: &str) -> Self {
+        let mut payload = BytesMut::new();
+        for (field, value) in [(b'S', "NOTICE"), (b'C', "00000"), (b'M', message)] {
+            payload.put_u8(field);
+            payload.put_slice(value.as_bytes());
+            payload.put_u8(0);
+        }
+        payload.put_u8(0);
+        self.message(backend::NOTICE_RESPONSE, &payload)
+    }
+
+    pub fn build(self) -> MockUpstreamStream {
+        MockUpstreamStream::new(self.chunks)
+    }
+}
@@ -1,56 +1,313 @@
 //! TLS configuration builders — server (termination) and client (origination).
 
-use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
-use rustls::{ClientConfig, ServerConfig};
-use std::fs::File;
+use base64::Engine;
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{
+    ClientConfig, DigitallySignedStruct, ServerConfig, SignatureScheme, SupportedCipherSuite,
+    SupportedProtocolVersion,
+};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
 use std::io::{self, BufReader};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio_rustls::TlsAcceptor;
+use tracing::{info, warn};
 
-/// Build a `ServerConfig` for TLS termination (client → Pgvpd).
-pub fn build_server_config(cert_path: &str, key_path: &str) -> io::Result<Arc<ServerConfig>> {
+use crate::config::{Config, TlsSniCert};
+
+// ─── Policy (cipher suites + protocol versions) ─────────────────────────────
+
+/// Cipher suite and protocol version restrictions, applied to both
+/// the server (termination) and client (origination) configs.
+#[derive(Debug, Clone, Default)]
+pub struct TlsPolicy {
+    /// Allowed cipher suite names (e.g. `TLS13_AES_256_GCM_SHA384`). `None` = rustls defaults.
+    pub cipher_suites: Option<Vec<String>>,
+    /// Minimum protocol version: `"1.2"` or `"1.3"`. `None` = rustls defaults.
+    pub min_version: Option<String>,
+}
+
+impl TlsPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            cipher_suites: config.tls_cipher_suites.clone(),
+            min_version: config.tls_min_version.clone(),
+        }
+    }
+
+    /// The aws-lc-rs provider, with cipher suites filtered to the configured list.
+    fn provider(&self) -> io::Result<Arc<CryptoProvider>> {
+        let mut provider = rustls::crypto::aws_lc_rs::default_provider();
+        if let Some(ref names) = self.cipher_suites {
+            provider.cipher_suites = parse_cipher_suites(names)?;
+        }
+        Ok(Arc::new(provider))
+    }
+
+    /// Protocol versions at or above the configured minimum.
+    fn versions(&self) -> io::Result<Vec<&'static SupportedProtocolVersion>> {
+        match self.min_version.as_deref() {
+            None | Some("1.2") => Ok(vec![&rustls::version::TLS13, &rustls::version::TLS12]),
+            Some("1.3") => Ok(vec![&rustls::version::TLS13]),
+            Some(other) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported tls_min_version '{other}' (expected 1.2 or 1.3)"),
+            )),
+        }
+    }
+}
+
+/// Resolve cipher suite names against the aws-lc-rs provider.
+/// Names are matched case-insensitively; unknown names are an error.
+pub fn parse_cipher_suites(names: &[String]) -> io::Result<Vec<SupportedCipherSuite>> {
+    let available = rustls::crypto::aws_lc_rs::default_provider().cipher_suites;
+    let mut suites = Vec::with_capacity(names.len());
+    for name in names {
+        let wanted = name.trim().to_ascii_uppercase();
+        match available
+            .iter()
+            .find(|s| format!("{:?}", s.suite()) == wanted)
+        {
+            Some(suite) => suites.push(*suite),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unknown TLS cipher suite: '{}'", name.trim()),
+                ));
+            }
+        }
+    }
+    if suites.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "tls_cipher_suites is empty",
+        ));
+    }
+    Ok(suites)
+}
+
+// ─── Builders ───────────────────────────────────────────────────────────────
+
+/// Build a `ServerConfig` for TLS termination (client → Pgvpd). With
+/// `sni_certs`, clients asking for a matching server name get that
+/// certificate instead; the cert/key pair is the fallback.
+pub fn build_server_config(
+    cert_path: &str,
+    key_path: &str,
+    sni_certs: &[TlsSniCert],
+    policy: &TlsPolicy,
+) -> io::Result<Arc<ServerConfig>> {
     let certs = load_certs(cert_path)?;
     let key = load_private_key(key_path)?;
+    server_config_from_parts(certs, key, sni_certs, policy)
+}
 
-    let config = ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(certs, key)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+/// Build a `ServerConfig` for TLS termination from a PKCS#12 (`.p12` / `.pfx`) bundle.
+///
+/// The bundle must contain the leaf certificate (plus any intermediates) and its
+/// private key, encrypted with `password`.
+pub fn build_server_config_pkcs12(
+    path: &str,
+    password: &str,
+    sni_certs: &[TlsSniCert],
+    policy: &TlsPolicy,
+) -> io::Result<Arc<ServerConfig>> {
+    let (certs, key) = load_pkcs12(path, password)?;
+    server_config_from_parts(certs, key, sni_certs, policy)
+}
+
+fn server_config_from_parts(
+    certs: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    sni_certs: &[TlsSniCert],
+    policy: &TlsPolicy,
+) -> io::Result<Arc<ServerConfig>> {
+    let provider = policy.provider()?;
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_protocol_versions(&policy.versions()?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        .with_no_client_auth();
+    let config = if sni_certs.is_empty() {
+        builder
+            .with_single_cert(certs, key)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+    } else {
+        let resolver = SniCertResolver::new(certs, key, sni_certs, &provider)?;
+        builder.with_cert_resolver(Arc::new(resolver))
+    };
 
     Ok(Arc::new(config))
 }
 
+// ─── SNI certificate selection ──────────────────────────────────────────────
+
+/// Picks the server certificate by the name in the ClientHello: exact
+/// `[[tls_cert]]` patterns first, then wildcards, each in config order, and
+/// the default certificate when nothing matches (or the client sent no SNI).
+/// Every certificate is loaded once, when the `ServerConfig` is built.
+#[derive(Debug)]
+struct SniCertResolver {
+    default: Arc<CertifiedKey>,
+    /// Lowercased patterns and their certificates.
+    certs: Vec<(String, Arc<CertifiedKey>)>,
+}
+
+impl SniCertResolver {
+    fn new(
+        certs: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+        sni_certs: &[TlsSniCert],
+        provider: &CryptoProvider,
+    ) -> io::Result<Self> {
+        let mut resolver = Self {
+            default: certified_key(certs, key, provider)?,
+            certs: Vec::with_capacity(sni_certs.len()),
+        };
+        for entry in sni_certs {
+            let certs = load_certs(&entry.cert)?;
+            if certs.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: no certificates found", entry.cert),
+                ));
+            }
+            let key = certified_key(certs, load_private_key(&entry.key)?, provider)?;
+            resolver.certs.push((entry.sni.to_ascii_lowercase(), key));
+        }
+        Ok(resolver)
+    }
+
+    fn lookup(&self, server_name: Option<&str>) -> &Arc<CertifiedKey> {
+        let Some(name) = server_name.map(str::to_ascii_lowercase) else {
+            return &self.default;
+        };
+        self.certs
+            .iter()
+            .find(|(pattern, _)| *pattern == name)
+            .or_else(|| {
+                self.certs
+                    .iter()
+                    .find(|(pattern, _)| sni_matches(pattern, &name))
+            })
+            .map_or(&self.default, |(_, cert)| cert)
+    }
+}
+
+impl ResolvesServerCert for SniCertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(self.lookup(client_hello.server_name())))
+    }
+}
+
+fn certified_key(
+    certs: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    provider: &CryptoProvider,
+) -> io::Result<Arc<CertifiedKey>> {
+    let signing_key = provider
+        .key_provider
+        .load_private_key(key)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(Arc::new(CertifiedKey::new(certs, signing_key)))
+}
+
+/// Does lowercase `name` match `pattern`? `*.example.com` matches exactly one
+/// label in front of `example.com`; anything else must be equal.
+fn sni_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => name
+            .strip_suffix(suffix)
+            .and_then(|rest| rest.strip_suffix('.'))
+            .is_some_and(|label| !label.is_empty() && !label.contains('.')),
+        None => pattern == name,
+    }
+}
+
+/// A valid `[[tls_cert]]` `sni` value: a host name whose first label may be `*`.
+pub fn is_valid_sni_pattern(pattern: &str) -> bool {
+    let host = pattern.strip_prefix("*.").unwrap_or(pattern);
+    !host.is_empty() && !host.contains('*') && host.split('.').all(|label| !label.is_empty())
+}
+
 /// Build a `ClientConfig` for TLS origination (Pgvpd → upstream Postgres).
 ///
 /// - `verify`: if false, skip certificate verification (for dev/self-signed)
 /// - `ca_path`: optional path to a custom CA certificate
-pub fn build_client_config(verify: bool, ca_path: Option<&str>) -> io::Result<Arc<ClientConfig>> {
-    let config = if !verify {
-        ClientConfig::builder()
-            .dangerous()
-            .with_custom_certificate_verifier(Arc::new(NoVerifier))
-            .with_no_client_auth()
-    } else if let Some(ca) = ca_path {
-        let ca_certs = load_certs(ca)?;
-        let mut root_store = rustls::RootCertStore::empty();
-        for cert in ca_certs {
-            root_store
-                .add(cert)
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        }
-        ClientConfig::builder()
-            .with_root_certificates(root_store)
-            .with_no_client_auth()
+/// - `pin`: optional base64 SHA-256 of the leaf's SubjectPublicKeyInfo; checked
+///   after chain validation (or on its own when `verify` is false)
+/// - `policy`: cipher suite / protocol version restrictions
+pub fn build_client_config(
+    verify: bool,
+    ca_path: Option<&str>,
+    pin: Option<&str>,
+    policy: &TlsPolicy,
+) -> io::Result<Arc<ClientConfig>> {
+    let provider = policy.provider()?;
+    let builder = ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_protocol_versions(&policy.versions()?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let verifier: Arc<dyn ServerCertVerifier> = if !verify {
+        Arc::new(NoVerifier)
     } else {
-        let root_store =
-            rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-        ClientConfig::builder()
-            .with_root_certificates(root_store)
-            .with_no_client_auth()
+        let root_store = match ca_path {
+            Some(ca) => {
+                let mut store = rustls::RootCertStore::empty();
+                for cert in load_certs(ca)? {
+                    store
+                        .add(cert)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                }
+                store
+            }
+            None => {
+                rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned())
+            }
+        };
+        WebPkiServerVerifier::builder_with_provider(Arc::new(root_store), provider)
+            .build()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
     };
 
+    let verifier = match pin {
+        Some(pin) => Arc::new(PinnedCertVerifier {
+            inner: verifier,
+            pin: parse_pin(pin)?,
+        }),
+        None => verifier,
+    };
+
+    let config = builder
+        .dangerous()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+
     Ok(Arc::new(config))
 }
 
+/// Decode an `upstream_tls_pin_pubkey` value: base64 of a SHA-256 digest,
+/// optionally prefixed with `sha256//` (curl's `--pinnedpubkey` form).
+pub fn parse_pin(pin: &str) -> io::Result<[u8; 32]> {
+    let encoded = pin.trim();
+    let encoded = encoded.strip_prefix("sha256//").unwrap_or(encoded);
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "upstream_tls_pin_pubkey must be a base64-encoded SHA-256 hash",
+            )
+        })
+}
+
 /// Parse the upstream host into a `ServerName` for the TLS handshake.
 /// Handles both DNS names and IP addresses.
 pub fn parse_server_name(host: &str) -> io::Result<ServerName<'static>> {
@@ -77,6 +334,295 @@ fn load_private_key(path: &str) -> io::Result<PrivateKeyDer<'static>> {
     })
 }
 
+/// Open a PKCS#12 bundle and extract its certificate chain and private key.
+fn load_pkcs12(
+    path: &str,
+    password: &str,
+) -> io::Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let der = fs::read(path).map_err(|e| io::Error::new(e.kind(), format!("{path}: {e}")))?;
+    let invalid =
+        |msg: String| io::Error::new(io::ErrorKind::InvalidData, format!("{path}: {msg}"));
+
+    let pfx = p12::PFX::parse(&der).map_err(|e| invalid(format!("invalid PKCS#12: {e}")))?;
+    if !pfx.verify_mac(password) {
+        return Err(invalid("PKCS#12 MAC check failed (wrong password?)".into()));
+    }
+
+    let certs: Vec<CertificateDer<'static>> = pfx
+        .cert_x509_bags(password)
+        .map_err(|e| invalid(format!("cannot decrypt certificates: {e}")))?
+        .into_iter()
+        .map(CertificateDer::from)
+        .collect();
+    if certs.is_empty() {
+        return Err(invalid("no certificate found in PKCS#12 bundle".into()));
+    }
+
+    let key = pfx
+        .key_bags(password)
+        .map_err(|e| invalid(format!("cannot decrypt private key: {e}")))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| invalid("no private key found in PKCS#12 bundle".into()))?;
+
+    Ok((certs, PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key))))
+}
+
+// ─── PinnedCertVerifier (public key pinning) ────────────────────────────────
+
+/// Runs the wrapped verifier, then requires the leaf certificate's
+/// SubjectPublicKeyInfo to hash to the pinned value.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    pin: [u8; 32],
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        let spki = subject_public_key_info(end_entity).ok_or_else(|| {
+            rustls::Error::General("cannot read public key from upstream certificate".into())
+        })?;
+        if Sha256::digest(spki)[..] != self.pin[..] {
+            return Err(rustls::Error::General(
+                "upstream certificate public key does not match upstream_tls_pin_pubkey".into(),
+            ));
+        }
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// The DER-encoded SubjectPublicKeyInfo of an X.509 certificate.
+///
+/// Walks just enough DER to find it: Certificate → tbsCertificate → skip
+/// [0] version, serialNumber, signature, issuer, validity, subject.
+fn subject_public_key_info(cert: &[u8]) -> Option<&[u8]> {
+    let (_, cert_body, _) = der_element(cert)?;
+    let (_, tbs, _) = der_element(cert_body)?;
+    let mut rest = tbs;
+    let (tag, _, _) = der_element(rest)?;
+    if tag == 0xa0 {
+        rest = der_element(rest)?.2;
+    }
+    for _ in 0..5 {
+        rest = der_element(rest)?.2;
+    }
+    let (tag, _, after) = der_element(rest)?;
+    (tag == 0x30).then(|| &rest[..rest.len() - after.len()])
+}
+
+/// The notAfter time of an X.509 certificate, as `YYYY-MM-DD HH:MM:SS UTC`.
+fn not_after(cert: &[u8]) -> Option<String> {
+    let (_, cert_body, _) = der_element(cert)?;
+    let (_, tbs, _) = der_element(cert_body)?;
+    let mut rest = tbs;
+    let (tag, _, _) = der_element(rest)?;
+    if tag == 0xa0 {
+        rest = der_element(rest)?.2;
+    }
+    // serialNumber, signature, issuer
+    for _ in 0..3 {
+        rest = der_element(rest)?.2;
+    }
+    let (_, validity, _) = der_element(rest)?;
+    let (_, _, validity) = der_element(validity)?; // skip notBefore
+    let (tag, time, _) = der_element(validity)?;
+    let time = std::str::from_utf8(time).ok()?;
+    let (year, time) = match tag {
+        // UTCTime: YYMMDDHHMMSSZ, years 1950–2049
+        0x17 => {
+            let yy: u32 = time.get(..2)?.parse().ok()?;
+            (if yy >= 50 { 1900 + yy } else { 2000 + yy }, time.get(2..)?)
+        }
+        // GeneralizedTime: YYYYMMDDHHMMSSZ
+        0x18 => (time.get(..4)?.parse().ok()?, time.get(4..)?),
+        _ => return None,
+    };
+    let field = |i: usize| time.get(i..i + 2);
+    Some(format!(
+        "{year}-{}-{} {}:{}:{} UTC",
+        field(0)?,
+        field(2)?,
+        field(4)?,
+        field(6)?,
+        field(8)?
+    ))
+}
+
+/// Split one DER element off `data`: `(tag, contents, remainder)`.
+fn der_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first = *data.get(1)? as usize;
+    let (len, header) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let n = first & 0x7f;
+        if n == 0 || n > 4 {
+            return None;
+        }
+        let bytes = data.get(2..2 + n)?;
+        (
+            bytes.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize),
+            2 + n,
+        )
+    };
+    let end = header.checked_add(len)?;
+    Some((tag, data.get(header..end)?, data.get(end..)?))
+}
+
+// ─── Certificate reload ─────────────────────────────────────────────────────
+
+/// The active TLS acceptor, replaced when the certificate files change.
+/// Connections take a copy at accept time, so a reload only affects new
+/// handshakes; established sessions keep the certificate they started with.
+#[derive(Clone)]
+pub struct SharedTlsAcceptor(Arc<std::sync::RwLock<TlsAcceptor>>);
+
+impl SharedTlsAcceptor {
+    pub fn new(acceptor: TlsAcceptor) -> Self {
+        Self(Arc::new(std::sync::RwLock::new(acceptor)))
+    }
+
+    pub fn current(&self) -> TlsAcceptor {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn replace(&self, acceptor: TlsAcceptor) {
+        *self.0.write().unwrap() = acceptor;
+    }
+}
+
+/// Polls the modification times of `tls_cert` and `tls_key` and rebuilds the
+/// shared acceptor when either changes (cert-manager, certbot renewals).
+pub struct CertWatcher {
+    cert_path: String,
+    key_path: String,
+    /// Rebuilt along with the default certificate (their files aren't watched).
+    sni_certs: Vec<TlsSniCert>,
+    policy: TlsPolicy,
+    acceptor: SharedTlsAcceptor,
+    /// Modification times of the files the acceptor was last built from.
+    loaded: Option<(SystemTime, SystemTime)>,
+}
+
+impl CertWatcher {
+    pub fn new(
+        cert_path: String,
+        key_path: String,
+        sni_certs: Vec<TlsSniCert>,
+        policy: TlsPolicy,
+        acceptor: SharedTlsAcceptor,
+    ) -> Self {
+        let loaded = modified(&cert_path, &key_path);
+        Self {
+            cert_path,
+            key_path,
+            sni_certs,
+            policy,
+            acceptor,
+            loaded,
+        }
+    }
+
+    /// Check the files every `interval`, forever.
+    pub async fn run(mut self, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            self.poll();
+        }
+    }
+
+    /// Check the files once; true if the acceptor was replaced. A failed
+    /// rebuild keeps the current certificate and is retried on the next
+    /// change (e.g. the key written after the cert).
+    fn poll(&mut self) -> bool {
+        let Some(current) = modified(&self.cert_path, &self.key_path) else {
+            return false; // mid-rotation rename; look again next time
+        };
+        if self.loaded == Some(current) {
+            return false;
+        }
+        self.loaded = Some(current);
+        match build_server_config(
+            &self.cert_path,
+            &self.key_path,
+            &self.sni_certs,
+            &self.policy,
+        ) {
+            Ok(server_config) => {
+                self.acceptor.replace(TlsAcceptor::from(server_config));
+                let expires =
+                    cert_not_after(&self.cert_path).unwrap_or_else(|e| format!("unknown ({e})"));
+                info!(cert = %self.cert_path, expires = %expires, "TLS certificate reloaded");
+                true
+            }
+            Err(e) => {
+                warn!(
+                    cert = %self.cert_path,
+                    error = %e,
+                    "TLS certificate reload failed — keeping current certificate"
+                );
+                false
+            }
+        }
+    }
+}
+
+fn modified(cert_path: &str, key_path: &str) -> Option<(SystemTime, SystemTime)> {
+    let mtime = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+    Some((mtime(cert_path)?, mtime(key_path)?))
+}
+
+/// Expiry of the first certificate in a PEM file, for logging.
+pub fn cert_not_after(cert_path: &str) -> io::Result<String> {
+    let certs = load_certs(cert_path)?;
+    certs
+        .first()
+        .and_then(|cert| not_after(cert.as_ref()))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{cert_path}: no parsable certificate"),
+            )
+        })
+}
+
 // ─── NoVerifier (skip-verify mode) ──────────────────────────────────────────
 
 #[derive(Debug)]
@@ -118,3 +664,295 @@ impl rustls::client::danger::ServerCertVerifier for NoVerifier {
             .supported_schemes()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_P12: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/server.p12");
+    const TEST_P12_PASSWORD: &str = "pgvpd-test";
+
+    fn policy(suites: Option<&[&str]>, min_version: Option<&str>) -> TlsPolicy {
+        TlsPolicy {
+            cipher_suites: suites.map(|v| v.iter().map(|s| s.to_string()).collect()),
+            min_version: min_version.map(String::from),
+        }
+    }
+
+    #[test]
+    fn pkcs12_bundle_builds_server_config() {
+        let p = TlsPolicy::default();
+        assert!(build_server_config_pkcs12(TEST_P12, TEST_P12_PASSWORD, &[], &p).is_ok());
+    }
+
+    #[test]
+    fn pkcs12_bundle_extracts_cert_and_key() {
+        let (certs, key) = load_pkcs12(TEST_P12, TEST_P12_PASSWORD).unwrap();
+        assert_eq!(certs.len(), 1);
+        assert!(matches!(key, PrivateKeyDer::Pkcs8(_)));
+    }
+
+    #[test]
+    fn pkcs12_wrong_password_fails() {
+        let p = TlsPolicy::default();
+        let err = build_server_config_pkcs12(TEST_P12, "wrong", &[], &p).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn pkcs12_missing_file_fails() {
+        let p = TlsPolicy::default();
+        assert!(build_server_config_pkcs12("/nonexistent/server.p12", "x", &[], &p).is_err());
+    }
+
+    // ─── Cipher suites / protocol versions ───────────────────────────────
+
+    #[test]
+    fn parse_known_cipher_suites() {
+        let names = vec![
+            "TLS13_AES_256_GCM_SHA384".to_string(),
+            "tls13_chacha20_poly1305_sha256".to_string(),
+        ];
+        let suites = parse_cipher_suites(&names).unwrap();
+        assert_eq!(suites.len(), 2);
+    }
+
+    #[test]
+    fn parse_unknown_cipher_suite_fails() {
+        let names = vec![
+            "TLS13_AES_256_GCM_SHA384".to_string(),
+            "TLS_BOGUS".to_string(),
+        ];
+        let err = parse_cipher_suites(&names).unwrap_err();
+        assert!(err.to_string().contains("TLS_BOGUS"));
+    }
+
+    #[test]
+    fn tls13_only_policy_builds_server_config() {
+        let p = policy(Some(&["TLS13_AES_256_GCM_SHA384"]), Some("1.3"));
+        assert!(build_server_config_pkcs12(TEST_P12, TEST_P12_PASSWORD, &[], &p).is_ok());
+    }
+
+    #[test]
+    fn tls12_suites_with_tls13_minimum_fails() {
+        let p = policy(
+            Some(&["TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384"]),
+            Some("1.3"),
+        );
+        assert!(build_server_config_pkcs12(TEST_P12, TEST_P12_PASSWORD, &[], &p).is_err());
+    }
+
+    #[test]
+    fn invalid_min_version_fails() {
+        let p = policy(None, Some("1.1"));
+        assert!(build_client_config(false, None, None, &p).is_err());
+    }
+
+    #[test]
+    fn restricted_client_config_builds() {
+        let p = policy(Some(&["TLS13_AES_128_GCM_SHA256"]), Some("1.3"));
+        assert!(build_client_config(false, None, None, &p).is_ok());
+    }
+
+    // ─── Public key pinning ──────────────────────────────────────────────
+
+    const TEST_CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/server.crt");
+    /// `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der |
+    /// openssl dgst -sha256 -binary | base64` of tests/certs/server.crt
+    const TEST_CERT_PIN: &str = "17oGZDtiduoVaCbZ9A0iIPq6QfYvhKGOATvaCkogqGI=";
+
+    fn pinned(pin: &str) -> PinnedCertVerifier {
+        PinnedCertVerifier {
+            inner: Arc::new(NoVerifier),
+            pin: parse_pin(pin).unwrap(),
+        }
+    }
+
+    fn verify(verifier: &PinnedCertVerifier) -> Result<ServerCertVerified, rustls::Error> {
+        let cert = load_certs(TEST_CERT).unwrap().remove(0);
+        let name = parse_server_name("localhost").unwrap();
+        verifier.verify_server_cert(&cert, &[], &name, &[], UnixTime::now())
+    }
+
+    #[test]
+    fn pin_matches_known_cert() {
+        assert!(verify(&pinned(TEST_CERT_PIN)).is_ok());
+        assert!(verify(&pinned(&format!("sha256//{TEST_CERT_PIN}"))).is_ok());
+    }
+
+    #[test]
+    fn wrong_pin_rejected() {
+        let err = verify(&pinned("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=")).unwrap_err();
+        assert!(err.to_string().contains("upstream_tls_pin_pubkey"));
+    }
+
+    #[test]
+    fn malformed_pin_rejected() {
+        assert!(parse_pin("not base64!").is_err());
+        assert!(parse_pin("c2hvcnQ=").is_err()); // valid base64, wrong length
+    }
+
+    // ─── Certificate reload ──────────────────────────────────────────────
+
+    const TEST_KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/server.key");
+
+    #[test]
+    fn cert_expiry_is_read() {
+        assert_eq!(
+            cert_not_after(TEST_CERT).unwrap(),
+            "2126-09-22 00:28:14 UTC"
+        );
+        assert!(cert_not_after(TEST_KEY).is_err());
+    }
+
+    #[test]
+    fn watcher_reloads_on_change() {
+        let dir = std::env::temp_dir().join(format!("pgvpd-cert-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let cert = dir.join("server.crt").to_string_lossy().into_owned();
+        let key = dir.join("server.key").to_string_lossy().into_owned();
+        fs::copy(TEST_CERT, &cert).unwrap();
+        fs::copy(TEST_KEY, &key).unwrap();
+
+        let p = TlsPolicy::default();
+        let acceptor = SharedTlsAcceptor::new(TlsAcceptor::from(
+            build_server_config(&cert, &key, &[], &p).unwrap(),
+        ));
+        let mut watcher = CertWatcher::new(cert.clone(), key.clone(), Vec::new(), p, acceptor);
+        assert!(!watcher.poll());
+
+        let touch = |path: &str, at: SystemTime| {
+            File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(at)
+                .unwrap();
+        };
+        let later = SystemTime::now() + Duration::from_secs(10);
+        touch(&cert, later);
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        // A broken cert keeps the old acceptor and isn't retried until it changes
+        fs::write(&cert, "not a certificate").unwrap();
+        touch(&cert, later + Duration::from_secs(10));
+        assert!(!watcher.poll());
+        assert!(!watcher.poll());
+
+        fs::copy(TEST_CERT, &cert).unwrap();
+        touch(&cert, later + Duration::from_secs(20));
+        assert!(watcher.poll());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // ─── SNI certificate selection ───────────────────────────────────────
+
+    const TENANT_A_CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/tenant-a.crt");
+    const TENANT_A_KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/tenant-a.key");
+
+    fn sni_cert(sni: &str, cert: &str, key: &str) -> TlsSniCert {
+        TlsSniCert {
+            sni: sni.into(),
+            cert: cert.into(),
+            key: key.into(),
+        }
+    }
+
+    #[test]
+    fn sni_selects_matching_certificate() {
+        let provider = TlsPolicy::default().provider().unwrap();
+        let resolver = SniCertResolver::new(
+            load_certs(TEST_CERT).unwrap(),
+            load_private_key(TEST_KEY).unwrap(),
+            &[
+                sni_cert("*.tenant-a.example.com", TENANT_A_CERT, TENANT_A_KEY),
+                sni_cert("db.tenant-b.example.com", TEST_CERT, TEST_KEY),
+            ],
+            &provider,
+        )
+        .unwrap();
+        let default = &load_certs(TEST_CERT).unwrap()[0];
+        let tenant_a = &load_certs(TENANT_A_CERT).unwrap()[0];
+        let selected = |name: Option<&str>| resolver.lookup(name).cert[0].clone();
+
+        assert_eq!(&selected(Some("db.tenant-a.example.com")), tenant_a);
+        assert_eq!(&selected(Some("DB.Tenant-A.example.com")), tenant_a);
+        assert_eq!(&selected(Some("db.tenant-b.example.com")), default);
+        // A wildcard covers exactly one label
+        assert_eq!(&selected(Some("tenant-a.example.com")), default);
+        assert_eq!(&selected(Some("x.db.tenant-a.example.com")), default);
+        assert_eq!(&selected(Some("other.example.com")), default);
+        assert_eq!(&selected(None), default);
+    }
+
+    #[test]
+    fn exact_sni_beats_wildcard() {
+        let provider = TlsPolicy::default().provider().unwrap();
+        let resolver = SniCertResolver::new(
+            load_certs(TEST_CERT).unwrap(),
+            load_private_key(TEST_KEY).unwrap(),
+            &[
+                sni_cert("*.tenant-a.example.com", TEST_CERT, TEST_KEY),
+                sni_cert("db.tenant-a.example.com", TENANT_A_CERT, TENANT_A_KEY),
+            ],
+            &provider,
+        )
+        .unwrap();
+        let tenant_a = &load_certs(TENANT_A_CERT).unwrap()[0];
+        assert_eq!(
+            &resolver.lookup(Some("db.tenant-a.example.com")).cert[0],
+            tenant_a
+        );
+    }
+
+    #[test]
+    fn sni_server_config_builds() {
+        let p = TlsPolicy::default();
+        let sni = [sni_cert(
+            "*.tenant-a.example.com",
+            TENANT_A_CERT,
+            TENANT_A_KEY,
+        )];
+        assert!(build_server_config(TEST_CERT, TEST_KEY, &sni, &p).is_ok());
+        assert!(build_server_config_pkcs12(TEST_P12, TEST_P12_PASSWORD, &sni, &p).is_ok());
+
+        let missing = [sni_cert(
+            "*.tenant-a.example.com",
+            "/nonexistent.crt",
+            TENANT_A_KEY,
+        )];
+        assert!(build_server_config(TEST_CERT, TEST_KEY, &missing, &p).is_err());
+        // A key file is not a certificate chain
+        let empty = [sni_cert(
+            "*.tenant-a.example.com",
+            TENANT_A_KEY,
+            TENANT_A_KEY,
+        )];
+        assert!(build_server_config(TEST_CERT, TEST_KEY, &empty, &p).is_err());
+    }
+
+    #[test]
+    fn sni_patterns() {
+        assert!(sni_matches("*.example.com", "db.example.com"));
+        assert!(!sni_matches("*.example.com", "example.com"));
+        assert!(!sni_matches("*.example.com", "a.b.example.com"));
+        assert!(!sni_matches("*.example.com", "dbexample.com"));
+        assert!(sni_matches("db.example.com", "db.example.com"));
+        assert!(!sni_matches("db.example.com", "x.db.example.com"));
+
+        assert!(is_valid_sni_pattern("*.example.com"));
+        assert!(is_valid_sni_pattern("localhost"));
+        for bad in ["", "*", "*.", "a.*.com", "*x.com", "a..com", ".com"] {
+            assert!(!is_valid_sni_pattern(bad), "{bad}");
+        }
+    }
+
+    #[test]
+    fn pinned_client_config_builds() {
+        let p = TlsPolicy::default();
+        assert!(build_client_config(true, None, Some(TEST_CERT_PIN), &p).is_ok());
+        assert!(build_client_config(true, None, Some("bogus"), &p).is_err());
+    }
+}
@@ -83,6 +83,33 @@ INSERT INTO acl_cases (id, creator_id, org_id, title) VALUES
 
 GRANT SELECT, INSERT, UPDATE, DELETE ON acl_cases TO app_user;
 
+-- ─── 7. Resolver that raises a NOTICE (forwarding test) ──────────────────
+
+CREATE OR REPLACE FUNCTION resolver_notice_probe(uid uuid) RETURNS text
+LANGUAGE plpgsql AS $$
+BEGIN
+  RAISE NOTICE 'resolver notice for %', uid;
+  RETURN 'ok';
+END $$;
+
+GRANT EXECUTE ON FUNCTION resolver_notice_probe(uuid) TO app_user;
+
+-- ─── 8. Login trigger that raises a NOTICE (forwarding test) ─────────────
+-- Fires after authentication, before ReadyForQuery. Only sessions that set
+-- application_name = pgvpd_notice_probe see it.
+
+CREATE OR REPLACE FUNCTION login_notice_probe() RETURNS event_trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+  IF current_setting('application_name', true) = 'pgvpd_notice_probe' THEN
+    RAISE NOTICE 'login notice for %', session_user;
+  END IF;
+END $$;
+
+DROP EVENT TRIGGER IF EXISTS login_notice_probe;
+CREATE EVENT TRIGGER login_notice_probe ON login
+  EXECUTE FUNCTION login_notice_probe();
+
 -- ─── Done ─────────────────────────────────────────────────────────────────
 
 DO $$ BEGIN RAISE NOTICE 'Test fixtures loaded.'; END $$;
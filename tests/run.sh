@@ -178,6 +178,14 @@ else
   fail "1.5 Context variable — unexpected result: $result"
 fi
 
+# Test 1.6: NOTICE sent between authentication and ReadyForQuery reaches the client
+result=$(PGAPPNAME=pgvpd_notice_probe run_psql "app_user.tenant_a" -c "SELECT 1")
+if echo "$result" | grep -q "login notice for app_user"; then
+  pass "1.6 Login NOTICE — forwarded to client"
+else
+  fail "1.6 Login NOTICE — not forwarded: $result"
+fi
+
 stop_pgvpd
 
 # ═══════════════════════════════════════════════════════════════════════════
@@ -268,6 +276,14 @@ else
   fail "3.3 Cache hit — no 'cache hit' in logs"
 fi
 
+# Test 3.4: NOTICE raised by a resolver query reaches the client
+result=$(run_psql "app_user.$TEST_UUID" -c "SELECT 1")
+if echo "$result" | grep -q "resolver notice for $TEST_UUID"; then
+  pass "3.4 Resolver NOTICE — forwarded to client"
+else
+  fail "3.4 Resolver NOTICE — not forwarded: $result"
+fi
+
 stop_pgvpd
 
 # ═══════════════════════════════════════════════════════════════════════════